@@ -0,0 +1,12 @@
+pragma circom 2.1.3;
+
+// the smallest circuit with a single public input (its output)
+template Multiplier() {
+    signal input a;
+    signal input b;
+    signal output c;
+
+    c <== a * b;
+}
+
+component main = Multiplier();
@@ -1,41 +1,75 @@
-use std::str::FromStr;
-
-use anyhow::Result;
-use bitcoin::{absolute::LockTime, transaction::Version, Amount, PublicKey, Transaction, TxOut};
+use anyhow::{Context, Result};
+use bitcoin::{
+    absolute::LockTime, transaction::Version, Amount, FeeRate, Transaction, TxOut, Txid,
+};
 use log::{debug, info};
 
-use crate::constants::ZKBITCOIN_PUBKEY;
+use crate::coin_selection::CoinSelection;
+use crate::constants::DEFAULT_MIN_FEERATE_SAT_VB;
 use crate::json_rpc_stuff::{
-    fund_raw_transaction, send_raw_transaction, sign_transaction, RpcCtx, TransactionOrHex,
+    check_spendable_balance, fund_raw_transaction_with_options, send_raw_transaction,
+    sign_transaction, FundOptions, RpcCtx, TransactionOrHex, FUNDING_ESTIMATE_VBYTES,
 };
-use crate::{op_return_script_for, p2tr_script_to};
+use crate::proof_system::ProofScheme;
+use crate::{op_return_script_for_scheme, p2tr_script_to, zkbitcoin_pubkey};
 
-/// Generates and broadcasts a transaction to the network.
-/// Specifically, this sends a transaction to 0xzkBitcoin, for some given amount in satoshis,
-/// and authenticates the verifier key `vk` that can unlock the founds.
-pub async fn generate_and_broadcast_transaction(
-    ctx: &RpcCtx,
-    vk_hash: &[u8; 32],
-    initial_state: Option<&str>,
-    satoshi_amount: u64,
-) -> Result<bitcoin::Txid> {
+/// The parameters needed to deploy a zkapp.
+#[derive(Debug, Clone)]
+pub struct DeployParams {
+    /// The hash of the verifier key that can unlock the funds.
+    pub vk_hash: [u8; 32],
+
+    /// The proof system of the verifier key (recorded on-chain, see [crate::op_return_script_for_scheme]).
+    pub scheme: ProofScheme,
+
+    /// The initial state of the zkapp (a Circom field element), if the zkapp is stateful.
+    pub initial_state: Option<String>,
+
+    /// The amount (in satoshis) to lock in the zkapp.
+    pub satoshi_amount: u64,
+
+    /// The fee rate (in sat/vB) to use. If not set, the wallet estimates it.
+    pub fee_rate: Option<u64>,
+
+    /// How the coins funding the deployment are selected.
+    pub coin_selection: CoinSelection,
+}
+
+/// A zkapp that was deployed on-chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeployedZkapp {
+    /// The transaction that deployed the zkapp.
+    pub txid: Txid,
+
+    /// The output of that transaction that locks the funds of the zkapp.
+    pub vout: u32,
+}
+
+/// Deploys a zkapp: builds the transaction to 0xzkBitcoin (with the verifier key and initial state in an OP_RETURN),
+/// funds it and signs it with the wallet, and broadcasts it to the network.
+pub async fn deploy_zkapp(ctx: &RpcCtx, params: DeployParams) -> Result<DeployedZkapp> {
     // 1. create transaction based on VK + amount
     // https://developer.bitcoin.org/reference/rpc/createrawtransaction.html
     //
-    let (_tx, tx_hex) = {
+    let zkapp_script = p2tr_script_to(zkbitcoin_pubkey()?);
+    let (tx, tx_hex) = {
         let mut outputs = vec![];
         // first output is a P2PK to 0xzkBitcoin
         {
-            let zkbitcoin_pubkey: PublicKey = PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
             outputs.push(TxOut {
-                value: Amount::from_sat(satoshi_amount),
-                script_pubkey: p2tr_script_to(zkbitcoin_pubkey),
+                value: Amount::from_sat(params.satoshi_amount),
+                script_pubkey: zkapp_script.clone(),
             });
         }
 
         // second output is VK + initial state
+        // (this fails if the data doesn't fit in a standard OP_RETURN)
         {
-            let script_pubkey = op_return_script_for(vk_hash, initial_state)?;
+            let script_pubkey = op_return_script_for_scheme(
+                params.scheme,
+                &params.vk_hash,
+                params.initial_state.as_deref(),
+            )?;
             let value = script_pubkey.dust_value();
             outputs.push(TxOut {
                 value,
@@ -58,25 +92,70 @@ pub async fn generate_and_broadcast_transaction(
         (tx, tx_hex)
     };
 
+    // fail early (and clearly) if the wallet can't pay for the zkapp and the fee
+    let fee_rate = FeeRate::from_sat_per_vb(params.fee_rate.unwrap_or(DEFAULT_MIN_FEERATE_SAT_VB))
+        .context("the feerate is too high")?;
+    let required = tx.output.iter().map(|output| output.value).sum::<Amount>()
+        + fee_rate
+            .fee_vb(FUNDING_ESTIMATE_VBYTES)
+            .context("the feerate is too high")?;
+    check_spendable_balance(ctx, required).await?;
+
     // 2. ask wallet to add inputs to fund the transaction
     // https://developer.bitcoin.org/reference/rpc/fundrawtransaction.html
     //
+    let options = FundOptions {
+        fee_rate: params.fee_rate,
+        coin_selection: params.coin_selection,
+        ..Default::default()
+    };
     let (raw_tx_with_inputs_hex, _raw_tx_with_inputs, fee) =
-        fund_raw_transaction(ctx, TransactionOrHex::Hex(tx_hex)).await?;
+        fund_raw_transaction_with_options(ctx, TransactionOrHex::Hex(tx_hex), &options).await?;
     info!("- funded transaction with fee: {fee}");
 
     // 3. sign transaction
     // https://developer.bitcoin.org/reference/rpc/signrawtransactionwithwallet.html
     //
-    let (signed_tx_hex, _signed_tx) =
+    let (signed_tx_hex, signed_tx) =
         sign_transaction(ctx, TransactionOrHex::Hex(raw_tx_with_inputs_hex)).await?;
 
+    // the wallet might have inserted a change output anywhere
+    let vout = signed_tx
+        .output
+        .iter()
+        .position(|output| output.script_pubkey == zkapp_script)
+        .context("the signed transaction doesn't send anything to 0xzkBitcoin")?;
+
     // 4. broadcast transaction
     // https://developer.bitcoin.org/reference/rpc/sendrawtransaction.html
     //
     let txid = send_raw_transaction(ctx, TransactionOrHex::Hex(signed_tx_hex)).await?;
 
     //
+    Ok(DeployedZkapp {
+        txid,
+        vout: vout as u32,
+    })
+}
+
+/// Generates and broadcasts a transaction to the network.
+/// Specifically, this sends a transaction to 0xzkBitcoin, for some given amount in satoshis,
+/// and authenticates the verifier key `vk` that can unlock the founds.
+pub async fn generate_and_broadcast_transaction(
+    ctx: &RpcCtx,
+    vk_hash: &[u8; 32],
+    initial_state: Option<&str>,
+    satoshi_amount: u64,
+) -> Result<bitcoin::Txid> {
+    let params = DeployParams {
+        vk_hash: *vk_hash,
+        scheme: ProofScheme::Plonk,
+        initial_state: initial_state.map(str::to_string),
+        satoshi_amount,
+        fee_rate: None,
+        coin_selection: CoinSelection::default(),
+    };
+    let DeployedZkapp { txid, .. } = deploy_zkapp(ctx, params).await?;
     Ok(txid)
 }
 
@@ -2,31 +2,45 @@ use std::{collections::HashMap, path::Path, str::FromStr, sync::Arc, vec};
 
 use anyhow::{bail, ensure, Context, Result};
 use bitcoin::{
-    opcodes::all::OP_RETURN, script::Instruction, Address, Amount, Denomination, OutPoint,
-    PublicKey, Transaction, TxOut, Txid, Witness,
+    absolute::LockTime, opcodes::all::OP_RETURN, script::Instruction, Address, Amount,
+    Denomination, FeeRate, OutPoint, PublicKey, ScriptBuf, Sequence, TapSighashType, Transaction,
+    TxOut, Txid, Witness,
 };
-use log::{debug, info};
+use log::{debug, info, warn};
 use num_bigint::BigUint;
 use serde::{Deserialize, Serialize};
 
+use crate::json_rpc_stuff::RpcCtx;
 use crate::{
-    circom_field_from_bytes, circom_field_to_bytes,
+    circom_field_from_bytes,
+    committee::node::{VerifyResponse, VERIFY_PATH},
     compliance::Compliance,
     constants::{
-        FEE_ZKBITCOIN_SAT, MINIMUM_CONFIRMATIONS, STATEFUL_ZKAPP_PUBLIC_INPUT_LEN,
-        ZKBITCOIN_FEE_PUBKEY, ZKBITCOIN_PUBKEY,
+        DEFAULT_MAX_PROOF_SIZE, DEFAULT_MAX_PUBLIC_INPUTS, DEFAULT_MIN_FEERATE_SAT_VB,
+        DEFAULT_VK_CACHE_SIZE, FEE_ZKBITCOIN_SAT, MAX_CORRELATION_ID_LEN, MINIMUM_CONFIRMATIONS,
+        MIN_ZKAPP_CONFIRMATIONS_MAINNET, MIN_ZKAPP_CONFIRMATIONS_TESTNET, ZKBITCOIN_FEE_PUBKEY,
     },
+    fee_policy::FeePolicy,
     get_network,
     json_rpc_stuff::{
-        createrawtransaction, fund_raw_transaction, get_transaction, json_rpc_request,
-        TransactionOrHex,
+        check_spendable_balance, createrawtransaction, fund_raw_transaction_with_options,
+        get_block_height, get_raw_transaction, get_transaction, get_tx_out, json_rpc_request,
+        scan_txout_set, unlock_unspent, FundOptions, TransactionOrHex, FUNDING_ESTIMATE_VBYTES,
     },
-    p2tr_script_to,
+    op_return_data_for, p2tr_script_to,
     plonk::PublicInputs,
-    snarkjs::{self, verify_proof},
-    taproot_addr_from, truncate_txid,
+    proof_system::{self, check_scheme, ProofScheme},
+    public_inputs::{self, PublicInputError, STATEFUL_INPUT_NAMES, STATELESS_INPUT_NAMES},
+    sighash::{
+        check_sighash_type, compute_keyspend_sighash_with_type, default_sighash_type,
+        KEYSPEND_SIGHASH_TYPE,
+    },
+    snarkjs, taproot_addr_from, truncate_txid,
+    tx_template::check_lock_time,
+    vk_cache::VkCache,
+    vk_commitment::{verify_vk_commitment, VkCommitment},
+    zkbitcoin_pubkey,
 };
-use crate::{json_rpc_stuff::RpcCtx, plonk};
 
 //
 // Helpers
@@ -76,6 +90,81 @@ pub struct Update {
     pub amount_in: String,
 }
 
+impl Update {
+    /// The same update, with its public inputs checked to be field elements and given in decimal
+    /// (they can be supplied in 0x-prefixed hex).
+    pub fn normalized(&self) -> Result<Self, PublicInputError> {
+        let input = |index: usize, value: &str| {
+            public_inputs::normalize(index, STATEFUL_INPUT_NAMES[index], value)
+        };
+        Ok(Self {
+            new_state: input(0, &self.new_state)?,
+            prev_state: input(1, &self.prev_state)?,
+            truncated_txid: self.truncated_txid.clone(),
+            amount_out: input(3, &self.amount_out)?,
+            amount_in: input(4, &self.amount_in)?,
+        })
+    }
+}
+
+/// Another zkapp spent by the transaction of a request (see [BobRequest::other_zkapps]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZkappSpend {
+    /// The transaction that deployed the zkapp.
+    pub zkapp_tx: Transaction,
+
+    /// The verifier key authenticated by the deployed transaction.
+    pub vk: proof_system::VerifierKey,
+
+    /// A proof of execution (over the same transaction as the request),
+    /// of the scheme the zkapp was deployed with.
+    pub proof: proof_system::Proof,
+
+    /// In case of stateful zkapps, the update that can be converted as public inputs.
+    pub update: Option<Update>,
+
+    /// The sighash type the committee signs the input of this zkapp with.
+    #[serde(default = "default_sighash_type")]
+    pub sighash_type: TapSighashType,
+}
+
+/// A zkapp input that the committee signs, once its request validated (see [BobRequest::validate_zkapps]).
+#[derive(Debug, Clone)]
+pub struct ZkappInput {
+    /// The index of the input in the transaction.
+    pub input_index: usize,
+
+    pub smart_contract: SmartContract,
+
+    pub sighash_type: TapSighashType,
+}
+
+/// What the committee signs for a zkapp input (see [BobRequest::sighash_preview]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputSighash {
+    /// The index of the input in the transaction.
+    pub input_index: usize,
+
+    pub outpoint: OutPoint,
+
+    /// The output spent by the input (its script and amount), as given by Bob.
+    pub prev_out: TxOut,
+
+    pub sighash_type: TapSighashType,
+
+    /// The taproot key-spend sighash of the input (in hex).
+    pub sighash: String,
+}
+
+/// The sighashes the committee would sign for a request, one per zkapp input (in the order of the inputs).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SighashPreview {
+    /// The txid of the (unsigned) transaction.
+    pub txid: Txid,
+
+    pub inputs: Vec<InputSighash>,
+}
+
 /// A request from Bob to unlock funds from a smart contract.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BobRequest {
@@ -91,28 +180,111 @@ pub struct BobRequest {
     pub zkapp_tx: Transaction,
 
     /// The verifier key authenticated by the deployed transaction.
-    pub vk: plonk::VerifierKey,
+    pub vk: proof_system::VerifierKey,
 
-    /// A proof of execution.
-    pub proof: plonk::Proof,
+    /// A proof of execution, of the scheme the zkapp was deployed with
+    /// (in the snarkjs JSON format of that scheme).
+    pub proof: proof_system::Proof,
 
     /// In case of stateful zkapps, the update that can be converted as public inputs.
     pub update: Option<Update>,
 
     /// List of all the [TxOut] pointed out by the inputs.
     /// (This is needed to sign the transaction.)
-    /// We can trust this because if Bob sends us wrong data the signature we create simply won't verify.
+    /// If Bob sends us wrong data the signature we create simply won't verify,
+    /// but nodes with access to a bitcoind node check them against the chain anyway (see [check_prev_outs]).
     pub prev_outs: Vec<TxOut>,
+
+    /// The sighash type the committee signs the zkapp input with (see [check_sighash_type]).
+    #[serde(default = "default_sighash_type")]
+    pub sighash_type: TapSighashType,
+
+    /// Optionally, a secret picked by Bob to identify his signing session (see [crate::committee::session_history::session_id_from_token]),
+    /// and to cancel it while it's running. The orchestrator doesn't forward it to the committee members.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_token: Option<String>,
+
+    /// An id tying together the log lines of the client, the orchestrator, and the committee members about this request
+    /// (see [check_correlation_id]). The orchestrator picks one if the client didn't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+
+    /// Other zkapps spent by the same transaction (e.g. to consolidate several deposits), each with its own proof.
+    /// The committee signs each of their inputs separately, within the same session.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub other_zkapps: Vec<ZkappSpend>,
+}
+
+/// Options for the transaction spending a zkapp.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpendOptions {
+    /// The lock time of the transaction (e.g. to make it valid only after some block height).
+    pub lock_time: Option<LockTime>,
+
+    /// The sequence of the zkapp input (e.g. to opt into RBF).
+    /// If a lock time is set, it defaults to a non-final sequence so that the lock time is enforced.
+    pub sequence: Option<Sequence>,
+
+    /// The sighash type the committee signs the zkapp input with (defaults to [KEYSPEND_SIGHASH_TYPE]).
+    pub sighash_type: Option<TapSighashType>,
+
+    /// The service fee paid to zkBitcoinFund, deducted from what Bob withdraws
+    /// (it must match the fee policy of the orchestrator).
+    pub fee_policy: FeePolicy,
+}
+
+/// Generates a new correlation id (see [BobRequest::correlation_id]).
+pub fn new_correlation_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Checks that a correlation id is short, and only made of characters that can safely end up in logs
+/// (alphanumerics, `-`, `_`, and `.`, e.g. a UUID).
+pub fn check_correlation_id(correlation_id: &str) -> Result<()> {
+    ensure!(
+        !correlation_id.is_empty() && correlation_id.len() <= MAX_CORRELATION_ID_LEN,
+        "the correlation id must be between 1 and {MAX_CORRELATION_ID_LEN} characters long"
+    );
+    ensure!(
+        correlation_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')),
+        "the correlation id can only contain alphanumerics, `-`, `_`, and `.`"
+    );
+    Ok(())
 }
 
 impl BobRequest {
-    #[allow(clippy::absurd_extreme_comparisons)]
     pub async fn new(
+        rpc_ctx: &RpcCtx,
+        bob_address: Address,
+        txid: bitcoin::Txid, // of zkapp
+        circom_circuit_path: &Path,
+        proof_inputs: HashMap<String, Vec<String>>,
+    ) -> Result<Self> {
+        Self::new_with_options(
+            rpc_ctx,
+            bob_address,
+            txid,
+            circom_circuit_path,
+            proof_inputs,
+            SpendOptions::default(),
+        )
+        .await
+    }
+
+    /// Same as [BobRequest::new], but with control over the lock time and sequence of the transaction.
+    /// The wallet inputs funding the transaction are locked (see [FundOptions::lock_unspents]):
+    /// they are unlocked if creating the request fails, otherwise it's up to the caller to unlock them
+    /// if the request doesn't end up broadcast (see [BobRequest::wallet_inputs]).
+    #[allow(clippy::absurd_extreme_comparisons)]
+    pub async fn new_with_options(
         rpc_ctx: &RpcCtx,
         bob_address: Address,
         txid: bitcoin::Txid, // of zkapp
         circom_circuit_path: &Path,
         mut proof_inputs: HashMap<String, Vec<String>>,
+        spend_options: SpendOptions,
     ) -> Result<Self> {
         // fetch transaction + metadata based on txid
         debug!("- fetching txid {txid}");
@@ -128,6 +300,31 @@ impl BobRequest {
         let smart_contract = extract_smart_contract_from_tx(&zkapp_tx)?;
         debug!("- smart contract being used: {smart_contract:?}",);
 
+        // we can only prove with snarkjs' PLONK for now
+        // (proofs of other schemes have to be produced separately, and set on the request)
+        ensure!(
+            smart_contract.scheme == ProofScheme::Plonk,
+            "the zkapp was deployed with {}, but only PLONK proofs can be created here",
+            smart_contract.scheme
+        );
+
+        // fail early (and clearly) if the wallet can't pay for its part of the transaction (before proving anything):
+        // the network fee, and what Bob deposits in a stateful zkapp
+        let deposit = if smart_contract.is_stateful() {
+            string_to_amount(
+                proof_inputs
+                    .get("amount_in")
+                    .and_then(|x| x.first())
+                    .context("amount_in in proof inputs must be of length 1")?,
+            )?
+        } else {
+            Amount::ZERO
+        };
+        let network_fee = FeeRate::from_sat_per_vb(DEFAULT_MIN_FEERATE_SAT_VB)
+            .and_then(|fee_rate| fee_rate.fee_vb(FUNDING_ESTIMATE_VBYTES))
+            .context("the fee estimate overflowed")?;
+        check_spendable_balance(rpc_ctx, deposit + network_fee).await?;
+
         // create a proof with a 0 txid
         // (we expect the proof to give the same `new_state` with the correct `truncated_txid` later)
         // we need to do this because we need to include the `new_state` in a stateful zkapp transaction
@@ -156,20 +353,43 @@ impl BobRequest {
 
         // create funded transaction
         let tx = {
-            let inputs = vec![
-                // the zkapp being used
-                serde_json::json!({
-                    "txid": txid.to_string(),
-                    "vout": smart_contract.vout_of_zkbitcoin_utxo,
-                }),
-            ];
+            // the zkapp being used
+            // (bitcoind uses a non-final sequence by default if there's a lock time)
+            let mut zkapp_input = serde_json::json!({
+                "txid": txid.to_string(),
+                "vout": smart_contract.vout_of_zkbitcoin_utxo,
+            });
+            if let Some(sequence) = spend_options.sequence {
+                zkapp_input["sequence"] = sequence.to_consensus_u32().into();
+            }
+            let inputs = vec![zkapp_input];
 
             let fee_address = taproot_addr_from(ZKBITCOIN_FEE_PUBKEY)?;
-            let fee = Amount::from_sat(FEE_ZKBITCOIN_SAT).to_string_in(Denomination::Bitcoin);
+
+            // the amount Bob withdraws from the zkapp, which the service fee is deducted from
+            let withdrawn = if smart_contract.is_stateless() {
+                smart_contract.locked_value
+            } else {
+                string_to_amount(
+                    proof_inputs
+                        .get("amount_out")
+                        .and_then(|x| x.first())
+                        .context("amount_out in proof inputs must be of length 1")?,
+                )?
+            };
+            let (fee, amount_for_bob) = if withdrawn > Amount::ZERO {
+                spend_options
+                    .fee_policy
+                    .deduct(withdrawn, &bob_address.script_pubkey())?
+            } else {
+                // nothing to deduct it from, so the wallet pays it
+                (spend_options.fee_policy.fee(withdrawn), Amount::ZERO)
+            };
             debug!(
-                "- first output is to zkBitcoinFund: {} for {} BTC",
-                fee_address, fee
+                "- first output is to zkBitcoinFund: {} for {} ({})",
+                fee_address, fee, spend_options.fee_policy
             );
+            let fee = fee.to_string_in(Denomination::Bitcoin);
 
             let mut outputs = vec![
                 // first output is to zkBitcoinFund
@@ -179,10 +399,8 @@ impl BobRequest {
             ];
 
             if smart_contract.is_stateless() {
-                // move all the funds to Bob's address
-                let amount_out = smart_contract
-                    .locked_value
-                    .to_string_in(Denomination::Bitcoin);
+                // move all the funds (minus the service fee) to Bob's address
+                let amount_out = amount_for_bob.to_string_in(Denomination::Bitcoin);
                 debug!(
                     "- stateless: second output is to ourselves: {} for {} BTC",
                     bob_address, amount_out
@@ -222,7 +440,7 @@ impl BobRequest {
                 );
 
                 // the updated zkapp
-                let zkbitcoin_address = taproot_addr_from(ZKBITCOIN_PUBKEY)?;
+                let zkbitcoin_address = taproot_addr_from(&zkbitcoin_pubkey()?.to_string())?;
                 debug!(
                     "- stateful: second output is to zkBitcoin: {} for {} BTC",
                     zkbitcoin_address, new_value
@@ -231,30 +449,49 @@ impl BobRequest {
                     zkbitcoin_address.to_string(): new_value
                 }));
 
-                // Bob can only withdraw amount_out
+                // Bob can only withdraw amount_out (minus the service fee)
                 if withdraw_happening {
-                    debug!("- Bob is receiving amount_out: {}", amount_out);
+                    debug!(
+                        "- Bob is receiving amount_out: {} (minus the service fee)",
+                        amount_out
+                    );
                     outputs.push(serde_json::json!({
-                        bob_address.to_string(): amount_out,
+                        bob_address.to_string(): amount_for_bob.to_string_in(Denomination::Bitcoin),
                     }));
                 }
 
                 // its vk + new state
+                // (this fails if the data doesn't fit in a standard OP_RETURN)
                 let new_state = new_state.as_ref().context("no new state")?;
-                let mut data = smart_contract.vk_hash.to_vec();
-                data.extend(circom_field_to_bytes(new_state).context("incorrect new state given")?);
+                let data = op_return_data_for(&smart_contract.vk_hash, Some(new_state))
+                    .context("incorrect new state given")?;
                 outputs.push(serde_json::json!({
                     "data": hex::encode(data),
                 }));
             }
 
             // call createrawtransaction
-            let (tx_hex, tx) = createrawtransaction(rpc_ctx, inputs, outputs, 0).await?;
+            let lock_time = spend_options.lock_time.unwrap_or(LockTime::ZERO);
+            let (tx_hex, tx) = createrawtransaction(
+                rpc_ctx,
+                inputs,
+                outputs,
+                lock_time.to_consensus_u32() as usize,
+            )
+            .await?;
+            check_lock_time(&tx)?;
             debug!("- tx created: {tx:?}");
 
             // fund that transaction
+            // (the inputs picked by the wallet stay locked until the transaction is broadcast,
+            // or until they are unlocked via `wallet_inputs()` if the request fails later on)
+            let options = FundOptions {
+                lock_unspents: true,
+                ..Default::default()
+            };
             let (_tx_hex, tx, fee) =
-                fund_raw_transaction(rpc_ctx, TransactionOrHex::Hex(tx_hex)).await?;
+                fund_raw_transaction_with_options(rpc_ctx, TransactionOrHex::Hex(tx_hex), &options)
+                    .await?;
 
             info!("- funded tx with fee {fee}");
             debug!("- tx funded: {tx:?}");
@@ -262,87 +499,246 @@ impl BobRequest {
             tx
         };
 
-        // create a proof with the correct txid this time
-        let truncated_txid = truncate_txid(tx.txid());
-        proof_inputs.insert("truncated_txid".to_string(), vec![truncated_txid]);
-
-        let (proof, public_inputs, vk) = snarkjs::prove(circom_circuit_path, &proof_inputs).await?;
-        debug!(
-            "- public_inputs used to create the proof: {:?}",
-            public_inputs.0
-        );
+        // from now on, release the inputs the wallet locked if we fail to create the request
+        let wallet_inputs = tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .filter(|outpoint| {
+                *outpoint != OutPoint::new(txid, smart_contract.vout_of_zkbitcoin_utxo)
+            })
+            .collect::<Vec<_>>();
+        let res: Result<Self> = async {
+            // create a proof with the correct txid this time
+            let truncated_txid = truncate_txid(tx.txid());
+            proof_inputs.insert("truncated_txid".to_string(), vec![truncated_txid]);
 
-        // sanity check
-        ensure!(
-            vk.hash() == smart_contract.vk_hash,
-            "the zkapp being used does not match the circuit passed"
-        );
+            let (proof, public_inputs, vk) = snarkjs::prove(circom_circuit_path, &proof_inputs).await?;
+            debug!(
+                "- public_inputs used to create the proof: {:?}",
+                public_inputs.0
+            );
 
-        // and ensure it created the same new_state
-        let update = if smart_contract.is_stateful() {
-            let new_state = new_state.unwrap();
+            // sanity check
             ensure!(
-                public_inputs.0.len()
-                    == 2 /* prev/new_state */ + 1 /* truncated txid */ + 1 /* amount_out */ + 1, /* amount_in */
-                "the number of public inputs is not correct"
+                vk.hash() == smart_contract.vk_hash,
+                "the zkapp being used does not match the circuit passed"
             );
 
-            let new_state2 = &public_inputs.0[0];
+            // and ensure it created the same new_state
+            let update = if smart_contract.is_stateful() {
+                let new_state = new_state.unwrap();
+                ensure!(
+                    public_inputs.0.len()
+                        == 2 /* prev/new_state */ + 1 /* truncated txid */ + 1 /* amount_out */ + 1, /* amount_in */
+                    "the number of public inputs is not correct"
+                );
+
+                let new_state2 = &public_inputs.0[0];
+                ensure!(
+                    &new_state == new_state2,
+                    "the circuit must return the same output given different txid"
+                );
+
+                Some(public_inputs.to_update())
+            } else {
+                None
+            };
+
+            // compute zkapp input as the input that uses the zkapp
+            let zkapp_inputs = tx
+                .input
+                .iter()
+                .filter(|x| x.previous_output.txid == txid)
+                .collect::<Vec<_>>();
+
             ensure!(
-                &new_state == new_state2,
-                "the circuit must return the same output given different txid"
+                zkapp_inputs.len() == 1,
+                "internal error: the transaction does not contain the zkapp being used or it contains duplicate inputs"
             );
 
-            Some(public_inputs.to_update())
-        } else {
-            None
+            // compute prev_outs as all the TxOut pointed out by the inputs
+            let mut prev_outs = vec![];
+            for (input_idx, input) in tx.input.iter().enumerate() {
+                let (_, tx, confirmations) =
+                    get_transaction(rpc_ctx, input.previous_output.txid).await?;
+                // TODO: this is not useful as the transaction itself has received enough confirmation at this point
+                ensure!(
+                    confirmations >= MINIMUM_CONFIRMATIONS,
+                    "one of the input ({}) is not confirmed yet",
+                    input.previous_output.txid
+                );
+
+                prev_outs.push(
+                    tx.output
+                        .get(input.previous_output.vout as usize)
+                        .context(format!("the input {input_idx} does not exist"))?
+                        .clone(),
+                );
+            }
+
+            // create request
+            let res = Self {
+                tx,
+                zkapp_tx,
+                vk: vk.into(),
+                proof: proof.into(),
+                update,
+                prev_outs,
+                sighash_type: spend_options.sighash_type.unwrap_or(KEYSPEND_SIGHASH_TYPE),
+                session_token: None,
+                correlation_id: None,
+                other_zkapps: vec![],
+            };
+
+            Ok(res)
+        }
+        .await;
+        let res = match res {
+            Ok(res) => res,
+            Err(err) => {
+                if let Err(unlock_err) = unlock_unspent(rpc_ctx, &wallet_inputs).await {
+                    warn!("- couldn't unlock the wallet inputs: {unlock_err}");
+                }
+                return Err(err);
+            }
         };
 
-        // compute zkapp input as the input that uses the zkapp
-        let zkapp_inputs = tx
-            .input
-            .iter()
-            .filter(|x| x.previous_output.txid == txid)
-            .collect::<Vec<_>>();
+        debug!("- Bob's request: {res:?}");
 
-        ensure!(
-            zkapp_inputs.len() == 1,
-            "internal error: the transaction does not contain the zkapp being used or it contains duplicate inputs"
-        );
+        Ok(res)
+    }
+
+    /// The correlation id of the request, as shown in logs (`-` if it doesn't have one yet).
+    pub fn log_id(&self) -> &str {
+        self.correlation_id.as_deref().unwrap_or("-")
+    }
 
-        // compute prev_outs as all the TxOut pointed out by the inputs
-        let mut prev_outs = vec![];
-        for (input_idx, input) in tx.input.iter().enumerate() {
-            let (_, tx, confirmations) =
-                get_transaction(rpc_ctx, input.previous_output.txid).await?;
-            // TODO: this is not useful as the transaction itself has received enough confirmation at this point
+    /// The requests for each zkapp spent by the transaction (this one, and the ones of [BobRequest::other_zkapps]),
+    /// sorted by the index of their input, so that everyone goes through them in the same order.
+    pub fn zkapp_requests(&self) -> Result<Vec<BobRequest>> {
+        let mut requests = vec![Self {
+            other_zkapps: vec![],
+            ..self.clone()
+        }];
+        for zkapp in &self.other_zkapps {
+            requests.push(Self {
+                zkapp_tx: zkapp.zkapp_tx.clone(),
+                vk: zkapp.vk.clone(),
+                proof: zkapp.proof.clone(),
+                update: zkapp.update.clone(),
+                sighash_type: zkapp.sighash_type,
+                other_zkapps: vec![],
+                ..self.clone()
+            });
+        }
+
+        let mut indexed = vec![];
+        for request in requests {
+            let outpoint = request.zkapp_outpoint()?;
+            let input_index = self
+                .tx
+                .input
+                .iter()
+                .position(|input| input.previous_output == outpoint)
+                .context("couldn't find zkapp input in transaction")?;
             ensure!(
-                confirmations >= MINIMUM_CONFIRMATIONS,
-                "one of the input ({}) is not confirmed yet",
-                input.previous_output.txid
+                indexed.iter().all(|(idx, _)| *idx != input_index),
+                "the zkapp {outpoint} is spent twice in the request"
             );
+            indexed.push((input_index, request));
+        }
+        indexed.sort_by_key(|(input_index, _)| *input_index);
+        Ok(indexed.into_iter().map(|(_, request)| request).collect())
+    }
 
-            prev_outs.push(
-                tx.output
-                    .get(input.previous_output.vout as usize)
-                    .context(format!("the input {input_idx} does not exist"))?
-                    .clone(),
-            );
+    /// Validates the request of every zkapp spent by the transaction (see [BobRequest::zkapp_requests]),
+    /// and returns their inputs, in order.
+    pub async fn validate_zkapps(&self) -> Result<Vec<ZkappInput>> {
+        self.validate_zkapps_with_limits(&ProofLimits::default())
+            .await
+    }
+
+    /// Same as [BobRequest::validate_zkapps], but refuses proofs exceeding `limits` (see [BobRequest::validate_request_with_limits]).
+    pub async fn validate_zkapps_with_limits(
+        &self,
+        limits: &ProofLimits,
+    ) -> Result<Vec<ZkappInput>> {
+        self.validate_zkapps_with(limits, &VkCache::new(0)).await
+    }
+
+    /// Same as [BobRequest::validate_zkapps_with_limits], but with the verifier keys checked before kept in `vk_cache`.
+    pub async fn validate_zkapps_with(
+        &self,
+        limits: &ProofLimits,
+        vk_cache: &VkCache,
+    ) -> Result<Vec<ZkappInput>> {
+        let mut inputs = vec![];
+        for request in self.zkapp_requests()? {
+            let smart_contract = request
+                .validate_request_with(limits, vk_cache)
+                .await
+                .with_context(|| {
+                    format!(
+                        "the request for zkapp {} didn't validate",
+                        request.zkapp_tx.txid()
+                    )
+                })?;
+            let outpoint = request.zkapp_outpoint()?;
+            let input_index = self
+                .tx
+                .input
+                .iter()
+                .position(|input| input.previous_output == outpoint)
+                .context("couldn't find zkapp input in transaction")?;
+            inputs.push(ZkappInput {
+                input_index,
+                smart_contract,
+                sighash_type: request.sighash_type,
+            });
         }
+        Ok(inputs)
+    }
 
-        // create request
-        let res = Self {
-            tx,
-            zkapp_tx,
-            vk,
-            proof,
-            update,
-            prev_outs,
-        };
+    /// The sighashes the committee would sign for this request, without validating it (see [BobRequest::validate_zkapps]).
+    /// They only depend on the transaction and on the prevouts given by Bob,
+    /// so that anyone can recompute them to check what the committee is asked to sign.
+    pub fn sighash_preview(&self) -> Result<SighashPreview> {
+        ensure!(
+            self.prev_outs.len() == self.tx.input.len(),
+            "got {} prevouts for {} inputs",
+            self.prev_outs.len(),
+            self.tx.input.len()
+        );
 
-        debug!("- Bob's request: {res:?}");
+        let mut inputs = vec![];
+        for request in self.zkapp_requests()? {
+            let outpoint = request.zkapp_outpoint()?;
+            let input_index = self
+                .tx
+                .input
+                .iter()
+                .position(|input| input.previous_output == outpoint)
+                .context("couldn't find zkapp input in transaction")?;
+            let sighash = compute_keyspend_sighash_with_type(
+                &self.tx,
+                input_index,
+                &self.prev_outs,
+                request.sighash_type,
+            )?;
+            inputs.push(InputSighash {
+                input_index,
+                outpoint,
+                prev_out: self.prev_outs[input_index].clone(),
+                sighash_type: request.sighash_type,
+                sighash: hex::encode(sighash),
+            });
+        }
 
-        Ok(res)
+        Ok(SighashPreview {
+            txid: self.tx.txid(),
+            inputs,
+        })
     }
 
     pub fn unlocked_tx(&self, witness: Witness) -> Result<Transaction> {
@@ -359,7 +755,7 @@ impl BobRequest {
     }
 
     /// The transaction ID and output index of the zkapp used in the request.
-    fn zkapp_outpoint(&self) -> Result<OutPoint> {
+    pub fn zkapp_outpoint(&self) -> Result<OutPoint> {
         let outpoint = self
             .tx
             .input
@@ -377,6 +773,23 @@ impl BobRequest {
         Ok(self.zkapp_outpoint()?.txid)
     }
 
+    /// The inputs of the transaction that were added by Bob's wallet to fund it (i.e. all inputs except the zkapps).
+    pub fn wallet_inputs(&self) -> Result<Vec<OutPoint>> {
+        let zkapp_outpoints = self
+            .zkapp_requests()?
+            .iter()
+            .map(|request| request.zkapp_outpoint())
+            .collect::<Result<Vec<_>>>()?;
+        let outpoints = self
+            .tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .filter(|outpoint| !zkapp_outpoints.contains(outpoint))
+            .collect();
+        Ok(outpoints)
+    }
+
     /// Validate the unsigned transaction contained in Bob's request.
     /// It checks outputs, but not inputs.
     /// The caller will be in charge of retrieving the smart contract and verifying its execution.
@@ -387,6 +800,9 @@ impl BobRequest {
     ) -> Result<()> {
         // TODO: we need to make sure that amount_out < smart_contract.locked_value
 
+        // the lock time (if any) must be enforced, as that's what the committee signs
+        check_lock_time(tx)?;
+
         // it must contain an output fee paid to zkBitcoinFund
         {
             let pay_to_zkbitcoin_fund_script =
@@ -418,9 +834,10 @@ impl BobRequest {
             // if the zkapp is stateful, it must also produce a new stateful zkapp as output
             let new_zkapp = extract_smart_contract_from_tx(tx)?;
 
-            // it contains the same VK
+            // it contains the same VK (for the same scheme)
             ensure!(
-                new_zkapp.vk_hash == smart_contract.vk_hash,
+                new_zkapp.vk_hash == smart_contract.vk_hash
+                    && new_zkapp.scheme == smart_contract.scheme,
                 "the updated zkapp is not the same as the previous zkapp"
             );
 
@@ -428,36 +845,54 @@ impl BobRequest {
             let new_state_observed = new_zkapp.state.context(
                 "the zkapp created as output is not stateful, but the consumed zkapp was stateful",
             )?;
-            ensure!(
-                new_state_observed == update.new_state,
-                "the updated zkapp does not contain the correct new state"
-            );
+            public_inputs::check_reserved(
+                0,
+                STATEFUL_INPUT_NAMES[0],
+                &new_state_observed,
+                &update.new_state,
+                "the state of the zkapp created by the transaction",
+            )?;
 
             // ensure that it contains the correct locked value after withdrawl and funding
             let new_value = new_zkapp.locked_value;
             let expected_value = {
-                let amount_out = Amount::from_str_in(&update.amount_out, Denomination::Satoshi)?;
-                let amount_in = Amount::from_str_in(&update.amount_in, Denomination::Satoshi)?;
-                smart_contract.locked_value + amount_in - amount_out
+                let amount = |index: usize, value: &str| {
+                    Amount::from_str_in(value, Denomination::Satoshi).with_context(|| {
+                        format!(
+                            "public input #{index} ({}) `{value}` is not an amount of satoshis",
+                            STATEFUL_INPUT_NAMES[index]
+                        )
+                    })
+                };
+                let amount_out = amount(3, &update.amount_out)?;
+                let amount_in = amount(4, &update.amount_in)?;
+                (smart_contract.locked_value + amount_in)
+                    .checked_sub(amount_out)
+                    .with_context(|| format!("public input #3 (amount_out) withdraws {amount_out}, but the zkapp only locks {} (plus {amount_in} deposited)", smart_contract.locked_value))?
             };
-            ensure!(expected_value == new_value, "the updated zkapp does not contain the correct locked value after withdrawl and funding");
+            ensure!(expected_value == new_value, "the updated zkapp locks {new_value}, but amount_in (public input #4) and amount_out (public input #3) imply {expected_value} after withdrawal and funding");
         }
 
         //
         Ok(())
     }
 
-    /// Check that the zkapp input transactions are compliant
+    /// Check that the outputs spent by the transaction are compliant.
+    /// Inputs whose prevout doesn't map to an address can't be checked, so they're refused.
     pub async fn check_compliance(&self, compliance: Arc<Compliance>) -> Result<()> {
-        for (index, zkapp_txin) in self.zkapp_tx.input.iter().enumerate() {
-            let addr = Address::from_script(
-                &zkapp_txin.script_sig.clone().into_boxed_script(),
-                get_network(),
-            )?;
+        ensure!(
+            self.prev_outs.len() == self.tx.input.len(),
+            "the request gives {} prevouts for {} inputs",
+            self.prev_outs.len(),
+            self.tx.input.len()
+        );
+        for (index, prev_out) in self.prev_outs.iter().enumerate() {
+            let addr = Address::from_script(&prev_out.script_pubkey, get_network())
+                .with_context(|| format!("input #{index} doesn't spend from an address"))?;
 
             ensure!(
                 !compliance.is_sanctioned(&addr).await,
-                format!("ZkApp input #{index} is sanctioned"),
+                format!("input #{index} is sanctioned"),
             );
         }
 
@@ -466,6 +901,29 @@ impl BobRequest {
 
     /// Validates a request received from Bob.
     pub async fn validate_request(&self) -> Result<SmartContract> {
+        self.validate_request_with_limits(&ProofLimits::default())
+            .await
+    }
+
+    /// Same as [BobRequest::validate_request], but refuses proofs exceeding `limits`
+    /// before anything else (the error can then be downcast to [ProofLimitExceeded]).
+    pub async fn validate_request_with_limits(
+        &self,
+        limits: &ProofLimits,
+    ) -> Result<SmartContract> {
+        self.validate_request_with(limits, &VkCache::new(0)).await
+    }
+
+    /// Same as [BobRequest::validate_request_with_limits], but the verifier key is only checked against the zkapp
+    /// (and prepared) if it's not in `vk_cache` already.
+    pub async fn validate_request_with(
+        &self,
+        limits: &ProofLimits,
+        vk_cache: &VkCache,
+    ) -> Result<SmartContract> {
+        // don't let oversized proofs anywhere near the verifier
+        limits.check(&self.vk, &self.proof)?;
+
         // extract smart contract from tx
         let smart_contract = extract_smart_contract_from_tx(&self.zkapp_tx)?;
 
@@ -476,52 +934,79 @@ impl BobRequest {
             "the zkapp_tx given is not the one being used"
         );
 
+        // the proof (and VK) must be of the scheme the zkapp was deployed with
+        // (the error can be downcast to [proof_system::SchemeMismatch])
+        check_scheme(smart_contract.scheme, &self.vk, &self.proof)?;
+
+        // the public inputs given with an update must be field elements
+        // (the error can be downcast to [PublicInputError], as all the errors about public inputs)
+        let update = self.update.as_ref().map(Update::normalized).transpose()?;
+
+        // the signature must commit to all the outputs checked below
+        check_sighash_type(self.sighash_type)?;
+
         // validate the unsigned transaction
-        Self::validate_transaction(&self.tx, &smart_contract, self.update.as_ref())?;
+        Self::validate_transaction(&self.tx, &smart_contract, update.as_ref())?;
 
-        // ensure that the hash of the VK correctly gives us the vk_hash
-        ensure!(
-            smart_contract.vk_hash[..] == self.vk.hash(),
-            "VK does not match the VK hash in the smart contract"
-        );
+        // the VK must be the one committed to by the zkapp, not one the orchestrator picked
+        // (the error can be downcast to [crate::vk_commitment::CommitmentError])
+        verify_vk_commitment(&self.zkapp_tx, &serde_json::to_vec(&self.vk)?)?;
 
-        // TODO: do we need to check that vk.nPublic makes sense?
+        // ensure that the hash of the VK correctly gives us the vk_hash
+        // (unless we already checked it, in which case we use the VK we checked)
+        let vk = vk_cache.get_or_insert(&smart_contract.vk_hash, &self.vk)?;
 
         // create truncated txid of Bob's transaction
         let bob_txid = self.tx.txid();
         let truncated_txid = truncate_txid(bob_txid);
 
         // retrieve amount to be moved
-        let public_inputs = if let Some(prev_state) = &smart_contract.state {
-            // ensure that we have an update
-            let update = self
-                .update
-                .as_ref()
-                .context("an update was expected as the smart contract is stateful")?;
-
-            // ensure that the smart contract expects the correct number of public inputs
-            ensure!(
-                self.vk.nPublic == STATEFUL_ZKAPP_PUBLIC_INPUT_LEN,
-                "the smart contract is malformed, we observed {nPublic} public inputs, but expected {STATEFUL_ZKAPP_PUBLIC_INPUT_LEN} for a stateful zkapp", nPublic=self.vk.nPublic
-            );
+        let (public_inputs, names): (_, &'static [&'static str]) =
+            if let Some(prev_state) = &smart_contract.state {
+                // ensure that we have an update
+                let update = update
+                    .as_ref()
+                    .context("an update was expected as the smart contract is stateful")?;
+
+                // ensure that the previous state used is correctly used
+                public_inputs::check_reserved(
+                    1,
+                    STATEFUL_INPUT_NAMES[1],
+                    prev_state,
+                    &update.prev_state,
+                    "the state of the zkapp being spent",
+                )?;
 
-            // ensure that the previous state used is correctly used
-            ensure!(prev_state == &update.prev_state);
+                //
+                (
+                    PublicInputs::from_update(update, truncated_txid)?.0,
+                    &STATEFUL_INPUT_NAMES,
+                )
+            } else {
+                (vec![truncated_txid], &STATELESS_INPUT_NAMES)
+            };
 
-            //
-            PublicInputs::from_update(update, truncated_txid)?.0
-        } else {
-            vec![truncated_txid]
-        };
+        // the verifier key must expect as many public inputs as the zkapp uses
+        let public_inputs =
+            public_inputs::validate(vk.vk.num_public_inputs(), names, &public_inputs)?;
         debug!("- using public inputs: {public_inputs:?}");
 
         // TODO: ensure that there's enough funds remaining to cover for bitcoin and zkBitcoin fee
         // TODO: we need to make sure that new_locked = prev_locked + amount_in - amount_out and that amount_out < prev_locked + amount_in
         //smart_contract.check_remaining_funds(&self)?;
 
-        // verify proof using snarkjs
-        debug!("- attempting to verify proof");
-        verify_proof(&self.vk, &public_inputs, &self.proof)?;
+        // verify proof (using snarkjs for PLONK, natively for Groth16)
+        debug!("- attempting to verify {} proof", self.proof.scheme());
+        if let Err(err) = vk.prepared.verify(&public_inputs, &self.proof) {
+            // a cached VK that doesn't match its hash anymore gets evicted: check the one given again
+            if vk_cache.revalidate(&smart_contract.vk_hash) {
+                return Err(err);
+            }
+            vk_cache
+                .get_or_insert(&smart_contract.vk_hash, &self.vk)?
+                .prepared
+                .verify(&public_inputs, &self.proof)?;
+        }
 
         //
         Ok(smart_contract)
@@ -533,8 +1018,15 @@ pub struct BobResponse {
     pub unlocked_tx: Transaction,
 }
 
-pub async fn send_bob_request(address: &str, request: BobRequest) -> Result<BobResponse> {
-    let ctx = RpcCtx::new(Some("2.0"), None, Some(address.to_string()), None, None);
+/// Sends Bob's request to the orchestrator, with a new correlation id if it doesn't have one
+/// (errors mention it, so that it can be looked up in the logs of the orchestrator and of the committee).
+pub async fn send_bob_request(address: &str, mut request: BobRequest) -> Result<BobResponse> {
+    let correlation_id = request
+        .correlation_id
+        .get_or_insert_with(new_correlation_id)
+        .clone();
+    info!("- sending request with correlation id {correlation_id} to the orchestrator");
+    let ctx = RpcCtx::builder().version("2.0").url(address).build()?;
 
     let resp = json_rpc_request(
         &ctx,
@@ -542,15 +1034,58 @@ pub async fn send_bob_request(address: &str, request: BobRequest) -> Result<BobR
         &[serde_json::value::to_raw_value(&request).unwrap()],
     )
     .await
-    .context("couldn't send unlock_funds request to orchestrator")?;
+    .with_context(|| {
+        format!("couldn't send unlock_funds request {correlation_id} to orchestrator")
+    })?;
 
     let response: bitcoincore_rpc::jsonrpc::Response =
         serde_json::from_str(&resp).context("couldn't deserialize orchestrator's response")?;
-    let bob_response: BobResponse = response.result().context("bob request failed")?;
+    let bob_response: BobResponse = response
+        .result()
+        .with_context(|| format!("bob request failed (correlation id {correlation_id})"))?;
 
     Ok(bob_response)
 }
 
+/// Asks the orchestrator for the sighashes the committee would sign for a request (see [BobRequest::sighash_preview]),
+/// without starting a signing session.
+pub async fn fetch_sighash_preview(address: &str, request: &BobRequest) -> Result<SighashPreview> {
+    let ctx = RpcCtx::builder().version("2.0").url(address).build()?;
+
+    let resp = json_rpc_request(
+        &ctx,
+        "preview_sighashes",
+        &[serde_json::value::to_raw_value(request)?],
+    )
+    .await
+    .context("couldn't send preview_sighashes request to orchestrator")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response =
+        serde_json::from_str(&resp).context("couldn't deserialize orchestrator's response")?;
+    response
+        .result()
+        .context("the orchestrator couldn't preview the sighashes")
+}
+
+/// Asks a committee node (at `node_address`) whether it would sign a request, without signing anything
+/// (see [crate::committee::node::VERIFY_PATH]). Useful to debug proofs before starting a signing session.
+pub async fn verify_with_node(node_address: &str, request: &BobRequest) -> Result<VerifyResponse> {
+    let url = format!("{}{VERIFY_PATH}", node_address.trim_end_matches('/'));
+    let response = reqwest::Client::new()
+        .post(&url)
+        .json(request)
+        .send()
+        .await
+        .with_context(|| format!("couldn't reach {url}"))?;
+    let status = response.status();
+    let body = response.text().await?;
+    ensure!(
+        status.is_success(),
+        "the node couldn't verify the request ({status}): {body}"
+    );
+    serde_json::from_str(&body).context("couldn't deserialize the node's response")
+}
+
 //
 // Everything at this point is to parse and validate Bob's request.
 //
@@ -563,6 +1098,9 @@ pub struct SmartContract {
     pub vk_hash: [u8; 32],
     pub state: Option<String>,
     pub vout_of_zkbitcoin_utxo: u32,
+
+    /// The proof system the zkapp is verified with.
+    pub scheme: ProofScheme,
 }
 
 impl std::fmt::Display for SmartContract {
@@ -573,11 +1111,12 @@ impl std::fmt::Display for SmartContract {
             vk_hash,
             state,
             vout_of_zkbitcoin_utxo,
+            scheme,
         } = &self;
 
         write!(
             f,
-            "- txid: {txid} (output #{vout}), locked_value: {locked_value}, vk_hash: {vk_hash}, {state}",
+            "- txid: {txid} (output #{vout}), locked_value: {locked_value}, vk_hash: {vk_hash} ({scheme}), {state}",
             vk_hash=hex::encode(vk_hash),
             vout=vout_of_zkbitcoin_utxo,
             state=state.as_ref().map(|s| format!("state: {s}")).unwrap_or("stateless".to_string())
@@ -599,7 +1138,16 @@ impl SmartContract {
 }
 
 pub fn parse_op_return_data(script: &bitcoin::ScriptBuf) -> Result<Vec<u8>> {
-    // we expect [OP_RETURN, OP_PUSHBYTES]
+    parse_op_return_data_with_scheme(script).map(|(_, data)| data)
+}
+
+/// Parses the OP_RETURN of a zkapp (see [crate::op_return_script_for_scheme]),
+/// returning the scheme of the zkapp and its data.
+pub fn parse_op_return_data_with_scheme(
+    script: &bitcoin::ScriptBuf,
+) -> Result<(ProofScheme, Vec<u8>)> {
+    // we expect [OP_RETURN, OP_PUSHBYTES] (PLONK)
+    // or [OP_RETURN, OP_PUSHBYTES_1 <scheme tag>, OP_PUSHBYTES] (other schemes)
     // anything else we won't accept
     let mut instructions = script.instructions();
     let inst = instructions
@@ -613,29 +1161,43 @@ pub fn parse_op_return_data(script: &bitcoin::ScriptBuf) -> Result<Vec<u8>> {
         "caller should have checked that this is an OP_RETURN"
     );
 
-    let inst = instructions
-        .next()
-        .context("no data was pushed as the last instruction of the script")?;
-
-    let res = if let Ok(Instruction::PushBytes(bytes)) = inst {
-        bytes.as_bytes().to_vec()
-    } else {
-        bail!("last instruction of the script was not a pushdata");
-    };
-
-    ensure!(
-        instructions.next().is_none(),
-        "we only expect one pushdata in an OP_RETURN"
-    );
+    let mut pushes = vec![];
+    for inst in instructions {
+        if let Ok(Instruction::PushBytes(bytes)) = inst {
+            pushes.push(bytes.as_bytes().to_vec());
+        } else {
+            bail!("an instruction of the script was not a pushdata");
+        }
+    }
 
-    Ok(res)
+    match <[Vec<u8>; 2]>::try_from(pushes) {
+        Ok([tag, data]) => {
+            ensure!(tag.len() == 1, "the scheme tag must be a single byte");
+            let scheme = ProofScheme::from_tag(tag[0])?;
+            ensure!(
+                scheme != ProofScheme::Plonk,
+                "PLONK zkapps don't tag their scheme"
+            );
+            Ok((scheme, data))
+        }
+        Err(mut pushes) => {
+            ensure!(
+                !pushes.is_empty(),
+                "no data was pushed as the last instruction of the script"
+            );
+            ensure!(
+                pushes.len() == 1,
+                "we only expect the data (and optionally a scheme tag) in an OP_RETURN"
+            );
+            Ok((ProofScheme::Plonk, pushes.remove(0)))
+        }
+    }
 }
 
 /// Extracts smart contract information as a [SmartContract] from a transaction.
 pub fn extract_smart_contract_from_tx(raw_tx: &Transaction) -> Result<SmartContract> {
     // extract zkapp locked amount
-    let zkbitcoin_pubkey: PublicKey = PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
-    let expected_script = p2tr_script_to(zkbitcoin_pubkey);
+    let expected_script = p2tr_script_to(zkbitcoin_pubkey()?);
     let (vout, output) = raw_tx
         .output
         .iter()
@@ -644,31 +1206,20 @@ pub fn extract_smart_contract_from_tx(raw_tx: &Transaction) -> Result<SmartContr
         .context("Transaction does not contain an output for 0xzkBitcoin")?;
     let locked_value = output.value;
 
-    // extract OP_RETURN data
-    let (vk_hash, state) = {
-        let output = raw_tx
-            .output
-            .iter()
-            .find(|x| x.script_pubkey.is_op_return())
-            .context("Transaction has no OP_RETURN")?;
-        let data = parse_op_return_data(&output.script_pubkey)?;
-
-        // ensure that the list at least contains the VK hash
-        // other elements in the list are presumed to contain public inputs
-        ensure!(data.len() >= 32, "OP_RETURN output is too small, it should at least contain the 32-byte hash of the verifier key");
-
-        let (vk_hash, state) = data.split_at(32);
-        let vk_hash: [u8; 32] = vk_hash.try_into().unwrap();
+    // extract OP_RETURN data (the VK hash, followed by the state if any)
+    // (the error can be downcast to [crate::vk_commitment::CommitmentError])
+    let (scheme, vk_hash, state) = {
+        let commitment = VkCommitment::from_tx(raw_tx)?;
 
         // parse state
-        let state = if state.is_empty() {
+        let state = if commitment.state.is_empty() {
             None
         } else {
-            let res = circom_field_from_bytes(state)?;
+            let res = circom_field_from_bytes(&commitment.state)?;
             Some(res)
         };
 
-        (vk_hash, state)
+        (commitment.scheme, commitment.vk_hash, state)
     };
 
     let smart_contract = SmartContract {
@@ -677,10 +1228,391 @@ pub fn extract_smart_contract_from_tx(raw_tx: &Transaction) -> Result<SmartContr
         vk_hash,
         state,
         vout_of_zkbitcoin_utxo: vout as u32,
+        scheme,
     };
     Ok(smart_contract)
 }
 
+/// An unspent zkapp output found on-chain.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZkappUtxo {
+    pub outpoint: OutPoint,
+
+    /// The amount locked in the zkapp.
+    pub amount: Amount,
+
+    /// The height of the block that included the zkapp (0 if it's still in the mempool).
+    pub height: u64,
+
+    /// The number of confirmations of the zkapp (0 if it's still in the mempool).
+    pub confirmations: u64,
+
+    /// The OP_RETURN payload of the transaction (in hex),
+    /// which should contain the verifier key hash and the state of the zkapp (if any).
+    pub op_return_payload: Option<String>,
+}
+
+/// Finds all the unspent zkapps locked at `zkbitcoin_addr` (optionally, only those included from height `from_height`).
+/// Note that fetching the OP_RETURN payloads requires bitcoind to run with `-txindex`,
+/// if they can't be fetched they are left empty.
+pub async fn find_zkapps(
+    ctx: &RpcCtx,
+    zkbitcoin_addr: &Address,
+    from_height: Option<u64>,
+) -> Result<Vec<ZkappUtxo>> {
+    let res = scan_txout_set(ctx, &zkbitcoin_addr.to_string()).await?;
+    let tip_height = res
+        .height
+        .context("scantxoutset didn't return the tip height")?;
+
+    let mut zkapps = vec![];
+    for unspent in res.unspents {
+        if from_height.is_some_and(|from_height| unspent.height < from_height) {
+            continue;
+        }
+
+        let op_return_payload = match get_raw_transaction(ctx, unspent.txid).await {
+            Ok(tx) => tx
+                .output
+                .iter()
+                .find(|output| output.script_pubkey.is_op_return())
+                .and_then(|output| parse_op_return_data(&output.script_pubkey).ok())
+                .map(hex::encode),
+            Err(err) => {
+                debug!("- couldn't fetch transaction {}: {err}", unspent.txid);
+                None
+            }
+        };
+
+        zkapps.push(ZkappUtxo {
+            outpoint: OutPoint::new(unspent.txid, unspent.vout),
+            amount: unspent.amount,
+            height: unspent.height,
+            confirmations: tip_height.saturating_sub(unspent.height) + 1,
+            op_return_payload,
+        });
+    }
+
+    Ok(zkapps)
+}
+
+/// Returns the zkapp locked at `outpoint`, or `None` if it was spent (including by a transaction in the mempool).
+/// This is cheaper than [fetch_smart_contract], but doesn't fetch the OP_RETURN payload.
+pub async fn get_zkapp_utxo(ctx: &RpcCtx, outpoint: OutPoint) -> Result<Option<ZkappUtxo>> {
+    let Some(txout) = get_tx_out(ctx, outpoint, true).await? else {
+        return Ok(None);
+    };
+
+    ensure!(
+        txout.script_pub_key.hex == p2tr_script_to(zkbitcoin_pubkey()?).into_bytes(),
+        "{outpoint} is not locked at the zkBitcoin address"
+    );
+
+    let confirmations = txout.confirmations as u64;
+    let height = if confirmations == 0 {
+        0
+    } else {
+        get_block_height(ctx, txout.bestblock).await? + 1 - confirmations
+    };
+
+    Ok(Some(ZkappUtxo {
+        outpoint,
+        amount: txout.value,
+        height,
+        confirmations,
+        op_return_payload: None,
+    }))
+}
+
+/// The default number of confirmations a zkapp needs before the committee signs a spend of it.
+pub fn default_min_zkapp_confirmations(network: bitcoin::Network) -> u64 {
+    match network {
+        bitcoin::Network::Bitcoin => MIN_ZKAPP_CONFIRMATIONS_MAINNET,
+        _ => MIN_ZKAPP_CONFIRMATIONS_TESTNET,
+    }
+}
+
+/// Limits on the proofs we verify, so that huge or malformed proofs are refused before burning CPU on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofLimits {
+    /// The maximum size of a proof, serialized as JSON (in bytes).
+    pub max_proof_size: usize,
+
+    /// The maximum number of public inputs (as declared by the verifier key).
+    pub max_public_inputs: usize,
+
+    /// The number of verifier keys kept ready to verify proofs (see [VkCache]).
+    pub vk_cache_size: usize,
+}
+
+impl Default for ProofLimits {
+    fn default() -> Self {
+        Self {
+            max_proof_size: DEFAULT_MAX_PROOF_SIZE,
+            max_public_inputs: DEFAULT_MAX_PUBLIC_INPUTS,
+            vk_cache_size: DEFAULT_VK_CACHE_SIZE,
+        }
+    }
+}
+
+impl ProofLimits {
+    /// Checks a proof (and the verifier key it's checked against) against the limits, without verifying it.
+    pub fn check(
+        &self,
+        vk: &proof_system::VerifierKey,
+        proof: &proof_system::Proof,
+    ) -> Result<(), ProofLimitExceeded> {
+        if vk.num_public_inputs() > self.max_public_inputs {
+            return Err(ProofLimitExceeded::PublicInputs {
+                count: vk.num_public_inputs(),
+                max: self.max_public_inputs,
+            });
+        }
+        let size = serde_json::to_vec(proof).map_or(usize::MAX, |proof| proof.len());
+        if size > self.max_proof_size {
+            return Err(ProofLimitExceeded::ProofSize {
+                size,
+                max: self.max_proof_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A proof was refused without being verified, as it exceeds the [ProofLimits] of the node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "limit", rename_all = "snake_case")]
+pub enum ProofLimitExceeded {
+    ProofSize { size: usize, max: usize },
+    PublicInputs { count: usize, max: usize },
+}
+
+impl std::fmt::Display for ProofLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ProofSize { size, max } => {
+                write!(
+                    f,
+                    "the proof is too large ({size} bytes, the limit is {max})"
+                )
+            }
+            Self::PublicInputs { count, max } => write!(
+                f,
+                "the proof has too many public inputs ({count}, the limit is {max})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProofLimitExceeded {}
+
+/// The zkapp being spent doesn't have enough confirmations yet (see [check_zkapp_confirmations]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InsufficientConfirmations {
+    pub have: u64,
+    pub need: u64,
+}
+
+impl std::fmt::Display for InsufficientConfirmations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "insufficient confirmations (have {}, need {})",
+            self.have, self.need
+        )
+    }
+}
+
+impl std::error::Error for InsufficientConfirmations {}
+
+/// The zkapp being spent was already spent (or never existed), see [check_zkapp_confirmations].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZkappAlreadySpent {
+    pub outpoint: OutPoint,
+
+    /// The transaction that spent it, if it could be found.
+    pub spent_by: Option<Txid>,
+
+    /// The number of confirmations of that transaction (0 if it's in the mempool), if known.
+    pub confirmations: Option<u64>,
+}
+
+impl std::fmt::Display for ZkappAlreadySpent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the zkapp {} is already spent", self.outpoint)?;
+        if let Some(txid) = self.spent_by {
+            write!(f, " by {txid}")?;
+            match self.confirmations {
+                Some(0) => write!(f, " (in the mempool)")?,
+                Some(confirmations) => write!(f, " ({confirmations} confirmations)")?,
+                None => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ZkappAlreadySpent {}
+
+/// Checks that the zkapp at `outpoint` (as fetched by [get_zkapp_utxo]) is unspent,
+/// and has at least `min_confirmations` confirmations.
+/// If it doesn't, the error can be downcast to [ZkappAlreadySpent] or [InsufficientConfirmations].
+pub fn check_zkapp_confirmations(
+    outpoint: OutPoint,
+    zkapp: Option<&ZkappUtxo>,
+    min_confirmations: u64,
+) -> Result<()> {
+    let Some(zkapp) = zkapp else {
+        return Err(ZkappAlreadySpent {
+            outpoint,
+            spent_by: None,
+            confirmations: None,
+        }
+        .into());
+    };
+    if zkapp.confirmations < min_confirmations {
+        return Err(InsufficientConfirmations {
+            have: zkapp.confirmations,
+            need: min_confirmations,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// A prevout given with a request that doesn't match the chain (see [check_prev_outs]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mismatch", rename_all = "snake_case")]
+pub enum PrevoutMismatch {
+    /// The request doesn't give exactly one prevout per input.
+    Count { inputs: usize, prev_outs: usize },
+
+    /// The output spent by the input is already spent, or doesn't exist.
+    Missing {
+        input_index: usize,
+        outpoint: OutPoint,
+    },
+
+    /// The amount of the output isn't the one given.
+    Amount {
+        input_index: usize,
+        outpoint: OutPoint,
+        provided: u64,
+        actual: u64,
+    },
+
+    /// The script of the output isn't the one given.
+    ScriptPubkey {
+        input_index: usize,
+        outpoint: OutPoint,
+    },
+}
+
+impl std::fmt::Display for PrevoutMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Count { inputs, prev_outs } => {
+                write!(f, "got {prev_outs} prevouts for {inputs} inputs")
+            }
+            Self::Missing {
+                input_index,
+                outpoint,
+            } => write!(
+                f,
+                "the prevout of input {input_index} ({outpoint}) is spent, or doesn't exist"
+            ),
+            Self::Amount {
+                input_index,
+                outpoint,
+                provided,
+                actual,
+            } => write!(
+                f,
+                "the prevout of input {input_index} ({outpoint}) is worth {actual} sat, not {provided} sat"
+            ),
+            Self::ScriptPubkey {
+                input_index,
+                outpoint,
+            } => write!(
+                f,
+                "the prevout of input {input_index} ({outpoint}) doesn't have the given scriptPubkey"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PrevoutMismatch {}
+
+/// Compares the prevouts given with a request to the outputs found on chain (`None` if spent, or unknown),
+/// for each input of `tx`.
+pub fn match_prev_outs(
+    tx: &Transaction,
+    provided: &[TxOut],
+    on_chain: &[Option<TxOut>],
+) -> Result<(), PrevoutMismatch> {
+    if provided.len() != tx.input.len() || on_chain.len() != tx.input.len() {
+        return Err(PrevoutMismatch::Count {
+            inputs: tx.input.len(),
+            prev_outs: provided.len(),
+        });
+    }
+    for (input_index, ((input, provided), actual)) in
+        tx.input.iter().zip(provided).zip(on_chain).enumerate()
+    {
+        let outpoint = input.previous_output;
+        let Some(actual) = actual else {
+            return Err(PrevoutMismatch::Missing {
+                input_index,
+                outpoint,
+            });
+        };
+        if actual.value != provided.value {
+            return Err(PrevoutMismatch::Amount {
+                input_index,
+                outpoint,
+                provided: provided.value.to_sat(),
+                actual: actual.value.to_sat(),
+            });
+        }
+        if actual.script_pubkey != provided.script_pubkey {
+            return Err(PrevoutMismatch::ScriptPubkey {
+                input_index,
+                outpoint,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Checks the prevouts given with a request against the chain (including the mempool),
+/// before trusting them to compute sighashes (which commit to the amounts and scripts of every input).
+/// If they don't match, the error can be downcast to [PrevoutMismatch].
+pub async fn check_prev_outs(
+    rpc_ctx: &RpcCtx,
+    tx: &Transaction,
+    prev_outs: &[TxOut],
+) -> Result<()> {
+    if prev_outs.len() != tx.input.len() {
+        return Err(PrevoutMismatch::Count {
+            inputs: tx.input.len(),
+            prev_outs: prev_outs.len(),
+        }
+        .into());
+    }
+    let mut on_chain = Vec::with_capacity(tx.input.len());
+    for input in &tx.input {
+        let txout = get_tx_out(rpc_ctx, input.previous_output, true)
+            .await
+            .with_context(|| format!("couldn't look up the prevout {}", input.previous_output))?;
+        on_chain.push(txout.map(|txout| TxOut {
+            value: txout.value,
+            script_pubkey: ScriptBuf::from_bytes(txout.script_pub_key.hex),
+        }));
+    }
+    match_prev_outs(tx, prev_outs, &on_chain)?;
+    Ok(())
+}
+
 /// Fetch the smart contract on-chain from the txid.
 #[allow(clippy::absurd_extreme_comparisons)]
 pub async fn fetch_smart_contract(ctx: &RpcCtx, txid: bitcoin::Txid) -> Result<SmartContract> {
@@ -701,6 +1633,532 @@ pub async fn fetch_smart_contract(ctx: &RpcCtx, txid: bitcoin::Txid) -> Result<S
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{groth16, op_return_script_for_scheme, plonk};
+
+    #[test]
+    fn test_sighash_preview() {
+        use bitcoin::{
+            hashes::Hash,
+            sighash::{Prevouts, SighashCache},
+            transaction::Version,
+            ScriptBuf, TxIn,
+        };
+
+        // a zkapp spend, funded by a wallet input
+        let zkapp_tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: p2tr_script_to(zkbitcoin_pubkey().unwrap()),
+            }],
+        };
+        let wallet_input = OutPoint::new(Txid::from_byte_array([7; 32]), 1);
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![
+                TxIn {
+                    previous_output: wallet_input,
+                    ..Default::default()
+                },
+                TxIn {
+                    previous_output: OutPoint::new(zkapp_tx.txid(), 0),
+                    ..Default::default()
+                },
+            ],
+            output: vec![TxOut {
+                value: Amount::from_sat(14_000),
+                script_pubkey: ScriptBuf::from_hex(&format!("0014{}", "cc".repeat(20))).unwrap(),
+            }],
+        };
+        let prev_outs = vec![
+            TxOut {
+                value: Amount::from_sat(5_000),
+                script_pubkey: ScriptBuf::from_hex(&format!("0014{}", "dd".repeat(20))).unwrap(),
+            },
+            zkapp_tx.output[0].clone(),
+        ];
+        let bob_request = BobRequest {
+            prev_outs: prev_outs.clone(),
+            tx: tx.clone(),
+            zkapp_tx,
+            vk: serde_json::from_str(include_str!("../examples/circuit/vk.json")).unwrap(),
+            proof: serde_json::from_str(include_str!("../examples/circuit/proof.json")).unwrap(),
+            update: None,
+            sighash_type: TapSighashType::AllPlusAnyoneCanPay,
+            session_token: None,
+            correlation_id: None,
+            other_zkapps: vec![],
+        };
+
+        let preview = bob_request.sighash_preview().unwrap();
+        assert_eq!(preview.txid, tx.txid());
+        assert_eq!(preview.inputs.len(), 1);
+        let input = &preview.inputs[0];
+        assert_eq!(input.input_index, 1);
+        assert_eq!(input.prev_out, prev_outs[1]);
+        assert_eq!(input.sighash_type, TapSighashType::AllPlusAnyoneCanPay);
+
+        // it matches an independent computation
+        let expected = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(
+                1,
+                &Prevouts::All(&prev_outs),
+                TapSighashType::AllPlusAnyoneCanPay,
+            )
+            .unwrap();
+        assert_eq!(input.sighash, hex::encode(expected.to_byte_array()));
+
+        // and survives the trip through JSON
+        let json = serde_json::to_string(&preview).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SighashPreview>(&json).unwrap(),
+            preview
+        );
+
+        // missing prevouts are caught
+        let mut missing = bob_request.clone();
+        missing.prev_outs.pop();
+        assert!(missing.sighash_preview().is_err());
+    }
+
+    fn example_vk() -> plonk::VerifierKey {
+        serde_json::from_str(include_str!("../examples/circuit/vk.json")).unwrap()
+    }
+
+    fn example_proof() -> plonk::Proof {
+        serde_json::from_str(include_str!("../examples/circuit/proof.json")).unwrap()
+    }
+
+    /// A request that doesn't validate, except for its proof.
+    fn request_with(
+        vk: impl Into<proof_system::VerifierKey>,
+        proof: impl Into<proof_system::Proof>,
+    ) -> BobRequest {
+        let empty_tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        BobRequest {
+            tx: empty_tx.clone(),
+            zkapp_tx: empty_tx,
+            vk: vk.into(),
+            proof: proof.into(),
+            update: None,
+            prev_outs: vec![],
+            sighash_type: default_sighash_type(),
+            session_token: None,
+            correlation_id: None,
+            other_zkapps: vec![],
+        }
+    }
+
+    fn prev_outs_tx() -> (Transaction, Vec<TxOut>) {
+        use bitcoin::{hashes::Hash, transaction::Version, TxIn};
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: (0..2)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), vout),
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![],
+        };
+        let prev_outs = vec![
+            TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: p2tr_script_to(zkbitcoin_pubkey().unwrap()),
+            },
+            TxOut {
+                value: Amount::from_sat(5_000),
+                script_pubkey: ScriptBuf::from_hex(&format!("0014{}", "cc".repeat(20))).unwrap(),
+            },
+        ];
+        (tx, prev_outs)
+    }
+
+    #[test]
+    fn test_match_prev_outs() {
+        let (tx, prev_outs) = prev_outs_tx();
+        let on_chain = prev_outs.iter().cloned().map(Some).collect::<Vec<_>>();
+
+        // matching prevouts are accepted
+        assert_eq!(match_prev_outs(&tx, &prev_outs, &on_chain), Ok(()));
+
+        // a spoofed amount is rejected
+        let mut spoofed = prev_outs.clone();
+        spoofed[1].value = Amount::from_sat(50_000);
+        assert_eq!(
+            match_prev_outs(&tx, &spoofed, &on_chain),
+            Err(PrevoutMismatch::Amount {
+                input_index: 1,
+                outpoint: tx.input[1].previous_output,
+                provided: 50_000,
+                actual: 5_000,
+            })
+        );
+
+        // so is a spoofed script
+        let mut spoofed = prev_outs.clone();
+        spoofed[1].script_pubkey = prev_outs[0].script_pubkey.clone();
+        assert!(matches!(
+            match_prev_outs(&tx, &spoofed, &on_chain),
+            Err(PrevoutMismatch::ScriptPubkey { input_index: 1, .. })
+        ));
+
+        // and a spent prevout, or a missing one
+        let mut spent = on_chain.clone();
+        spent[0] = None;
+        assert!(matches!(
+            match_prev_outs(&tx, &prev_outs, &spent),
+            Err(PrevoutMismatch::Missing { input_index: 0, .. })
+        ));
+        assert!(matches!(
+            match_prev_outs(&tx, &prev_outs[..1], &on_chain),
+            Err(PrevoutMismatch::Count {
+                inputs: 2,
+                prev_outs: 1
+            })
+        ));
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_check_prev_outs_against_the_chain() {
+        use crate::testing::mock_rpc::MockRpc;
+
+        let (tx, prev_outs) = prev_outs_tx();
+        let gettxout = |txout: &TxOut| {
+            serde_json::json!({
+                "bestblock": "00".repeat(32),
+                "confirmations": 3,
+                "value": txout.value.to_btc(),
+                "scriptPubKey": {
+                    "asm": "",
+                    "hex": txout.script_pubkey.to_hex_string(),
+                },
+                "coinbase": false,
+            })
+        };
+        // (one lookup per input, for each check)
+        let mock = MockRpc::start().unwrap();
+        for _ in 0..2 {
+            mock.on("gettxout", gettxout(&prev_outs[0]))
+                .on("gettxout", gettxout(&prev_outs[1]));
+        }
+
+        // the prevouts match what the node has
+        check_prev_outs(&mock.ctx(), &tx, &prev_outs).await.unwrap();
+
+        // a spoofed amount is rejected once looked up on chain
+        let mut spoofed = prev_outs.clone();
+        spoofed[0].value = Amount::from_sat(1);
+        let err = check_prev_outs(&mock.ctx(), &tx, &spoofed)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PrevoutMismatch>(),
+            Some(PrevoutMismatch::Amount {
+                input_index: 0,
+                provided: 1,
+                actual: 10_000,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_check_correlation_id() {
+        assert!(check_correlation_id(&new_correlation_id()).is_ok());
+        assert!(check_correlation_id("bob.unlock_42").is_ok());
+        assert!(check_correlation_id("").is_err());
+        assert!(check_correlation_id(&"a".repeat(MAX_CORRELATION_ID_LEN + 1)).is_err());
+        // nothing that could forge log lines
+        assert!(check_correlation_id("id\n[other] forged").is_err());
+        assert!(check_correlation_id("id with spaces").is_err());
+    }
+
+    #[test]
+    fn test_proof_limits() {
+        let limits = ProofLimits::default();
+        let check = |limits: &ProofLimits, vk: plonk::VerifierKey, proof: plonk::Proof| {
+            limits.check(&vk.into(), &proof.into())
+        };
+        check(&limits, example_vk(), example_proof()).unwrap();
+
+        // a proof padded with junk
+        let mut proof = serde_json::to_value(example_proof()).unwrap();
+        proof["eval_a"] = "1".repeat(DEFAULT_MAX_PROOF_SIZE).into();
+        let proof: plonk::Proof = serde_json::from_value(proof).unwrap();
+        assert!(matches!(
+            check(&limits, example_vk(), proof),
+            Err(ProofLimitExceeded::ProofSize {
+                max: DEFAULT_MAX_PROOF_SIZE,
+                ..
+            })
+        ));
+
+        // a verifier key declaring too many public inputs
+        let mut vk = example_vk();
+        vk.nPublic = DEFAULT_MAX_PUBLIC_INPUTS + 1;
+        assert_eq!(
+            check(&limits, vk, example_proof()),
+            Err(ProofLimitExceeded::PublicInputs {
+                count: DEFAULT_MAX_PUBLIC_INPUTS + 1,
+                max: DEFAULT_MAX_PUBLIC_INPUTS
+            })
+        );
+
+        // the limits can be tightened
+        let tight = ProofLimits {
+            max_proof_size: 100,
+            ..Default::default()
+        };
+        assert!(check(&tight, example_vk(), example_proof()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_proofs_of_another_scheme_are_refused() {
+        use bitcoin::{transaction::Version, TxIn};
+
+        let groth16_vk: groth16::VerifierKey =
+            serde_json::from_str(include_str!("../examples/circuit/groth16/vk.json")).unwrap();
+        let groth16_proof: groth16::Proof =
+            serde_json::from_str(include_str!("../examples/circuit/groth16/proof.json")).unwrap();
+
+        // a zkapp deployed with `scheme`, and a request spending it
+        let request =
+            |scheme: ProofScheme, vk: proof_system::VerifierKey, proof: proof_system::Proof| {
+                let zkapp_tx = Transaction {
+                    version: Version::TWO,
+                    lock_time: LockTime::ZERO,
+                    input: vec![],
+                    output: vec![
+                        TxOut {
+                            value: Amount::from_sat(10_000),
+                            script_pubkey: p2tr_script_to(zkbitcoin_pubkey().unwrap()),
+                        },
+                        TxOut {
+                            value: Amount::ZERO,
+                            script_pubkey: op_return_script_for_scheme(scheme, &vk.hash(), None)
+                                .unwrap(),
+                        },
+                    ],
+                };
+                let mut request = request_with(vk, proof);
+                request.tx.input.push(TxIn {
+                    previous_output: OutPoint::new(zkapp_tx.txid(), 0),
+                    ..Default::default()
+                });
+                request.zkapp_tx = zkapp_tx;
+                request
+            };
+
+        // the scheme of the zkapp is recorded when it's deployed
+        let plonk_zkapp = request(
+            ProofScheme::Plonk,
+            example_vk().into(),
+            example_proof().into(),
+        );
+        let smart_contract = extract_smart_contract_from_tx(&plonk_zkapp.zkapp_tx).unwrap();
+        assert_eq!(smart_contract.scheme, ProofScheme::Plonk);
+
+        // a groth16 proof for a PLONK zkapp
+        let err = request(
+            ProofScheme::Plonk,
+            example_vk().into(),
+            groth16_proof.clone().into(),
+        )
+        .validate_request()
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "zkapp was deployed with PLONK but a Groth16 proof was supplied"
+        );
+
+        // a PLONK proof (and verifier key) for a groth16 zkapp
+        let err = request(
+            ProofScheme::Groth16,
+            groth16_vk.clone().into(),
+            example_proof().into(),
+        )
+        .validate_request()
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<proof_system::SchemeMismatch>(),
+            Some(&proof_system::SchemeMismatch {
+                deployed: ProofScheme::Groth16,
+                supplied: ProofScheme::Plonk,
+                what: "proof",
+            })
+        );
+
+        // a groth16 zkapp used with its own scheme gets past that check
+        // (to fail on the transaction, which doesn't pay the zkBitcoin fee)
+        let err = request(
+            ProofScheme::Groth16,
+            groth16_vk.into(),
+            groth16_proof.into(),
+        )
+        .validate_request()
+        .await
+        .unwrap_err();
+        assert!(err.downcast_ref::<proof_system::SchemeMismatch>().is_none());
+        assert!(err.to_string().contains("zkBitcoinFund"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_check_compliance() {
+        let (tx, prev_outs) = prev_outs_tx();
+        let mut request = request_with(example_vk(), example_proof());
+        request.tx = tx;
+        request.prev_outs = prev_outs;
+        let compliance = Arc::new(Compliance::new());
+        request
+            .check_compliance(Arc::clone(&compliance))
+            .await
+            .unwrap();
+
+        // a sanctioned prevout
+        let sanctioned =
+            Address::from_script(&request.prev_outs[1].script_pubkey, get_network()).unwrap();
+        compliance.sanction(&sanctioned).await;
+        let err = request
+            .check_compliance(Arc::clone(&compliance))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "input #1 is sanctioned");
+
+        // prevouts that can't be checked are refused, instead of skipped
+        let compliance = Arc::new(Compliance::new());
+        request.prev_outs[1].script_pubkey = ScriptBuf::new_op_return(&[0u8; 4]);
+        let err = request
+            .check_compliance(Arc::clone(&compliance))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "input #1 doesn't spend from an address");
+        request.prev_outs.pop();
+        assert!(request.check_compliance(compliance).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_single_sighash_is_refused() {
+        use bitcoin::{transaction::Version, TxIn};
+
+        let zkapp_tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: p2tr_script_to(zkbitcoin_pubkey().unwrap()),
+                },
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: op_return_script_for_scheme(
+                        ProofScheme::Plonk,
+                        &proof_system::VerifierKey::from(example_vk()).hash(),
+                        None,
+                    )
+                    .unwrap(),
+                },
+            ],
+        };
+        let mut request = request_with(example_vk(), example_proof());
+        request.tx.input.push(TxIn {
+            previous_output: OutPoint::new(zkapp_tx.txid(), 0),
+            ..Default::default()
+        });
+        request.zkapp_tx = zkapp_tx;
+
+        // the signature would only cover the output at the index of the zkapp input,
+        // so the other ones (e.g. the fee to zkBitcoinFund) could be dropped
+        for sighash_type in [
+            TapSighashType::Single,
+            TapSighashType::SinglePlusAnyoneCanPay,
+        ] {
+            request.sighash_type = sighash_type;
+            let err = request.validate_request().await.unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("sighash type {sighash_type} doesn't commit to all the outputs")
+            );
+        }
+    }
+
+    #[test]
+    fn test_update_public_inputs_are_normalized() {
+        let update = Update {
+            new_state: "0x2a".to_string(),
+            prev_state: "1".to_string(),
+            truncated_txid: None,
+            amount_out: "0x10".to_string(),
+            amount_in: "0".to_string(),
+        };
+        let normalized = update.normalized().unwrap();
+        assert_eq!(normalized.new_state, "42");
+        assert_eq!(normalized.prev_state, "1");
+        assert_eq!(normalized.amount_out, "16");
+
+        // hex without its prefix is named, with the input it was given for
+        let err = Update {
+            amount_in: "ff".to_string(),
+            ..update
+        }
+        .normalized()
+        .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                PublicInputError::Malformed {
+                    index: 4,
+                    name: "amount_in",
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        assert!(err.to_string().contains("prefixed with 0x"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_proofs_are_refused_before_verification() {
+        // the requests don't validate otherwise (nor does snarkjs run in tests),
+        // so getting a limit error means that nothing else was checked first
+        let mut proof = serde_json::to_value(example_proof()).unwrap();
+        proof["eval_b"] = "1".repeat(DEFAULT_MAX_PROOF_SIZE).into();
+        let oversized = request_with(
+            example_vk(),
+            serde_json::from_value::<plonk::Proof>(proof).unwrap(),
+        );
+        let err = oversized.validate_request().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProofLimitExceeded>(),
+            Some(ProofLimitExceeded::ProofSize { .. })
+        ));
+
+        let mut vk = example_vk();
+        vk.nPublic = 1_000;
+        let too_many_inputs = request_with(vk, example_proof());
+        let err = too_many_inputs
+            .validate_request_with_limits(&ProofLimits::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProofLimitExceeded>(),
+            Some(ProofLimitExceeded::PublicInputs { count: 1_000, .. })
+        ));
+    }
 
     #[test]
     fn test_zero() {
@@ -708,4 +2166,67 @@ mod tests {
         let amount_in = amount_in.to_string_in(Denomination::Bitcoin);
         assert_eq!(&amount_in, "0");
     }
+
+    #[test]
+    fn test_check_zkapp_confirmations() {
+        let outpoint = OutPoint::null();
+        let zkapp = |confirmations| ZkappUtxo {
+            outpoint,
+            amount: Amount::from_sat(10_000),
+            height: 0,
+            confirmations,
+            op_return_payload: None,
+        };
+
+        assert!(check_zkapp_confirmations(outpoint, Some(&zkapp(6)), 6).is_ok());
+        assert!(check_zkapp_confirmations(outpoint, Some(&zkapp(0)), 0).is_ok());
+
+        // not enough confirmations (e.g. the zkapp got reorged back into the mempool)
+        let err = check_zkapp_confirmations(outpoint, Some(&zkapp(0)), 6).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<InsufficientConfirmations>(),
+            Some(&InsufficientConfirmations { have: 0, need: 6 })
+        );
+        assert_eq!(
+            err.to_string(),
+            "insufficient confirmations (have 0, need 6)"
+        );
+
+        // spent
+        let err = check_zkapp_confirmations(outpoint, None, 0).unwrap_err();
+        assert!(err.to_string().contains("already spent"));
+        assert!(err.downcast_ref::<InsufficientConfirmations>().is_none());
+        assert_eq!(
+            err.downcast_ref::<ZkappAlreadySpent>(),
+            Some(&ZkappAlreadySpent {
+                outpoint,
+                spent_by: None,
+                confirmations: None,
+            })
+        );
+
+        // (with the transaction that spent it)
+        let spent_by = <Txid as bitcoin::hashes::Hash>::from_byte_array([1; 32]);
+        let err = ZkappAlreadySpent {
+            outpoint,
+            spent_by: Some(spent_by),
+            confirmations: Some(3),
+        };
+        assert_eq!(
+            err.to_string(),
+            format!("the zkapp {outpoint} is already spent by {spent_by} (3 confirmations)")
+        );
+    }
+
+    #[test]
+    fn test_default_min_zkapp_confirmations() {
+        assert_eq!(
+            default_min_zkapp_confirmations(bitcoin::Network::Bitcoin),
+            MIN_ZKAPP_CONFIRMATIONS_MAINNET
+        );
+        assert_eq!(
+            default_min_zkapp_confirmations(bitcoin::Network::Regtest),
+            MIN_ZKAPP_CONFIRMATIONS_TESTNET
+        );
+    }
 }
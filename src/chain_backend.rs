@@ -0,0 +1,271 @@
+//! What the orchestrator needs from the chain (see [ChainBackend]): the zkapps it's asked to spend, feerate estimates,
+//! and broadcasting the transactions it signs.
+//! It's a bitcoind node in production ([BitcoindBackend]), and can be faked in tests (see `FakeChain`, with the `testing` feature).
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bitcoin::{FeeRate, OutPoint, Transaction, Txid};
+use log::warn;
+
+use crate::{
+    bob_request::{get_zkapp_utxo, ZkappUtxo},
+    json_rpc_stuff::{
+        broadcast_to_fallbacks, broadcast_transaction, estimate_smart_fee, get_confirmations,
+        BroadcastOutcome, BroadcastTarget, EstimateMode, RpcCtx, TransactionOrHex,
+    },
+};
+
+/// A view of the chain.
+#[async_trait]
+pub trait ChainBackend: Send + Sync {
+    /// Returns the zkapp at `outpoint` (or `None` if it's spent, or doesn't exist).
+    async fn zkapp_utxo(&self, outpoint: OutPoint) -> Result<Option<ZkappUtxo>>;
+
+    /// Estimates the feerate needed to confirm within `conf_target` blocks (`None` if there's not enough data).
+    async fn estimate_fee_rate(
+        &self,
+        conf_target: u16,
+        mode: EstimateMode,
+    ) -> Result<Option<FeeRate>>;
+
+    /// Broadcasts a transaction (a transaction that is already known counts as a success).
+    async fn broadcast(&self, tx: &Transaction) -> Result<BroadcastOutcome>;
+
+    /// Returns how many confirmations a transaction has (0 if it's in the mempool), or `None` if it's unknown.
+    async fn confirmations(&self, txid: Txid) -> Result<Option<u64>>;
+
+    /// Returns the transaction that spent `outpoint`, if the backend keeps track of spends (bitcoind doesn't).
+    async fn spending_tx(&self, _outpoint: OutPoint) -> Result<Option<Txid>> {
+        Ok(None)
+    }
+
+    /// The number of requests that had to wait for other requests to the backend (if it keeps track of them).
+    fn queue_waits(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A bitcoind node, through its JSON RPC interface.
+pub struct BitcoindBackend {
+    rpc_ctx: RpcCtx,
+
+    /// Where transactions are broadcast if the node refuses them (see [BitcoindBackend::with_broadcast_fallbacks]).
+    broadcast_fallbacks: Vec<BroadcastTarget>,
+}
+
+impl BitcoindBackend {
+    pub fn new(rpc_ctx: RpcCtx) -> Self {
+        Self {
+            rpc_ctx,
+            broadcast_fallbacks: vec![],
+        }
+    }
+
+    /// Broadcasts transactions to each of `broadcast_fallbacks` (in order) if the node fails to
+    /// (see [crate::json_rpc_stuff::broadcast_with_fallback]).
+    pub fn with_broadcast_fallbacks(mut self, broadcast_fallbacks: Vec<BroadcastTarget>) -> Self {
+        self.broadcast_fallbacks = broadcast_fallbacks;
+        self
+    }
+
+    pub fn rpc_ctx(&self) -> &RpcCtx {
+        &self.rpc_ctx
+    }
+}
+
+#[async_trait]
+impl ChainBackend for BitcoindBackend {
+    async fn zkapp_utxo(&self, outpoint: OutPoint) -> Result<Option<ZkappUtxo>> {
+        get_zkapp_utxo(&self.rpc_ctx, outpoint).await
+    }
+
+    async fn estimate_fee_rate(
+        &self,
+        conf_target: u16,
+        mode: EstimateMode,
+    ) -> Result<Option<FeeRate>> {
+        estimate_smart_fee(&self.rpc_ctx, conf_target, mode).await
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<BroadcastOutcome> {
+        let err =
+            match broadcast_transaction(&self.rpc_ctx, TransactionOrHex::Transaction(tx)).await {
+                Ok(outcome) => return Ok(outcome),
+                Err(err) if self.broadcast_fallbacks.is_empty() => return Err(err),
+                Err(err) => err,
+            };
+        warn!(
+            "- couldn't broadcast to {}: {err:#}",
+            self.rpc_ctx.address()
+        );
+        let errors = vec![format!("{}: {err:#}", self.rpc_ctx.address())];
+        let txid = broadcast_to_fallbacks(&self.broadcast_fallbacks, tx, errors).await?;
+        Ok(BroadcastOutcome::New(txid))
+    }
+
+    async fn confirmations(&self, txid: Txid) -> Result<Option<u64>> {
+        let confirmations = get_confirmations(&self.rpc_ctx, &txid).await?;
+        Ok(confirmations.map(|(confirmations, _)| confirmations.into()))
+    }
+
+    fn queue_waits(&self) -> Option<u64> {
+        Some(self.rpc_ctx.queue_waits())
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+pub use fake::FakeChain;
+
+#[cfg(any(test, feature = "testing"))]
+mod fake {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use anyhow::bail;
+    use bitcoin::Amount;
+
+    use super::*;
+
+    /// A chain that only exists in memory, with programmable zkapps, feerate, and broadcast outcomes.
+    /// Broadcasting a transaction spends the zkapps it spends.
+    #[derive(Default)]
+    pub struct FakeChain {
+        zkapps: Mutex<HashMap<OutPoint, ZkappUtxo>>,
+        fee_rate: Mutex<Option<FeeRate>>,
+
+        /// Why broadcasts are rejected (they're accepted if it's not set).
+        broadcast_rejection: Mutex<Option<String>>,
+
+        broadcasts: Mutex<Vec<Transaction>>,
+
+        /// The transactions that spent each zkapp.
+        spent_by: Mutex<HashMap<OutPoint, Txid>>,
+
+        /// The confirmations of the transactions broadcast (0 until [FakeChain::confirm] is called).
+        confirmations: Mutex<HashMap<Txid, u64>>,
+    }
+
+    impl FakeChain {
+        /// Adds an unspent zkapp with `confirmations` confirmations (0 if it's in the mempool),
+        /// as well as its deployment transaction.
+        pub fn add_zkapp(&self, outpoint: OutPoint, amount: Amount, confirmations: u64) {
+            let zkapp = ZkappUtxo {
+                outpoint,
+                amount,
+                // (as if the tip was at height 1000)
+                height: if confirmations == 0 {
+                    0
+                } else {
+                    1001u64.saturating_sub(confirmations)
+                },
+                confirmations,
+                op_return_payload: None,
+            };
+            self.zkapps.lock().unwrap().insert(outpoint, zkapp);
+            self.confirm(outpoint.txid, confirmations);
+        }
+
+        /// Changes the number of confirmations of a zkapp (e.g. to simulate a reorg), panics if it's not there.
+        pub fn set_confirmations(&self, outpoint: OutPoint, confirmations: u64) {
+            let mut zkapps = self.zkapps.lock().unwrap();
+            let zkapp = zkapps.get_mut(&outpoint).expect("unknown zkapp");
+            zkapp.confirmations = confirmations;
+            self.confirm(outpoint.txid, confirmations);
+        }
+
+        /// Drops a transaction from the chain and the mempool (e.g. to simulate a reorg followed by a double spend),
+        /// along with its outputs and the transactions spending them.
+        pub fn forget(&self, txid: Txid) {
+            self.confirmations.lock().unwrap().remove(&txid);
+            self.zkapps
+                .lock()
+                .unwrap()
+                .retain(|outpoint, _| outpoint.txid != txid);
+
+            let mut spent_by = self.spent_by.lock().unwrap();
+            let children = spent_by
+                .iter()
+                .filter(|(outpoint, _)| outpoint.txid == txid)
+                .map(|(_, child)| *child)
+                .collect::<Vec<_>>();
+            spent_by.retain(|outpoint, _| outpoint.txid != txid);
+            drop(spent_by);
+
+            let mut broadcasts = self.broadcasts.lock().unwrap();
+            broadcasts.retain(|tx| !children.contains(&tx.txid()));
+            drop(broadcasts);
+            for child in children {
+                self.confirmations.lock().unwrap().remove(&child);
+            }
+        }
+
+        /// Spends a zkapp (or drops it from the chain).
+        pub fn spend(&self, outpoint: &OutPoint) {
+            self.zkapps.lock().unwrap().remove(outpoint);
+        }
+
+        /// Sets the number of confirmations of a transaction.
+        pub fn confirm(&self, txid: Txid, confirmations: u64) {
+            self.confirmations
+                .lock()
+                .unwrap()
+                .insert(txid, confirmations);
+        }
+
+        pub fn set_fee_rate(&self, fee_rate: Option<FeeRate>) {
+            *self.fee_rate.lock().unwrap() = fee_rate;
+        }
+
+        /// Rejects the next broadcasts with `reason` (or accepts them again with `None`).
+        pub fn reject_broadcasts(&self, reason: Option<&str>) {
+            *self.broadcast_rejection.lock().unwrap() = reason.map(str::to_string);
+        }
+
+        /// The transactions that were broadcast (and accepted).
+        pub fn broadcasts(&self) -> Vec<Transaction> {
+            self.broadcasts.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl ChainBackend for FakeChain {
+        async fn zkapp_utxo(&self, outpoint: OutPoint) -> Result<Option<ZkappUtxo>> {
+            Ok(self.zkapps.lock().unwrap().get(&outpoint).cloned())
+        }
+
+        async fn estimate_fee_rate(
+            &self,
+            _conf_target: u16,
+            _mode: EstimateMode,
+        ) -> Result<Option<FeeRate>> {
+            Ok(*self.fee_rate.lock().unwrap())
+        }
+
+        async fn broadcast(&self, tx: &Transaction) -> Result<BroadcastOutcome> {
+            if let Some(reason) = &*self.broadcast_rejection.lock().unwrap() {
+                bail!("sendrawtransaction error: {reason}");
+            }
+            let mut broadcasts = self.broadcasts.lock().unwrap();
+            if broadcasts.iter().any(|known| known.txid() == tx.txid()) {
+                return Ok(BroadcastOutcome::AlreadyKnown(tx.txid()));
+            }
+            let mut zkapps = self.zkapps.lock().unwrap();
+            let mut spent_by = self.spent_by.lock().unwrap();
+            for input in &tx.input {
+                if zkapps.remove(&input.previous_output).is_some() {
+                    spent_by.insert(input.previous_output, tx.txid());
+                }
+            }
+            self.confirm(tx.txid(), 0);
+            broadcasts.push(tx.clone());
+            Ok(BroadcastOutcome::New(tx.txid()))
+        }
+
+        async fn confirmations(&self, txid: Txid) -> Result<Option<u64>> {
+            Ok(self.confirmations.lock().unwrap().get(&txid).copied())
+        }
+
+        async fn spending_tx(&self, outpoint: OutPoint) -> Result<Option<Txid>> {
+            Ok(self.spent_by.lock().unwrap().get(&outpoint).copied())
+        }
+    }
+}
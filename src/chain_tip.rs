@@ -0,0 +1,158 @@
+//! Keeps track of the tip of the chain of a bitcoind node (see [ChainTipTracker]),
+//! so that what depends on confirmations can be re-checked when a block comes in,
+//! instead of every signing session (or every wait) polling the node on its own.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use bitcoin::BlockHash;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+use crate::json_rpc_stuff::{get_best_block_hash, get_block_header, RpcCtx};
+
+/// The tip of the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainTip {
+    pub height: u64,
+    pub hash: BlockHash,
+}
+
+/// Polls a node for the tip of its chain, and notifies subscribers when it changes (see [ChainTipTracker::subscribe]).
+pub struct ChainTipTracker {
+    ctx: RpcCtx,
+    interval: Duration,
+    tip: watch::Sender<Option<ChainTip>>,
+}
+
+impl ChainTipTracker {
+    /// Tracks the tip of the node of `ctx`, polling it every `interval` (see [ChainTipTracker::run]).
+    pub fn new(ctx: RpcCtx, interval: Duration) -> Self {
+        Self {
+            ctx,
+            interval,
+            tip: watch::Sender::new(None),
+        }
+    }
+
+    /// Returns a receiver of the tip, which is notified each time a new tip is seen (`None` until the first poll).
+    pub fn subscribe(&self) -> watch::Receiver<Option<ChainTip>> {
+        self.tip.subscribe()
+    }
+
+    /// The last tip seen.
+    pub fn latest(&self) -> Option<ChainTip> {
+        *self.tip.borrow()
+    }
+
+    /// Asks the node for its tip, notifying subscribers if it changed (in which case this returns true).
+    pub async fn poll(&self) -> Result<bool> {
+        let hash = get_best_block_hash(&self.ctx).await?;
+        if self.latest().map(|tip| tip.hash) == Some(hash) {
+            return Ok(false);
+        }
+        let header = get_block_header(&self.ctx, hash).await?;
+        let tip = ChainTip {
+            height: header.height,
+            hash,
+        };
+        debug!("- new chain tip {} at height {}", tip.hash, tip.height);
+        Ok(self.tip.send_if_modified(|current| {
+            let changed = *current != Some(tip);
+            *current = Some(tip);
+            changed
+        }))
+    }
+
+    /// Polls the node every `interval`, forever. Failed polls are logged, and retried at the next tick.
+    pub async fn run(self) {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.poll().await {
+                warn!(
+                    "- couldn't get the chain tip from {}: {err:#}",
+                    self.ctx.address()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::testing::mock_rpc::MockRpc;
+
+    fn block_hash(byte: u8) -> BlockHash {
+        use bitcoin::hashes::Hash;
+        BlockHash::from_byte_array([byte; 32])
+    }
+
+    fn header(hash: BlockHash, height: u64, prev: BlockHash) -> serde_json::Value {
+        serde_json::json!({
+            "hash": hash,
+            "height": height,
+            "previousblockhash": prev,
+            "confirmations": 1,
+            "time": 1_700_000_000 + height,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_tracker_notifies_new_tips() {
+        let mock = MockRpc::start().unwrap();
+        let (first, second) = (block_hash(1), block_hash(2));
+        mock.on("getbestblockhash", first)
+            .on("getbestblockhash", first)
+            .on("getbestblockhash", second)
+            .on("getblockheader", header(first, 100, block_hash(0)))
+            .on("getblockheader", header(second, 101, first));
+
+        let tracker = ChainTipTracker::new(mock.ctx(), Duration::from_millis(10));
+        let mut tips = tracker.subscribe();
+        assert_eq!(tracker.latest(), None);
+
+        // the first tip
+        assert!(tracker.poll().await.unwrap());
+        assert!(tips.has_changed().unwrap());
+        assert_eq!(
+            *tips.borrow_and_update(),
+            Some(ChainTip {
+                height: 100,
+                hash: first
+            })
+        );
+
+        // nothing new (and the header isn't fetched again)
+        assert!(!tracker.poll().await.unwrap());
+        assert!(!tips.has_changed().unwrap());
+        assert_eq!(mock.requests_for("getblockheader").len(), 1);
+
+        // a new block, noticed by the background loop
+        tokio::spawn(tracker.run());
+        tokio::time::timeout(Duration::from_secs(5), tips.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            *tips.borrow(),
+            Some(ChainTip {
+                height: 101,
+                hash: second
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tracker_errors() {
+        let mock = MockRpc::start().unwrap();
+        mock.on_error("getbestblockhash", -28, "Loading block index...");
+        let tracker = ChainTipTracker::new(mock.ctx(), Duration::from_millis(10));
+
+        let err = tracker.poll().await.unwrap_err();
+        assert!(err.to_string().contains("Loading block index"), "{err}");
+        assert_eq!(tracker.latest(), None);
+    }
+}
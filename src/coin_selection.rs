@@ -0,0 +1,338 @@
+//! How the coins funding a transaction are picked (see [CoinSelection]),
+//! and what to do with the dust change a funded transaction can end up with (see [DustChangePolicy]).
+
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use bitcoin::{Amount, OutPoint, Transaction, Txid};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::json_rpc_stuff::FundOptions;
+
+/// The weight of an input, for inputs the wallet can't estimate (see [FundOptions::input_weights]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct InputWeight {
+    pub txid: Txid,
+    pub vout: u32,
+    /// The weight of the input, including its witness.
+    pub weight: u64,
+}
+
+/// Keys, scripts, and descriptors describing inputs the wallet doesn't know about
+/// (see [FundOptions::solving_data]).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SolvingData {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pubkeys: Vec<bitcoin::PublicKey>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scripts: Vec<bitcoin::ScriptBuf>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub descriptors: Vec<String>,
+}
+
+/// How coins are selected to fund a transaction.
+/// bitcoind only implements its own strategy, the others pre-select the inputs from `listunspent`
+/// (see [CoinSelection::fund_options]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoinSelection {
+    /// bitcoind's coin selection (branch-and-bound, which avoids a change output when it can).
+    #[default]
+    Wallet,
+
+    /// Spends the coins with the most confirmations first.
+    OldestFirst,
+
+    /// Spends the largest coins first, which keeps the number of inputs (and the fee) low.
+    LargestFirst,
+
+    /// Spends all the coins of the wallet, to consolidate them in a single change output.
+    Consolidate,
+}
+
+impl FromStr for CoinSelection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "wallet" => Ok(Self::Wallet),
+            "oldest-first" => Ok(Self::OldestFirst),
+            "largest-first" => Ok(Self::LargestFirst),
+            "consolidate" => Ok(Self::Consolidate),
+            _ => bail!(
+                "invalid coin selection {s:?} (expected wallet, oldest-first, largest-first, or consolidate)"
+            ),
+        }
+    }
+}
+
+impl CoinSelection {
+    /// Returns the options funding a transaction that needs `target` (its outputs and an estimate of its fee)
+    /// with the coins of the wallet (`utxos`), according to the strategy.
+    /// Only safe and spendable coins are selected.
+    pub fn fund_options(
+        &self,
+        options: &FundOptions,
+        utxos: &[bitcoincore_rpc::json::ListUnspentResultEntry],
+        target: Amount,
+    ) -> FundOptions {
+        let mut utxos = utxos
+            .iter()
+            .filter(|utxo| utxo.spendable && utxo.safe)
+            .filter(|utxo| {
+                let outpoint = OutPoint::new(utxo.txid, utxo.vout);
+                !options.inputs.contains(&outpoint)
+            })
+            .collect::<Vec<_>>();
+        // ties are broken by outpoint, so that the selection is deterministic
+        utxos.sort_by_key(|utxo| (utxo.txid, utxo.vout));
+
+        let selected = match self {
+            Self::Wallet => return options.clone(),
+            Self::OldestFirst => {
+                utxos.sort_by_key(|utxo| std::cmp::Reverse(utxo.confirmations));
+                select_until(utxos, target)
+            }
+            Self::LargestFirst => {
+                utxos.sort_by_key(|utxo| std::cmp::Reverse(utxo.amount));
+                select_until(utxos, target)
+            }
+            Self::Consolidate => utxos,
+        };
+
+        let mut options = options.clone();
+        options.inputs.extend(
+            selected
+                .into_iter()
+                .map(|utxo| OutPoint::new(utxo.txid, utxo.vout)),
+        );
+        options.add_inputs = Some(match self {
+            // the wallet only adds inputs if the fee ends up higher than estimated
+            Self::OldestFirst | Self::LargestFirst => options.add_inputs.unwrap_or(true),
+            // there's nothing left to add
+            _ => false,
+        });
+        options
+    }
+}
+
+/// Takes coins, in order, until they add up to `target`.
+fn select_until(
+    utxos: Vec<&bitcoincore_rpc::json::ListUnspentResultEntry>,
+    target: Amount,
+) -> Vec<&bitcoincore_rpc::json::ListUnspentResultEntry> {
+    let mut total = Amount::ZERO;
+    utxos
+        .into_iter()
+        .take_while(|utxo| {
+            let needed = total < target;
+            total += utxo.amount;
+            needed
+        })
+        .collect()
+}
+
+/// What to do with a change output below the dust threshold (which would make the transaction non-standard).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DustChangePolicy {
+    /// Drop the change output, its value goes to the fee.
+    #[default]
+    FoldIntoFee,
+
+    /// Return an error.
+    Reject,
+}
+
+/// Applies the [DustChangePolicy] to the change output (if any) of a funded transaction.
+/// Returns the (potentially increased) fee.
+pub fn apply_dust_change_policy(
+    tx: &mut Transaction,
+    change_position: Option<usize>,
+    fee: Amount,
+    policy: DustChangePolicy,
+) -> Result<Amount> {
+    let Some(change_position) = change_position else {
+        return Ok(fee);
+    };
+    let change = tx
+        .output
+        .get(change_position)
+        .context("the change position is out of range")?;
+
+    // the threshold depends on the type of script (using the default dust relay fee)
+    let dust_threshold = change.script_pubkey.dust_value();
+    if change.value >= dust_threshold {
+        return Ok(fee);
+    }
+
+    match policy {
+        DustChangePolicy::FoldIntoFee => {
+            let change = tx.output.remove(change_position);
+            debug!(
+                "- dropping dust change output of {} (the fee is now {})",
+                change.value,
+                fee + change.value
+            );
+            Ok(fee + change.value)
+        }
+        DustChangePolicy::Reject => bail!(
+            "the change output of {} is below the dust threshold of {dust_threshold}, \
+             either add funds to avoid it or let it go to the fee (see `DustChangePolicy::FoldIntoFee`)",
+            change.value
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    fn utxo(
+        txid: u8,
+        sats: u64,
+        confirmations: u32,
+    ) -> bitcoincore_rpc::json::ListUnspentResultEntry {
+        serde_json::from_value(serde_json::json!({
+            "txid": Txid::from_byte_array([txid; 32]),
+            "vout": 0,
+            "scriptPubKey": "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            "amount": Amount::from_sat(sats).to_btc(),
+            "confirmations": confirmations,
+            "spendable": true,
+            "solvable": true,
+            "safe": confirmations > 0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_coin_selection_options() {
+        let utxos = [
+            utxo(1, 5_000, 10),
+            utxo(2, 20_000, 2),
+            utxo(3, 8_000, 50),
+            utxo(4, 4_000, 30),
+            // unconfirmed coins from others are never selected
+            utxo(5, 100_000, 0),
+        ];
+        let outpoint = |txid: u8| OutPoint::new(Txid::from_byte_array([txid; 32]), 0);
+        let options = FundOptions {
+            fee_rate: Some(2),
+            lock_unspents: true,
+            ..Default::default()
+        };
+        let target = Amount::from_sat(10_000);
+
+        // the wallet selects the coins itself
+        let wallet = CoinSelection::Wallet.fund_options(&options, &utxos, target);
+        assert!(wallet.inputs.is_empty());
+        assert_eq!(
+            serde_json::to_value(&wallet).unwrap(),
+            serde_json::json!({ "fee_rate": 2, "lockUnspents": true })
+        );
+
+        let oldest = CoinSelection::OldestFirst.fund_options(&options, &utxos, target);
+        assert_eq!(oldest.inputs, [outpoint(3), outpoint(4)]);
+        assert_eq!(
+            serde_json::to_value(&oldest).unwrap(),
+            serde_json::json!({ "fee_rate": 2, "lockUnspents": true, "add_inputs": true })
+        );
+
+        let largest = CoinSelection::LargestFirst.fund_options(&options, &utxos, target);
+        assert_eq!(largest.inputs, [outpoint(2)]);
+        assert_eq!(largest.add_inputs, Some(true));
+
+        let consolidate = CoinSelection::Consolidate.fund_options(&options, &utxos, target);
+        assert_eq!(
+            consolidate.inputs,
+            [outpoint(1), outpoint(2), outpoint(3), outpoint(4)]
+        );
+        assert_eq!(
+            serde_json::to_value(&consolidate).unwrap(),
+            serde_json::json!({ "fee_rate": 2, "lockUnspents": true, "add_inputs": false })
+        );
+
+        // inputs that must be spent aren't selected twice
+        let options = FundOptions {
+            inputs: vec![outpoint(2)],
+            ..Default::default()
+        };
+        let largest = CoinSelection::LargestFirst.fund_options(&options, &utxos, target);
+        assert_eq!(largest.inputs, [outpoint(2), outpoint(3), outpoint(1)]);
+
+        assert_eq!(
+            "oldest_first".parse::<CoinSelection>().unwrap(),
+            CoinSelection::OldestFirst
+        );
+        assert!("random".parse::<CoinSelection>().is_err());
+    }
+
+    fn funded_tx(change_value: u64) -> Transaction {
+        let script_pubkey = bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::all_zeros());
+        Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                bitcoin::TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: script_pubkey.clone(),
+                },
+                bitcoin::TxOut {
+                    value: Amount::from_sat(change_value),
+                    script_pubkey,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_dust_change_folded_into_fee() {
+        let mut tx = funded_tx(100);
+        let fee = apply_dust_change_policy(
+            &mut tx,
+            Some(1),
+            Amount::from_sat(200),
+            DustChangePolicy::FoldIntoFee,
+        )
+        .unwrap();
+        assert_eq!(fee, Amount::from_sat(300));
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].value, Amount::from_sat(10_000));
+    }
+
+    #[test]
+    fn test_dust_change_rejected() {
+        let mut tx = funded_tx(100);
+        let err = apply_dust_change_policy(
+            &mut tx,
+            Some(1),
+            Amount::from_sat(200),
+            DustChangePolicy::Reject,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("dust"));
+        assert_eq!(tx.output.len(), 2);
+    }
+
+    #[test]
+    fn test_change_above_dust() {
+        for policy in [DustChangePolicy::FoldIntoFee, DustChangePolicy::Reject] {
+            let mut tx = funded_tx(5_000);
+            let fee =
+                apply_dust_change_policy(&mut tx, Some(1), Amount::from_sat(200), policy).unwrap();
+            assert_eq!(fee, Amount::from_sat(200));
+            assert_eq!(tx.output.len(), 2);
+
+            // no change at all
+            let fee =
+                apply_dust_change_policy(&mut tx, None, Amount::from_sat(200), policy).unwrap();
+            assert_eq!(fee, Amount::from_sat(200));
+            assert_eq!(tx.output.len(), 2);
+        }
+    }
+}
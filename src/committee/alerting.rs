@@ -0,0 +1,312 @@
+//! Alerts for on-call operators, sent by the orchestrator when signing sessions keep failing,
+//! when a member goes down, or when its bitcoind node can't be reached (see [Alerts]).
+//! Alerts are delivered by an [AlertSink], e.g. a webhook (see [WebhookAlertSink]).
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{ensure, Context, Result};
+use frost_secp256k1_tr::Identifier;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::constants::{ALERT_CONSECUTIVE_FAILURES, ALERT_WEBHOOK_TIMEOUT_SECONDS};
+
+/// Something operators should know about.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AlertEvent {
+    /// Signing sessions failed `failures` times in a row, the last one being `session_id`.
+    ConsecutiveFailures {
+        failures: usize,
+        session_id: String,
+        error: String,
+    },
+
+    /// A member stopped answering.
+    MemberDown {
+        member: Identifier,
+        address: String,
+        reason: String,
+    },
+
+    /// The bitcoind node of the orchestrator can't be reached.
+    BitcoindUnreachable { error: String },
+}
+
+/// What is sent to a webhook: an [AlertEvent], and when it happened.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    /// In seconds since the UNIX epoch.
+    pub timestamp: u64,
+
+    #[serde(flatten)]
+    pub event: AlertEvent,
+}
+
+/// Where alerts go.
+pub trait AlertSink: Send + Sync {
+    /// Delivers an alert. This must not block (e.g. alerts sent over the network are sent in the background),
+    /// as it's called in the middle of signing sessions.
+    fn notify(&self, event: AlertEvent);
+}
+
+/// Drops alerts (the default, when no alerting is configured).
+pub struct NoopAlertSink;
+
+impl AlertSink for NoopAlertSink {
+    fn notify(&self, _event: AlertEvent) {}
+}
+
+/// POSTs alerts as JSON (see [Alert]) to a URL, e.g. a Slack or PagerDuty integration.
+/// Alerts that can't be delivered are logged, and not retried.
+pub struct WebhookAlertSink {
+    url: Url,
+    client: reqwest::Client,
+}
+
+impl WebhookAlertSink {
+    pub fn new(url: &str) -> Result<Self> {
+        let url = Url::parse(url).with_context(|| format!("invalid webhook URL `{url}`"))?;
+        ensure!(
+            matches!(url.scheme(), "http" | "https"),
+            "invalid webhook URL `{url}`: the scheme must be http or https"
+        );
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(ALERT_WEBHOOK_TIMEOUT_SECONDS))
+            .build()
+            .context("couldn't build the webhook client")?;
+        Ok(Self { url, client })
+    }
+}
+
+impl AlertSink for WebhookAlertSink {
+    fn notify(&self, event: AlertEvent) {
+        let alert = Alert {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            event,
+        };
+        let request = self.client.post(self.url.clone()).json(&alert);
+        let url = self.url.clone();
+        tokio::spawn(async move {
+            match request
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status())
+            {
+                Ok(_) => debug!("- sent alert {alert:?} to {url}"),
+                Err(err) => warn!("- couldn't send alert {alert:?} to {url}: {err}"),
+            }
+        });
+    }
+}
+
+/// Decides when to alert operators, and sends alerts to an [AlertSink]:
+/// - when `max_consecutive_failures` signing sessions fail in a row (once per streak),
+/// - when a member goes down,
+/// - when bitcoind becomes unreachable (once, until it's reachable again).
+pub struct Alerts {
+    sink: Arc<dyn AlertSink>,
+
+    /// The number of sessions failing in a row that triggers an alert (0 never does).
+    max_consecutive_failures: usize,
+
+    consecutive_failures: AtomicUsize,
+
+    bitcoind_unreachable: AtomicBool,
+}
+
+impl Default for Alerts {
+    fn default() -> Self {
+        Self::new(Arc::new(NoopAlertSink), ALERT_CONSECUTIVE_FAILURES)
+    }
+}
+
+impl Alerts {
+    pub fn new(sink: Arc<dyn AlertSink>, max_consecutive_failures: usize) -> Self {
+        Self {
+            sink,
+            max_consecutive_failures,
+            consecutive_failures: AtomicUsize::new(0),
+            bitcoind_unreachable: AtomicBool::new(false),
+        }
+    }
+
+    /// Ends the current streak of failed sessions.
+    pub fn session_signed(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    pub fn session_failed(&self, session_id: &str, err: &anyhow::Error) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures == self.max_consecutive_failures {
+            self.sink.notify(AlertEvent::ConsecutiveFailures {
+                failures,
+                session_id: session_id.to_string(),
+                error: format!("{err:#}"),
+            });
+        }
+    }
+
+    pub fn member_down(&self, member: Identifier, address: &str, reason: &str) {
+        self.sink.notify(AlertEvent::MemberDown {
+            member,
+            address: address.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Alerts if `err` (returned by a call to bitcoind) means that bitcoind can't be reached.
+    pub fn bitcoind_error(&self, err: &anyhow::Error) {
+        if is_unreachable(err) && !self.bitcoind_unreachable.swap(true, Ordering::Relaxed) {
+            self.sink.notify(AlertEvent::BitcoindUnreachable {
+                error: format!("{err:#}"),
+            });
+        }
+    }
+
+    pub fn bitcoind_reachable(&self) {
+        self.bitcoind_unreachable.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Returns true if the error was caused by a server that couldn't be reached (or didn't answer in time).
+fn is_unreachable(err: &anyhow::Error) -> bool {
+    err.chain().any(|err| {
+        err.downcast_ref::<reqwest::Error>()
+            .map_or(false, |err| err.is_connect() || err.is_timeout())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{mpsc, Mutex},
+    };
+
+    use anyhow::anyhow;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// A sink keeping the alerts around.
+    #[derive(Default)]
+    struct CollectingSink(Mutex<Vec<AlertEvent>>);
+
+    impl AlertSink for CollectingSink {
+        fn notify(&self, event: AlertEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    /// Starts a webhook, sending the body of every request it receives to the returned channel.
+    fn start_webhook() -> (String, mpsc::Receiver<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/alerts", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).unwrap();
+                    let line = line.trim_end().to_ascii_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(length) = line.strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).unwrap();
+                stream
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .unwrap();
+                tx.send(serde_json::from_slice(&body).unwrap()).unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn test_webhook_after_consecutive_failures() {
+        let (url, rx) = start_webhook();
+        let alerts = Alerts::new(Arc::new(WebhookAlertSink::new(&url).unwrap()), 3);
+
+        alerts.session_failed("s1", &anyhow!("member 1 timed out"));
+        alerts.session_failed("s2", &anyhow!("member 1 timed out"));
+        alerts.session_signed();
+        alerts.session_failed("s3", &anyhow!("member 1 timed out"));
+        alerts.session_failed("s4", &anyhow!("member 2 timed out"));
+        alerts.session_failed("s5", &anyhow!("member 3 timed out"));
+        // the streak was already reported
+        alerts.session_failed("s6", &anyhow!("member 3 timed out"));
+
+        let alert = tokio::task::spawn_blocking(move || {
+            let alert = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+            alert
+        })
+        .await
+        .unwrap();
+        assert!(alert["timestamp"].as_u64().unwrap() > 0);
+        assert_eq!(
+            alert,
+            json!({
+                "timestamp": alert["timestamp"],
+                "event": "consecutive_failures",
+                "failures": 3,
+                "session_id": "s5",
+                "error": "member 3 timed out",
+            })
+        );
+        let alert: Alert = serde_json::from_value(alert).unwrap();
+        assert!(matches!(
+            alert.event,
+            AlertEvent::ConsecutiveFailures { failures: 3, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bitcoind_unreachable() {
+        let sink = Arc::new(CollectingSink::default());
+        let alerts = Alerts::new(Arc::clone(&sink) as Arc<dyn AlertSink>, 3);
+
+        // nothing listens there
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let err = anyhow::Error::from(reqwest::get(format!("http://{address}")).await.unwrap_err())
+            .context("couldn't send getblockcount request");
+
+        // errors that bitcoind returns don't count
+        alerts.bitcoind_error(&anyhow!("gettxout error"));
+        assert!(sink.0.lock().unwrap().is_empty());
+
+        // and the node being down is only reported once, until it's back
+        alerts.bitcoind_error(&err);
+        alerts.bitcoind_error(&err);
+        assert_eq!(sink.0.lock().unwrap().len(), 1);
+        alerts.bitcoind_reachable();
+        alerts.bitcoind_error(&err);
+        assert_eq!(sink.0.lock().unwrap().len(), 2);
+        assert!(matches!(
+            sink.0.lock().unwrap()[0],
+            AlertEvent::BitcoindUnreachable { .. }
+        ));
+    }
+}
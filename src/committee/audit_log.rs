@@ -0,0 +1,425 @@
+//! A tamper-evident log of the signing decisions of a committee node.
+//! Each entry is chained to the previous one (via its hash),
+//! and signed with a key dedicated to the audit log (see [AuditKey]), stored next to the node's key package,
+//! so that anyone can verify it with the public key of the node's audit key (see [crate::committee::smoke_test::NodeIdentity]).
+//! The signing share of the node is never used outside of FROST.
+//! Entries are stored as JSON lines in an append-only file.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::Txid;
+use secp256k1::{Keypair, Message, Secp256k1, SecretKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use crate::utils::secret_file::write_secret_json;
+
+/// Domain separator for the hash of entries,
+/// so that a signature on an entry can't be a valid signature on anything else.
+const AUDIT_LOG_DOMAIN: &[u8] = b"zkBitcoin audit log entry";
+
+/// The decision a node took on a signing request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Decision {
+    Approved,
+    Rejected { reason: String },
+}
+
+/// A signing decision to record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// The signing session (identified by the txid of the zkapp being unlocked).
+    pub session_id: Txid,
+
+    /// The correlation id of the request (see [crate::bob_request::BobRequest::correlation_id]), if any.
+    /// Left out when absent, so that the hashes of older entries don't change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+
+    /// The digest that was signed (if we got that far).
+    pub message: Option<[u8; 32]>,
+
+    /// The hash of the proof authenticating the request.
+    pub proof_hash: [u8; 32],
+
+    pub decision: Decision,
+}
+
+/// An entry of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// The position of the entry in the log (starting at 0).
+    pub index: u64,
+
+    /// When the entry was created (in seconds since the UNIX epoch).
+    pub timestamp: u64,
+
+    pub record: AuditRecord,
+
+    /// The hash of the previous entry (all zeros for the first entry).
+    pub prev_hash: [u8; 32],
+
+    /// The hash of this entry (covering all the fields above).
+    pub hash: [u8; 32],
+
+    /// A BIP-340 signature on `hash` (in hex).
+    pub signature: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(
+        index: u64,
+        timestamp: u64,
+        record: &AuditRecord,
+        prev_hash: &[u8; 32],
+    ) -> [u8; 32] {
+        let mut hasher = Keccak256::new();
+        hasher.update(AUDIT_LOG_DOMAIN);
+        hasher.update(serde_json::to_string(&(index, timestamp, record, prev_hash)).unwrap());
+        let hash = hasher.finalize().to_vec();
+        hash.try_into().unwrap()
+    }
+}
+
+/// The key a node signs its audit log with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditKey {
+    secret_key: SecretKey,
+}
+
+impl AuditKey {
+    pub fn generate() -> Self {
+        Self {
+            secret_key: SecretKey::new(&mut rand::thread_rng()),
+        }
+    }
+
+    /// The key entries are verified with.
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        self.keypair().x_only_public_key().0
+    }
+
+    fn keypair(&self) -> Keypair {
+        Keypair::from_secret_key(&Secp256k1::new(), &self.secret_key)
+    }
+
+    /// Where the audit key of the node with the key package at `key_path` is stored (e.g. `key-0.audit-key.json`).
+    pub fn path_for(key_path: &Path) -> PathBuf {
+        key_path.with_extension("audit-key.json")
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("couldn't open {}", path.display()))?;
+        serde_json::from_reader(file)
+            .with_context(|| format!("couldn't deserialize {}", path.display()))
+    }
+
+    /// Reads the audit key at `path`, or generates it (in a secret file) if the node doesn't have one yet.
+    pub fn read_or_generate(path: &Path) -> Result<Self> {
+        if path.exists() {
+            return Self::read(path);
+        }
+        let audit_key = Self::generate();
+        write_secret_json(path, &audit_key, false)?;
+        Ok(audit_key)
+    }
+}
+
+/// An append-only audit log.
+pub struct AuditLog {
+    path: PathBuf,
+
+    /// The key used to sign entries.
+    keypair: Keypair,
+
+    /// The index of the next entry.
+    next_index: u64,
+
+    /// The hash of the last entry.
+    last_hash: [u8; 32],
+}
+
+impl AuditLog {
+    /// Opens (or creates) the audit log at `path`, signed with `audit_key`.
+    /// If it already exists, it is verified before we append anything to it.
+    pub fn open(path: impl AsRef<Path>, audit_key: &AuditKey) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let keypair = audit_key.keypair();
+
+        let (next_index, last_hash) = if path.exists() {
+            let entries = read_entries(&path)?;
+            verify_entries(&entries, &keypair.x_only_public_key().0)
+                .with_context(|| format!("the audit log {} is corrupted", path.display()))?;
+            entries
+                .last()
+                .map(|entry| (entry.index + 1, entry.hash))
+                .unwrap_or((0, [0; 32]))
+        } else {
+            (0, [0; 32])
+        };
+
+        Ok(Self {
+            path,
+            keypair,
+            next_index,
+            last_hash,
+        })
+    }
+
+    /// The key entries are verified with (see [verify_audit_log]).
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        self.keypair.x_only_public_key().0
+    }
+
+    /// Appends a record to the log (and makes sure it's persisted).
+    /// This blocks on the disk: async callers should run it on the blocking pool.
+    pub fn append(&mut self, record: AuditRecord) -> Result<AuditEntry> {
+        let index = self.next_index;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time went backwards")
+            .as_secs();
+        let hash = AuditEntry::compute_hash(index, timestamp, &record, &self.last_hash);
+
+        let secp = Secp256k1::new();
+        let signature = secp.sign_schnorr_with_aux_rand(
+            &Message::from_digest(hash),
+            &self.keypair,
+            &rand::random(),
+        );
+
+        let entry = AuditEntry {
+            index,
+            timestamp,
+            record,
+            prev_hash: self.last_hash,
+            hash,
+            signature: signature.to_string(),
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("couldn't open the audit log")?;
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+
+        self.next_index += 1;
+        self.last_hash = hash;
+
+        Ok(entry)
+    }
+}
+
+fn read_entries(path: &Path) -> Result<Vec<AuditEntry>> {
+    let file = File::open(path).context("couldn't open the audit log")?;
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .map(|(line_number, line)| {
+            serde_json::from_str(&line?)
+                .with_context(|| format!("couldn't parse line {}", line_number + 1))
+        })
+        .collect()
+}
+
+fn verify_entries(entries: &[AuditEntry], pubkey: &XOnlyPublicKey) -> Result<()> {
+    let secp = Secp256k1::verification_only();
+    let mut prev_hash = [0; 32];
+    for (expected_index, entry) in entries.iter().enumerate() {
+        if entry.index != expected_index as u64 {
+            bail!(
+                "entry {expected_index} is missing (found entry {} instead)",
+                entry.index
+            );
+        }
+        ensure!(
+            entry.prev_hash == prev_hash,
+            "entry {} is not chained to the previous entry",
+            entry.index
+        );
+
+        let hash =
+            AuditEntry::compute_hash(entry.index, entry.timestamp, &entry.record, &prev_hash);
+        ensure!(entry.hash == hash, "entry {} was modified", entry.index);
+
+        let signature = entry
+            .signature
+            .parse::<secp256k1::schnorr::Signature>()
+            .with_context(|| format!("entry {} has an invalid signature", entry.index))?;
+        secp.verify_schnorr(&signature, &Message::from_digest(hash), pubkey)
+            .with_context(|| format!("entry {} has an invalid signature", entry.index))?;
+
+        prev_hash = hash;
+    }
+
+    Ok(())
+}
+
+/// Verifies the audit log written by the node with the audit key of public key `pubkey` (see [AuditKey::public_key]).
+/// Returns the number of entries, or an error pointing at the first gap or tampered entry.
+pub fn verify_audit_log(path: impl AsRef<Path>, pubkey: &XOnlyPublicKey) -> Result<usize> {
+    let entries = read_entries(path.as_ref())?;
+    verify_entries(&entries, pubkey)?;
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::hashes::Hash;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn record(decision: Decision) -> AuditRecord {
+        AuditRecord {
+            session_id: Txid::all_zeros(),
+            correlation_id: None,
+            message: Some([1; 32]),
+            proof_hash: [2; 32],
+            decision,
+        }
+    }
+
+    fn setup() -> (TempDir, PathBuf, AuditKey) {
+        let tmp_dir = TempDir::new("zkbitcoin_audit").unwrap();
+        let path = tmp_dir.path().join("audit.log");
+        (tmp_dir, path, AuditKey::generate())
+    }
+
+    #[test]
+    fn test_append_and_verify() {
+        let (_tmp_dir, path, audit_key) = setup();
+        let pubkey = audit_key.public_key();
+
+        let mut log = AuditLog::open(&path, &audit_key).unwrap();
+        let first = log.append(record(Decision::Approved)).unwrap();
+        let second = log
+            .append(record(Decision::Rejected {
+                reason: "invalid proof".to_string(),
+            }))
+            .unwrap();
+        assert_eq!(first.index, 0);
+        assert_eq!(second.index, 1);
+        assert_eq!(second.prev_hash, first.hash);
+        assert_eq!(verify_audit_log(&path, &pubkey).unwrap(), 2);
+
+        // reopening continues the chain
+        let mut log = AuditLog::open(&path, &audit_key).unwrap();
+        let third = log.append(record(Decision::Approved)).unwrap();
+        assert_eq!(third.index, 2);
+        assert_eq!(third.prev_hash, second.hash);
+        assert_eq!(verify_audit_log(&path, &pubkey).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_correlation_id() {
+        let (_tmp_dir, path, audit_key) = setup();
+        let pubkey = audit_key.public_key();
+
+        // entries without a correlation id are serialized (and thus hashed) as before
+        let without = serde_json::to_string(&record(Decision::Approved)).unwrap();
+        assert!(!without.contains("correlation_id"));
+
+        let mut log = AuditLog::open(&path, &audit_key).unwrap();
+        log.append(record(Decision::Approved)).unwrap();
+        let entry = log
+            .append(AuditRecord {
+                correlation_id: Some("a-correlation-id".to_string()),
+                ..record(Decision::Approved)
+            })
+            .unwrap();
+        assert_eq!(verify_audit_log(&path, &pubkey).unwrap(), 2);
+        assert_eq!(
+            read_entries(&path).unwrap()[1].record.correlation_id,
+            entry.record.correlation_id
+        );
+    }
+
+    #[test]
+    fn test_detect_tampering() {
+        let (_tmp_dir, path, audit_key) = setup();
+        let pubkey = audit_key.public_key();
+
+        let mut log = AuditLog::open(&path, &audit_key).unwrap();
+        for _ in 0..3 {
+            log.append(record(Decision::Approved)).unwrap();
+        }
+
+        // modify the middle entry
+        let mut entries = read_entries(&path).unwrap();
+        entries[1].record.decision = Decision::Rejected {
+            reason: "nothing to see here".to_string(),
+        };
+        let lines = entries
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap() + "\n")
+            .collect::<String>();
+        std::fs::write(&path, lines).unwrap();
+
+        let err = verify_audit_log(&path, &pubkey).unwrap_err();
+        assert!(err.to_string().contains("entry 1 was modified"));
+        assert!(AuditLog::open(&path, &audit_key).is_err());
+    }
+
+    #[test]
+    fn test_detect_gap() {
+        let (_tmp_dir, path, audit_key) = setup();
+        let pubkey = audit_key.public_key();
+
+        let mut log = AuditLog::open(&path, &audit_key).unwrap();
+        for _ in 0..3 {
+            log.append(record(Decision::Approved)).unwrap();
+        }
+
+        // remove the middle entry
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines = content
+            .lines()
+            .enumerate()
+            .filter(|(idx, _)| *idx != 1)
+            .map(|(_, line)| format!("{line}\n"))
+            .collect::<String>();
+        std::fs::write(&path, lines).unwrap();
+
+        let err = verify_audit_log(&path, &pubkey).unwrap_err();
+        assert!(err.to_string().contains("entry 1 is missing"));
+    }
+
+    #[test]
+    fn test_detect_wrong_signer() {
+        let (_tmp_dir, path, audit_key) = setup();
+
+        let mut log = AuditLog::open(&path, &audit_key).unwrap();
+        log.append(record(Decision::Approved)).unwrap();
+
+        let other_key = AuditKey::generate().public_key();
+        let err = verify_audit_log(&path, &other_key).unwrap_err();
+        assert!(err.to_string().contains("invalid signature"));
+    }
+
+    #[test]
+    fn test_audit_key_is_stored_next_to_the_key_package() {
+        let tmp_dir = TempDir::new("zkbitcoin_audit").unwrap();
+        let key_path = tmp_dir.path().join("key-0.json");
+        let path = AuditKey::path_for(&key_path);
+        assert_eq!(path, tmp_dir.path().join("key-0.audit-key.json"));
+
+        // generated once, then reused
+        let audit_key = AuditKey::read_or_generate(&path).unwrap();
+        assert_eq!(AuditKey::read_or_generate(&path).unwrap(), audit_key);
+        assert_eq!(
+            crate::utils::secret_file::readable_by_others(&path).unwrap(),
+            None
+        );
+    }
+}
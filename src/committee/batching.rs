@@ -0,0 +1,333 @@
+//! Batching of the calls the orchestrator makes to committee members.
+//! When several signing sessions run at the same time, their calls to the same member (and for the same round)
+//! are grouped into a single HTTP request, sent once a small batching window has elapsed
+//! (or as soon as it's full, see [MAX_SIGNING_BATCH]).
+//! If a member doesn't support batches, the calls are sent one by one.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use futures::future::join_all;
+use log::{debug, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::value::RawValue;
+use tokio::{sync::oneshot, time::sleep};
+
+use crate::{
+    committee::node::{BatchItemResult, BatchableMethod},
+    constants::MAX_SIGNING_BATCH,
+    json_rpc_stuff::{json_rpc_request, RpcCtx},
+};
+
+/// The JSON-RPC error code returned by members that don't know a method (e.g. older members without batches).
+const METHOD_NOT_FOUND_CODE: i64 = -32601;
+
+/// What the caller of a batched item gets back: either the item's result, or an error for the whole batch.
+type ItemSender = oneshot::Sender<Result<BatchItemResult<Box<RawValue>>, String>>;
+
+/// The items waiting to be sent to a member.
+#[derive(Default)]
+struct PendingBatch {
+    items: Vec<Box<RawValue>>,
+    senders: Vec<ItemSender>,
+}
+
+type PendingBatches = HashMap<(String, &'static str), PendingBatch>;
+
+pub struct Batcher {
+    /// How long to wait for more items before sending a batch.
+    window: Duration,
+
+    pending: Arc<Mutex<PendingBatches>>,
+}
+
+impl Batcher {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// How long calls are held back before their batch is sent.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Calls `method` on the member at `address` with `item`, as part of a batch.
+    /// The outer error means that the member couldn't be reached (or didn't respond correctly),
+    /// the inner error means that the member rejected this specific item.
+    pub async fn call<Req, Resp>(
+        &self,
+        address: &str,
+        method: BatchableMethod,
+        item: &Req,
+    ) -> Result<BatchItemResult<Resp>>
+    where
+        Req: Serialize,
+        Resp: DeserializeOwned,
+    {
+        let item = serde_json::value::to_raw_value(item)?;
+        let (sender, receiver) = oneshot::channel();
+
+        // queue the item, the first item of a batch is in charge of sending it
+        // (unless the batch gets full before, in which case the last item sends it right away)
+        let (first, full_batch) = {
+            let mut pending = self.pending.lock().unwrap();
+            let key = (address.to_string(), method.batch);
+            let batch = pending.entry(key.clone()).or_default();
+            batch.items.push(item);
+            batch.senders.push(sender);
+            let first = batch.items.len() == 1;
+            let full_batch = if batch.items.len() >= MAX_SIGNING_BATCH {
+                pending.remove(&key)
+            } else {
+                None
+            };
+            (first, full_batch)
+        };
+        if let Some(batch) = full_batch {
+            let address = address.to_string();
+            tokio::spawn(async move { send_batch(&address, method, batch).await });
+        } else if first {
+            let pending = Arc::clone(&self.pending);
+            let address = address.to_string();
+            let window = self.window;
+            tokio::spawn(async move {
+                sleep(window).await;
+                let batch = pending
+                    .lock()
+                    .unwrap()
+                    .remove(&(address.clone(), method.batch))
+                    .unwrap_or_default();
+                send_batch(&address, method, batch).await;
+            });
+        }
+
+        let res = receiver
+            .await
+            .context("the batch was dropped")?
+            .map_err(|err| anyhow!(err))?;
+        match res {
+            Ok(raw) => Ok(Ok(
+                serde_json::from_str(raw.get()).context("couldn't deserialize the response")?
+            )),
+            Err(err) => Ok(Err(err)),
+        }
+    }
+}
+
+/// Sends a batch to a member, and dispatches the results to the callers.
+async fn send_batch(address: &str, method: BatchableMethod, batch: PendingBatch) {
+    let PendingBatch { items, senders } = batch;
+    if items.is_empty() {
+        // (it was full, and already sent)
+        return;
+    }
+    debug!(
+        "sending a batch of {} items to {address} ({})",
+        items.len(),
+        method.batch
+    );
+
+    let results = match call_batch(address, method, &items).await {
+        Ok(results) => results,
+        Err(err) => {
+            let err = format!("{err:#}");
+            for sender in senders {
+                let _ = sender.send(Err(err.clone()));
+            }
+            return;
+        }
+    };
+
+    for (sender, res) in senders.into_iter().zip(results) {
+        let _ = sender.send(res);
+    }
+}
+
+/// Calls the batch method of a member, falling back on one call per item if the member doesn't support batches.
+/// Returns one result per item.
+async fn call_batch(
+    address: &str,
+    method: BatchableMethod,
+    items: &[Box<RawValue>],
+) -> Result<Vec<Result<BatchItemResult<Box<RawValue>>, String>>> {
+    let rpc_ctx = RpcCtx::builder()
+        .version("2.0")
+        .url(address)
+        .compress_requests(true)
+        .build()?;
+    let resp = json_rpc_request(
+        &rpc_ctx,
+        method.batch,
+        &[serde_json::value::to_raw_value(items)?],
+    )
+    .await?;
+    let response: bitcoincore_rpc::jsonrpc::Response =
+        serde_json::from_str(&resp).context("couldn't deserialize the response")?;
+
+    if matches!(&response.error, Some(err) if err.code as i64 == METHOD_NOT_FOUND_CODE) {
+        warn!("{address} doesn't support batches, sending the items one by one");
+        let results = join_all(items.iter().map(|item| call_single(&rpc_ctx, method, item))).await;
+        return Ok(results);
+    }
+
+    let results: Vec<BatchItemResult<Box<RawValue>>> = response.result()?;
+    anyhow::ensure!(
+        results.len() == items.len(),
+        "{address} returned {} results for a batch of {} items",
+        results.len(),
+        items.len()
+    );
+    Ok(results.into_iter().map(Ok).collect())
+}
+
+/// Calls the single-item method of a member.
+async fn call_single(
+    rpc_ctx: &RpcCtx,
+    method: BatchableMethod,
+    item: &RawValue,
+) -> Result<BatchItemResult<Box<RawValue>>, String> {
+    let item = item.to_owned();
+    let resp = json_rpc_request(rpc_ctx, method.single, &[item])
+        .await
+        .map_err(|err| format!("{err:#}"))?;
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&resp)
+        .map_err(|err| format!("couldn't deserialize the response: {err}"))?;
+    Ok(match response.error {
+        Some(err) => Err(match err.data {
+            Some(data) => format!("{}: {}", err.message, data),
+            None => err.message,
+        }),
+        None => response
+            .result
+            .context("no result in the response")
+            .map_err(|err| err.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use jsonrpsee::{server::Server, RpcModule};
+
+    use super::*;
+
+    const ECHO: BatchableMethod = BatchableMethod {
+        single: "echo",
+        batch: "echo_batch",
+    };
+
+    /// A member that echoes odd numbers, rejects even ones, and counts the requests it receives.
+    async fn mock_member(batches: bool) -> (SocketAddr, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let server = Server::builder()
+            .build("127.0.0.1:0".parse::<SocketAddr>().unwrap())
+            .await
+            .unwrap();
+        let mut module = RpcModule::new(Arc::clone(&requests));
+        module
+            .register_method("echo", |params, requests| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                let [n]: [u64; 1] = params.parse()?;
+                Ok::<_, jsonrpsee_types::ErrorObjectOwned>(n)
+            })
+            .unwrap();
+        if batches {
+            module
+                .register_method("echo_batch", |params, requests| {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    let [items]: [Vec<u64>; 1] = params.parse()?;
+                    let results: Vec<BatchItemResult<u64>> = items
+                        .into_iter()
+                        .map(|n| {
+                            if n % 2 == 0 {
+                                Err(format!("{n} is even"))
+                            } else {
+                                Ok(n)
+                            }
+                        })
+                        .collect();
+                    Ok::<_, jsonrpsee_types::ErrorObjectOwned>(results)
+                })
+                .unwrap();
+        }
+        let address = server.local_addr().unwrap();
+        let handle = server.start(module);
+        tokio::spawn(handle.stopped());
+        (address, requests)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_sessions_are_batched() {
+        let (address, requests) = mock_member(true).await;
+        let address = format!("http://{address}");
+        let batcher = Batcher::new(Duration::from_millis(50));
+
+        // 10 concurrent sessions, 2 rounds each
+        for _ in 0..2 {
+            let calls = (0..10u64).map(|n| batcher.call::<u64, u64>(&address, ECHO, &n));
+            let results = join_all(calls).await;
+            for (n, res) in results.into_iter().enumerate() {
+                let res = res.unwrap();
+                if n % 2 == 0 {
+                    // per-item errors don't fail the whole batch
+                    assert_eq!(res, Err(format!("{n} is even")));
+                } else {
+                    assert_eq!(res, Ok(n as u64));
+                }
+            }
+        }
+
+        // instead of 20 requests
+        assert!(requests.load(Ordering::SeqCst) <= 4);
+    }
+
+    #[tokio::test]
+    async fn test_full_batches_are_sent_right_away() {
+        let (address, requests) = mock_member(true).await;
+        let address = format!("http://{address}");
+        let batcher = Batcher::new(Duration::from_secs(60));
+
+        // a full batch doesn't wait for the (long) window
+        let calls = (0..MAX_SIGNING_BATCH as u64)
+            .map(|n| batcher.call::<u64, u64>(&address, ECHO, &(2 * n + 1)));
+        let results = tokio::time::timeout(Duration::from_secs(10), join_all(calls))
+            .await
+            .unwrap();
+        assert!(results.into_iter().all(|res| res.unwrap().is_ok()));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_without_batches() {
+        let (address, requests) = mock_member(false).await;
+        let address = format!("http://{address}");
+        let batcher = Batcher::new(Duration::from_millis(50));
+
+        let calls = (0..3u64).map(|n| batcher.call::<u64, u64>(&address, ECHO, &n));
+        let results = join_all(calls).await;
+        for (n, res) in results.into_iter().enumerate() {
+            assert_eq!(res.unwrap(), Ok(n as u64));
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_member() {
+        let batcher = Batcher::new(Duration::from_millis(10));
+        let res = batcher
+            .call::<u64, u64>("http://127.0.0.1:1", ECHO, &1)
+            .await;
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Benchmarks of the signing rounds of a committee, to measure how long a ceremony takes under real network conditions
+//! (e.g. when tuning the size and threshold of a committee).
+//! Each round is a smoke test ceremony (see [crate::committee::smoke_test]): it signs a throwaway message,
+//! so that it never touches UTXOs or produces a signature for a transaction.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Result};
+use frost_secp256k1_tr::Identifier;
+use itertools::Itertools;
+use log::{debug, warn};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    committee::{orchestrator::CommitteeConfig, smoke_test::run_ceremony},
+    frost,
+};
+
+/// The latencies and failures of a benchmark (see [run_bench]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    /// The number of rounds run.
+    pub rounds: usize,
+
+    /// The number of rounds that didn't produce a valid signature.
+    pub failures: usize,
+
+    /// The fraction of rounds that failed (between 0 and 1).
+    pub failure_rate: f64,
+
+    /// The percentiles of the latency of successful rounds (in milliseconds), unless all rounds failed.
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+
+    /// The errors of the failed rounds (deduplicated).
+    pub errors: Vec<String>,
+}
+
+impl BenchReport {
+    /// Summarizes the latencies of the successful rounds, and the errors of the failed ones.
+    pub fn new(latencies: &[Duration], errors: &[String]) -> Self {
+        let rounds = latencies.len() + errors.len();
+        let sorted = latencies.iter().copied().sorted().collect_vec();
+        let percentile = |p: usize| {
+            // nearest rank
+            let rank = (p * sorted.len()).div_ceil(100).max(1);
+            sorted
+                .get(rank - 1)
+                .map(|latency| latency.as_millis() as u64)
+        };
+        Self {
+            rounds,
+            failures: errors.len(),
+            failure_rate: if rounds == 0 {
+                0.0
+            } else {
+                errors.len() as f64 / rounds as f64
+            },
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            p99_ms: percentile(99),
+            errors: errors.iter().unique().cloned().collect(),
+        }
+    }
+}
+
+/// Runs `rounds` signing rounds (one after the other) with the committee, each with a random threshold of its members,
+/// and reports their latency (from the first request of round 1 to a verified signature).
+pub async fn run_bench(
+    committee_cfg: &CommitteeConfig,
+    pubkey_package: &frost::PublicKeyPackage,
+    rounds: usize,
+) -> Result<BenchReport> {
+    ensure!(rounds > 0, "at least one round is needed");
+    ensure!(
+        committee_cfg.members.len() >= committee_cfg.threshold,
+        "the committee has fewer members ({}) than its threshold ({})",
+        committee_cfg.members.len(),
+        committee_cfg.threshold
+    );
+    let members = committee_cfg.members.keys().copied().collect_vec();
+
+    let mut latencies = vec![];
+    let mut errors = vec![];
+    for round in 0..rounds {
+        let signers: Vec<Identifier> = members
+            .choose_multiple(&mut rand::thread_rng(), committee_cfg.threshold)
+            .copied()
+            .collect();
+
+        let start = Instant::now();
+        let res = run_ceremony(committee_cfg, pubkey_package, &signers)
+            .await
+            .and_then(|res| {
+                pubkey_package
+                    .verifying_key()
+                    .verify(&res.message, &res.signature)
+                    .map_err(|err| {
+                        anyhow::anyhow!("the signature doesn't verify under the group key: {err}")
+                    })
+            });
+        let latency = start.elapsed();
+
+        match res {
+            Ok(()) => {
+                debug!("- round {round}: {}ms", latency.as_millis());
+                latencies.push(latency);
+            }
+            Err(err) => {
+                warn!("- round {round} failed: {err:#}");
+                errors.push(format!("{err:#}"));
+            }
+        }
+    }
+
+    Ok(BenchReport::new(&latencies, &errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::Network;
+
+    use crate::{
+        committee::{node::NodeConfig, orchestrator::Member},
+        tx_sanity::FeeLimits,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let latencies = (1..=100).map(Duration::from_millis).collect_vec();
+        let errors = std::iter::repeat("timeout".to_string())
+            .take(25)
+            .collect_vec();
+        let report = BenchReport::new(&latencies, &errors);
+        assert_eq!(report.rounds, 125);
+        assert_eq!(report.failures, 25);
+        assert_eq!(report.failure_rate, 0.2);
+        assert_eq!(report.p50_ms, Some(50));
+        assert_eq!(report.p95_ms, Some(95));
+        assert_eq!(report.p99_ms, Some(99));
+        assert_eq!(report.errors, ["timeout"]);
+
+        // a single round is every percentile
+        let report = BenchReport::new(&[Duration::from_millis(7)], &[]);
+        assert_eq!(report.p50_ms, Some(7));
+        assert_eq!(report.p99_ms, Some(7));
+
+        // no successful rounds, no latencies
+        let report = BenchReport::new(&[], &["boom".to_string()]);
+        assert_eq!(report.failure_rate, 1.0);
+        assert_eq!(report.p50_ms, None);
+    }
+
+    #[tokio::test]
+    async fn test_bench() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let mut members = HashMap::new();
+        for (id, key_package) in key_packages {
+            let (address, handle) = crate::committee::node::start_server(
+                Some("127.0.0.1:0"),
+                key_package,
+                pubkey_package.clone(),
+                NodeConfig {
+                    fee_limits: FeeLimits::for_network(Network::Regtest),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            // the node stops once its handle is dropped
+            tokio::spawn(handle.stopped());
+            members.insert(
+                id,
+                Member {
+                    address: format!("http://{address}"),
+                    votes: None,
+                },
+            );
+        }
+        let mut committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members,
+            signature: None,
+        };
+
+        let report = run_bench(&committee_cfg, &pubkey_package, 5).await.unwrap();
+        assert_eq!(report.rounds, 5);
+        assert_eq!(report.failures, 0, "{:?}", report.errors);
+        assert!(report.p50_ms.is_some());
+        assert!(report.p50_ms <= report.p95_ms && report.p95_ms <= report.p99_ms);
+
+        // with an unreachable member, every round it takes part in fails
+        let id = *committee_cfg.members.keys().next().unwrap();
+        committee_cfg.members.insert(
+            id,
+            Member {
+                address: "http://127.0.0.1:1".to_string(),
+                votes: None,
+            },
+        );
+        committee_cfg.threshold = 3;
+        let report = run_bench(&committee_cfg, &pubkey_package, 2).await.unwrap();
+        assert_eq!(report.failures, 2);
+        assert_eq!(report.failure_rate, 1.0);
+        assert_eq!(report.p50_ms, None);
+        assert_eq!(report.errors.len(), 1);
+    }
+}
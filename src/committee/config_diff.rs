@@ -0,0 +1,227 @@
+//! Differences between two committee configurations, for operators to review membership changes before deploying them.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{committee::orchestrator::CommitteeConfig, frost};
+
+/// What changed from a committee configuration to another.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CommitteeDiff {
+    /// The old and new threshold, if it changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<(usize, usize)>,
+
+    /// The members only in the new configuration.
+    pub added: Vec<MemberEntry>,
+
+    /// The members only in the old configuration.
+    pub removed: Vec<MemberEntry>,
+
+    /// The member ids found in both configurations, but with another address.
+    pub address_changed: Vec<AddressChange>,
+
+    /// The members found in both configurations, but with another number of votes.
+    pub votes_changed: Vec<VotesChange>,
+}
+
+/// A member of a committee.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MemberEntry {
+    pub id: frost::Identifier,
+    pub address: String,
+}
+
+/// A member id reused with another address.
+/// (Whoever runs the new address must hold the key share of that id.)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AddressChange {
+    pub id: frost::Identifier,
+    pub old_address: String,
+    pub new_address: String,
+}
+
+/// A member with another number of votes (see [crate::committee::orchestrator::Member::votes]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VotesChange {
+    pub id: frost::Identifier,
+    pub old_votes: usize,
+    pub new_votes: usize,
+}
+
+impl CommitteeDiff {
+    /// Compares two configurations (their signatures are ignored), members are listed by id.
+    pub fn new(old: &CommitteeConfig, new: &CommitteeConfig) -> Self {
+        let old_members: BTreeMap<_, _> = old.members.iter().collect();
+        let new_members: BTreeMap<_, _> = new.members.iter().collect();
+
+        let mut diff = Self {
+            threshold: (old.threshold != new.threshold).then_some((old.threshold, new.threshold)),
+            ..Default::default()
+        };
+        for (id, old_member) in &old_members {
+            match new_members.get(id) {
+                None => diff.removed.push(MemberEntry {
+                    id: **id,
+                    address: old_member.address.clone(),
+                }),
+                Some(new_member) if new_member.address != old_member.address => {
+                    diff.address_changed.push(AddressChange {
+                        id: **id,
+                        old_address: old_member.address.clone(),
+                        new_address: new_member.address.clone(),
+                    })
+                }
+                Some(_) => (),
+            }
+            if let Some(new_member) = new_members.get(id) {
+                if new_member.votes() != old_member.votes() {
+                    diff.votes_changed.push(VotesChange {
+                        id: **id,
+                        old_votes: old_member.votes(),
+                        new_votes: new_member.votes(),
+                    });
+                }
+            }
+        }
+        for (id, new_member) in &new_members {
+            if !old_members.contains_key(id) {
+                diff.added.push(MemberEntry {
+                    id: **id,
+                    address: new_member.address.clone(),
+                });
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.threshold.is_none()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.address_changed.is_empty()
+            && self.votes_changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::committee::orchestrator::Member;
+
+    use super::*;
+
+    fn id(n: u16) -> frost::Identifier {
+        frost::Identifier::try_from(n).unwrap()
+    }
+
+    fn config(threshold: usize, members: &[(u16, &str)]) -> CommitteeConfig {
+        CommitteeConfig {
+            threshold,
+            members: members
+                .iter()
+                .map(|(n, address)| {
+                    (
+                        id(*n),
+                        Member {
+                            address: address.to_string(),
+                            votes: None,
+                        },
+                    )
+                })
+                .collect::<HashMap<_, _>>(),
+            signature: None,
+        }
+    }
+
+    fn entry(n: u16, address: &str) -> MemberEntry {
+        MemberEntry {
+            id: id(n),
+            address: address.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_unchanged_committee() {
+        let old = config(2, &[(1, "http://a"), (2, "http://b"), (3, "http://c")]);
+        let diff = CommitteeDiff::new(&old, &old.clone());
+        assert!(diff.is_empty());
+        assert_eq!(diff, CommitteeDiff::default());
+    }
+
+    #[test]
+    fn test_added_and_removed_members() {
+        let old = config(2, &[(1, "http://a"), (2, "http://b"), (3, "http://c")]);
+        let new = config(
+            3,
+            &[
+                (1, "http://a"),
+                (3, "http://c"),
+                (4, "http://d"),
+                (5, "http://e"),
+            ],
+        );
+        let diff = CommitteeDiff::new(&old, &new);
+        assert_eq!(diff.threshold, Some((2, 3)));
+        assert_eq!(diff.added, vec![entry(4, "http://d"), entry(5, "http://e")]);
+        assert_eq!(diff.removed, vec![entry(2, "http://b")]);
+        assert!(diff.address_changed.is_empty());
+        assert!(!diff.is_empty());
+
+        // and the other way around
+        let diff = CommitteeDiff::new(&new, &old);
+        assert_eq!(diff.threshold, Some((3, 2)));
+        assert_eq!(diff.added, vec![entry(2, "http://b")]);
+        assert_eq!(diff.removed.len(), 2);
+    }
+
+    #[test]
+    fn test_address_changed() {
+        let old = config(2, &[(1, "http://a"), (2, "http://b"), (3, "http://c")]);
+
+        // an id reused with another address
+        let new = config(2, &[(1, "http://a"), (2, "http://b2"), (3, "http://c")]);
+        let diff = CommitteeDiff::new(&old, &new);
+        assert_eq!(
+            diff.address_changed,
+            vec![AddressChange {
+                id: id(2),
+                old_address: "http://b".to_string(),
+                new_address: "http://b2".to_string(),
+            }]
+        );
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(diff.threshold, None);
+
+        // an address moved to another id is a removal and an addition
+        let new = config(2, &[(1, "http://a"), (3, "http://c"), (4, "http://b")]);
+        let diff = CommitteeDiff::new(&old, &new);
+        assert_eq!(diff.added, vec![entry(4, "http://b")]);
+        assert_eq!(diff.removed, vec![entry(2, "http://b")]);
+        assert!(diff.address_changed.is_empty());
+    }
+
+    #[test]
+    fn test_votes_changed() {
+        let old = config(2, &[(1, "http://a"), (2, "http://b"), (3, "http://c")]);
+
+        // an explicit single vote is the default
+        let mut new = old.clone();
+        new.members.get_mut(&id(1)).unwrap().votes = Some(1);
+        assert!(CommitteeDiff::new(&old, &new).is_empty());
+
+        new.members.get_mut(&id(1)).unwrap().votes = Some(2);
+        let diff = CommitteeDiff::new(&old, &new);
+        assert_eq!(
+            diff.votes_changed,
+            vec![VotesChange {
+                id: id(1),
+                old_votes: 1,
+                new_votes: 2,
+            }]
+        );
+        assert!(!diff.is_empty());
+    }
+}
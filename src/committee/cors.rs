@@ -0,0 +1,198 @@
+//! CORS for the orchestrator, so that browser-based clients (e.g. a web UI submitting unlock requests) can call it.
+//! It's disabled unless origins are configured, and committee nodes never enable it:
+//! only the orchestrator is meant to be called by clients.
+
+use std::fmt;
+
+use anyhow::{bail, ensure, Context, Result};
+use hyper::{
+    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    Method,
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// The origins allowed to call the orchestrator from a browser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CorsOrigins {
+    /// Any origin (`*`).
+    Any,
+
+    /// Only these origins (e.g. `https://app.zkbitcoin.com`).
+    List(Vec<String>),
+}
+
+impl CorsOrigins {
+    /// Parses the configured origins: `*`, or a list of `scheme://host[:port]` origins.
+    /// No origins means that CORS is disabled.
+    pub fn parse(origins: &[String]) -> Result<Option<Self>> {
+        if origins.is_empty() {
+            return Ok(None);
+        }
+        if origins.iter().any(|origin| origin.trim() == "*") {
+            ensure!(
+                origins.len() == 1,
+                "the `*` CORS origin already allows every origin, it can't be combined with others"
+            );
+            return Ok(Some(Self::Any));
+        }
+        let origins = origins
+            .iter()
+            .map(|origin| validate_origin(origin.trim()))
+            .collect::<Result<_>>()?;
+        Ok(Some(Self::List(origins)))
+    }
+
+    /// The layer answering preflight requests, and adding CORS headers to the responses to allowed origins.
+    pub fn layer(&self) -> CorsLayer {
+        let allow_origin = match self {
+            Self::Any => AllowOrigin::any(),
+            Self::List(origins) => AllowOrigin::list(
+                origins
+                    .iter()
+                    .map(|origin| HeaderValue::from_str(origin).expect("validated origin")),
+            ),
+        };
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            // JSON RPC calls are POSTs, and the committee info is also served as a GET
+            .allow_methods([Method::POST, Method::GET, Method::OPTIONS])
+            .allow_headers([CONTENT_TYPE, AUTHORIZATION])
+    }
+}
+
+impl fmt::Display for CorsOrigins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => write!(f, "any origin"),
+            Self::List(origins) => write!(f, "{}", origins.join(", ")),
+        }
+    }
+}
+
+/// Checks that `origin` is a `scheme://host[:port]` origin (browsers send exactly that, without a path or a trailing slash).
+fn validate_origin(origin: &str) -> Result<String> {
+    let url = reqwest::Url::parse(origin).with_context(|| {
+        format!("invalid CORS origin `{origin}` (expected e.g. https://example.com)")
+    })?;
+    ensure!(
+        matches!(url.scheme(), "http" | "https"),
+        "invalid CORS origin `{origin}`: the scheme must be http or https"
+    );
+    ensure!(
+        url.host_str().is_some(),
+        "invalid CORS origin `{origin}`: missing host"
+    );
+    let serialized = url.origin().ascii_serialization();
+    if serialized != origin {
+        bail!("invalid CORS origin `{origin}`: expected `{serialized}` (without a path)");
+    }
+    Ok(serialized)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use hyper::{
+        header::{
+            ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+            ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_HEADERS,
+            ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+        },
+        Body, Request, Response, StatusCode,
+    };
+    use tower::{Layer, ServiceExt};
+
+    use super::*;
+
+    async fn call(origins: &CorsOrigins, request: Request<Body>) -> Response<Body> {
+        let service = tower::service_fn(|_: Request<Body>| async {
+            Ok::<_, Infallible>(Response::new(Body::from("{}")))
+        });
+        origins
+            .layer()
+            .layer(service)
+            .oneshot(request)
+            .await
+            .unwrap()
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/")
+            .header(ORIGIN, origin)
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(
+                ACCESS_CONTROL_REQUEST_HEADERS,
+                "content-type, authorization",
+            )
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_origins() {
+        assert_eq!(CorsOrigins::parse(&[]).unwrap(), None);
+        assert_eq!(
+            CorsOrigins::parse(&["*".to_string()]).unwrap(),
+            Some(CorsOrigins::Any)
+        );
+        assert_eq!(
+            CorsOrigins::parse(&[
+                "https://app.example.com".to_string(),
+                "http://localhost:3000".to_string()
+            ])
+            .unwrap(),
+            Some(CorsOrigins::List(vec![
+                "https://app.example.com".to_string(),
+                "http://localhost:3000".to_string()
+            ]))
+        );
+        assert!(CorsOrigins::parse(&["*".to_string(), "https://a.com".to_string()]).is_err());
+        assert!(CorsOrigins::parse(&["https://a.com/path".to_string()]).is_err());
+        assert!(CorsOrigins::parse(&["ftp://a.com".to_string()]).is_err());
+        assert!(CorsOrigins::parse(&["a.com".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let origins = CorsOrigins::parse(&["http://localhost:3000".to_string()])
+            .unwrap()
+            .unwrap();
+
+        // an allowed origin
+        let response = call(&origins, preflight("http://localhost:3000")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:3000"
+        );
+        let allowed_methods = headers[ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap();
+        assert!(allowed_methods.contains("POST"));
+        let allowed_headers = headers[ACCESS_CONTROL_ALLOW_HEADERS].to_str().unwrap();
+        assert!(allowed_headers.contains("content-type"));
+        assert!(allowed_headers.contains("authorization"));
+
+        // the actual call
+        let request = Request::post("/")
+            .header(ORIGIN, "http://localhost:3000")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        let response = call(&origins, request).await;
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:3000"
+        );
+
+        // another origin isn't allowed
+        let response = call(&origins, preflight("https://evil.example.com")).await;
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        // any origin
+        let response = call(&CorsOrigins::Any, preflight("https://a.example.com")).await;
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+}
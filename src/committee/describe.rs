@@ -0,0 +1,250 @@
+//! Self-description of the JSON RPC servers of the orchestrator and of committee nodes.
+//! Methods are registered along with their description (see [DescribedModule]),
+//! and the `describe` method returns these descriptions, so that they can't drift from what the server actually serves.
+
+use std::{future::Future, sync::Arc};
+
+use anyhow::{Context, Result};
+use jsonrpsee::{server::RpcModule, types::Params};
+use jsonrpsee_core::{server::IntoResponse, RpcResult};
+use serde::{Deserialize, Serialize};
+
+use crate::json_rpc_stuff::{json_rpc_request, RpcCtx};
+
+/// The version of the protocol spoken between clients, the orchestrator, and committee nodes.
+/// It's bumped on breaking changes (e.g. a method removed, or its parameters changed).
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The name of the introspection method.
+pub const DESCRIBE_METHOD: &str = "describe";
+
+/// A method of a server, as registered (see [DescribedModule::register_async_method]).
+#[derive(Debug, Clone, Copy)]
+pub struct Method {
+    pub name: &'static str,
+
+    /// The name and type of each (positional) parameter.
+    pub params: &'static [(&'static str, &'static str)],
+
+    /// The type of the result.
+    pub returns: &'static str,
+
+    /// Why the method is deprecated (and what to use instead), if it is.
+    pub deprecated: Option<&'static str>,
+}
+
+impl Method {
+    pub const fn new(
+        name: &'static str,
+        params: &'static [(&'static str, &'static str)],
+        returns: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            params,
+            returns,
+            deprecated: None,
+        }
+    }
+
+    /// Marks the method as deprecated, before it's removed.
+    pub const fn deprecated(mut self, reason: &'static str) -> Self {
+        self.deprecated = Some(reason);
+        self
+    }
+
+    fn describe(&self) -> MethodDescription {
+        MethodDescription {
+            name: self.name.to_string(),
+            params: self
+                .params
+                .iter()
+                .map(|(name, ty)| ParamDescription {
+                    name: name.to_string(),
+                    ty: ty.to_string(),
+                })
+                .collect(),
+            returns: self.returns.to_string(),
+            deprecated: self.deprecated.map(str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParamDescription {
+    pub name: String,
+
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodDescription {
+    pub name: String,
+    pub params: Vec<ParamDescription>,
+    pub returns: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+}
+
+/// What the `describe` method returns.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerDescription {
+    /// The kind of server (`orchestrator` or `node`).
+    pub server: String,
+
+    /// See [PROTOCOL_VERSION].
+    pub protocol_version: u32,
+
+    /// The version of the software.
+    pub version: String,
+
+    /// The methods served, in registration order.
+    pub methods: Vec<MethodDescription>,
+}
+
+impl ServerDescription {
+    pub fn method(&self, name: &str) -> Option<&MethodDescription> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+}
+
+/// An [RpcModule] whose methods are registered along with their description.
+pub struct DescribedModule<Context> {
+    module: RpcModule<Context>,
+    methods: Vec<MethodDescription>,
+}
+
+impl<Context: Send + Sync + 'static> DescribedModule<Context> {
+    pub fn new(ctx: Context) -> Self {
+        Self {
+            module: RpcModule::new(ctx),
+            methods: vec![],
+        }
+    }
+
+    /// Same as [RpcModule::register_async_method], with the description of the method.
+    pub fn register_async_method<R, Fun, Fut>(
+        &mut self,
+        method: Method,
+        callback: Fun,
+    ) -> Result<()>
+    where
+        R: IntoResponse + 'static,
+        Fut: Future<Output = R> + Send,
+        Fun: Fn(Params<'static>, Arc<Context>) -> Fut + Clone + Send + Sync + 'static,
+    {
+        self.module
+            .register_async_method(method.name, callback)
+            .with_context(|| format!("couldn't register {}", method.name))?;
+        self.methods.push(method.describe());
+        Ok(())
+    }
+
+    /// Registers the `describe` method (describing every method registered so far, and itself),
+    /// and returns the module to serve.
+    pub fn finish(mut self, server: &str) -> Result<RpcModule<Context>> {
+        self.methods
+            .push(Method::new(DESCRIBE_METHOD, &[], "ServerDescription").describe());
+        let description = ServerDescription {
+            server: server.to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            methods: self.methods,
+        };
+        self.module
+            .register_method(DESCRIBE_METHOD, move |_, _| {
+                RpcResult::Ok(description.clone())
+            })
+            .context("couldn't register describe")?;
+        Ok(self.module)
+    }
+}
+
+/// Asks the server at `address` (an orchestrator or a node) to describe itself.
+pub async fn describe_server(address: &str) -> Result<ServerDescription> {
+    let rpc_ctx = RpcCtx::builder().version("2.0").url(address).build()?;
+    let resp = json_rpc_request(&rpc_ctx, DESCRIBE_METHOD, &[])
+        .await
+        .with_context(|| format!("couldn't reach {address}"))?;
+    let response: bitcoincore_rpc::jsonrpc::Response =
+        serde_json::from_str(&resp).context("couldn't deserialize the response")?;
+    Ok(response.result()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use jsonrpsee::server::Server;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_describe() {
+        let mut module = DescribedModule::new(());
+        module
+            .register_async_method(
+                Method::new("echo", &[("value", "u64")], "u64"),
+                |params: Params<'static>, _| async move {
+                    RpcResult::Ok(params.parse::<[u64; 1]>()?[0])
+                },
+            )
+            .unwrap();
+        module
+            .register_async_method(
+                Method::new("old_echo", &[("value", "u64")], "u64").deprecated("use echo"),
+                |params: Params<'static>, _| async move {
+                    RpcResult::Ok(params.parse::<[u64; 1]>()?[0])
+                },
+            )
+            .unwrap();
+        let module = module.finish("test").unwrap();
+        let mut served = module.method_names().collect::<Vec<_>>();
+        served.sort();
+
+        let server = Server::builder()
+            .build("127.0.0.1:0".parse::<SocketAddr>().unwrap())
+            .await
+            .unwrap();
+        let address = server.local_addr().unwrap();
+        let handle = server.start(module);
+        tokio::spawn(handle.stopped());
+
+        // every method served is described (including describe itself)
+        let description = describe_server(&format!("http://{address}")).await.unwrap();
+        assert_eq!(description.server, "test");
+        assert_eq!(description.protocol_version, PROTOCOL_VERSION);
+        let mut described = description
+            .methods
+            .iter()
+            .map(|method| method.name.as_str())
+            .collect::<Vec<_>>();
+        described.sort();
+        assert_eq!(served, described);
+
+        let echo = description.method("echo").unwrap();
+        assert_eq!(
+            echo.params,
+            [ParamDescription {
+                name: "value".to_string(),
+                ty: "u64".to_string()
+            }]
+        );
+        assert_eq!(echo.deprecated, None);
+        assert_eq!(
+            description
+                .method("old_echo")
+                .unwrap()
+                .deprecated
+                .as_deref(),
+            Some("use echo")
+        );
+
+        // the type of parameters is called `type` in JSON
+        let json = serde_json::to_value(&description).unwrap();
+        assert_eq!(json["methods"][0]["params"][0]["type"], "u64");
+        assert!(json["methods"][0].get("deprecated").is_none());
+    }
+}
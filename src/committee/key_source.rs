@@ -0,0 +1,249 @@
+//! Where a committee node gets its key package from.
+//! Besides a file, the key package can be passed in an environment variable (e.g. a systemd credential),
+//! or printed by a command (e.g. `vault kv get -field=key ...` or `pass show ...`), so that it never sits on disk.
+
+use std::{fmt, path::PathBuf, process::Stdio, str::FromStr, time::Duration};
+
+use anyhow::{bail, Result};
+use base64::{engine::general_purpose, Engine};
+use tokio::process::Command;
+use zeroize::Zeroizing;
+
+use crate::{frost, utils::secret_file::check_secret_permissions};
+
+/// Where to load a key package from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// A JSON file (`file:PATH`).
+    File(PathBuf),
+
+    /// An environment variable containing the base64-encoded JSON (`env:VAR`).
+    Env(String),
+
+    /// A command printing the JSON on its standard output (`cmd:COMMAND`, run with `sh -c`).
+    Command(String),
+}
+
+impl FromStr for KeySource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((kind, value)) = s.split_once(':') else {
+            bail!("invalid key source `{s}` (expected file:PATH, env:VAR, or cmd:COMMAND)");
+        };
+        if value.is_empty() {
+            bail!("invalid key source `{s}`: missing {kind}");
+        }
+        match kind {
+            "file" => Ok(Self::File(PathBuf::from(value))),
+            "env" => Ok(Self::Env(value.to_string())),
+            "cmd" => Ok(Self::Command(value.to_string())),
+            _ => bail!("unknown key source `{kind}` (expected file, env, or cmd)"),
+        }
+    }
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "file:{}", path.display()),
+            Self::Env(var) => write!(f, "env:{var}"),
+            Self::Command(command) => write!(f, "cmd:{command}"),
+        }
+    }
+}
+
+/// Why a key package couldn't be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySourceError {
+    /// The source couldn't be read (missing file or variable, failing command, etc.)
+    Unavailable { source: String, reason: String },
+
+    /// The source was read, but doesn't contain a valid key package.
+    InvalidKeyPackage { source: String, reason: String },
+}
+
+impl fmt::Display for KeySourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable { source, reason } => {
+                write!(f, "key source {source} is unavailable: {reason}")
+            }
+            Self::InvalidKeyPackage { source, reason } => {
+                write!(
+                    f,
+                    "key source {source} doesn't contain a valid key package: {reason}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeySourceError {}
+
+impl KeySource {
+    fn unavailable(&self, reason: impl fmt::Display) -> KeySourceError {
+        KeySourceError::Unavailable {
+            source: self.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn invalid(&self, reason: impl fmt::Display) -> KeySourceError {
+        KeySourceError::InvalidKeyPackage {
+            source: self.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Loads the key package.
+    /// Files that can be read by other users are refused if `strict_permissions` is set (and only warned about otherwise),
+    /// and commands are killed after `timeout`. The output of commands is never logged.
+    pub async fn load(
+        &self,
+        strict_permissions: bool,
+        timeout: Duration,
+    ) -> Result<frost::SecretKeyPackage, KeySourceError> {
+        let json = match self {
+            Self::File(path) => {
+                check_secret_permissions(path, strict_permissions)
+                    .map_err(|err| self.unavailable(err))?;
+                Zeroizing::new(std::fs::read(path).map_err(|err| self.unavailable(err))?)
+            }
+            Self::Env(var) => {
+                let encoded = Zeroizing::new(
+                    std::env::var(var).map_err(|err| self.unavailable(format!("{var}: {err}")))?,
+                );
+                Zeroizing::new(
+                    general_purpose::STANDARD
+                        .decode(encoded.trim())
+                        .map_err(|err| self.invalid(format!("invalid base64: {err}")))?,
+                )
+            }
+            Self::Command(command) => self.run(command, timeout).await?,
+        };
+
+        let key_package: frost::KeyPackage =
+            serde_json::from_slice(&json).map_err(|err| self.invalid(err))?;
+        Ok(frost::SecretKeyPackage::new(key_package))
+    }
+
+    /// Runs the command, and returns its standard output.
+    async fn run(
+        &self,
+        command: &str,
+        timeout: Duration,
+    ) -> Result<Zeroizing<Vec<u8>>, KeySourceError> {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| self.unavailable(format!("couldn't run the command: {err}")))?;
+
+        let output = tokio::time::timeout(timeout, child.wait_with_output())
+            .await
+            .map_err(|_| self.unavailable(format!("the command timed out after {timeout:?}")))?
+            .map_err(|err| self.unavailable(format!("couldn't run the command: {err}")))?;
+
+        // don't leak what the command printed, even on failure
+        let stdout = Zeroizing::new(output.stdout);
+        if !output.status.success() {
+            return Err(self.unavailable(format!("the command failed ({})", output.status)));
+        }
+        Ok(stdout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_parse_key_source() {
+        assert_eq!(
+            "file:keys/key-0.json".parse::<KeySource>().unwrap(),
+            KeySource::File(PathBuf::from("keys/key-0.json"))
+        );
+        assert_eq!(
+            "env:ZKBITCOIN_KEY".parse::<KeySource>().unwrap(),
+            KeySource::Env("ZKBITCOIN_KEY".to_string())
+        );
+        // only the first colon separates the kind
+        assert_eq!(
+            "cmd:vault kv get -field=key secret/zkbitcoin:0"
+                .parse::<KeySource>()
+                .unwrap(),
+            KeySource::Command("vault kv get -field=key secret/zkbitcoin:0".to_string())
+        );
+
+        assert!("keys/key-0.json".parse::<KeySource>().is_err());
+        assert!("env:".parse::<KeySource>().is_err());
+        assert!("vault:key".parse::<KeySource>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_key_sources() {
+        let (key_packages, _) = frost::gen_frost_keys(3, 2).unwrap();
+        let key_package = key_packages.into_values().next().unwrap();
+        let json = serde_json::to_string(&key_package).unwrap();
+
+        // file
+        let tmp_dir = TempDir::new("zkbitcoin_key_source").unwrap();
+        let path = tmp_dir.path().join("key-0.json");
+        crate::utils::secret_file::write_secret_json(&path, &key_package, false).unwrap();
+        let loaded = KeySource::File(path).load(true, TIMEOUT).await.unwrap();
+        assert_eq!(*loaded, key_package);
+
+        // env
+        let var = "ZKBITCOIN_TEST_KEY_SOURCE";
+        std::env::set_var(var, general_purpose::STANDARD.encode(&json));
+        let loaded = KeySource::Env(var.to_string())
+            .load(true, TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(*loaded, key_package);
+
+        // command
+        let command = format!("echo '{json}'");
+        let loaded = KeySource::Command(command)
+            .load(true, TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(*loaded, key_package);
+    }
+
+    #[tokio::test]
+    async fn test_key_source_errors() {
+        // the source is unavailable
+        let missing = KeySource::Env("ZKBITCOIN_TEST_MISSING_KEY".to_string());
+        assert!(matches!(
+            missing.load(true, TIMEOUT).await.unwrap_err(),
+            KeySourceError::Unavailable { .. }
+        ));
+        let failing = KeySource::Command("echo secret; exit 3".to_string());
+        let err = failing.load(true, TIMEOUT).await.unwrap_err();
+        assert!(matches!(err, KeySourceError::Unavailable { .. }));
+        assert!(!err.to_string().contains("secret"), "{err}");
+        let slow = KeySource::Command("sleep 10".to_string());
+        let err = slow
+            .load(true, Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err}");
+
+        // the source doesn't contain a key package
+        let invalid = KeySource::Command("echo '{}'".to_string());
+        assert!(matches!(
+            invalid.load(true, TIMEOUT).await.unwrap_err(),
+            KeySourceError::InvalidKeyPackage { .. }
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+//! A manifest of the files produced when generating (or resharing) a committee,
+//! so that members receiving their key file over a side channel can check that it's the right one, and that it wasn't tampered with.
+//!
+//! The manifest lists the SHA-256 of every file (see [CommitteeManifest::verify_file]),
+//! and hashes itself (see [CommitteeManifest::self_hash]) so that a truncated or edited manifest is detected.
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::hashes::{sha256, Hash};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    committee::orchestrator::{CommitteeConfig, CommitteeInfo},
+    frost,
+};
+
+/// The name of the manifest, next to the files it lists.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// A file listed in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// The name of the file (in the committee directory).
+    pub name: String,
+
+    /// The SHA-256 of the file (in hex).
+    pub sha256: String,
+
+    /// The member the file belongs to (for key files).
+    pub member: Option<frost::Identifier>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitteeManifest {
+    /// The x-only group verifying key of the committee (in hex).
+    pub verifying_key: String,
+
+    /// The taproot address (for the current network) derived from the verifying key.
+    pub address: String,
+
+    pub threshold: usize,
+
+    /// The identifiers of the members, sorted.
+    pub members: Vec<frost::Identifier>,
+
+    /// When the committee was generated (in seconds since the UNIX epoch).
+    pub created_at: u64,
+
+    /// The version of the crate that generated the committee.
+    pub version: String,
+
+    pub files: Vec<ManifestFile>,
+
+    /// The SHA-256 of the manifest without this field (see [CommitteeManifest::self_hash]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub self_hash: Option<String>,
+}
+
+/// The SHA-256 of some bytes, in hex.
+fn sha256_hex(data: &[u8]) -> String {
+    sha256::Hash::hash(data).to_string()
+}
+
+impl CommitteeManifest {
+    /// Creates the manifest of the committee in `dir`, given the names of its files (and the member of each key file).
+    pub fn new(
+        dir: &Path,
+        pubkey_package: &frost::PublicKeyPackage,
+        committee_cfg: &CommitteeConfig,
+        files: &[(String, Option<frost::Identifier>)],
+    ) -> Result<Self> {
+        let info = CommitteeInfo::new(pubkey_package, committee_cfg)?;
+        let mut members = committee_cfg.members.keys().copied().collect::<Vec<_>>();
+        members.sort();
+
+        let files = files
+            .iter()
+            .map(|(name, member)| {
+                let data = std::fs::read(dir.join(name))
+                    .with_context(|| format!("couldn't read {name}"))?;
+                Ok(ManifestFile {
+                    name: name.clone(),
+                    sha256: sha256_hex(&data),
+                    member: *member,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut manifest = Self {
+            verifying_key: info.pubkey,
+            address: info.address,
+            threshold: committee_cfg.threshold,
+            members,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            version: info.version,
+            files,
+            self_hash: None,
+        };
+        manifest.self_hash = Some(manifest.self_hash()?);
+        Ok(manifest)
+    }
+
+    /// The hash of the manifest, computed over its JSON serialization without the `self_hash` field.
+    pub fn self_hash(&self) -> Result<String> {
+        let unhashed = Self {
+            self_hash: None,
+            ..self.clone()
+        };
+        let serialized =
+            serde_json::to_vec(&unhashed).context("couldn't serialize the manifest")?;
+        Ok(sha256_hex(&serialized))
+    }
+
+    /// Writes the manifest to `dir`.
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let file = std::fs::File::create(dir.join(MANIFEST_FILE))
+            .context("couldn't create the manifest")?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Reads a manifest, checking its self hash.
+    pub fn read(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).context("couldn't read the manifest")?;
+        let manifest: Self = serde_json::from_slice(&data)
+            .context("couldn't deserialize the manifest (is it truncated?)")?;
+        let self_hash = manifest
+            .self_hash
+            .as_deref()
+            .context("the manifest has no self hash")?;
+        ensure!(
+            manifest.self_hash()? == self_hash,
+            "the manifest doesn't match its self hash (was it modified?)"
+        );
+        Ok(manifest)
+    }
+
+    /// Returns the entry of the manifest matching the content of the file at `path`.
+    pub fn verify_file(&self, path: &Path) -> Result<&ManifestFile> {
+        let data =
+            std::fs::read(path).with_context(|| format!("couldn't read {}", path.display()))?;
+        let sha256 = sha256_hex(&data);
+        if let Some(entry) = self.files.iter().find(|entry| entry.sha256 == sha256) {
+            return Ok(entry);
+        }
+
+        // give a hint if it looks like a file of the manifest
+        let name = path.file_name().and_then(|name| name.to_str());
+        match self
+            .files
+            .iter()
+            .find(|entry| Some(entry.name.as_str()) == name)
+        {
+            Some(entry) => bail!(
+                "{} doesn't match the hash of {} in the manifest (expected {}, got {sha256})",
+                path.display(),
+                entry.name,
+                entry.sha256
+            ),
+            None => bail!(
+                "{} doesn't match any file of the manifest (its hash is {sha256})",
+                path.display()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use crate::committee::orchestrator::Member;
+
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let tmp_dir = TempDir::new("zkbitcoin_manifest").unwrap();
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members: key_packages
+                .keys()
+                .map(|id| {
+                    (
+                        *id,
+                        Member {
+                            address: "http://127.0.0.1:8891".to_string(),
+                            votes: None,
+                        },
+                    )
+                })
+                .collect(),
+            signature: None,
+        };
+        let mut files = vec![];
+        for (idx, (id, key_package)) in key_packages.iter().enumerate() {
+            let name = format!("key-{idx}.json");
+            std::fs::write(
+                tmp_dir.path().join(&name),
+                serde_json::to_vec(key_package).unwrap(),
+            )
+            .unwrap();
+            files.push((name, Some(*id)));
+        }
+
+        let manifest =
+            CommitteeManifest::new(tmp_dir.path(), &pubkey_package, &committee_cfg, &files)
+                .unwrap();
+        manifest.write(tmp_dir.path()).unwrap();
+        let manifest_path = tmp_dir.path().join(MANIFEST_FILE);
+        let manifest = CommitteeManifest::read(&manifest_path).unwrap();
+        assert_eq!(manifest.members.len(), 3);
+
+        // files are matched to their member
+        let (id, _) = key_packages.iter().nth(1).unwrap();
+        let entry = manifest
+            .verify_file(&tmp_dir.path().join("key-1.json"))
+            .unwrap();
+        assert_eq!(entry.member, Some(*id));
+
+        // tampered files are detected
+        let key_path = tmp_dir.path().join("key-2.json");
+        let mut data = std::fs::read(&key_path).unwrap();
+        data.push(b' ');
+        std::fs::write(&key_path, data).unwrap();
+        let err = manifest.verify_file(&key_path).unwrap_err();
+        assert!(err.to_string().contains("key-2.json"), "{err}");
+
+        // and so is a modified or truncated manifest
+        let json = std::fs::read_to_string(&manifest_path).unwrap();
+        std::fs::write(
+            &manifest_path,
+            json.replace("\"threshold\": 2", "\"threshold\": 1"),
+        )
+        .unwrap();
+        assert!(CommitteeManifest::read(&manifest_path).is_err());
+        std::fs::write(&manifest_path, &json[..json.len() / 2]).unwrap();
+        assert!(CommitteeManifest::read(&manifest_path).is_err());
+    }
+}
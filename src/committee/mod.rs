@@ -1,2 +1,21 @@
+pub mod alerting;
+pub mod audit_log;
+pub mod batching;
+pub mod bench;
+pub mod config_diff;
+pub mod cors;
+pub mod describe;
+pub mod key_source;
+pub mod manifest;
 pub mod node;
+pub mod nonces;
 pub mod orchestrator;
+pub mod path_prefix;
+pub mod proxy_post;
+pub mod readiness;
+pub mod reputation;
+pub mod reshare;
+pub mod session_history;
+pub mod share_encryption;
+pub mod signed_config;
+pub mod smoke_test;
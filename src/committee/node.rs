@@ -1,27 +1,57 @@
 use std::{
     collections::BTreeMap,
     net::SocketAddr,
-    sync::{Arc, RwLock},
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex, RwLock},
 };
 
-use bitcoin::{Transaction, TxOut, Txid};
-use frost_secp256k1_tr::round1;
+use anyhow::Context;
+use bitcoin::{
+    hashes::Hash,
+    secp256k1::{PublicKey, XOnlyPublicKey},
+    TapSighashType, Transaction, TxOut, Txid,
+};
+use futures::{stream, StreamExt};
 use jsonrpsee::{
-    server::{RpcModule, Server},
+    server::{Server, ServerHandle},
     types::Params,
 };
 use jsonrpsee_core::RpcResult;
 use jsonrpsee_types::ErrorObjectOwned;
-use log::info;
-use rand::thread_rng;
 use serde::{Deserialize, Serialize};
+use tracing::{error, info, info_span, instrument, Instrument};
 
 use crate::{
-    bob_request::{BobRequest, SmartContract},
+    bob_request::{
+        check_correlation_id, check_prev_outs, check_zkapp_confirmations,
+        default_min_zkapp_confirmations, get_zkapp_utxo, BobRequest, InsufficientConfirmations,
+        PrevoutMismatch, ProofLimitExceeded, ProofLimits, SmartContract, ZkappInput,
+    },
     capped_hashmap::CappedHashMap,
-    constants::MAX_SIGNING_TASK,
-    frost,
+    committee::{
+        audit_log::{AuditKey, AuditLog, AuditRecord, Decision},
+        describe::{DescribedModule, Method},
+        nonces::NonceSource,
+        orchestrator::CommitteeConfig,
+        path_prefix::PathPrefix,
+        proxy_post::ProxyPostRequestLayer,
+        reshare::{self, ReshareDeal, ReshareRequest, ReshareTarget},
+        share_encryption::{encrypt_shares, EncryptedShares, ShareKey},
+        signed_config::config_message,
+        smoke_test::{smoke_test_message, NodeIdentity, SmokeTestRound2Request},
+    },
+    compression::CompressionLayer,
+    constants::{
+        MAX_REQUEST_BODY_SIZE, MAX_SIGNING_BATCH, MAX_SIGNING_TASK, SIGNING_BATCH_CONCURRENCY,
+    },
+    frost, get_network,
+    json_rpc_stuff::{get_raw_transaction, RpcCtx},
     mpc_sign_tx::get_digest_to_hash,
+    sighash::default_sighash_type,
+    tx_sanity::{check_fee_limits, FeeLimits},
+    vk_cache::{VkCache, VkCacheStats},
+    vk_commitment::verify_vk_commitment,
 };
 
 //
@@ -31,37 +61,211 @@ use crate::{
 /// State of a node.
 pub struct NodeState {
     /// The secret key stuff they need.
-    pub key_package: frost::KeyPackage,
+    pub key_package: frost::SecretKeyPackage,
 
     /// The public key stuff they need.
     pub pubkey_package: frost::PublicKeyPackage,
 
     /// The current pending signing tasks
     pub signing_tasks: RwLock<CappedHashMap<Txid, LocalSigningTask>>,
+
+    /// Where signing decisions get recorded (if enabled).
+    pub audit_log: Option<Arc<Mutex<AuditLog>>>,
+
+    /// The key signing the audit log (kept apart, as the log is locked while it's synced to disk).
+    pub audit_key: Option<XOnlyPublicKey>,
+
+    /// If set, a bitcoind node used to check that zkapps are unspent and have enough confirmations,
+    /// independently of the orchestrator.
+    pub rpc_ctx: Option<RpcCtx>,
+
+    /// The number of confirmations a zkapp needs before we sign a spend of it (only enforced with [NodeState::rpc_ctx]).
+    pub min_zkapp_confirmations: u64,
+
+    /// The fees we accept to co-sign a transaction with.
+    pub fee_limits: FeeLimits,
+
+    /// The largest proofs we're willing to verify.
+    pub proof_limits: ProofLimits,
+
+    /// The verifier keys we already checked against the zkapps they were given for.
+    pub vk_cache: VkCache,
+
+    /// Where our nonces come from (always [NonceSource::Random], outside of tests).
+    pub nonce_source: NonceSource,
+
+    /// The nonces of pending smoke tests (see [crate::committee::smoke_test]), by challenge.
+    pub smoke_tests: RwLock<CappedHashMap<[u8; 32], frost::SecretNonces>>,
+
+    /// If set, the key of the orchestrator that signature shares are encrypted to
+    /// (see [crate::committee::share_encryption]). They're sent in clear otherwise.
+    pub orchestrator_share_key: Option<PublicKey>,
+
+    /// The reshare this node's operator approved, if any (see [crate::committee::reshare]).
+    /// The node refuses to deal its share towards any other committee.
+    pub reshare_target: Option<ReshareTarget>,
+
+    /// The message of the committee configuration this node's operator approved, if any
+    /// (see [crate::committee::signed_config::config_message]). The node refuses to co-sign any other configuration.
+    pub approved_config: Option<[u8; 32]>,
+
+    /// The nonces of pending signatures of the approved configuration, by message.
+    pub config_signings: RwLock<CappedHashMap<[u8; 32], frost::SecretNonces>>,
+}
+
+impl NodeState {
+    /// Checks that the zkapps spent by the request are unspent and have enough confirmations,
+    /// and that their verifier keys are the ones committed to on-chain (if we have access to a bitcoind node).
+    async fn check_zkapp(&self, bob_request: &BobRequest) -> anyhow::Result<()> {
+        let Some(rpc_ctx) = &self.rpc_ctx else {
+            return Ok(());
+        };
+        for request in bob_request.zkapp_requests()? {
+            let outpoint = request.zkapp_outpoint()?;
+            let zkapp = get_zkapp_utxo(rpc_ctx, outpoint).await?;
+            check_zkapp_confirmations(outpoint, zkapp.as_ref(), self.min_zkapp_confirmations)?;
+
+            // check the VK against the deployment transaction we fetch, not only the one that was relayed to us
+            let deploy_tx = get_raw_transaction(rpc_ctx, outpoint.txid)
+                .await
+                .context("couldn't fetch the deployment transaction of the zkapp")?;
+            verify_vk_commitment(&deploy_tx, &serde_json::to_vec(&request.vk)?)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the prevouts given with the request against the chain (if we have access to a bitcoind node),
+    /// as the sighashes we sign are computed from them.
+    async fn check_prev_outs(&self, bob_request: &BobRequest) -> anyhow::Result<()> {
+        let Some(rpc_ctx) = &self.rpc_ctx else {
+            return Ok(());
+        };
+        check_prev_outs(rpc_ctx, &bob_request.tx, &bob_request.prev_outs).await
+    }
+
+    /// Records a signing decision in the audit log (if enabled).
+    /// Appending syncs the log to disk, so it's done on the blocking pool rather than on the runtime.
+    async fn audit(&self, record: AuditRecord) -> anyhow::Result<()> {
+        let Some(audit_log) = &self.audit_log else {
+            return Ok(());
+        };
+        let audit_log = Arc::clone(audit_log);
+        tokio::task::spawn_blocking(move || audit_log.lock().unwrap().append(record))
+            .await
+            .context("the audit log writer panicked")??;
+        Ok(())
+    }
+
+    /// Records a rejected signing request in the audit log (if enabled).
+    /// As we're already returning an error, failing to record it is only logged.
+    async fn audit_rejection(
+        &self,
+        session_id: Txid,
+        correlation_id: Option<&str>,
+        proof_hash: [u8; 32],
+        message: Option<[u8; 32]>,
+        reason: &str,
+    ) {
+        let record = AuditRecord {
+            session_id,
+            correlation_id: correlation_id.map(str::to_string),
+            message,
+            proof_hash,
+            decision: Decision::Rejected {
+                reason: reason.to_string(),
+            },
+        };
+        if let Err(err) = self.audit(record).await {
+            error!("couldn't record rejection in the audit log: {err}");
+        }
+    }
 }
 
-#[derive(Clone)]
 pub struct LocalSigningTask {
     /// So we know if we're processing the same request twice.
     pub proof_hash: [u8; 32],
-    /// The smart contract that locked the value.
-    pub smart_contract: SmartContract,
     /// transaction to sign.
     pub tx: Transaction,
     /// The previous outputs that are being spent by the transaction (needed to sign).
     pub prev_outs: Vec<TxOut>,
-    /// The nonces behind these commitments
-    pub nonces: round1::SigningNonces,
+    /// The zkapp inputs to sign, in input order (see [BobRequest::zkapp_requests]).
+    pub inputs: Vec<LocalInputTask>,
     // TODO: should we keep track of commitments here also to double check?
 }
 
+/// What's needed to sign one zkapp input of a [LocalSigningTask].
+pub struct LocalInputTask {
+    /// The smart contract that locked the value.
+    pub smart_contract: SmartContract,
+    /// The sighash type requested by Bob.
+    pub sighash_type: TapSighashType,
+    /// The nonces behind these commitments (wiped once the task is consumed or evicted).
+    pub nonces: frost::SecretNonces,
+}
+
 //
 // Methods
 //
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Round1Response {
+    /// The commitments for the first zkapp input.
     pub commitments: frost_secp256k1_tr::round1::SigningCommitments,
+
+    /// The commitments for the other zkapp inputs (if any), in input order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub other_commitments: Vec<frost_secp256k1_tr::round1::SigningCommitments>,
+}
+
+impl Round1Response {
+    /// The commitments for each zkapp input, in input order.
+    pub fn into_all_commitments(self) -> Vec<frost_secp256k1_tr::round1::SigningCommitments> {
+        std::iter::once(self.commitments)
+            .chain(self.other_commitments)
+            .collect()
+    }
+}
+
+/// The result of one item of a batch (see [round_1_signing_batch] and [round_2_signing_batch]).
+/// Items fail independently, the error being a description of what went wrong.
+pub type BatchItemResult<T> = Result<T, String>;
+
+/// The name of a method of the node that can be called with a single item, or with a batch of items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchableMethod {
+    pub single: &'static str,
+    pub batch: &'static str,
+}
+
+pub const ROUND_1_SIGNING: BatchableMethod = BatchableMethod {
+    single: "round_1_signing",
+    batch: "round_1_signing_batch",
+};
+
+pub const ROUND_2_SIGNING: BatchableMethod = BatchableMethod {
+    single: "round_2_signing",
+    batch: "round_2_signing_batch",
+};
+
+/// Refuses batches of more than [MAX_SIGNING_BATCH] items.
+fn check_batch_size(len: usize) -> RpcResult<()> {
+    if len > MAX_SIGNING_BATCH {
+        return RpcResult::Err(ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "batch too large",
+            Some(format!(
+                "the batch has {len} items, but at most {MAX_SIGNING_BATCH} are accepted"
+            )),
+        ));
+    }
+    Ok(())
+}
+
+fn batch_item_result<T>(res: RpcResult<T>) -> BatchItemResult<T> {
+    res.map_err(|err| match err.data() {
+        Some(data) => format!("{}: {}", err.message(), data.get()),
+        None => err.message().to_string(),
+    })
 }
 
 /// Bob's request to unlock funds from a smart contract.
@@ -71,8 +275,184 @@ async fn round_1_signing(
 ) -> RpcResult<Round1Response> {
     // get bob request
     let bob_request: [BobRequest; 1] = params.parse()?;
-    let bob_request = &bob_request[0];
-    info!("received request: {:?}", bob_request);
+    handle_round_1(&context, &bob_request[0]).await
+}
+
+/// Same as [round_1_signing], but for several requests at once.
+async fn round_1_signing_batch(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Vec<BatchItemResult<Round1Response>>> {
+    let [bob_requests]: [Vec<BobRequest>; 1] = params.parse()?;
+    check_batch_size(bob_requests.len())?;
+    let results: Vec<_> = stream::iter(&bob_requests)
+        .map(|bob_request| handle_round_1(&context, bob_request))
+        .buffered(SIGNING_BATCH_CONCURRENCY)
+        .map(batch_item_result)
+        .collect()
+        .await;
+    RpcResult::Ok(results)
+}
+
+/// Refuses correlation ids that could mess with our logs (see [check_correlation_id]).
+fn check_request_correlation_id(correlation_id: Option<&str>) -> RpcResult<()> {
+    let Some(correlation_id) = correlation_id else {
+        return Ok(());
+    };
+    check_correlation_id(correlation_id).map_err(|err| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "invalid correlation id",
+            Some(format!("{err}")),
+        )
+    })
+}
+
+/// Why a request was rejected (see [check_request]).
+struct Rejection {
+    /// What gets recorded in the audit log.
+    reason: String,
+
+    /// What gets returned to the orchestrator.
+    error: ErrorObjectOwned,
+}
+
+/// Runs all the checks we make before signing a request:
+/// its proofs, its fee, and (if we have access to a bitcoind node) the zkapps it spends and its prevouts.
+/// Returns the zkapp inputs to sign.
+async fn check_request(
+    context: &NodeState,
+    bob_request: &BobRequest,
+) -> Result<Vec<ZkappInput>, Rejection> {
+    // validate request (for every zkapp it spends)
+    let zkapp_inputs = bob_request
+        .validate_zkapps_with(&context.proof_limits, &context.vk_cache)
+        .instrument(info_span!("verify_proofs"))
+        .await
+        .map_err(|err| Rejection {
+            reason: format!("the request didn't validate: {err:#}"),
+            error: match err.downcast_ref::<ProofLimitExceeded>() {
+                Some(exceeded) => ErrorObjectOwned::owned(
+                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                    exceeded.to_string(),
+                    Some(exceeded.clone()),
+                ),
+                None => ErrorObjectOwned::owned(
+                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                    "the request didn't validate",
+                    Some(format!("{err}")),
+                ),
+            },
+        })?;
+
+    // refuse transactions that would never confirm, or that would burn too much in fees
+    check_fee_limits(&bob_request.tx, &bob_request.prev_outs, &context.fee_limits).map_err(
+        |refusal| Rejection {
+            reason: format!("the fee is outside of our limits: {refusal}"),
+            error: ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                refusal.to_string(),
+                Some(refusal),
+            ),
+        },
+    )?;
+
+    // don't trust the orchestrator to have checked the zkapp
+    context
+        .check_zkapp(bob_request)
+        .await
+        .map_err(|err| Rejection {
+            reason: format!("the zkapp can't be spent yet: {err}"),
+            error: match err.downcast_ref::<InsufficientConfirmations>() {
+                Some(insufficient) => ErrorObjectOwned::owned(
+                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                    insufficient.to_string(),
+                    Some(insufficient.clone()),
+                ),
+                None => ErrorObjectOwned::owned(
+                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                    "the zkapp can't be spent",
+                    Some(format!("{err}")),
+                ),
+            },
+        })?;
+
+    // don't trust the prevouts we compute the sighashes from either
+    context
+        .check_prev_outs(bob_request)
+        .await
+        .map_err(|err| Rejection {
+            reason: format!("the prevouts don't match the chain: {err:#}"),
+            error: match err.downcast_ref::<PrevoutMismatch>() {
+                Some(mismatch) => ErrorObjectOwned::owned(
+                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                    mismatch.to_string(),
+                    Some(mismatch.clone()),
+                ),
+                None => ErrorObjectOwned::owned(
+                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                    "couldn't check the prevouts",
+                    Some(format!("{err:#}")),
+                ),
+            },
+        })?;
+
+    Ok(zkapp_inputs)
+}
+
+/// The path users can POST their requests to, to check them (see [verify]).
+pub const VERIFY_PATH: &str = "/verify";
+
+/// What a node answers to a dry run of a request (see [verify]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyResponse {
+    /// Whether the node would sign the request.
+    pub accepted: bool,
+
+    /// Why the request would be rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Runs the checks of round 1 on Bob's request (see [check_request]) without signing anything:
+/// no nonces are generated, and no signing task is created.
+/// Also served at `POST /verify`, with the request as body.
+async fn verify(params: Params<'static>, context: Arc<NodeState>) -> RpcResult<VerifyResponse> {
+    let [bob_request]: [BobRequest; 1] = params.parse()?;
+    check_request_correlation_id(bob_request.correlation_id.as_deref())?;
+    let response = match check_request(&context, &bob_request).await {
+        Ok(_) => VerifyResponse {
+            accepted: true,
+            reason: None,
+        },
+        Err(rejection) => VerifyResponse {
+            accepted: false,
+            reason: Some(rejection.reason),
+        },
+    };
+    info!(
+        "[{}] verified request (accepted: {})",
+        bob_request.log_id(),
+        response.accepted
+    );
+    RpcResult::Ok(response)
+}
+
+#[instrument(
+    name = "round_1",
+    skip_all,
+    fields(correlation_id = bob_request.log_id())
+)]
+async fn handle_round_1(
+    context: &NodeState,
+    bob_request: &BobRequest,
+) -> RpcResult<Round1Response> {
+    check_request_correlation_id(bob_request.correlation_id.as_deref())?;
+    info!(
+        "[{}] received request: {:?}",
+        bob_request.log_id(),
+        bob_request
+    );
 
     // check if we already have a local signing task under that txid
     let txid = bob_request.txid().map_err(|e| {
@@ -83,19 +463,43 @@ async fn round_1_signing(
         )
     })?;
 
-    // validate request
-    let smart_contract = bob_request.validate_request().await.map_err(|err| {
-        ErrorObjectOwned::owned(
-            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-            "the request didn't validate",
-            Some(format!("{err}")),
-        )
-    })?;
+    let zkapp_inputs = match check_request(context, bob_request).await {
+        Ok(zkapp_inputs) => zkapp_inputs,
+        Err(rejection) => {
+            context
+                .audit_rejection(
+                    txid,
+                    bob_request.correlation_id.as_deref(),
+                    bob_request.proof.hash(),
+                    None,
+                    &rejection.reason,
+                )
+                .await;
+            return Err(rejection.error);
+        }
+    };
 
-    // round 1 of FROST
-    let rng = &mut thread_rng();
-    let (nonces, commitments) =
-        frost_secp256k1_tr::round1::commit(context.key_package.signing_share(), rng);
+    // round 1 of FROST, with fresh nonces for each zkapp input (in input order)
+    let mut inputs = vec![];
+    let mut commitments = vec![];
+    for zkapp_input in zkapp_inputs {
+        let nonce_context = [
+            &txid.to_byte_array()[..],
+            &(zkapp_input.input_index as u64).to_be_bytes(),
+        ]
+        .concat();
+        let (nonces, input_commitments) = context.nonce_source.commit(
+            context.key_package.identifier(),
+            context.key_package.signing_share(),
+            &nonce_context,
+        );
+        inputs.push(LocalInputTask {
+            smart_contract: zkapp_input.smart_contract,
+            sighash_type: zkapp_input.sighash_type,
+            nonces: frost::SecretNonces::new(nonces),
+        });
+        commitments.push(input_commitments);
+    }
 
     // store it locally
     {
@@ -104,21 +508,30 @@ async fn round_1_signing(
             txid,
             LocalSigningTask {
                 proof_hash: bob_request.proof.hash(),
-                smart_contract,
                 tx: bob_request.tx.clone(),
-                nonces,
                 prev_outs: bob_request.prev_outs.clone(),
+                inputs,
             },
         );
     }
 
     // response
-    let resp = Round1Response { commitments };
+    let mut commitments = commitments.into_iter();
+    let resp = Round1Response {
+        commitments: commitments
+            .next()
+            .expect("a request spends at least one zkapp"),
+        other_commitments: commitments.collect(),
+    };
     RpcResult::Ok(resp)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Round2Request {
+    /// The correlation id of Bob's request (see [BobRequest::correlation_id]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+
     /// The txid that we're referring to.
     pub txid: Txid,
 
@@ -132,33 +545,187 @@ pub struct Round2Request {
     /// Digest to hash.
     /// While not necessary as nodes will recompute it themselves, it is good to double check that everyone is on the same page.
     pub message: [u8; 32],
+
+    /// The sighash type the message was computed with.
+    /// All members must sign with the same one for their shares to aggregate, so nodes check it against the request of round 1.
+    #[serde(default = "default_sighash_type")]
+    pub sighash_type: TapSighashType,
+
+    /// The same as above, for the other zkapp inputs (if any), in input order.
+    /// The fields above are about the first zkapp input.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub other_inputs: Vec<InputSigningRequest>,
+}
+
+/// What to sign for one zkapp input (see [Round2Request::other_inputs]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputSigningRequest {
+    pub commitments_map:
+        BTreeMap<frost_secp256k1_tr::Identifier, frost_secp256k1_tr::round1::SigningCommitments>,
+
+    pub message: [u8; 32],
+
+    #[serde(default = "default_sighash_type")]
+    pub sighash_type: TapSighashType,
+}
+
+impl Round2Request {
+    /// The correlation id of the request, as shown in logs (`-` if it doesn't have one).
+    pub fn log_id(&self) -> &str {
+        self.correlation_id.as_deref().unwrap_or("-")
+    }
+
+    /// What to sign for each zkapp input, in input order.
+    pub fn inputs(&self) -> Vec<InputSigningRequest> {
+        let first = InputSigningRequest {
+            commitments_map: self.commitments_map.clone(),
+            message: self.message,
+            sighash_type: self.sighash_type,
+        };
+        std::iter::once(first)
+            .chain(self.other_inputs.iter().cloned())
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Round2Response {
+    /// The signature share for the first zkapp input.
     pub signature_share: frost_secp256k1_tr::round2::SignatureShare,
+
+    /// The signature shares for the other zkapp inputs (if any), in input order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub other_signature_shares: Vec<frost_secp256k1_tr::round2::SignatureShare>,
+}
+
+impl Round2Response {
+    /// The signature shares for each zkapp input, in input order.
+    pub fn into_all_signature_shares(self) -> Vec<frost_secp256k1_tr::round2::SignatureShare> {
+        std::iter::once(self.signature_share)
+            .chain(self.other_signature_shares)
+            .collect()
+    }
+}
+
+/// What a node answers in round 2: its signature shares,
+/// encrypted to the orchestrator if the node was given its key (see [NodeState::orchestrator_share_key]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Round2Reply {
+    Encrypted { encrypted_shares: EncryptedShares },
+    Plain(Round2Response),
+}
+
+impl Round2Reply {
+    /// The signature shares that `member` sent for the signing session of `txid`, for each zkapp input, in input order.
+    /// Encrypted shares can only be read with the orchestrator's `share_key`.
+    pub fn into_all_signature_shares(
+        self,
+        share_key: Option<&ShareKey>,
+        txid: &Txid,
+        member: &frost_secp256k1_tr::Identifier,
+    ) -> anyhow::Result<Vec<frost_secp256k1_tr::round2::SignatureShare>> {
+        match self {
+            Self::Plain(response) => Ok(response.into_all_signature_shares()),
+            Self::Encrypted { encrypted_shares } => {
+                let share_key = share_key.ok_or_else(|| {
+                    anyhow::anyhow!("{member:?} encrypted its signature shares, but we don't have a share encryption key")
+                })?;
+                share_key.decrypt_shares(txid, member, &encrypted_shares)
+            }
+        }
+    }
 }
 
 async fn round_2_signing(
     params: Params<'static>,
     context: Arc<NodeState>,
-) -> RpcResult<Round2Response> {
+) -> RpcResult<Round2Reply> {
     // get commitments from params
     let round2request: [Round2Request; 1] = params.parse()?;
-    let round2request = &round2request[0];
-    info!("received request: {:?}", round2request);
+    reply_round_2(&context, &round2request[0]).await
+}
+
+/// Same as [round_2_signing], but for several requests at once.
+async fn round_2_signing_batch(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Vec<BatchItemResult<Round2Reply>>> {
+    let [round2requests]: [Vec<Round2Request>; 1] = params.parse()?;
+    check_batch_size(round2requests.len())?;
+    let results: Vec<_> = stream::iter(&round2requests)
+        .map(|round2request| reply_round_2(&context, round2request))
+        .buffered(SIGNING_BATCH_CONCURRENCY)
+        .map(batch_item_result)
+        .collect()
+        .await;
+    RpcResult::Ok(results)
+}
+
+/// Signs (see [handle_round_2]), and encrypts the signature shares to the orchestrator if we have its key.
+async fn reply_round_2(
+    context: &NodeState,
+    round2request: &Round2Request,
+) -> RpcResult<Round2Reply> {
+    let round2_response = handle_round_2(context, round2request).await?;
+    let Some(orchestrator_share_key) = &context.orchestrator_share_key else {
+        return RpcResult::Ok(Round2Reply::Plain(round2_response));
+    };
+    let encrypted_shares = encrypt_shares(
+        orchestrator_share_key,
+        &round2request.txid,
+        context.key_package.identifier(),
+        &round2_response.into_all_signature_shares(),
+    )
+    .map_err(|err| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while encrypting the signature shares",
+            Some(format!("{err}")),
+        )
+    })?;
+    RpcResult::Ok(Round2Reply::Encrypted { encrypted_shares })
+}
+
+#[instrument(
+    name = "round_2",
+    skip_all,
+    fields(correlation_id = round2request.log_id(), txid = %round2request.txid)
+)]
+async fn handle_round_2(
+    context: &NodeState,
+    round2request: &Round2Request,
+) -> RpcResult<Round2Response> {
+    check_request_correlation_id(round2request.correlation_id.as_deref())?;
+    info!(
+        "[{}] received request: {:?}",
+        round2request.log_id(),
+        round2request
+    );
 
     // retrieve metadata for this task (and prune it)
     let LocalSigningTask {
         proof_hash: _,
-        smart_contract,
         tx,
-        nonces,
         prev_outs,
+        inputs,
     } = {
-        let mut signing_tasks = context.signing_tasks.write().unwrap();
-        if let Some(local_signing_task) = signing_tasks.remove(&round2request.txid) {
+        let local_signing_task = context
+            .signing_tasks
+            .write()
+            .unwrap()
+            .remove(&round2request.txid);
+        if let Some(local_signing_task) = local_signing_task {
             if local_signing_task.proof_hash != round2request.proof_hash {
+                context
+                    .audit_rejection(
+                        round2request.txid,
+                        round2request.correlation_id.as_deref(),
+                        round2request.proof_hash,
+                        Some(round2request.message),
+                        "proof hash doesn't match",
+                    )
+                    .await;
                 return RpcResult::Err(ErrorObjectOwned::owned(
                     jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
                     "proof hash doesn't match",
@@ -166,7 +733,7 @@ async fn round_2_signing(
                 ));
             }
 
-            local_signing_task.clone()
+            local_signing_task
         } else {
             return RpcResult::Err(ErrorObjectOwned::owned(
                 jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
@@ -176,81 +743,1170 @@ async fn round_2_signing(
         }
     };
 
-    // deterministically create transaction
-    let message = get_digest_to_hash(&prev_outs, &tx, &smart_contract).map_err(|err| {
-        ErrorObjectOwned::owned(
+    let input_requests = round2request.inputs();
+    if input_requests.len() != inputs.len() {
+        context
+            .audit_rejection(
+                round2request.txid,
+                round2request.correlation_id.as_deref(),
+                round2request.proof_hash,
+                Some(round2request.message),
+                "number of inputs doesn't match",
+            )
+            .await;
+        return RpcResult::Err(ErrorObjectOwned::owned(
             jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-            "error while hashing",
-            Some(format!("the request didn't validate: {err}")),
-        )
-    })?;
+            "number of inputs doesn't match",
+            Some(format!(
+                "the request was for {} zkapp inputs, but round 2 is for {}",
+                inputs.len(),
+                input_requests.len()
+            )),
+        ));
+    }
 
-    // sanity check
-    if round2request.message != message {
-        return RpcResult::Err(ErrorObjectOwned::owned(
+    // sign each zkapp input, in input order
+    let mut signature_shares = vec![];
+    for (input, input_request) in inputs.into_iter().zip(&input_requests) {
+        let LocalInputTask {
+            smart_contract,
+            sighash_type,
+            nonces,
+        } = input;
+
+        if input_request.sighash_type != sighash_type {
+            context
+                .audit_rejection(
+                    round2request.txid,
+                    round2request.correlation_id.as_deref(),
+                    round2request.proof_hash,
+                    Some(input_request.message),
+                    "sighash type doesn't match",
+                )
+                .await;
+            return RpcResult::Err(ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "sighash type doesn't match",
+                Some(format!(
+                    "the request was for {sighash_type}, but round 2 is for {}",
+                    input_request.sighash_type
+                )),
+            ));
+        }
+
+        // deterministically create transaction
+        let message =
+            get_digest_to_hash(&prev_outs, &tx, &smart_contract, sighash_type).map_err(|err| {
+                ErrorObjectOwned::owned(
+                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                    "error while hashing",
+                    Some(format!("the request didn't validate: {err}")),
+                )
+            })?;
+
+        // sanity check
+        if input_request.message != message {
+            context
+                .audit_rejection(
+                    round2request.txid,
+                    round2request.correlation_id.as_deref(),
+                    round2request.proof_hash,
+                    Some(input_request.message),
+                    "message doesn't match",
+                )
+                .await;
+            return RpcResult::Err(ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "message doesn't match",
+                Some("message doesn't match".to_string()),
+            ));
+        }
+
+        // signing package should be recreated no? as we want to ensure that we agree on what is being signed (should be a deterministic process).
+        let signing_package = frost_secp256k1_tr::SigningPackage::new(
+            input_request.commitments_map.clone(),
+            &message,
+        );
+        let signature_share =
+            frost_secp256k1_tr::round2::sign(&signing_package, &nonces, &context.key_package);
+
+        // the nonces must never be used again, wipe them right away
+        drop(nonces);
+
+        let signature_share = signature_share.map_err(|err| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "error while signing",
+                Some(format!("the request didn't validate: {err}")),
+            )
+        })?;
+        signature_shares.push((message, signature_share));
+    }
+
+    // record our decisions before releasing the signature shares
+    for (message, _) in &signature_shares {
+        context
+            .audit(AuditRecord {
+                session_id: round2request.txid,
+                correlation_id: round2request.correlation_id.clone(),
+                message: Some(*message),
+                proof_hash: round2request.proof_hash,
+                decision: Decision::Approved,
+            })
+            .await
+            .map_err(|err| {
+                error!("couldn't record approval in the audit log: {err}");
+                ErrorObjectOwned::owned(
+                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                    "error while recording the signing decision",
+                    Some(format!("{err}")),
+                )
+            })?;
+    }
+
+    // return signature shares
+    let mut signature_shares = signature_shares.into_iter().map(|(_, share)| share);
+    let round2_response = Round2Response {
+        signature_share: signature_shares
+            .next()
+            .expect("a request spends at least one zkapp"),
+        other_signature_shares: signature_shares.collect(),
+    };
+    RpcResult::Ok(round2_response)
+}
+
+/// Discards the signing task of a cancelled session (see [crate::committee::orchestrator::Orchestrator::cancel_session]),
+/// so that its nonces are wiped right away. Returns `false` if there was no such task.
+async fn discard_signing_task(params: Params<'static>, context: Arc<NodeState>) -> RpcResult<bool> {
+    let (txid, proof_hash): (Txid, [u8; 32]) = params.parse()?;
+    RpcResult::Ok(handle_discard_signing_task(&context, txid, proof_hash))
+}
+
+fn handle_discard_signing_task(context: &NodeState, txid: Txid, proof_hash: [u8; 32]) -> bool {
+    let mut signing_tasks = context.signing_tasks.write().unwrap();
+    // only the session that created the task can discard it
+    if signing_tasks
+        .get(&txid)
+        .map_or(true, |task| task.proof_hash != proof_hash)
+    {
+        return false;
+    }
+    info!("- discarding the signing task for {txid} (the session was cancelled)");
+    signing_tasks.remove(&txid).is_some()
+}
+
+async fn is_alive(params: Params<'static>, _context: Arc<NodeState>) -> RpcResult<u64> {
+    Ok(params.parse::<[u64; 1]>()?[0])
+}
+
+/// Who we are (so that operators can check that the right key is behind the right address).
+async fn identity(_params: Params<'static>, context: Arc<NodeState>) -> RpcResult<NodeIdentity> {
+    RpcResult::Ok(NodeIdentity {
+        identifier: *context.key_package.identifier(),
+        verifying_share: *context.key_package.verifying_share(),
+        audit_key: context.audit_key,
+    })
+}
+
+/// The hits and misses of the cache of verifier keys.
+async fn vk_cache_stats(
+    _params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<VkCacheStats> {
+    RpcResult::Ok(context.vk_cache.stats())
+}
+
+/// Round 1 of a smoke test: commits to nonces for signing the smoke test message of a challenge.
+async fn smoke_test_round_1(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Round1Response> {
+    let [challenge]: [[u8; 32]; 1] = params.parse()?;
+    info!(
+        "received smoke test for challenge {}",
+        hex::encode(challenge)
+    );
+
+    let (nonces, commitments) = context.nonce_source.commit(
+        context.key_package.identifier(),
+        context.key_package.signing_share(),
+        &challenge,
+    );
+    context
+        .smoke_tests
+        .write()
+        .unwrap()
+        .add_entry(challenge, frost::SecretNonces::new(nonces));
+
+    RpcResult::Ok(Round1Response {
+        commitments,
+        other_commitments: vec![],
+    })
+}
+
+/// Forgets the nonces of a smoke test that won't make it to round 2 (e.g. because another member failed round 1).
+async fn discard_smoke_test(params: Params<'static>, context: Arc<NodeState>) -> RpcResult<bool> {
+    let [challenge]: [[u8; 32]; 1] = params.parse()?;
+    RpcResult::Ok(
+        context
+            .smoke_tests
+            .write()
+            .unwrap()
+            .remove(&challenge)
+            .is_some(),
+    )
+}
+
+/// Round 2 of a smoke test: signs the smoke test message of the challenge (never anything else).
+async fn smoke_test_round_2(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Round2Response> {
+    let [request]: [SmokeTestRound2Request; 1] = params.parse()?;
+
+    let nonces = context
+        .smoke_tests
+        .write()
+        .unwrap()
+        .remove(&request.challenge)
+        .ok_or_else(|| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "no smoke test found for this challenge",
+                Some("no smoke test found for this challenge".to_string()),
+            )
+        })?;
+
+    // we compute the message ourselves, so that this can't be used to sign a transaction
+    let message = smoke_test_message(&request.challenge);
+    let signing_package =
+        frost_secp256k1_tr::SigningPackage::new(request.commitments_map, &message);
+    let signature_share =
+        frost_secp256k1_tr::round2::sign(&signing_package, &nonces, &context.key_package).map_err(
+            |err| {
+                ErrorObjectOwned::owned(
+                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                    "error while signing",
+                    Some(format!("{err}")),
+                )
+            },
+        )?;
+
+    RpcResult::Ok(Round2Response {
+        signature_share,
+        other_signature_shares: vec![],
+    })
+}
+
+/// Refuses to co-sign any configuration but the one our operator approved.
+fn check_approved_config(context: &NodeState, message: &[u8; 32]) -> RpcResult<()> {
+    if context.approved_config.as_ref() != Some(message) {
+        error!("refusing to co-sign a committee configuration we didn't approve");
+        return Err(ErrorObjectOwned::owned(
             jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-            "message doesn't match",
-            Some("message doesn't match".to_string()),
+            "this node didn't approve this committee configuration",
+            Some("this node didn't approve this committee configuration".to_string()),
         ));
     }
+    Ok(())
+}
+
+/// Round 1 of the signature of a committee configuration (see [crate::committee::signed_config]):
+/// commits to nonces for signing its message, if it's the one our operator approved.
+async fn sign_config_round_1(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Round1Response> {
+    let [message]: [[u8; 32]; 1] = params.parse()?;
+    check_approved_config(&context, &message)?;
+    info!("co-signing the approved committee configuration");
+
+    let (nonces, commitments) = context.nonce_source.commit(
+        context.key_package.identifier(),
+        context.key_package.signing_share(),
+        &message,
+    );
+    context
+        .config_signings
+        .write()
+        .unwrap()
+        .add_entry(message, frost::SecretNonces::new(nonces));
+
+    RpcResult::Ok(Round1Response {
+        commitments,
+        other_commitments: vec![],
+    })
+}
+
+/// Round 2 of the signature of a committee configuration: signs its message (the `challenge` of the request),
+/// if it's the one our operator approved.
+async fn sign_config_round_2(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Round2Response> {
+    let [request]: [SmokeTestRound2Request; 1] = params.parse()?;
+    let message = request.challenge;
+    check_approved_config(&context, &message)?;
+
+    let nonces = context
+        .config_signings
+        .write()
+        .unwrap()
+        .remove(&message)
+        .ok_or_else(|| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "no pending signature of this committee configuration",
+                Some("no pending signature of this committee configuration".to_string()),
+            )
+        })?;
 
-    // signing package should be recreated no? as we want to ensure that we agree on what is being signed (should be a deterministic process).
     let signing_package =
-        frost_secp256k1_tr::SigningPackage::new(round2request.commitments_map.clone(), &message);
+        frost_secp256k1_tr::SigningPackage::new(request.commitments_map, &message);
     let signature_share =
         frost_secp256k1_tr::round2::sign(&signing_package, &nonces, &context.key_package).map_err(
             |err| {
                 ErrorObjectOwned::owned(
                     jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
                     "error while signing",
-                    Some(format!("the request didn't validate: {err}")),
+                    Some(format!("{err}")),
                 )
             },
         )?;
 
-    // return signature shares
-    let round2_response = Round2Response { signature_share };
-    RpcResult::Ok(round2_response)
+    RpcResult::Ok(Round2Response {
+        signature_share,
+        other_signature_shares: vec![],
+    })
 }
 
-async fn is_alive(params: Params<'static>, _context: Arc<NodeState>) -> RpcResult<u64> {
-    Ok(params.parse::<[u64; 1]>()?[0])
+/// Forgets the nonces of a signature of a committee configuration that won't make it to round 2.
+async fn discard_config_signing(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<bool> {
+    let [message]: [[u8; 32]; 1] = params.parse()?;
+    RpcResult::Ok(
+        context
+            .config_signings
+            .write()
+            .unwrap()
+            .remove(&message)
+            .is_some(),
+    )
+}
+
+/// Deals our key share to the members of a new committee (see [crate::committee::reshare]),
+/// if it's the one our operator approved.
+async fn reshare_deal(params: Params<'static>, context: Arc<NodeState>) -> RpcResult<ReshareDeal> {
+    let [request]: [ReshareRequest; 1] = params.parse()?;
+
+    if context.reshare_target.as_ref() != Some(&request.target) {
+        error!("refusing to deal our share towards a committee we didn't approve");
+        return Err(ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "this node didn't approve this reshare",
+            Some("this node didn't approve this reshare".to_string()),
+        ));
+    }
+
+    info!(
+        "dealing our share to a {}-of-{} committee",
+        request.target.min_signers,
+        request.target.recipients.len()
+    );
+    let deal = reshare::deal(&context.key_package, &request).map_err(|err| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while dealing our share",
+            Some(format!("{err}")),
+        )
+    })?;
+
+    RpcResult::Ok(deal)
 }
 
 //
 // Main server code
 //
 
+/// How to run a node of a committee (see [start_server]).
+pub struct NodeConfig {
+    /// Where signing decisions get recorded, and the key they're signed with (see [crate::committee::audit_log]).
+    pub audit_log: Option<(PathBuf, AuditKey)>,
+
+    /// A bitcoind node (with the minimum number of confirmations a zkapp needs),
+    /// to check that zkapps are unspent before signing.
+    pub bitcoind: Option<(RpcCtx, u64)>,
+
+    /// The node refuses to co-sign transactions with a fee outside of these.
+    pub fee_limits: FeeLimits,
+
+    /// The node refuses to verify proofs exceeding these.
+    pub proof_limits: ProofLimits,
+
+    /// The key signature shares are encrypted to (see [crate::committee::share_encryption]).
+    pub orchestrator_share_key: Option<PublicKey>,
+
+    /// The path the node is only served under (see [crate::committee::path_prefix]).
+    pub path_prefix: Option<String>,
+
+    /// The committee the node deals its share towards when asked (see [crate::committee::reshare]).
+    pub reshare_target: Option<ReshareTarget>,
+
+    /// The committee configuration the node co-signs when asked (see [crate::committee::signed_config]).
+    pub approved_config: Option<CommitteeConfig>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            audit_log: None,
+            bitcoind: None,
+            fee_limits: FeeLimits::for_network(get_network()),
+            proof_limits: ProofLimits::default(),
+            orchestrator_share_key: None,
+            path_prefix: None,
+            reshare_target: None,
+            approved_config: None,
+        }
+    }
+}
+
+/// Runs a node until it's stopped (see [start_server]).
 pub async fn run_server(
     address: Option<&str>,
-    key_package: frost::KeyPackage,
+    key_package: impl Into<frost::SecretKeyPackage>,
     pubkey_package: frost::PublicKeyPackage,
+    config: NodeConfig,
 ) -> anyhow::Result<SocketAddr> {
+    let (addr, handle) = start_server(address, key_package, pubkey_package, config).await?;
+
+    handle.stopped().await;
+
+    Ok(addr)
+}
+
+/// Binds a node to `address` and starts serving in the background (until the returned handle is stopped or dropped).
+/// Returns the address it actually listens on (e.g. when binding to port 0), so it can be reached right away.
+pub async fn start_server(
+    address: Option<&str>,
+    key_package: impl Into<frost::SecretKeyPackage>,
+    pubkey_package: frost::PublicKeyPackage,
+    config: NodeConfig,
+) -> anyhow::Result<(SocketAddr, ServerHandle)> {
+    let NodeConfig {
+        audit_log,
+        bitcoind,
+        fee_limits,
+        proof_limits,
+        orchestrator_share_key,
+        path_prefix,
+        reshare_target,
+        approved_config,
+    } = config;
+    let key_package = key_package.into();
+    let path_prefix = path_prefix
+        .as_deref()
+        .map(PathPrefix::from_str)
+        .transpose()?;
+
+    // fail early (and clearly) if the key share comes from another committee
+    frost::check_key_package(&key_package, &pubkey_package)?;
+
     let address = address.unwrap_or("127.0.0.1:6666");
     info!(
         "- starting node for identifier {id:?} at address http://{address}",
         id = key_package.identifier()
     );
 
-    let ctx = NodeState {
+    let audit_log = match audit_log {
+        Some((path, audit_key)) => {
+            let audit_log = AuditLog::open(&path, &audit_key)?;
+            info!(
+                "- recording signing decisions in {} (signed by audit key {})",
+                path.display(),
+                audit_log.public_key()
+            );
+            Some(audit_log)
+        }
+        None => None,
+    };
+    let audit_key = audit_log.as_ref().map(AuditLog::public_key);
+    let audit_log = audit_log.map(|audit_log| Arc::new(Mutex::new(audit_log)));
+
+    info!(
+        "- accepting feerates from {} sat/vB, and fees up to {}",
+        fee_limits.min_feerate.to_sat_per_vb_ceil(),
+        fee_limits.max_fee_absolute
+    );
+    info!(
+        "- verifying proofs of up to {} bytes, with up to {} public inputs",
+        proof_limits.max_proof_size, proof_limits.max_public_inputs
+    );
+    info!(
+        "- keeping up to {} verifier keys ready to verify proofs",
+        proof_limits.vk_cache_size
+    );
+    let mut ctx = NodeState {
         key_package,
         pubkey_package,
         signing_tasks: RwLock::new(CappedHashMap::new(MAX_SIGNING_TASK)),
+        audit_log,
+        audit_key,
+        rpc_ctx: None,
+        min_zkapp_confirmations: default_min_zkapp_confirmations(get_network()),
+        fee_limits,
+        vk_cache: VkCache::new(proof_limits.vk_cache_size),
+        proof_limits,
+        nonce_source: NonceSource::from_env()?,
+        smoke_tests: RwLock::new(CappedHashMap::new(MAX_SIGNING_TASK)),
+        orchestrator_share_key,
+        reshare_target,
+        approved_config: approved_config.as_ref().map(config_message).transpose()?,
+        config_signings: RwLock::new(CappedHashMap::new(MAX_SIGNING_TASK)),
     };
+    if let Some(orchestrator_share_key) = &orchestrator_share_key {
+        info!("- encrypting signature shares to the orchestrator key {orchestrator_share_key}");
+    }
+    if let Some(target) = &ctx.reshare_target {
+        info!(
+            "- approved a reshare to a {}-of-{} committee",
+            target.min_signers,
+            target.recipients.len()
+        );
+    }
+    if let Some(committee_cfg) = &approved_config {
+        info!(
+            "- approved a committee configuration with {} members (threshold of {})",
+            committee_cfg.members.len(),
+            committee_cfg.threshold
+        );
+    }
+    if let Some((rpc_ctx, min_zkapp_confirmations)) = bitcoind {
+        info!(
+            "- checking zkapps with the RPC node at {}",
+            rpc_ctx.address()
+        );
+        ctx.rpc_ctx = Some(rpc_ctx);
+        ctx.min_zkapp_confirmations = min_zkapp_confirmations;
+    }
 
+    // only answer under the path prefix (if any), compress large payloads (for the orchestrators that support it),
+    // and let users check their requests without a JSON-RPC client
+    let http_middleware = tower::ServiceBuilder::new()
+        .option_layer(path_prefix.as_ref().map(PathPrefix::layer))
+        .layer(CompressionLayer::new(MAX_REQUEST_BODY_SIZE as usize))
+        .layer(ProxyPostRequestLayer::new(VERIFY_PATH, "verify"));
     let server = Server::builder()
+        .max_request_body_size(MAX_REQUEST_BODY_SIZE)
+        .set_http_middleware(http_middleware)
         .build(address.parse::<SocketAddr>()?)
         .await?;
-    let mut module = RpcModule::new(ctx);
+    let mut module = DescribedModule::new(ctx);
 
-    module.register_async_method("round_1_signing", round_1_signing)?;
-    module.register_async_method("round_2_signing", round_2_signing)?;
-    module.register_async_method("ping", is_alive)?;
+    module.register_async_method(
+        Method::new(
+            ROUND_1_SIGNING.single,
+            &[("bob_request", "BobRequest")],
+            "Round1Response",
+        ),
+        round_1_signing,
+    )?;
+    module.register_async_method(
+        Method::new(
+            ROUND_2_SIGNING.single,
+            &[("round2_request", "Round2Request")],
+            "Round2Reply",
+        ),
+        round_2_signing,
+    )?;
+    module.register_async_method(
+        Method::new(
+            ROUND_1_SIGNING.batch,
+            &[("bob_requests", "Vec<BobRequest>")],
+            "Vec<BatchItemResult<Round1Response>>",
+        ),
+        round_1_signing_batch,
+    )?;
+    module.register_async_method(
+        Method::new(
+            ROUND_2_SIGNING.batch,
+            &[("round2_requests", "Vec<Round2Request>")],
+            "Vec<BatchItemResult<Round2Reply>>",
+        ),
+        round_2_signing_batch,
+    )?;
+    module.register_async_method(
+        Method::new("verify", &[("bob_request", "BobRequest")], "VerifyResponse"),
+        verify,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "discard_signing_task",
+            &[("txid", "Txid"), ("proof_hash", "[u8; 32]")],
+            "bool",
+        ),
+        discard_signing_task,
+    )?;
+    module.register_async_method(Method::new("ping", &[("data", "u64")], "u64"), is_alive)?;
+    module.register_async_method(Method::new("identity", &[], "NodeIdentity"), identity)?;
+    module.register_async_method(
+        Method::new("vk_cache_stats", &[], "VkCacheStats"),
+        vk_cache_stats,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "smoke_test_round_1",
+            &[("challenge", "[u8; 32]")],
+            "Round1Response",
+        ),
+        smoke_test_round_1,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "smoke_test_round_2",
+            &[("request", "SmokeTestRound2Request")],
+            "Round2Response",
+        ),
+        smoke_test_round_2,
+    )?;
+    module.register_async_method(
+        Method::new("discard_smoke_test", &[("challenge", "[u8; 32]")], "bool"),
+        discard_smoke_test,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "sign_config_round_1",
+            &[("message", "[u8; 32]")],
+            "Round1Response",
+        ),
+        sign_config_round_1,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "sign_config_round_2",
+            &[("request", "SmokeTestRound2Request")],
+            "Round2Response",
+        ),
+        sign_config_round_2,
+    )?;
+    module.register_async_method(
+        Method::new("discard_config_signing", &[("message", "[u8; 32]")], "bool"),
+        discard_config_signing,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "reshare_deal",
+            &[("request", "ReshareRequest")],
+            "ReshareDeal",
+        ),
+        reshare_deal,
+    )?;
+    let module = module.finish("node")?;
 
     let addr = server.local_addr()?;
+    info!(
+        "- node listening on http://{addr}{}",
+        path_prefix
+            .map(|prefix| prefix.to_string())
+            .unwrap_or_default()
+    );
     let handle = server.start(module);
 
-    handle.stopped().await;
+    Ok((addr, handle))
+}
 
-    Ok(addr)
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        absolute::LockTime, transaction::Version, Amount, Network, OutPoint, ScriptBuf, TxIn,
+    };
+
+    use bitcoin::key::TapTweak;
+    use rand::thread_rng;
+
+    use crate::{
+        committee::orchestrator::{aggregate_signatures, witness_from_signature},
+        sighash::KEYSPEND_SIGHASH_TYPE,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_round_2_consumes_nonces() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let mut key_packages = key_packages.into_values();
+        let key_package = key_packages.next().unwrap();
+        let other_key_package = key_packages.next().unwrap();
+        let context = NodeState {
+            key_package: frost::SecretKeyPackage::new(key_package.clone()),
+            pubkey_package,
+            signing_tasks: RwLock::new(CappedHashMap::new(MAX_SIGNING_TASK)),
+            audit_log: None,
+            audit_key: None,
+            rpc_ctx: None,
+            min_zkapp_confirmations: 1,
+            fee_limits: FeeLimits::for_network(Network::Regtest),
+            proof_limits: ProofLimits::default(),
+            vk_cache: VkCache::default(),
+            nonce_source: NonceSource::default(),
+            smoke_tests: RwLock::new(CappedHashMap::new(MAX_SIGNING_TASK)),
+            orchestrator_share_key: None,
+            reshare_target: None,
+            approved_config: None,
+            config_signings: RwLock::new(CappedHashMap::new(MAX_SIGNING_TASK)),
+        };
+
+        // a pending signing task
+        let txid = Txid::from_byte_array([1; 32]);
+        let smart_contract = SmartContract {
+            txid,
+            locked_value: Amount::from_sat(10_000),
+            vk_hash: [0; 32],
+            state: None,
+            vout_of_zkbitcoin_utxo: 0,
+            scheme: Default::default(),
+        };
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid, vout: 0 },
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(9_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let prev_outs = vec![TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new(),
+        }];
+        let rng = &mut thread_rng();
+        let (nonces, commitments) =
+            frost_secp256k1_tr::round1::commit(key_package.signing_share(), rng);
+        let (_, other_commitments) =
+            frost_secp256k1_tr::round1::commit(other_key_package.signing_share(), rng);
+        context.signing_tasks.write().unwrap().add_entry(
+            txid,
+            LocalSigningTask {
+                proof_hash: [2; 32],
+                tx: tx.clone(),
+                prev_outs: prev_outs.clone(),
+                inputs: vec![LocalInputTask {
+                    smart_contract: smart_contract.clone(),
+                    sighash_type: KEYSPEND_SIGHASH_TYPE,
+                    nonces: frost::SecretNonces::new(nonces),
+                }],
+            },
+        );
+
+        let round2request = Round2Request {
+            correlation_id: None,
+            txid,
+            proof_hash: [2; 32],
+            commitments_map: BTreeMap::from([
+                (*key_package.identifier(), commitments),
+                (*other_key_package.identifier(), other_commitments),
+            ]),
+            message: get_digest_to_hash(&prev_outs, &tx, &smart_contract, KEYSPEND_SIGHASH_TYPE)
+                .unwrap(),
+            sighash_type: KEYSPEND_SIGHASH_TYPE,
+            other_inputs: vec![],
+        };
+        handle_round_2(&context, &round2request).await.unwrap();
+
+        // the nonces are gone, so the same request can't get a second signature share
+        assert!(context.signing_tasks.read().unwrap().get(&txid).is_none());
+        let err = handle_round_2(&context, &round2request).await.unwrap_err();
+        assert_eq!(err.message(), "no signing task found for this txid");
+    }
+
+    #[tokio::test]
+    async fn test_sign_two_zkapp_inputs() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let signers = key_packages.into_values().take(2).collect::<Vec<_>>();
+        // the members encrypt their signature shares to the orchestrator
+        let share_key = ShareKey::generate();
+        let contexts = signers
+            .iter()
+            .map(|key_package| NodeState {
+                key_package: frost::SecretKeyPackage::new(key_package.clone()),
+                pubkey_package: pubkey_package.clone(),
+                signing_tasks: RwLock::new(CappedHashMap::new(MAX_SIGNING_TASK)),
+                audit_log: None,
+                audit_key: None,
+                rpc_ctx: None,
+                min_zkapp_confirmations: 1,
+                fee_limits: FeeLimits::for_network(Network::Regtest),
+                proof_limits: ProofLimits::default(),
+                vk_cache: VkCache::default(),
+                nonce_source: NonceSource::default(),
+                smoke_tests: RwLock::new(CappedHashMap::new(MAX_SIGNING_TASK)),
+                orchestrator_share_key: Some(share_key.public_key()),
+                reshare_target: None,
+                approved_config: None,
+                config_signings: RwLock::new(CappedHashMap::new(MAX_SIGNING_TASK)),
+            })
+            .collect::<Vec<_>>();
+
+        // a transaction spending two committee outputs, from different deposits
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let xonly = frost::to_xonly_pubkey(pubkey_package.verifying_key());
+        let committee_script = ScriptBuf::new_p2tr(&secp, xonly, None);
+        let smart_contracts = [1u8, 2].map(|seed| SmartContract {
+            txid: Txid::from_byte_array([seed; 32]),
+            locked_value: Amount::from_sat(10_000),
+            vk_hash: [0; 32],
+            state: None,
+            vout_of_zkbitcoin_utxo: 0,
+            scheme: Default::default(),
+        });
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: smart_contracts
+                .iter()
+                .map(|smart_contract| TxIn {
+                    previous_output: OutPoint {
+                        txid: smart_contract.txid,
+                        vout: 0,
+                    },
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(19_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let prev_outs = vec![
+            TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: committee_script.clone(),
+            };
+            2
+        ];
+        let txid = tx.txid();
+
+        // round 1: every member commits once per input
+        let rng = &mut thread_rng();
+        let mut commitments_maps = vec![BTreeMap::new(); 2];
+        for (key_package, context) in signers.iter().zip(&contexts) {
+            let inputs = smart_contracts
+                .iter()
+                .zip(commitments_maps.iter_mut())
+                .map(|(smart_contract, commitments_map)| {
+                    let (nonces, commitments) =
+                        frost_secp256k1_tr::round1::commit(key_package.signing_share(), rng);
+                    commitments_map.insert(*key_package.identifier(), commitments);
+                    LocalInputTask {
+                        smart_contract: smart_contract.clone(),
+                        sighash_type: KEYSPEND_SIGHASH_TYPE,
+                        nonces: frost::SecretNonces::new(nonces),
+                    }
+                })
+                .collect();
+            context.signing_tasks.write().unwrap().add_entry(
+                txid,
+                LocalSigningTask {
+                    proof_hash: [2; 32],
+                    tx: tx.clone(),
+                    prev_outs: prev_outs.clone(),
+                    inputs,
+                },
+            );
+        }
+
+        // round 2: every member signs each input
+        let input_requests = smart_contracts
+            .iter()
+            .zip(commitments_maps)
+            .map(|(smart_contract, commitments_map)| InputSigningRequest {
+                commitments_map,
+                message: get_digest_to_hash(&prev_outs, &tx, smart_contract, KEYSPEND_SIGHASH_TYPE)
+                    .unwrap(),
+                sighash_type: KEYSPEND_SIGHASH_TYPE,
+            })
+            .collect::<Vec<_>>();
+        assert_ne!(input_requests[0].message, input_requests[1].message);
+        let round2request = Round2Request {
+            correlation_id: None,
+            txid,
+            proof_hash: [2; 32],
+            commitments_map: input_requests[0].commitments_map.clone(),
+            message: input_requests[0].message,
+            sighash_type: KEYSPEND_SIGHASH_TYPE,
+            other_inputs: input_requests[1..].to_vec(),
+        };
+        let mut signature_shares = vec![BTreeMap::new(); 2];
+        for (key_package, context) in signers.iter().zip(&contexts) {
+            let reply = reply_round_2(context, &round2request).await.unwrap();
+            assert!(matches!(reply, Round2Reply::Encrypted { .. }));
+            let shares = reply
+                .into_all_signature_shares(Some(&share_key), &txid, key_package.identifier())
+                .unwrap();
+            assert_eq!(shares.len(), 2);
+            for (input_shares, share) in signature_shares.iter_mut().zip(shares) {
+                input_shares.insert(*key_package.identifier(), share);
+            }
+        }
+
+        // each input gets its own witness, which verifies against its own sighash
+        let signatures =
+            aggregate_signatures(&pubkey_package, &input_requests, &signature_shares).unwrap();
+        let (output_key, _) = xonly.tap_tweak(&secp, None);
+        for (input_request, signature) in input_requests.iter().zip(&signatures) {
+            let witness = witness_from_signature(signature, KEYSPEND_SIGHASH_TYPE).unwrap();
+            let sig = bitcoin::taproot::Signature::from_slice(witness.nth(0).unwrap()).unwrap();
+            secp.verify_schnorr(
+                &sig.sig,
+                &bitcoin::secp256k1::Message::from_digest(input_request.message),
+                &output_key.to_inner(),
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_check_batch_size() {
+        check_batch_size(0).unwrap();
+        check_batch_size(MAX_SIGNING_BATCH).unwrap();
+        let err = check_batch_size(MAX_SIGNING_BATCH + 1).unwrap_err();
+        assert_eq!(err.message(), "batch too large");
+    }
+
+    #[tokio::test]
+    async fn test_start_server_on_port_0() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let key_package = key_packages.into_values().next().unwrap();
+
+        let (addr, handle) = start_server(
+            Some("127.0.0.1:0"),
+            key_package,
+            pubkey_package,
+            NodeConfig {
+                fee_limits: FeeLimits::for_network(Network::Regtest),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_ne!(addr.port(), 0);
+
+        // the node is reachable right away
+        tokio::net::TcpStream::connect(addr).await.unwrap();
+
+        // it describes what it serves
+        let description = crate::committee::describe::describe_server(&format!("http://{addr}"))
+            .await
+            .unwrap();
+        assert_eq!(description.server, "node");
+        assert!(description.method(ROUND_1_SIGNING.batch).is_some());
+        assert!(description.method("describe").is_some());
+
+        // and never answers CORS preflights (only the orchestrator can be called from browsers)
+        let response = reqwest::Client::new()
+            .request(reqwest::Method::OPTIONS, format!("http://{addr}"))
+            .header("origin", "https://app.example.com")
+            .header("access-control-request-method", "POST")
+            .send()
+            .await
+            .unwrap();
+        assert!(!response
+            .headers()
+            .contains_key("access-control-allow-origin"));
+
+        handle.stop().unwrap();
+        handle.stopped().await;
+    }
+
+    #[tokio::test]
+    async fn test_verify_endpoint() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let key_package = key_packages.into_values().next().unwrap();
+        let (addr, handle) = start_server(
+            Some("127.0.0.1:0"),
+            key_package,
+            pubkey_package,
+            NodeConfig {
+                fee_limits: FeeLimits::for_network(Network::Regtest),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let address = format!("http://{addr}");
+
+        // the example proof doesn't prove anything about this spend
+        let zkapp_tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(zkapp_tx.txid(), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(9_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let bob_request = BobRequest {
+            prev_outs: zkapp_tx.output.clone(),
+            tx,
+            zkapp_tx,
+            vk: serde_json::from_str(include_str!("../../examples/circuit/vk.json")).unwrap(),
+            proof: serde_json::from_str(include_str!("../../examples/circuit/proof.json")).unwrap(),
+            update: None,
+            sighash_type: KEYSPEND_SIGHASH_TYPE,
+            session_token: None,
+            correlation_id: None,
+            other_zkapps: vec![],
+        };
+
+        // the node says why it would reject it
+        let response = crate::bob_request::verify_with_node(&address, &bob_request)
+            .await
+            .unwrap();
+        assert!(!response.accepted);
+        let reason = response.reason.unwrap();
+        assert!(
+            reason.starts_with("the request didn't validate"),
+            "{reason}"
+        );
+
+        // a groth16 zkapp, with a proof of this very spend, passes all the checks
+        let (vk, pk) = crate::groth16::test_circuit::setup();
+        let vk = crate::proof_system::VerifierKey::from(vk);
+        let zkapp_tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: crate::p2tr_script_to(crate::zkbitcoin_pubkey().unwrap()),
+                },
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: crate::op_return_script_for_scheme(
+                        crate::proof_system::ProofScheme::Groth16,
+                        &vk.hash(),
+                        None,
+                    )
+                    .unwrap(),
+                },
+            ],
+        };
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(zkapp_tx.txid(), 0),
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(crate::constants::FEE_ZKBITCOIN_SAT),
+                    script_pubkey: crate::p2tr_script_to(
+                        crate::constants::ZKBITCOIN_FEE_PUBKEY.parse().unwrap(),
+                    ),
+                },
+                TxOut {
+                    value: Amount::from_sat(9_000),
+                    script_pubkey: ScriptBuf::new(),
+                },
+            ],
+        };
+        let proof = crate::groth16::test_circuit::prove(&pk, &crate::truncate_txid(tx.txid()));
+        let valid_request = BobRequest {
+            prev_outs: vec![zkapp_tx.output[0].clone()],
+            tx,
+            zkapp_tx,
+            vk,
+            proof: proof.into(),
+            update: None,
+            sighash_type: KEYSPEND_SIGHASH_TYPE,
+            session_token: None,
+            correlation_id: None,
+            other_zkapps: vec![],
+        };
+        let response = crate::bob_request::verify_with_node(&address, &valid_request)
+            .await
+            .unwrap();
+        assert_eq!(
+            response,
+            VerifyResponse {
+                accepted: true,
+                reason: None,
+            }
+        );
+
+        // neither request got a signing task (nor nonces)
+        let ctx = RpcCtx::builder()
+            .version("2.0")
+            .url(address.clone())
+            .build()
+            .unwrap();
+        for request in [&bob_request, &valid_request] {
+            let discarded: bool = crate::json_rpc_stuff::json_rpc_request_deserialize(
+                &ctx,
+                "discard_signing_task",
+                &[
+                    serde_json::value::to_raw_value(&request.txid().unwrap()).unwrap(),
+                    serde_json::value::to_raw_value(&request.proof.hash()).unwrap(),
+                ],
+            )
+            .await
+            .unwrap();
+            assert!(!discarded);
+        }
+
+        // and refuses what isn't a request
+        let response = reqwest::Client::new()
+            .post(format!("{address}{VERIFY_PATH}"))
+            .json(&serde_json::json!({ "not": "a request" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        handle.stop().unwrap();
+        handle.stopped().await;
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_key_share() {
+        let (key_packages, _) = frost::gen_frost_keys(3, 2).unwrap();
+        let (_, other_pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let key_package = key_packages.into_values().next().unwrap();
+
+        let err = run_server(
+            Some("127.0.0.1:0"),
+            key_package,
+            other_pubkey_package,
+            NodeConfig {
+                fee_limits: FeeLimits::for_network(Network::Regtest),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("key share does not match public key package"),
+            "{err}"
+        );
+    }
 }
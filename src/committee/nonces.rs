@@ -0,0 +1,252 @@
+//! Where nodes draw their FROST nonces from.
+//!
+//! Nonces are always random in production. For reproducible test vectors, a build with the
+//! `unsafe-deterministic-nonces` feature can derive them from a seed instead (see [UNSAFE_NONCE_SEED_ENV]),
+//! in the spirit of RFC 6979: from a hash of the seed, the member, and what is being signed.
+//!
+//! **Never run such a build with real keys.** Anyone who knows the seed knows the nonces,
+//! and a signature share made with a known nonce gives away the key share it was made with.
+
+use anyhow::Result;
+use frost_secp256k1_tr::{
+    keys::SigningShare,
+    round1::{self, SigningCommitments, SigningNonces},
+    Identifier,
+};
+use rand::{rngs::ThreadRng, thread_rng, CryptoRng, RngCore};
+
+#[cfg(feature = "unsafe-deterministic-nonces")]
+use {
+    anyhow::Context,
+    bitcoin::hashes::{sha256, Hash, HashEngine},
+    log::warn,
+    rand::SeedableRng,
+    rand_chacha::ChaCha20Rng,
+};
+
+/// The environment variable holding the (hex-encoded, 32-byte) seed of deterministic nonces.
+/// It's only read by builds with the `unsafe-deterministic-nonces` feature, other builds refuse to start if it's set.
+pub const UNSAFE_NONCE_SEED_ENV: &str = "ZKBITCOIN_UNSAFE_NONCE_SEED";
+
+/// The tag of the hashes deterministic nonces are derived from.
+#[cfg(feature = "unsafe-deterministic-nonces")]
+const NONCE_SEED_TAG: &str = "zkBitcoin/unsafe-deterministic-nonce";
+
+/// How a node generates its nonces.
+#[derive(Debug, Clone, Default)]
+pub enum NonceSource {
+    /// Fresh random nonces, for every signature.
+    #[default]
+    Random,
+
+    /// Nonces derived from a seed (see the module documentation). Only for tests.
+    #[cfg(feature = "unsafe-deterministic-nonces")]
+    UnsafeDeterministic([u8; 32]),
+}
+
+impl NonceSource {
+    /// Reads the source from the [UNSAFE_NONCE_SEED_ENV] variable (random nonces if it isn't set).
+    pub fn from_env() -> Result<Self> {
+        Self::from_seed(std::env::var(UNSAFE_NONCE_SEED_ENV).ok().as_deref())
+    }
+
+    /// Random nonces without a seed, deterministic nonces with one (if the build allows them).
+    pub fn from_seed(seed: Option<&str>) -> Result<Self> {
+        let Some(seed) = seed else {
+            return Ok(Self::Random);
+        };
+
+        #[cfg(feature = "unsafe-deterministic-nonces")]
+        {
+            let seed: [u8; 32] = hex::decode(seed)
+                .ok()
+                .and_then(|seed| seed.try_into().ok())
+                .with_context(|| format!("{UNSAFE_NONCE_SEED_ENV} must be 32 bytes, in hex"))?;
+            warn!("!!! deriving FROST nonces from {UNSAFE_NONCE_SEED_ENV}: signatures are reproducible, and key shares can be recovered from them. Never use this with real keys !!!");
+            Ok(Self::UnsafeDeterministic(seed))
+        }
+
+        #[cfg(not(feature = "unsafe-deterministic-nonces"))]
+        {
+            let _ = seed;
+            anyhow::bail!("{UNSAFE_NONCE_SEED_ENV} is set, but deterministic nonces are only available in builds with the `unsafe-deterministic-nonces` feature")
+        }
+    }
+
+    /// The RNG to draw the nonces of `identifier` from, to sign for `context`
+    /// (e.g. a transaction and one of its inputs, which must be unique to each signature).
+    pub fn rng(&self, identifier: &Identifier, context: &[u8]) -> NonceRng {
+        match self {
+            Self::Random => {
+                let _ = (identifier, context);
+                NonceRng::Random(thread_rng())
+            }
+
+            #[cfg(feature = "unsafe-deterministic-nonces")]
+            Self::UnsafeDeterministic(seed) => {
+                let tag = sha256::Hash::hash(NONCE_SEED_TAG.as_bytes());
+                let mut engine = sha256::Hash::engine();
+                engine.input(tag.as_ref());
+                engine.input(tag.as_ref());
+                engine.input(seed);
+                engine.input(&serde_json::to_vec(identifier).unwrap());
+                engine.input(context);
+                let seed = sha256::Hash::from_engine(engine).to_byte_array();
+                NonceRng::UnsafeDeterministic(ChaCha20Rng::from_seed(seed))
+            }
+        }
+    }
+
+    /// Round 1 of FROST: generates nonces (and their commitments) for `identifier` to sign for `context`.
+    pub fn commit(
+        &self,
+        identifier: &Identifier,
+        signing_share: &SigningShare,
+        context: &[u8],
+    ) -> (SigningNonces, SigningCommitments) {
+        round1::commit(signing_share, &mut self.rng(identifier, context))
+    }
+}
+
+/// The RNG nonces are drawn from (see [NonceSource::rng]).
+pub enum NonceRng {
+    Random(ThreadRng),
+
+    #[cfg(feature = "unsafe-deterministic-nonces")]
+    UnsafeDeterministic(ChaCha20Rng),
+}
+
+impl RngCore for NonceRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            Self::Random(rng) => rng.next_u32(),
+            #[cfg(feature = "unsafe-deterministic-nonces")]
+            Self::UnsafeDeterministic(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            Self::Random(rng) => rng.next_u64(),
+            #[cfg(feature = "unsafe-deterministic-nonces")]
+            Self::UnsafeDeterministic(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            Self::Random(rng) => rng.fill_bytes(dest),
+            #[cfg(feature = "unsafe-deterministic-nonces")]
+            Self::UnsafeDeterministic(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match self {
+            Self::Random(rng) => rng.try_fill_bytes(dest),
+            #[cfg(feature = "unsafe-deterministic-nonces")]
+            Self::UnsafeDeterministic(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+// (deterministic nonces are only as secret as their seed, which is the point of that feature)
+impl CryptoRng for NonceRng {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use frost_secp256k1_tr::{round2, SigningPackage};
+
+    use super::*;
+    use crate::frost;
+
+    /// Round 1 and 2 of FROST, returning the commitments and the signature shares (serialized).
+    fn sign_with(
+        signers: &[&frost::KeyPackage],
+        sources: [&NonceSource; 2],
+        message: &[u8],
+    ) -> (String, String) {
+        let mut nonces = BTreeMap::new();
+        let mut commitments = BTreeMap::new();
+        for (key_package, source) in signers.iter().zip(sources) {
+            let (member_nonces, member_commitments) = source.commit(
+                key_package.identifier(),
+                key_package.signing_share(),
+                message,
+            );
+            nonces.insert(*key_package.identifier(), member_nonces);
+            commitments.insert(*key_package.identifier(), member_commitments);
+        }
+        let signing_package = SigningPackage::new(commitments.clone(), message);
+        let shares = signers
+            .iter()
+            .map(|key_package| {
+                let share = round2::sign(
+                    &signing_package,
+                    &nonces[key_package.identifier()],
+                    key_package,
+                )
+                .unwrap();
+                (*key_package.identifier(), share)
+            })
+            .collect::<BTreeMap<_, _>>();
+        (
+            serde_json::to_string(&commitments).unwrap(),
+            serde_json::to_string(&shares).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_random_nonces() {
+        let random = NonceSource::from_seed(None).unwrap();
+        assert!(matches!(random, NonceSource::Random));
+
+        // two rounds never use the same nonces
+        let (key_packages, _) = frost::gen_frost_keys(3, 2).unwrap();
+        let signers = key_packages.values().take(2).collect::<Vec<_>>();
+        let first = sign_with(&signers, [&random, &random], b"message");
+        let second = sign_with(&signers, [&random, &random], b"message");
+        assert_ne!(first.0, second.0);
+        assert_ne!(first.1, second.1);
+    }
+
+    #[cfg(not(feature = "unsafe-deterministic-nonces"))]
+    #[test]
+    fn test_deterministic_nonces_are_unavailable_by_default() {
+        let err = NonceSource::from_seed(Some(&"11".repeat(32))).unwrap_err();
+        assert!(
+            err.to_string().contains("unsafe-deterministic-nonces"),
+            "{err}"
+        );
+    }
+
+    #[cfg(feature = "unsafe-deterministic-nonces")]
+    #[test]
+    fn test_deterministic_nonces() {
+        let seed = NonceSource::from_seed(Some(&"11".repeat(32))).unwrap();
+        let other_seed = NonceSource::from_seed(Some(&"22".repeat(32))).unwrap();
+        assert!(NonceSource::from_seed(Some("11")).is_err());
+
+        // the same seed, keys, and message give the same commitments and signature shares
+        let (key_packages, _) = frost::gen_frost_keys(3, 2).unwrap();
+        let signers = key_packages.values().take(2).collect::<Vec<_>>();
+        let first = sign_with(&signers, [&seed, &seed], b"message");
+        let second = sign_with(&signers, [&seed, &seed], b"message");
+        assert_eq!(first, second);
+
+        // but not another seed, or another message
+        assert_ne!(
+            sign_with(&signers, [&seed, &other_seed], b"message").0,
+            first.0
+        );
+        assert_ne!(sign_with(&signers, [&seed, &seed], b"other").0, first.0);
+
+        // members with the same seed still use different nonces
+        let commitments: BTreeMap<String, serde_json::Value> =
+            serde_json::from_str(&first.0).unwrap();
+        let [a, b]: [_; 2] = commitments.values().collect::<Vec<_>>().try_into().unwrap();
+        assert_ne!(a, b);
+    }
+}
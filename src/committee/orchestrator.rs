@@ -1,40 +1,80 @@
 use std::{
     collections::{BTreeMap, HashMap},
+    future::Future,
     net::SocketAddr,
-    str::FromStr,
-    sync::{Arc, RwLock},
-    time::{Duration, SystemTime},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use bitcoin::{
     hex::DisplayHex,
     key::{TapTweak, UntweakedPublicKey},
-    secp256k1, taproot, TapSighashType, Witness,
+    secp256k1, taproot, OutPoint, Txid, Witness,
 };
 use frost_secp256k1_tr::{Ciphersuite, Group, Identifier};
 use futures::future::join_all;
 use itertools::Itertools;
-use jsonrpsee::{server::Server, RpcModule};
+use jsonrpsee::server::{middleware::http::ProxyGetRequestLayer, Server, ServerHandle};
 use jsonrpsee_core::RpcResult;
 use jsonrpsee_types::{ErrorObjectOwned, Params};
-use log::{debug, error, info, warn};
 use rand::seq::SliceRandom;
 use secp256k1::XOnlyPublicKey;
 use serde::{Deserialize, Serialize};
-use tokio::time::sleep;
+use tokio::{
+    sync::{watch, Notify},
+    time::sleep,
+};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 use crate::{
-    bob_request::{BobRequest, BobResponse},
-    committee::node::Round1Response,
+    bob_request::{
+        check_correlation_id, check_zkapp_confirmations, default_min_zkapp_confirmations,
+        new_correlation_id, BobRequest, BobResponse, InsufficientConfirmations, ProofLimits,
+        SighashPreview, ZkappAlreadySpent, ZkappUtxo,
+    },
+    capped_hashmap::CappedHashMap,
+    chain_backend::{BitcoindBackend, ChainBackend},
+    chain_tip::ChainTip,
+    committee::{
+        alerting::Alerts,
+        batching::Batcher,
+        cors::CorsOrigins,
+        describe::{DescribedModule, Method},
+        node::{Round1Response, ROUND_1_SIGNING, ROUND_2_SIGNING},
+        readiness::{ReadinessCheck, ReadyQuorum, READY_PATH},
+        reputation::ReputationStore,
+        reshare::{ReshareDeal, ReshareRequest, ReshareTarget, ReshareTranscript},
+        session_history::{
+            chosen_fee_rate, session_id_from_token, InputReserved, RequestSummary,
+            SessionAbandoned, SessionCancelled, SessionEvent, SessionEvents, SessionFilter,
+            SessionHistory, SessionPage, SessionProgress, SessionRecord, SessionState,
+            SessionStatus, SessionSummary, ZkappReorged,
+        },
+        share_encryption::ShareKey,
+        smoke_test::call_member,
+    },
     compliance::Compliance,
-    constants::{KEEPALIVE_MAX_RETRIES, KEEPALIVE_WAIT_SECONDS, ZKBITCOIN_PUBKEY},
-    frost,
-    json_rpc_stuff::{json_rpc_request, RpcCtx},
+    compression::CompressionLayer,
+    constants::{
+        KEEPALIVE_MAX_RETRIES, KEEPALIVE_WAIT_SECONDS, MAX_REQUEST_BODY_SIZE, MEMBER_MAX_FAILURES,
+        MEMBER_QUARANTINE_SECONDS, REORG_WATCH_BLOCKS, SESSION_EVENTS_LONG_POLL_SECONDS,
+        SESSION_HISTORY_RETENTION_DAYS, SESSION_REAPER_INTERVAL_SECONDS, SIGNING_BATCH_WINDOW_MS,
+        SIGNING_SESSION_TIMEOUT_SECONDS, ZKAPP_UTXO_CACHE_SIZE, ZKAPP_UTXO_CACHE_TTL_SECONDS,
+    },
+    fee_policy::FeePolicy,
+    fee_strategy::FeeStrategy,
+    frost, get_network,
+    json_rpc_stuff::{json_rpc_request, BroadcastOutcome, BroadcastTarget, RpcCtx},
     mpc_sign_tx::get_digest_to_hash,
+    taproot_addr_from,
+    tx_sanity::{sanity_check_tx, SanityPolicy},
+    vk_cache::{VkCache, VkCacheStats},
+    zkbitcoin_pubkey,
 };
 
-use super::node::{Round2Request, Round2Response};
+use super::node::{InputSigningRequest, Round2Reply, Round2Request};
 
 //
 // Orchestration logic
@@ -45,7 +85,167 @@ pub struct CommitteeConfig {
     pub threshold: usize,
     // TODO: We could use a Vec instead of a HashMap for the members, since it would be more efficient.
     // We do not currently need hashmap functionality, but we might later, so left unchanged.
+    /// Duplicate ids are rejected when deserializing (instead of silently keeping the last one).
+    #[serde(deserialize_with = "deserialize_unique_members")]
     pub members: HashMap<Identifier, Member>,
+
+    /// A signature of the config by the committee, if any (see [crate::committee::signed_config]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<frost_secp256k1_tr::Signature>,
+}
+
+/// Deserializes the members of a committee, rejecting duplicate ids.
+fn deserialize_unique_members<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<Identifier, Member>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct UniqueMembers;
+
+    impl<'de> serde::de::Visitor<'de> for UniqueMembers {
+        type Value = HashMap<Identifier, Member>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a map of member ids to members")
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::MapAccess<'de>,
+        {
+            let mut members = HashMap::new();
+            while let Some((id, member)) = map.next_entry::<Identifier, Member>()? {
+                if members.insert(id, member).is_some() {
+                    return Err(serde::de::Error::custom(format!(
+                        "duplicate member id {id:?}"
+                    )));
+                }
+            }
+            Ok(members)
+        }
+    }
+
+    deserializer.deserialize_map(UniqueMembers)
+}
+
+/// Normalizes the address of a member (e.g. `http://127.0.0.1:8891/` and `127.0.0.1:8891` are the same node).
+pub(crate) fn normalize_address(address: &str) -> String {
+    let address = address.trim();
+    let address = address
+        .strip_prefix("http://")
+        .or_else(|| address.strip_prefix("https://"))
+        .unwrap_or(address);
+    address.trim_end_matches('/').to_lowercase()
+}
+
+impl CommitteeConfig {
+    /// Checks that the config makes sense: the threshold can be reached (by the votes of all the members),
+    /// and no two members share an address (a single node would then count twice towards the threshold).
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.threshold > 0, "the threshold must be at least 1");
+        for (id, member) in &self.members {
+            ensure!(member.votes() > 0, "{id:?} must have at least 1 vote");
+        }
+        ensure!(
+            self.threshold <= self.total_votes(),
+            "the threshold ({}) is larger than the votes of all the members ({})",
+            self.threshold,
+            self.total_votes()
+        );
+
+        let mut addresses = HashMap::new();
+        for (id, member) in &self.members {
+            if let Some(other) = addresses.insert(normalize_address(&member.address), id) {
+                bail!(
+                    "{id:?} and {other:?} have the same address ({})",
+                    member.address
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Same as [CommitteeConfig::validate], and checks the members against the committee key:
+    /// they must all hold a share of it, and any set of members whose votes reach the threshold
+    /// (as picked by [Orchestrator::select_signers]) must hold enough shares to sign.
+    pub fn validate_with_key(&self, pubkey_package: &frost::PublicKeyPackage) -> Result<()> {
+        self.validate()?;
+        for id in self.members.keys() {
+            ensure!(
+                pubkey_package.verifying_shares().contains_key(id),
+                "{id:?} doesn't hold a share of the committee key"
+            );
+        }
+
+        let min_signers = frost::key_threshold(pubkey_package)?;
+        let smallest_quorum = self.smallest_quorum();
+        ensure!(
+            smallest_quorum >= min_signers,
+            "{smallest_quorum} members can reach the threshold ({}) with their votes, but the committee key needs {min_signers} of them to sign",
+            self.threshold
+        );
+
+        Ok(())
+    }
+
+    /// The fewest members whose votes reach the threshold.
+    pub fn smallest_quorum(&self) -> usize {
+        let mut count = 0;
+        let mut votes = 0;
+        for member_votes in self.members.values().map(Member::votes).sorted().rev() {
+            if votes >= self.threshold {
+                break;
+            }
+            votes += member_votes;
+            count += 1;
+        }
+        count
+    }
+
+    /// The votes of all the members (their number if none of them has more than one vote).
+    pub fn total_votes(&self) -> usize {
+        self.members.values().map(Member::votes).sum()
+    }
+
+    /// Exports the config of an observer of some of the members (or all of them if `member_ids` is empty).
+    pub fn observer_config(&self, member_ids: &[Identifier]) -> Result<ObserverConfig> {
+        let members = if member_ids.is_empty() {
+            self.members.clone().into_iter().collect()
+        } else {
+            member_ids
+                .iter()
+                .map(|id| {
+                    let member = self
+                        .members
+                        .get(id)
+                        .with_context(|| format!("{id:?} is not a member of the committee"))?;
+                    Ok((*id, member.clone()))
+                })
+                .collect::<Result<_>>()?
+        };
+        Ok(ObserverConfig { members })
+    }
+}
+
+/// A stripped down [CommitteeConfig] for observers (e.g. a monitoring service),
+/// which only need to reach the members (see [ObserverConfig::ping]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObserverConfig {
+    pub members: BTreeMap<Identifier, Member>,
+}
+
+impl ObserverConfig {
+    /// Pings all the members, and returns which ones are alive.
+    pub async fn ping(&self) -> BTreeMap<Identifier, bool> {
+        let pings = self
+            .members
+            .values()
+            .map(|member| MemberStatusState::check_alive(member.address.clone()));
+        let alive = join_all(pings).await;
+        self.members.keys().copied().zip(alive).collect()
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
@@ -61,6 +261,52 @@ pub enum MemberStatus {
 pub struct StatusResponse {
     pub online_members: Vec<Identifier>,
     pub offline_members: Vec<Identifier>,
+
+    /// The members that are quarantined (see [ReputationStore]), and until when (in seconds since the UNIX epoch).
+    #[serde(default)]
+    pub blacklisted_members: BTreeMap<Identifier, u64>,
+
+    /// The number of calls to bitcoind that had to wait for other calls to finish (see [RpcCtx::queue_waits]),
+    /// if the orchestrator is connected to a bitcoind node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitcoind_queue_waits: Option<u64>,
+}
+
+/// Public information about the committee (served at `GET /committee-info`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitteeInfo {
+    /// The x-only group public key of the committee (in hex).
+    pub pubkey: String,
+
+    /// The taproot address (for the current network) to deploy zkapps to.
+    pub address: String,
+
+    pub threshold: usize,
+
+    pub members: usize,
+
+    /// The version of the orchestrator.
+    pub version: String,
+}
+
+impl CommitteeInfo {
+    pub fn new(
+        pubkey_package: &frost::PublicKeyPackage,
+        committee_cfg: &CommitteeConfig,
+    ) -> Result<Self> {
+        let verifying_key = pubkey_package.verifying_key();
+        let pubkey = bitcoin::PublicKey::from_slice(&verifying_key.serialize())
+            .context("invalid group public key")?;
+        let address = taproot_addr_from(&pubkey.to_string())?;
+
+        Ok(Self {
+            pubkey: frost::to_xonly_pubkey(verifying_key).to_string(),
+            address: address.to_string(),
+            threshold: committee_cfg.threshold,
+            members: committee_cfg.members.len(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        })
+    }
 }
 
 // This will be the second part in the RpcModule context wrapped in a RwLock.
@@ -163,7 +409,9 @@ impl MemberStatusState {
 
     async fn check_alive(address: String) -> bool {
         let data = Self::get_current_time_secs();
-        let rpc_ctx = RpcCtx::new(Some("2.0"), None, Some(address.clone()), None, None);
+        let Ok(rpc_ctx) = RpcCtx::builder().version("2.0").url(address).build() else {
+            return false;
+        };
         match json_rpc_request(
             &rpc_ctx,
             "ping",
@@ -189,7 +437,9 @@ impl MemberStatusState {
         }
     }
 
-    pub async fn keepalive_thread(state: Arc<RwLock<Self>>) {
+    /// Pings the members that are not offline for good, forever,
+    /// alerting when an online member stops answering (see [Alerts::member_down]).
+    pub async fn keepalive_thread(state: Arc<RwLock<Self>>, alerts: Arc<Alerts>) {
         debug!("Keepalive thread started");
         loop {
             // Sleep
@@ -237,7 +487,10 @@ impl MemberStatusState {
                     let member_status = state_w.status.get_mut(key).unwrap();
                     let last_retries = match old_status {
                         MemberStatus::Disconnected((_, last_retry_number)) => *last_retry_number,
-                        MemberStatus::Online => 0,
+                        MemberStatus::Online => {
+                            alerts.member_down(*key, address, "it didn't answer a keepalive ping");
+                            0
+                        }
                         MemberStatus::Offline => continue,
                     };
 
@@ -259,353 +512,3193 @@ impl MemberStatusState {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Member {
-    /// e.g. "127.0.0.1:8887"
-    pub address: String,
+/// A cache of zkapp UTXOs (see [ChainBackend::zkapp_utxo]), so that repeated requests for the same zkapp don't hammer bitcoind.
+/// Entries expire after [ZKAPP_UTXO_CACHE_TTL_SECONDS].
+pub struct ZkappUtxoCache {
+    ttl: Duration,
+    entries: Mutex<CappedHashMap<OutPoint, (Instant, Option<ZkappUtxo>)>>,
 }
 
-pub struct Orchestrator {
-    pub pubkey_package: frost_secp256k1_tr::keys::PublicKeyPackage,
-    pub committee_cfg: CommitteeConfig,
-    pub member_status: Arc<RwLock<MemberStatusState>>,
-    compliance: Arc<Compliance>,
+impl Default for ZkappUtxoCache {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(ZKAPP_UTXO_CACHE_TTL_SECONDS))
+    }
 }
 
-impl Orchestrator {
-    pub fn new(
-        pubkey_package: frost_secp256k1_tr::keys::PublicKeyPackage,
-        committee_cfg: CommitteeConfig,
-        member_status: Arc<RwLock<MemberStatusState>>,
-        compliance: Arc<Compliance>,
-    ) -> Self {
+impl ZkappUtxoCache {
+    pub fn new(ttl: Duration) -> Self {
         Self {
-            pubkey_package,
-            committee_cfg,
-            member_status,
-            compliance,
+            ttl,
+            entries: Mutex::new(CappedHashMap::new(ZKAPP_UTXO_CACHE_SIZE)),
         }
     }
 
-    /// Handles bob request from A to Z.
-    pub async fn handle_request(&self, bob_request: &BobRequest) -> Result<BobResponse> {
-        // Validate transaction before forwarding it, and get smart contract
-        bob_request
-            .check_compliance(Arc::clone(&self.compliance))
-            .await?;
-        let smart_contract = bob_request.validate_request().await?;
-
-        // TODO: we might want to check that the zkapp/UTXO is unspent here, but this requires us to have access to a bitcoin node, so for now we don't do it :o)
+    /// Returns the zkapp at `outpoint` (or `None` if it's spent), from the cache if it's fresh enough.
+    pub async fn get(
+        &self,
+        chain: &dyn ChainBackend,
+        outpoint: OutPoint,
+    ) -> Result<Option<ZkappUtxo>> {
+        let cached = {
+            let entries = self.entries.lock().unwrap();
+            entries
+                .get(&outpoint)
+                .filter(|(fetched_at, _)| fetched_at.elapsed() < self.ttl)
+                .map(|(_, zkapp)| zkapp.clone())
+        };
+        if let Some(zkapp) = cached {
+            return Ok(zkapp);
+        }
 
-        'retry: loop {
-            //
-            // Round 1
-            //
+        let zkapp = chain.zkapp_utxo(outpoint).await?;
+        self.entries
+            .lock()
+            .unwrap()
+            .add_entry(outpoint, (Instant::now(), zkapp.clone()));
+        Ok(zkapp)
+    }
 
-            let mut commitments_map = BTreeMap::new();
+    /// Forgets about a zkapp (e.g. because we just signed a transaction spending it).
+    pub fn invalidate(&self, outpoint: &OutPoint) {
+        self.entries.lock().unwrap().remove(outpoint);
+    }
+}
 
-            let mut available_members = {
-                let ms_r = self.member_status.read().unwrap();
-                self.committee_cfg
-                    .members
-                    .iter()
-                    .filter(|(key, _)| ms_r.get_member_status(key) == MemberStatus::Online)
-                    .collect_vec()
-            };
-            if available_members.len() < self.committee_cfg.threshold {
-                return Err(anyhow::Error::msg("not enough available signers"));
-            }
+/// Why a signing session couldn't gather a threshold of valid signature shares,
+/// telling a connectivity problem ([ThresholdError::NotEnoughResponses]) from a cryptographic one ([ThresholdError::InvalidShares]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ThresholdError {
+    /// Fewer than a threshold of members could be reached (or kept responding) during the session.
+    NotEnoughResponses {
+        threshold: usize,
 
-            available_members.shuffle(&mut rand::thread_rng());
-            available_members.truncate(self.committee_cfg.threshold);
+        /// The (votes of the) members that were still available when we gave up.
+        available: usize,
 
-            let futures = available_members
-                .iter()
-                .map(|(_, member)| async {
-                    let rpc_ctx =
-                        RpcCtx::new(Some("2.0"), None, Some(member.address.clone()), None, None);
-                    json_rpc_request(
-                        &rpc_ctx,
-                        "round_1_signing",
-                        &[serde_json::value::to_raw_value(&bob_request).unwrap()],
-                    )
-                    .await
-                })
-                .collect_vec();
+        /// The members that failed to respond during the session, and their error.
+        failed: BTreeMap<Identifier, String>,
+    },
 
-            let round_1_responses = join_all(futures).await;
+    /// A threshold of members responded, but some of their signature shares don't verify.
+    InvalidShares {
+        threshold: usize,
 
-            for (idx, resp) in round_1_responses.into_iter().enumerate() {
-                let (member_id, member) = available_members[idx];
-                debug!("resp to 1st request from {:?}: {:?}", member_id, resp);
-                let resp = match resp {
-                    Ok(x) => x,
-                    Err(rpc_error) => {
-                        warn!("Round 1 error with {}, marking as disconnected and retrying round 1: {rpc_error}", member.address);
-                        let mut ms_w = self.member_status.write().unwrap();
-                        ms_w.mark_as_disconnected(member_id);
-                        continue 'retry;
-                    }
-                };
+        /// The number of members that responded with signature shares.
+        responded: usize,
 
-                if let Ok(response) =
-                    serde_json::from_str::<bitcoincore_rpc::jsonrpc::Response>(&resp)
-                {
-                    let resp: Round1Response = response.result()?;
+        /// The members whose signature shares don't verify.
+        invalid: Vec<Identifier>,
+    },
+}
 
-                    // store the commitment
-                    commitments_map.insert(*member_id, resp.commitments);
-                } else {
-                    warn!("Round 1 error with {}, marking as offline and retrying from round 1: deserialize error", member.address);
-                    let mut ms_w = self.member_status.write().unwrap();
-                    ms_w.mark_as_disconnected(member_id);
-                    continue 'retry;
+impl std::fmt::Display for ThresholdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotEnoughResponses {
+                threshold,
+                available,
+                failed,
+            } => {
+                write!(
+                    f,
+                    "not enough available signers: {available} of the {threshold} needed"
+                )?;
+                if !failed.is_empty() {
+                    let failed = failed
+                        .iter()
+                        .map(|(id, err)| format!("{id:?} ({err})"))
+                        .join(", ");
+                    write!(f, ", members that failed to respond: {failed}")?;
                 }
+                Ok(())
             }
+            Self::InvalidShares {
+                threshold,
+                responded,
+                invalid,
+            } => write!(
+                f,
+                "{responded} members responded (for a threshold of {threshold}), but the signature shares of {invalid:?} are invalid"
+            ),
+        }
+    }
+}
 
-            //
-            // Produce transaction and digest
-            //
-            let message =
-                get_digest_to_hash(&bob_request.prev_outs, &bob_request.tx, &smart_contract)?;
+impl std::error::Error for ThresholdError {}
 
-            //
-            // Round 2
-            //
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Member {
+    /// e.g. "127.0.0.1:8887", or "https://committee.example.com/committee/node3" for a node served under a path prefix
+    /// (see [crate::committee::path_prefix]).
+    pub address: String,
 
-            let mut signature_shares = BTreeMap::new();
+    /// The votes of the member in the quorum policy of the committee (1 if not set):
+    /// signers are picked until their votes reach the threshold of the config (see [Orchestrator::select_signers]).
+    /// This is a policy on top of the committee key, not a share of it: each member holds a single key share,
+    /// so votes can require more signers than the threshold of the key, never fewer (see [CommitteeConfig::validate_with_key]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub votes: Option<usize>,
+}
 
-            let round2_request = Round2Request {
-                txid: bob_request.txid()?,
-                proof_hash: bob_request.proof.hash(),
-                commitments_map: commitments_map.clone(),
-                message,
-            };
+impl Member {
+    pub fn votes(&self) -> usize {
+        self.votes.unwrap_or(1)
+    }
+}
 
-            let futures = available_members
-                .iter()
-                .map(|(_, member)| async {
-                    let rpc_ctx =
-                        RpcCtx::new(Some("2.0"), None, Some(member.address.clone()), None, None);
-                    json_rpc_request(
-                        &rpc_ctx,
-                        "round_2_signing",
-                        &[serde_json::value::to_raw_value(&round2_request)?],
-                    )
-                    .await
-                })
-                .collect_vec();
+pub struct Orchestrator {
+    pub pubkey_package: frost_secp256k1_tr::keys::PublicKeyPackage,
+    pub committee_cfg: CommitteeConfig,
+    pub member_status: Arc<RwLock<MemberStatusState>>,
+    compliance: Arc<Compliance>,
 
-            let round_2_responses = join_all(futures).await;
+    /// The chain (usually a bitcoind node) to check that zkapps are still unspent before signing
+    /// (see [Orchestrator::with_chain_backend]).
+    chain: Option<Arc<dyn ChainBackend>>,
 
-            for (idx, resp) in round_2_responses.into_iter().enumerate() {
-                let (member_id, member) = available_members[idx];
-                debug!("resp to 2nd request from {:?}: {:?}", member_id, resp);
-                let resp = match resp {
-                    Ok(x) => x,
-                    Err(rpc_error) => {
-                        warn!("Round 2 error with {}, marking as offline and retrying from round 1: {rpc_error}", member.address);
-                        let mut ms_w = self.member_status.write().unwrap();
-                        ms_w.mark_as_offline(member_id);
-                        continue 'retry;
-                    }
-                };
+    /// Whether the transactions signed are broadcast (see [Orchestrator::with_broadcast]).
+    broadcast: bool,
 
-                if let Ok(response) =
-                    serde_json::from_str::<bitcoincore_rpc::jsonrpc::Response>(&resp)
-                {
-                    let round2_response: Round2Response = response.result()?;
+    /// The tips of the chain, to watch the transactions broadcast for reorgs (see [Orchestrator::with_chain_tips]).
+    chain_tips: Option<watch::Receiver<Option<ChainTip>>>,
 
-                    // store the commitment
-                    signature_shares.insert(*member_id, round2_response.signature_share);
-                } else {
-                    warn!("Round 2 error with {}, marking as offline and retrying from round 1: deserialize error", member.address);
-                    let mut ms_w = self.member_status.write().unwrap();
-                    ms_w.mark_as_offline(member_id);
-                    continue 'retry;
-                }
-            }
+    /// The number of confirmations a zkapp needs before the committee signs a spend of it.
+    min_zkapp_confirmations: u64,
 
-            //
-            // Aggregate signatures
-            //
+    /// The service fee spends must pay to the zkBitcoin fund (see [Orchestrator::with_fee_policy]).
+    fee_policy: FeePolicy,
 
-            debug!("- aggregate signature shares");
-            let signing_package =
-                frost_secp256k1_tr::SigningPackage::new(commitments_map, &message);
-            let group_signature = {
-                let res = frost_secp256k1_tr::aggregate(
-                    &signing_package,
-                    &signature_shares,
-                    &self.pubkey_package,
-                );
-                if let Some(err) = res.err() {
-                    error!("error: {}", err);
-                }
-                res.context("failed to aggregate signatures")?
-            };
+    /// How the feerate that spends must pay is picked (see [Orchestrator::with_fee_strategy]).
+    fee_strategy: FeeStrategy,
 
-            #[cfg(debug_assertions)]
-            {
-                // verify using FROST
-                let group_pubkey = self.pubkey_package.verifying_key();
-                assert!(group_pubkey.verify(&message, &group_signature).is_ok());
-                debug!("- the signature verified locally with FROST lib");
-
-                // assert that the pubkey is the same
-                let deserialized_pubkey =
-                    bitcoin::PublicKey::from_slice(&group_pubkey.serialize()).unwrap();
-                let zkbitcoin_pubkey: bitcoin::PublicKey =
-                    bitcoin::PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
-                assert_eq!(deserialized_pubkey, zkbitcoin_pubkey);
-
-                // let's compare pubkeys
-                {
-                    // from hardcoded
-                    let secp = secp256k1::Secp256k1::default();
-                    let zkbitcoin_pubkey: bitcoin::PublicKey =
-                        bitcoin::PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
-                    let internal_key = UntweakedPublicKey::from(zkbitcoin_pubkey);
-                    let (tweaked, _) = internal_key.tap_tweak(&secp, None);
-                    let tweaked = tweaked.to_string();
-                    debug!("tweaked: {}", tweaked);
-
-                    // from FROST
-                    let xone = XOnlyPublicKey::from_slice(&group_pubkey.serialize()[1..]).unwrap();
-                    let (tweaked2, _) = xone.tap_tweak(&secp, None);
-                    let tweaked2 = tweaked2.to_string();
-                    debug!("tweaked2: {}", tweaked2);
-                    assert_eq!(tweaked, tweaked2);
-
-                    // tweaked
-                    let tweaked3 = frost_secp256k1_tr::Secp256K1Sha256::tweaked_public_key(
-                        group_pubkey.element(),
-                    );
-                    let s = <frost_secp256k1_tr::Secp256K1Sha256 as Ciphersuite>::Group::serialize(
-                        &tweaked3,
-                    );
-                    let tweaked3 = s.to_lower_hex_string();
-                    debug!("tweaked3: {}", tweaked3);
-                    //assert_eq!(tweaked2, tweaked3);
-                }
+    zkapp_cache: ZkappUtxoCache,
 
-                // verify using bitcoin lib
-                let sig =
-                    secp256k1::schnorr::Signature::from_slice(&group_signature.serialize()[1..])
-                        .unwrap();
-                let zkbitcoin_pubkey: bitcoin::PublicKey =
-                    bitcoin::PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
-                let internal_key = UntweakedPublicKey::from(zkbitcoin_pubkey);
-                let secp = secp256k1::Secp256k1::default();
-                let (tweaked, _) = internal_key.tap_tweak(&secp, None);
-                let msg = secp256k1::Message::from_digest(message);
-                assert!(secp.verify_schnorr(&sig, &msg, &tweaked.into()).is_ok());
-                debug!("- the signature verified locally with bitcoin lib");
-            }
+    /// The verifier keys already checked against the zkapps they were given for (see [Orchestrator::with_vk_cache_size]).
+    vk_cache: VkCache,
 
-            //
-            // Include signature in the witness of the transaction
-            //
+    /// Keeps track of the members that keep failing signing sessions (see [Orchestrator::with_reputation]).
+    reputation: Mutex<ReputationStore>,
 
-            debug!("- include signature in witness of transaction");
-            let serialized = group_signature.serialize();
-            debug!("- serialized: {:?}", serialized);
-            let sig = secp256k1::schnorr::Signature::from_slice(&serialized[1..])
-                .context("couldn't convert signature type")?;
+    /// Groups the calls that concurrent signing sessions make to the same member (see [Orchestrator::with_batch_window]).
+    batcher: Batcher,
 
-            let hash_ty = TapSighashType::All;
-            let final_signature = taproot::Signature { sig, hash_ty };
-            let mut witness = Witness::new();
-            witness.push(final_signature.to_vec());
+    /// The signing sessions that finished recently (see [Orchestrator::with_session_history]).
+    history: Arc<Mutex<SessionHistory>>,
 
-            // return the signed transaction
-            return Ok(BobResponse {
-                unlocked_tx: bob_request.unlocked_tx(witness)?,
-            });
-        }
-    }
+    /// The signing sessions that are running.
+    active_sessions: Arc<Mutex<HashMap<String, Arc<ActiveSession>>>>,
+
+    /// The committee inputs spent by the running signing sessions (and the session spending them),
+    /// so that two sessions never sign conflicting transactions.
+    reserved_inputs: Arc<Mutex<HashMap<OutPoint, String>>>,
+
+    /// How long a signing session can run before it's abandoned (see [Orchestrator::with_session_timeout]).
+    session_timeout: Duration,
+
+    /// Tells operators when things go wrong (see [Orchestrator::with_alerts]).
+    alerts: Arc<Alerts>,
+
+    /// The key that members encrypt their signature shares to (see [Orchestrator::with_share_key]).
+    share_key: Option<ShareKey>,
 }
 
-//
-// Server logic
-//
+/// A running signing session.
+struct ActiveSession {
+    /// The zkapp being spent (if the request makes sense).
+    zkapp_outpoint: Option<OutPoint>,
 
-/// Bob's request to unlock funds from a smart contract.
-async fn unlock_funds(
-    params: Params<'static>,
-    context: Arc<Orchestrator>,
-) -> RpcResult<BobResponse> {
-    // get bob request
-    let bob_request: [BobRequest; 1] = params.parse()?;
-    let bob_request = &bob_request[0];
-    info!("received request: {:?}", bob_request);
+    /// When the session started (in seconds since the epoch).
+    started_at: u64,
 
-    let bob_response = context.handle_request(bob_request).await.map_err(|e| {
-        ErrorObjectOwned::owned(
-            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-            "error while unlocking funds",
-            Some(format!("the request didn't validate: {e}")),
-        )
-    })?;
+    /// Notified when the client cancels the session (see [Orchestrator::cancel_session]).
+    cancelled: Notify,
 
-    RpcResult::Ok(bob_response)
+    /// Notified when the session runs past the signing timeout (see [SessionReaper]).
+    abandoned: Notify,
+
+    /// What happened so far (see [Orchestrator::session_events]).
+    progress: Arc<SessionProgress>,
 }
 
-async fn get_nodes_status(
-    _params: Params<'static>,
-    context: Arc<Orchestrator>,
-) -> RpcResult<StatusResponse> {
-    let (online, offline) = {
-        let mss_r = context.member_status.read().unwrap();
-        mss_r.get_status()
-    };
+/// Unregisters a running session when it finishes (or when the request handling it is dropped),
+/// and releases the inputs it reserved.
+struct ActiveSessionGuard<'a> {
+    active_sessions: &'a Mutex<HashMap<String, Arc<ActiveSession>>>,
+    reserved_inputs: &'a Mutex<HashMap<OutPoint, String>>,
+    session_id: &'a str,
+}
 
-    RpcResult::Ok(StatusResponse {
-        online_members: online,
-        offline_members: offline,
-    })
+impl Drop for ActiveSessionGuard<'_> {
+    fn drop(&mut self) {
+        self.reserved_inputs
+            .lock()
+            .unwrap()
+            .retain(|_, session_id| session_id != self.session_id);
+        self.active_sessions.lock().unwrap().remove(self.session_id);
+    }
 }
 
-pub async fn run_server(
-    address: Option<&str>,
-    pubkey_package: frost::PublicKeyPackage,
-    committee_cfg: CommitteeConfig,
-) -> Result<SocketAddr> {
-    let address = address.unwrap_or("127.0.0.1:6666");
-    info!("- starting orchestrator at address http://{address}");
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
 
-    let mut compliance = Compliance::new();
-    // Orchestrator should sync the sanction list before doing anything else
-    compliance.sync().await.expect("sync sanction list");
+/// Garbage-collects the stale signing sessions of an orchestrator (see [Orchestrator::session_reaper]):
+/// finished sessions past the retention period of the history are dropped,
+/// and running sessions past the signing timeout are abandoned (e.g. when they wait on a member that never answers).
+#[derive(Clone)]
+pub struct SessionReaper {
+    history: Arc<Mutex<SessionHistory>>,
+    active_sessions: Arc<Mutex<HashMap<String, Arc<ActiveSession>>>>,
+    session_timeout: Duration,
+}
 
-    // wrap in an Arc after the first sync so it can be used in multiple request contexts
-    let compliance: Arc<Compliance> = Arc::new(compliance);
+/// What a pass of the [SessionReaper] did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReapedSessions {
+    /// The number of finished sessions dropped from the history.
+    pub pruned: usize,
 
-    let member_status_state = Arc::new(RwLock::new(MemberStatusState::new(&committee_cfg).await));
-    let mss_thread_copy = member_status_state.clone();
-    tokio::spawn(async move { MemberStatusState::keepalive_thread(mss_thread_copy).await });
+    /// The number of running sessions abandoned.
+    pub abandoned: usize,
+}
 
-    let ctx = Orchestrator::new(
-        pubkey_package,
-        committee_cfg,
-        member_status_state,
-        Arc::clone(&compliance),
-    );
+impl SessionReaper {
+    pub fn reap(&self) -> ReapedSessions {
+        self.reap_at(now_secs())
+    }
 
-    // Sync sanction list in a parallel thread
-    compliance.start();
+    fn reap_at(&self, now: u64) -> ReapedSessions {
+        let pruned = self.history.lock().unwrap().prune_at(now);
 
-    let server = Server::builder()
-        .build(address.parse::<SocketAddr>()?)
-        .await?;
-    let mut module = RpcModule::new(ctx);
-    module.register_async_method("unlock_funds", unlock_funds)?;
-    module.register_async_method("status", get_nodes_status)?;
+        let mut abandoned = 0;
+        for (session_id, session) in self.active_sessions.lock().unwrap().iter() {
+            if now.saturating_sub(session.started_at) < self.session_timeout.as_secs() {
+                continue;
+            }
+            // the session unregisters itself once it's recorded, a permit is stored until then
+            session.abandoned.notify_one();
+            warn!(
+                "- abandoning signing session {session_id}, running for more than {}s",
+                self.session_timeout.as_secs()
+            );
+            abandoned += 1;
+        }
 
-    let addr = server.local_addr()?;
-    let handle = server.start(module);
+        ReapedSessions { pruned, abandoned }
+    }
 
-    handle.stopped().await;
+    /// Reaps stale sessions every `interval`, forever.
+    pub async fn run(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let reaped = self.reap();
+            if reaped.pruned > 0 {
+                info!("- dropped {} expired signing sessions", reaped.pruned);
+            }
+        }
+    }
+}
 
-    Ok(addr)
+impl Orchestrator {
+    pub fn new(
+        pubkey_package: frost_secp256k1_tr::keys::PublicKeyPackage,
+        committee_cfg: CommitteeConfig,
+        member_status: Arc<RwLock<MemberStatusState>>,
+        compliance: Arc<Compliance>,
+    ) -> Self {
+        Self {
+            pubkey_package,
+            committee_cfg,
+            member_status,
+            compliance,
+            chain: None,
+            broadcast: false,
+            chain_tips: None,
+            min_zkapp_confirmations: default_min_zkapp_confirmations(get_network()),
+            fee_policy: FeePolicy::default(),
+            fee_strategy: FeeStrategy::default(),
+            zkapp_cache: ZkappUtxoCache::default(),
+            vk_cache: VkCache::default(),
+            reputation: Mutex::new(ReputationStore::default()),
+            batcher: Batcher::new(Duration::from_millis(SIGNING_BATCH_WINDOW_MS)),
+            history: Arc::new(Mutex::new(SessionHistory::default())),
+            active_sessions: Arc::new(Mutex::new(HashMap::new())),
+            reserved_inputs: Arc::new(Mutex::new(HashMap::new())),
+            session_timeout: Duration::from_secs(SIGNING_SESSION_TIMEOUT_SECONDS),
+            alerts: Arc::new(Alerts::default()),
+            share_key: None,
+        }
+    }
+
+    /// Uses the given history (e.g. one persisted on disk) to record finished signing sessions.
+    pub fn with_session_history(mut self, history: SessionHistory) -> Self {
+        self.history = Arc::new(Mutex::new(history));
+        self
+    }
+
+    /// Sets how long a signing session can run before the [SessionReaper] abandons it.
+    pub fn with_session_timeout(mut self, session_timeout: Duration) -> Self {
+        self.session_timeout = session_timeout;
+        self
+    }
+
+    /// Alerts operators when signing sessions keep failing, when members go down, or when bitcoind can't be reached.
+    /// The same alerts should be given to the keepalive thread (see [MemberStatusState::keepalive_thread]).
+    pub fn with_alerts(mut self, alerts: Arc<Alerts>) -> Self {
+        self.alerts = alerts;
+        self
+    }
+
+    /// Decrypts the signature shares of the members that encrypt them (see [crate::committee::share_encryption]).
+    /// Members that weren't given the public key of `share_key` still send their shares in clear.
+    pub fn with_share_key(mut self, share_key: ShareKey) -> Self {
+        self.share_key = Some(share_key);
+        self
+    }
+
+    /// Returns the reaper of the stale sessions of this orchestrator, to run in the background (see [SessionReaper::run]).
+    pub fn session_reaper(&self) -> SessionReaper {
+        SessionReaper {
+            history: Arc::clone(&self.history),
+            active_sessions: Arc::clone(&self.active_sessions),
+            session_timeout: self.session_timeout,
+        }
+    }
+
+    /// Returns a finished signing session.
+    pub fn session(&self, session_id: &str) -> Option<SessionRecord> {
+        self.history.lock().unwrap().get(session_id).cloned()
+    }
+
+    /// Cancels a running signing session, which can only be done by the client that started it
+    /// (by revealing the token it sent with its request, see [BobRequest::session_token]).
+    /// Returns `false` if the session is not running (anymore).
+    pub fn cancel_session(&self, session_id: &str, session_token: &str) -> Result<bool> {
+        ensure!(
+            session_id_from_token(session_token) == session_id,
+            "the session token doesn't match the session"
+        );
+        let active_sessions = self.active_sessions.lock().unwrap();
+        let Some(session) = active_sessions.get(session_id) else {
+            return Ok(false);
+        };
+        // a permit is stored if the session isn't waiting yet, so it can't be missed
+        session.cancelled.notify_one();
+        info!("- signing session {session_id} was cancelled by its client");
+        Ok(true)
+    }
+
+    /// Returns the events of a session past `since_event`, waiting (up to `timeout`) for new ones if the session is running.
+    /// Returns `None` if the session is unknown (e.g. it didn't start yet).
+    pub async fn session_events(
+        &self,
+        session_id: &str,
+        since_event: usize,
+        timeout: Duration,
+    ) -> Option<SessionEvents> {
+        let progress = self
+            .active_sessions
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .map(|session| Arc::clone(&session.progress));
+        match progress {
+            Some(progress) => Some(progress.wait_since(since_event, timeout).await),
+            None => self
+                .session(session_id)
+                .map(|session| SessionEvents::since(&session.events, since_event)),
+        }
+    }
+
+    /// Lists the finished signing sessions matching `filter` (most recent first).
+    pub fn sessions(&self, filter: &SessionFilter) -> SessionPage {
+        self.history.lock().unwrap().list(filter)
+    }
+
+    /// Lists the running signing sessions (oldest first), followed by the most recent finished ones.
+    pub fn session_overview(&self) -> Vec<SessionSummary> {
+        let now = now_secs();
+        let mut sessions = self
+            .active_sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(session_id, session)| SessionSummary {
+                session_id: session_id.clone(),
+                state: SessionState::Running,
+                zkapp_outpoint: session.zkapp_outpoint,
+                started_at: session.started_at,
+                age_secs: now.saturating_sub(session.started_at),
+            })
+            .collect_vec();
+        sessions.sort_by_key(|session| session.started_at);
+
+        let finished = self.sessions(&SessionFilter::default());
+        sessions.extend(
+            finished
+                .sessions
+                .iter()
+                .map(|session| SessionSummary::new(session, now)),
+        );
+        sessions
+    }
+
+    /// Sets how long calls to a member are held back, waiting for calls from other signing sessions to batch them with.
+    pub fn with_batch_window(mut self, window: Duration) -> Self {
+        self.batcher = Batcher::new(window);
+        self
+    }
+
+    /// Sets the service fee that spends must pay to the zkBitcoin fund (spends that don't are rejected before signing).
+    pub fn with_fee_policy(mut self, fee_policy: FeePolicy) -> Self {
+        self.fee_policy = fee_policy;
+        self
+    }
+
+    /// Sets how the feerate that spends must pay is picked for each session
+    /// (estimates are asked to the chain given to [Orchestrator::with_chain_backend], if any).
+    pub fn with_fee_strategy(mut self, fee_strategy: FeeStrategy) -> Self {
+        self.fee_strategy = fee_strategy;
+        self
+    }
+
+    /// Sets the number of verifier keys kept ready to verify proofs (0 to check and prepare them for every request).
+    pub fn with_vk_cache_size(mut self, size: usize) -> Self {
+        self.vk_cache = VkCache::new(size);
+        self
+    }
+
+    /// Uses the given store (e.g. one persisted on disk) to keep track of the reputation of members.
+    pub fn with_reputation(mut self, reputation: ReputationStore) -> Self {
+        self.reputation = Mutex::new(reputation);
+        self
+    }
+
+    /// The members that are currently quarantined, and until when.
+    pub fn blacklist(&self) -> BTreeMap<Identifier, u64> {
+        self.reputation.lock().unwrap().blacklist()
+    }
+
+    /// Lifts the quarantine of a member (see [ReputationStore::clear]).
+    pub fn clear_member_blacklist(&self, id: &Identifier) -> bool {
+        self.reputation.lock().unwrap().clear(id)
+    }
+
+    /// Picks (at random) online members to run a signing session with, until their votes add up to the threshold.
+    /// Quarantined members are avoided (unless we don't have enough members without them),
+    /// and at most one member is picked per address, so that a single node never counts twice towards the threshold.
+    /// If there aren't enough of them, the error lists the members that `failed` to respond earlier in the session.
+    fn select_signers(
+        &self,
+        failed: &BTreeMap<Identifier, String>,
+    ) -> Result<Vec<(Identifier, &Member)>, ThresholdError> {
+        let mut available_members = {
+            let ms_r = self.member_status.read().unwrap();
+            self.committee_cfg
+                .members
+                .iter()
+                .filter(|(key, _)| ms_r.get_member_status(key) == MemberStatus::Online)
+                .map(|(id, member)| (*id, member))
+                .collect_vec()
+        };
+        available_members.shuffle(&mut rand::thread_rng());
+
+        let available_members = self
+            .reputation
+            .lock()
+            .unwrap()
+            .select(available_members, self.committee_cfg.threshold)
+            .into_iter()
+            .unique_by(|(_, member)| normalize_address(&member.address));
+
+        let mut signers = vec![];
+        let mut votes = 0;
+        for (id, member) in available_members {
+            if votes >= self.committee_cfg.threshold {
+                break;
+            }
+            votes += member.votes();
+            signers.push((id, member));
+        }
+        if votes < self.committee_cfg.threshold {
+            return Err(ThresholdError::NotEnoughResponses {
+                threshold: self.committee_cfg.threshold,
+                available: votes,
+                failed: failed.clone(),
+            });
+        }
+
+        Ok(signers)
+    }
+
+    /// Reshares the committee key to the `target` committee (see [crate::committee::reshare]):
+    /// asks enough members to deal their share, and collects their deals into a transcript for the new members.
+    /// Each dealer must have approved the same target, and we never see anything secret.
+    pub async fn reshare(&self, target: ReshareTarget) -> Result<ReshareTranscript> {
+        target.validate()?;
+        let dealers = self.select_signers(&BTreeMap::new())?;
+        let request = ReshareRequest {
+            target: target.clone(),
+            dealers: dealers.iter().map(|(id, _)| *id).collect(),
+        };
+        info!(
+            "resharing the committee key to a {}-of-{} committee, dealt by {:?}",
+            target.min_signers,
+            target.recipients.len(),
+            request.dealers
+        );
+
+        let params = [serde_json::value::to_raw_value(&request)?];
+        let deals = join_all(dealers.iter().map(|(id, member)| async {
+            let deal: ReshareDeal = call_member(&member.address, "reshare_deal", &params)
+                .await
+                .with_context(|| format!("{id:?} ({}) didn't deal its share", member.address))?;
+            ensure!(
+                deal.dealer == *id,
+                "{} dealt the share of {:?} instead of {id:?}",
+                member.address,
+                deal.dealer
+            );
+            Ok(deal)
+        }))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+
+        let transcript = ReshareTranscript {
+            verifying_key: *self.pubkey_package.verifying_key(),
+            target,
+            deals,
+        };
+        transcript.verify(&self.pubkey_package)?;
+        Ok(transcript)
+    }
+
+    fn record_failure(&self, id: &Identifier) {
+        self.reputation.lock().unwrap().record_failure(id);
+    }
+
+    /// Uses a bitcoind node to check that zkapps are unspent,
+    /// and have at least `min_zkapp_confirmations` confirmations, before signing a transaction spending them.
+    pub fn with_bitcoind(self, rpc_ctx: RpcCtx, min_zkapp_confirmations: u64) -> Self {
+        self.with_chain_backend(
+            Arc::new(BitcoindBackend::new(rpc_ctx)),
+            min_zkapp_confirmations,
+        )
+    }
+
+    /// Same as [Orchestrator::with_bitcoind], with another view of the chain (e.g. a fake one in tests).
+    pub fn with_chain_backend(
+        mut self,
+        chain: Arc<dyn ChainBackend>,
+        min_zkapp_confirmations: u64,
+    ) -> Self {
+        self.chain = Some(chain);
+        self.min_zkapp_confirmations = min_zkapp_confirmations;
+        self
+    }
+
+    /// Broadcasts the transactions it signs (with the chain backend), instead of only returning them to the user.
+    /// The zkapps are checked again right before, in case the chain was reorganized since they were verified.
+    pub fn with_broadcast(mut self) -> Self {
+        self.broadcast = true;
+        self
+    }
+
+    /// Watches the transactions it broadcasts for [REORG_WATCH_BLOCKS] blocks (given the tips of the chain,
+    /// e.g. from [crate::chain_tip::ChainTipTracker::subscribe]): if a reorg drops the deployment of their zkapp,
+    /// their session is marked as [SessionStatus::InvalidatedByReorg] (see [Orchestrator::rebroadcast_session]).
+    pub fn with_chain_tips(mut self, chain_tips: watch::Receiver<Option<ChainTip>>) -> Self {
+        self.chain_tips = Some(chain_tips);
+        self
+    }
+
+    /// Returns the zkapp at `outpoint` (or `None` if it's spent).
+    pub async fn zkapp_status(&self, outpoint: OutPoint) -> Result<Option<ZkappUtxo>> {
+        let chain = self
+            .chain
+            .as_deref()
+            .context("the orchestrator is not connected to a bitcoind node")?;
+        let res = self.zkapp_cache.get(chain, outpoint).await;
+        match &res {
+            Ok(_) => self.alerts.bitcoind_reachable(),
+            Err(err) => self.alerts.bitcoind_error(err),
+        }
+        res
+    }
+
+    /// Handles bob request from A to Z.
+    /// Requests that make it to a signing ceremony are recorded in the session history (whatever their outcome).
+    pub async fn handle_request(&self, bob_request: &BobRequest) -> Result<BobResponse> {
+        // the members don't need the token (which would allow them to cancel the session)
+        let mut bob_request = bob_request.clone();
+        let session_id = match bob_request.session_token.take() {
+            Some(session_token) => session_id_from_token(&session_token),
+            None => uuid::Uuid::new_v4().to_string(),
+        };
+        // but they log the correlation id along with everything they do for this request
+        match &bob_request.correlation_id {
+            Some(correlation_id) => check_correlation_id(correlation_id)?,
+            None => bob_request.correlation_id = Some(new_correlation_id()),
+        }
+        info!(
+            "[{}] - starting signing session {session_id}",
+            bob_request.log_id()
+        );
+
+        let signers = Mutex::new(vec![]);
+        let progress = Arc::new(SessionProgress::default());
+        let signing = self.sign_request(&session_id, &bob_request, &signers, &progress);
+        let span = info_span!(
+            "signing_session",
+            session_id = %session_id,
+            correlation_id = bob_request.log_id()
+        );
+        self.run_session(&session_id, &bob_request, &signers, &progress, signing)
+            .instrument(span)
+            .await
+    }
+
+    /// Runs `signing` until it finishes, or until the client cancels the session (see [Orchestrator::cancel_session]).
+    /// Sessions that reached the committee (or were cancelled) are then recorded in the session history,
+    /// along with their `progress`.
+    async fn run_session(
+        &self,
+        session_id: &str,
+        bob_request: &BobRequest,
+        signers: &Mutex<Vec<Identifier>>,
+        progress: &Arc<SessionProgress>,
+        signing: impl Future<Output = Result<BobResponse>>,
+    ) -> Result<BobResponse> {
+        let started_at = now_secs();
+
+        let session = Arc::new(ActiveSession {
+            zkapp_outpoint: bob_request.zkapp_outpoint().ok(),
+            started_at,
+            cancelled: Notify::new(),
+            abandoned: Notify::new(),
+            progress: Arc::clone(progress),
+        });
+        // the committee inputs the transaction spends (requests that don't make sense get rejected later)
+        let inputs: Vec<OutPoint> = bob_request
+            .zkapp_requests()
+            .and_then(|requests| requests.iter().map(BobRequest::zkapp_outpoint).collect())
+            .unwrap_or_else(|_| vec![]);
+        {
+            let mut active_sessions = self.active_sessions.lock().unwrap();
+            ensure!(
+                !active_sessions.contains_key(session_id) && self.session(session_id).is_none(),
+                "session {session_id} already exists (session tokens can't be reused)"
+            );
+
+            // refuse to sign a transaction conflicting with one being signed
+            // (the error can be downcast to [InputReserved])
+            let mut reserved_inputs = self.reserved_inputs.lock().unwrap();
+            if let Some((outpoint, other_session_id)) = inputs
+                .iter()
+                .find_map(|outpoint| reserved_inputs.get_key_value(outpoint))
+            {
+                warn!(
+                    "[{}] - refusing session {session_id}: input {outpoint} is reserved by session {other_session_id}",
+                    bob_request.log_id()
+                );
+                return Err(InputReserved {
+                    outpoint: *outpoint,
+                }
+                .into());
+            }
+            for outpoint in inputs {
+                reserved_inputs.insert(outpoint, session_id.to_string());
+            }
+
+            active_sessions.insert(session_id.to_string(), Arc::clone(&session));
+        }
+        // the session stays registered until it's recorded in the history, so that subscribers never lose track of it
+        let _guard = ActiveSessionGuard {
+            active_sessions: &self.active_sessions,
+            reserved_inputs: &self.reserved_inputs,
+            session_id,
+        };
+        let res = tokio::select! {
+            res = signing => res,
+            _ = session.cancelled.notified() => Err(SessionCancelled.into()),
+            _ = session.abandoned.notified() => Err(SessionAbandoned { timeout: self.session_timeout }.into()),
+        };
+
+        let signers = std::mem::take(&mut *signers.lock().unwrap());
+        let was_cancelled = matches!(&res, Err(err) if err.is::<SessionCancelled>());
+        let was_abandoned = matches!(&res, Err(err) if err.is::<SessionAbandoned>());
+        if was_cancelled || was_abandoned {
+            self.discard_signing_tasks(bob_request, &signers);
+        }
+
+        // a signed transaction whose zkapp was reorged out is kept, to be rebroadcast later
+        let reorged = res
+            .as_ref()
+            .err()
+            .and_then(|err| err.downcast_ref::<ZkappReorged>());
+        let signed_tx = reorged.map(|reorged| reorged.signed_tx.clone());
+
+        let status = match &res {
+            Ok(_) => SessionStatus::Signed,
+            Err(_) if was_cancelled => SessionStatus::Cancelled,
+            Err(_) if was_abandoned => SessionStatus::Abandoned,
+            Err(_) if reorged.is_some() => SessionStatus::InvalidatedByReorg,
+            Err(_) => SessionStatus::Failed,
+        };
+        let txid = match &res {
+            Ok(resp) => Some(resp.unlocked_tx.txid()),
+            Err(_) => signed_tx.as_ref().map(Transaction::txid),
+        };
+        if let Some(reorged) = reorged {
+            progress.push(SessionEvent::InvalidatedByReorg {
+                zkapp_outpoint: reorged.zkapp_outpoint,
+            });
+        }
+        progress.push(SessionEvent::Finished { status, txid });
+
+        // requests that got rejected before reaching the committee are not sessions
+        // (but cancelled ones are recorded, so that the client can check that its cancellation went through,
+        // and so are abandoned ones, which got stuck somewhere)
+        if signers.is_empty() && !was_cancelled && !was_abandoned {
+            return res;
+        }
+        match &res {
+            Ok(_) => self.alerts.session_signed(),
+            Err(err) if !was_cancelled => self.alerts.session_failed(session_id, err),
+            Err(_) => (),
+        }
+        let events = progress.events();
+        let session = SessionRecord {
+            session_id: session_id.to_string(),
+            correlation_id: bob_request.correlation_id.clone(),
+            request: RequestSummary::new(bob_request),
+            zkapp_outpoint: bob_request.zkapp_outpoint()?,
+            txid,
+            signed_tx,
+            members: signers,
+            started_at,
+            finished_at: now_secs(),
+            status,
+            error: res.as_ref().err().map(|err| format!("{err:#}")),
+            fee_rate: chosen_fee_rate(&events),
+            events,
+        };
+        info!(
+            "[{}] - signing session {} for {} finished: {:?}",
+            bob_request.log_id(),
+            session.session_id,
+            session.zkapp_outpoint,
+            session.status
+        );
+        self.history.lock().unwrap().record(session);
+
+        res
+    }
+
+    /// Tells the members of a cancelled session to discard their signing task (and thus their nonces).
+    /// This is done in the background, and only on a best-effort basis (members evict old tasks anyway).
+    fn discard_signing_tasks(&self, bob_request: &BobRequest, signers: &[Identifier]) {
+        let Ok(txid) = bob_request.txid() else {
+            return;
+        };
+        let proof_hash = bob_request.proof.hash();
+        let addresses = signers
+            .iter()
+            .filter_map(|id| self.committee_cfg.members.get(id))
+            .map(|member| member.address.clone())
+            .collect_vec();
+        // round 1 calls might still be waiting to be batched
+        let delay = self.batcher.window();
+        tokio::spawn(async move {
+            sleep(delay).await;
+            for address in addresses {
+                let res = async {
+                    let rpc_ctx = RpcCtx::builder().version("2.0").url(&address).build()?;
+                    let resp = json_rpc_request(
+                        &rpc_ctx,
+                        "discard_signing_task",
+                        &[
+                            serde_json::value::to_raw_value(&txid)?,
+                            serde_json::value::to_raw_value(&proof_hash)?,
+                        ],
+                    )
+                    .await?;
+                    Ok::<_, anyhow::Error>(resp)
+                }
+                .await;
+                if let Err(err) = res {
+                    warn!("couldn't tell {address} to discard its signing task: {err}");
+                }
+            }
+        });
+    }
+
+    /// Checks that the zkapps are still unspent and deep enough in the chain (if we have access to the chain).
+    /// If they're not, the error can be downcast to [ZkappAlreadySpent] (pointing to the transaction that spent it,
+    /// if it can be found) or [InsufficientConfirmations].
+    pub async fn check_zkapps(&self, zkapp_outpoints: &[OutPoint]) -> Result<()> {
+        let Some(chain) = self.chain.as_deref() else {
+            return Ok(());
+        };
+        for zkapp_outpoint in zkapp_outpoints {
+            let zkapp = self.zkapp_status(*zkapp_outpoint).await?;
+            if zkapp.is_none() {
+                return Err(self.already_spent(chain, *zkapp_outpoint).await.into());
+            }
+            check_zkapp_confirmations(
+                *zkapp_outpoint,
+                zkapp.as_ref(),
+                self.min_zkapp_confirmations,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Finds the transaction that spent a zkapp: the one the committee signed (see [SessionHistory::spent_by]),
+    /// or the one the chain knows about. Failed lookups are only logged, as the zkapp is spent either way.
+    async fn already_spent(
+        &self,
+        chain: &dyn ChainBackend,
+        outpoint: OutPoint,
+    ) -> ZkappAlreadySpent {
+        let signed = self.history.lock().unwrap().spent_by(&outpoint);
+        let spent_by = match signed {
+            Some(txid) => Some(txid),
+            None => chain.spending_tx(outpoint).await.unwrap_or_else(|err| {
+                debug!("couldn't find the transaction that spent {outpoint}: {err:#}");
+                None
+            }),
+        };
+        let confirmations = match spent_by {
+            Some(txid) => chain.confirmations(txid).await.unwrap_or_else(|err| {
+                debug!("couldn't get the confirmations of {txid}: {err:#}");
+                None
+            }),
+            None => None,
+        };
+        ZkappAlreadySpent {
+            outpoint,
+            spent_by,
+            confirmations,
+        }
+    }
+
+    /// Broadcasts a signed transaction spending `zkapp_outpoints`,
+    /// after checking the zkapps again (bypassing the cache): the chain might have been reorganized since they were verified.
+    pub async fn broadcast_signed(
+        &self,
+        unlocked_tx: &Transaction,
+        zkapp_outpoints: &[OutPoint],
+    ) -> Result<BroadcastOutcome> {
+        let chain = self
+            .chain
+            .as_deref()
+            .context("the orchestrator is not connected to a bitcoind node")?;
+        for zkapp_outpoint in zkapp_outpoints {
+            let zkapp = chain.zkapp_utxo(*zkapp_outpoint).await?;
+            if let Err(err) = check_zkapp_confirmations(
+                *zkapp_outpoint,
+                zkapp.as_ref(),
+                self.min_zkapp_confirmations,
+            ) {
+                return Err(ZkappReorged {
+                    zkapp_outpoint: *zkapp_outpoint,
+                    signed_tx: unlocked_tx.clone(),
+                    reason: err.to_string(),
+                }
+                .into());
+            }
+        }
+        chain
+            .broadcast(unlocked_tx)
+            .await
+            .context("the signed transaction was rejected by the network")
+    }
+
+    /// Broadcasts again the transaction signed in a session invalidated by a reorg (see [SessionStatus::InvalidatedByReorg]),
+    /// once its zkapp confirmed again, without a new signing ceremony. Rebroadcasting a transaction twice is harmless.
+    pub async fn rebroadcast_session(&self, session_id: &str) -> Result<BroadcastOutcome> {
+        let chain = self
+            .chain
+            .as_ref()
+            .context("the orchestrator is not connected to a bitcoind node")?;
+        let session = self
+            .session(session_id)
+            .with_context(|| format!("unknown session {session_id}"))?;
+        ensure!(
+            session.status == SessionStatus::InvalidatedByReorg,
+            "session {session_id} wasn't invalidated by a reorg"
+        );
+        let signed_tx = session
+            .signed_tx
+            .with_context(|| format!("session {session_id} lost its signed transaction"))?;
+
+        // the deployment of the zkapp must be back in the chain, deep enough
+        let zkapp_outpoint = session.zkapp_outpoint;
+        let confirmations = chain
+            .confirmations(zkapp_outpoint.txid)
+            .await?
+            .unwrap_or_default();
+        let need = self.min_zkapp_confirmations.max(1);
+        if confirmations < need {
+            return Err(InsufficientConfirmations {
+                have: confirmations,
+                need,
+            })
+            .with_context(|| format!("the zkapp {zkapp_outpoint} didn't confirm again yet"));
+        }
+
+        let outcome = chain
+            .broadcast(&signed_tx)
+            .await
+            .context("the signed transaction was rejected by the network")?;
+        info!(
+            "- rebroadcast transaction {} of session {session_id}",
+            outcome.txid()
+        );
+        self.history.lock().unwrap().update(session_id, |session| {
+            session.status = SessionStatus::Signed;
+            session.signed_tx = None;
+            session.push_event(SessionEvent::Rebroadcast {
+                txid: outcome.txid(),
+            });
+        });
+        self.spawn_reorg_watch(session_id, &signed_tx, vec![zkapp_outpoint]);
+        Ok(outcome)
+    }
+
+    /// Watches a transaction that was just broadcast for [REORG_WATCH_BLOCKS] blocks, in the background
+    /// (if the orchestrator follows the tips of the chain, see [Orchestrator::with_chain_tips]).
+    fn spawn_reorg_watch(
+        &self,
+        session_id: &str,
+        signed_tx: &Transaction,
+        zkapp_outpoints: Vec<OutPoint>,
+    ) {
+        let (Some(chain), Some(mut chain_tips)) = (self.chain.clone(), self.chain_tips.clone())
+        else {
+            return;
+        };
+        // (only the tips after the broadcast count)
+        chain_tips.borrow_and_update();
+        tokio::spawn(watch_for_reorg(
+            chain,
+            chain_tips,
+            Arc::clone(&self.history),
+            session_id.to_string(),
+            signed_tx.clone(),
+            zkapp_outpoints,
+        ));
+    }
+
+    /// Runs the signing ceremony for a request, keeping track of the members taking part in it in `signers`,
+    /// and of its steps in `progress`.
+    /// Every zkapp input of the transaction gets its own signature, within the same ceremony.
+    async fn sign_request(
+        &self,
+        session_id: &str,
+        bob_request: &BobRequest,
+        signers: &Mutex<Vec<Identifier>>,
+        progress: &SessionProgress,
+    ) -> Result<BobResponse> {
+        // Validate transaction before forwarding it, and get the smart contract of each zkapp input
+        bob_request
+            .check_compliance(Arc::clone(&self.compliance))
+            .await?;
+        progress.push(SessionEvent::RequestValidated);
+
+        // fail early if the network would reject the transaction, e.g. if its inputs don't cover its outputs and fee
+        // (before verifying proofs, or wasting a signing ceremony)
+        sanity_check_tx(
+            &bob_request.tx,
+            &bob_request.prev_outs,
+            &SanityPolicy::default(),
+        )
+        .context("the transaction to sign didn't pass sanity checks")?;
+
+        // the spend must pay the service fee
+        self.fee_policy
+            .check_spend(&bob_request.tx, &bob_request.prev_outs)
+            .context("the transaction doesn't follow the fee policy")?;
+
+        // and the feerate of the orchestrator
+        let fee_rate = self.fee_strategy.resolve(self.chain.as_deref()).await;
+        progress.push(SessionEvent::FeeRateChosen {
+            sat_per_vb: fee_rate.sat_per_vb,
+            source: fee_rate.source,
+        });
+        fee_rate
+            .check(&bob_request.tx, &bob_request.prev_outs)
+            .context("the transaction doesn't pay the feerate required by the orchestrator")?;
+
+        // check that the zkapps are still unspent and deep enough in the chain (if we have access to a bitcoin node),
+        // before verifying their proofs
+        let zkapp_outpoints = bob_request
+            .zkapp_requests()?
+            .iter()
+            .map(BobRequest::zkapp_outpoint)
+            .collect::<Result<Vec<_>>>()?;
+        self.check_zkapps(&zkapp_outpoints).await?;
+
+        let zkapp_inputs = bob_request
+            .validate_zkapps_with(&ProofLimits::default(), &self.vk_cache)
+            .instrument(info_span!("verify_proofs"))
+            .await?;
+        progress.push(SessionEvent::ProofVerified);
+
+        // the members that failed to respond, across attempts
+        let mut failed = BTreeMap::new();
+
+        'retry: loop {
+            //
+            // Round 1
+            //
+
+            // the commitments of each member, for each zkapp input
+            let mut commitments_maps = vec![BTreeMap::new(); zkapp_inputs.len()];
+
+            let available_members = self.select_signers(&failed)?;
+            *signers.lock().unwrap() = available_members.iter().map(|(id, _)| *id).collect();
+
+            let futures = available_members
+                .iter()
+                .map(|(_, member)| {
+                    member_call(
+                        1,
+                        member,
+                        self.batcher.call::<_, Round1Response>(
+                            &member.address,
+                            ROUND_1_SIGNING,
+                            bob_request,
+                        ),
+                    )
+                })
+                .collect_vec();
+
+            let round_1_responses = join_all(futures).await;
+
+            for (idx, resp) in round_1_responses.into_iter().enumerate() {
+                let (member_id, member) = &available_members[idx];
+                debug!(
+                    "[{}] resp to 1st request from {:?}: {:?}",
+                    bob_request.log_id(),
+                    member_id,
+                    resp
+                );
+                let resp = match resp {
+                    Ok(x) => x,
+                    Err(rpc_error) => {
+                        warn!("[{}] Round 1 error with {}, marking as disconnected and retrying round 1: {rpc_error}", bob_request.log_id(), member.address);
+                        failed.insert(*member_id, format!("round 1: {rpc_error}"));
+                        self.record_failure(member_id);
+                        let mut ms_w = self.member_status.write().unwrap();
+                        if ms_w.get_member_status(member_id) == MemberStatus::Online {
+                            self.alerts.member_down(
+                                *member_id,
+                                &member.address,
+                                &format!("round 1: {rpc_error}"),
+                            );
+                        }
+                        ms_w.mark_as_disconnected(member_id);
+                        continue 'retry;
+                    }
+                };
+                let resp = resp.map_err(|err| {
+                    anyhow!("{} rejected the signing request: {err}", member.address)
+                })?;
+
+                // store the commitments (a member only ever contributes one per input)
+                let commitments = resp.into_all_commitments();
+                ensure!(
+                    commitments.len() == zkapp_inputs.len(),
+                    "{member_id:?} contributed {} commitments for {} zkapp inputs",
+                    commitments.len(),
+                    zkapp_inputs.len()
+                );
+                for (commitments_map, commitments) in commitments_maps.iter_mut().zip(commitments) {
+                    ensure!(
+                        commitments_map.insert(*member_id, commitments).is_none(),
+                        "{member_id:?} contributed more than one commitment"
+                    );
+                }
+            }
+
+            progress.push(SessionEvent::Round1Complete {
+                members: available_members.iter().map(|(id, _)| *id).collect(),
+            });
+
+            //
+            // Produce transaction and digest of each zkapp input
+            //
+            let mut input_requests = vec![];
+            for (zkapp_input, commitments_map) in zkapp_inputs.iter().zip(commitments_maps) {
+                let message = get_digest_to_hash(
+                    &bob_request.prev_outs,
+                    &bob_request.tx,
+                    &zkapp_input.smart_contract,
+                    zkapp_input.sighash_type,
+                )?;
+                input_requests.push(InputSigningRequest {
+                    commitments_map,
+                    message,
+                    sighash_type: zkapp_input.sighash_type,
+                });
+            }
+
+            //
+            // Round 2
+            //
+
+            // the signature shares of each member, for each zkapp input
+            let mut signature_shares = vec![BTreeMap::new(); zkapp_inputs.len()];
+
+            let round2_request = {
+                let first = &input_requests[0];
+                Round2Request {
+                    correlation_id: bob_request.correlation_id.clone(),
+                    txid: bob_request.txid()?,
+                    proof_hash: bob_request.proof.hash(),
+                    commitments_map: first.commitments_map.clone(),
+                    message: first.message,
+                    sighash_type: first.sighash_type,
+                    other_inputs: input_requests[1..].to_vec(),
+                }
+            };
+
+            let futures = available_members
+                .iter()
+                .map(|(_, member)| {
+                    member_call(
+                        2,
+                        member,
+                        self.batcher.call::<_, Round2Reply>(
+                            &member.address,
+                            ROUND_2_SIGNING,
+                            &round2_request,
+                        ),
+                    )
+                })
+                .collect_vec();
+
+            let round_2_responses = join_all(futures).await;
+
+            for (idx, resp) in round_2_responses.into_iter().enumerate() {
+                let (member_id, member) = &available_members[idx];
+                debug!(
+                    "[{}] resp to 2nd request from {:?}: {:?}",
+                    bob_request.log_id(),
+                    member_id,
+                    resp
+                );
+                let resp = match resp {
+                    Ok(x) => x,
+                    Err(rpc_error) => {
+                        warn!("[{}] Round 2 error with {}, marking as offline and retrying from round 1: {rpc_error}", bob_request.log_id(), member.address);
+                        failed.insert(*member_id, format!("round 2: {rpc_error}"));
+                        self.record_failure(member_id);
+                        let mut ms_w = self.member_status.write().unwrap();
+                        if ms_w.get_member_status(member_id) == MemberStatus::Online {
+                            self.alerts.member_down(
+                                *member_id,
+                                &member.address,
+                                &format!("round 2: {rpc_error}"),
+                            );
+                        }
+                        ms_w.mark_as_offline(member_id);
+                        continue 'retry;
+                    }
+                };
+                let round2_reply = resp.map_err(|err| {
+                    anyhow!("{} rejected the signing request: {err}", member.address)
+                })?;
+
+                // store the signature shares (a member only ever contributes one per input)
+                let shares = round2_reply.into_all_signature_shares(
+                    self.share_key.as_ref(),
+                    &round2_request.txid,
+                    member_id,
+                )?;
+                ensure!(
+                    shares.len() == zkapp_inputs.len(),
+                    "{member_id:?} contributed {} signature shares for {} zkapp inputs",
+                    shares.len(),
+                    zkapp_inputs.len()
+                );
+                for (input_shares, share) in signature_shares.iter_mut().zip(shares) {
+                    ensure!(
+                        input_shares.insert(*member_id, share).is_none(),
+                        "{member_id:?} contributed more than one signature share"
+                    );
+                }
+            }
+
+            progress.push(SessionEvent::Round2Complete);
+
+            //
+            // Aggregate signatures
+            //
+
+            debug!("[{}] - aggregate signature shares", bob_request.log_id());
+            let group_signatures = match aggregate_or_blame(
+                &self.pubkey_package,
+                &input_requests,
+                &signature_shares,
+                self.committee_cfg.threshold,
+            ) {
+                Ok(group_signatures) => group_signatures,
+                Err(err) => {
+                    error!("[{}] error: {}", bob_request.log_id(), err);
+                    if let Some(ThresholdError::InvalidShares { invalid, .. }) =
+                        err.downcast_ref::<ThresholdError>()
+                    {
+                        for culprit in invalid {
+                            self.record_failure(culprit);
+                        }
+                    }
+                    return Err(err);
+                }
+            };
+            {
+                let mut reputation = self.reputation.lock().unwrap();
+                for (member_id, _) in &available_members {
+                    reputation.record_success(member_id);
+                }
+            }
+            progress.push(SessionEvent::SignatureVerified);
+
+            #[cfg(debug_assertions)]
+            for (input_request, group_signature) in input_requests.iter().zip(&group_signatures) {
+                let message = input_request.message;
+                {
+                    // verify using FROST
+                    let group_pubkey = self.pubkey_package.verifying_key();
+                    assert!(group_pubkey.verify(&message, group_signature).is_ok());
+                    debug!("- the signature verified locally with FROST lib");
+
+                    // assert that the pubkey is the same
+                    let deserialized_pubkey =
+                        bitcoin::PublicKey::from_slice(&group_pubkey.serialize()).unwrap();
+                    assert_eq!(deserialized_pubkey, zkbitcoin_pubkey()?);
+
+                    // let's compare pubkeys
+                    {
+                        // from hardcoded
+                        let secp = secp256k1::Secp256k1::default();
+                        let internal_key = UntweakedPublicKey::from(zkbitcoin_pubkey()?);
+                        let (tweaked, _) = internal_key.tap_tweak(&secp, None);
+                        let tweaked = tweaked.to_string();
+                        debug!("tweaked: {}", tweaked);
+
+                        // from FROST
+                        let xone =
+                            XOnlyPublicKey::from_slice(&group_pubkey.serialize()[1..]).unwrap();
+                        let (tweaked2, _) = xone.tap_tweak(&secp, None);
+                        let tweaked2 = tweaked2.to_string();
+                        debug!("tweaked2: {}", tweaked2);
+                        assert_eq!(tweaked, tweaked2);
+
+                        // tweaked
+                        let tweaked3 = frost_secp256k1_tr::Secp256K1Sha256::tweaked_public_key(
+                            group_pubkey.element(),
+                        );
+                        let s =
+                            <frost_secp256k1_tr::Secp256K1Sha256 as Ciphersuite>::Group::serialize(
+                                &tweaked3,
+                            );
+                        let tweaked3 = s.to_lower_hex_string();
+                        debug!("tweaked3: {}", tweaked3);
+                        //assert_eq!(tweaked2, tweaked3);
+                    }
+
+                    // verify using bitcoin lib
+                    let sig = secp256k1::schnorr::Signature::from_slice(
+                        &group_signature.serialize()[1..],
+                    )
+                    .unwrap();
+                    let internal_key = UntweakedPublicKey::from(zkbitcoin_pubkey()?);
+                    let secp = secp256k1::Secp256k1::default();
+                    let (tweaked, _) = internal_key.tap_tweak(&secp, None);
+                    let msg = secp256k1::Message::from_digest(message);
+                    assert!(secp.verify_schnorr(&sig, &msg, &tweaked.into()).is_ok());
+                    debug!("- the signature verified locally with bitcoin lib");
+                }
+            }
+
+            //
+            // Include the signatures in the witnesses of the transaction
+            //
+
+            debug!("- include signatures in witnesses of transaction");
+            let mut unlocked_tx = bob_request.tx.clone();
+            for ((zkapp_input, input_request), group_signature) in zkapp_inputs
+                .iter()
+                .zip(&input_requests)
+                .zip(&group_signatures)
+            {
+                unlocked_tx.input[zkapp_input.input_index].witness =
+                    witness_from_signature(group_signature, input_request.sighash_type)?;
+            }
+
+            // the zkapps are about to be spent
+            if self.broadcast {
+                let outcome = self
+                    .broadcast_signed(&unlocked_tx, &zkapp_outpoints)
+                    .await?;
+                info!(
+                    "[{}] - broadcast transaction {}",
+                    bob_request.log_id(),
+                    outcome.txid()
+                );
+                self.spawn_reorg_watch(session_id, &unlocked_tx, zkapp_outpoints.clone());
+            }
+            for zkapp_outpoint in &zkapp_outpoints {
+                self.zkapp_cache.invalidate(zkapp_outpoint);
+            }
+
+            // return the signed transaction
+            return Ok(BobResponse { unlocked_tx });
+        }
+    }
+}
+
+/// Whether the deployment of a zkapp is out of the chain (or out of the node entirely).
+async fn deployment_vanished(chain: &dyn ChainBackend, zkapp_outpoint: OutPoint) -> Result<bool> {
+    let confirmations = chain.confirmations(zkapp_outpoint.txid).await?;
+    Ok(matches!(confirmations, None | Some(0)))
+}
+
+/// Checks the zkapps spent by a broadcast transaction each time the tip of the chain changes, for [REORG_WATCH_BLOCKS] blocks.
+/// If the deployment of one of them vanished, the session is marked as [SessionStatus::InvalidatedByReorg]
+/// (keeping the signed transaction, to rebroadcast it once the zkapp confirms again).
+async fn watch_for_reorg(
+    chain: Arc<dyn ChainBackend>,
+    mut chain_tips: watch::Receiver<Option<ChainTip>>,
+    history: Arc<Mutex<SessionHistory>>,
+    session_id: String,
+    signed_tx: Transaction,
+    zkapp_outpoints: Vec<OutPoint>,
+) {
+    for _ in 0..REORG_WATCH_BLOCKS {
+        if chain_tips.changed().await.is_err() {
+            // nobody tracks the tip anymore
+            return;
+        }
+        for zkapp_outpoint in &zkapp_outpoints {
+            match deployment_vanished(chain.as_ref(), *zkapp_outpoint).await {
+                Ok(false) => continue,
+                Ok(true) => (),
+                Err(err) => {
+                    debug!("couldn't check the deployment of {zkapp_outpoint}: {err:#}");
+                    continue;
+                }
+            }
+            warn!(
+                "- the zkapp {zkapp_outpoint} spent by {} (session {session_id}) was reorganized out of the chain",
+                signed_tx.txid()
+            );
+            let updated = history.lock().unwrap().update(&session_id, |session| {
+                session.status = SessionStatus::InvalidatedByReorg;
+                session.signed_tx = Some(signed_tx.clone());
+                session.push_event(SessionEvent::InvalidatedByReorg {
+                    zkapp_outpoint: *zkapp_outpoint,
+                });
+            });
+            // (the session is recorded right after the broadcast, so it's there unless it was pruned)
+            if updated.is_some() {
+                return;
+            }
+        }
+    }
+}
+
+/// Aggregates the signature shares of each zkapp input (in input order).
+pub(crate) fn aggregate_signatures(
+    pubkey_package: &frost::PublicKeyPackage,
+    input_requests: &[InputSigningRequest],
+    signature_shares: &[BTreeMap<Identifier, frost_secp256k1_tr::round2::SignatureShare>],
+) -> Result<Vec<frost_secp256k1_tr::Signature>, frost_secp256k1_tr::Error> {
+    input_requests
+        .iter()
+        .zip(signature_shares)
+        .map(|(input_request, shares)| {
+            let signing_package = frost_secp256k1_tr::SigningPackage::new(
+                input_request.commitments_map.clone(),
+                &input_request.message,
+            );
+            frost_secp256k1_tr::aggregate(&signing_package, shares, pubkey_package)
+        })
+        .collect()
+}
+
+/// Aggregates the signature shares of each input (see [aggregate_signatures]).
+/// If some shares don't verify, the error is a [ThresholdError::InvalidShares] listing their members
+/// (aggregation stops at the first invalid share of each input, so a member could go unnoticed
+/// if another member's share of the same input is invalid).
+pub(crate) fn aggregate_or_blame(
+    pubkey_package: &frost::PublicKeyPackage,
+    input_requests: &[InputSigningRequest],
+    signature_shares: &[BTreeMap<Identifier, frost_secp256k1_tr::round2::SignatureShare>],
+    threshold: usize,
+) -> Result<Vec<frost_secp256k1_tr::Signature>> {
+    let mut group_signatures = vec![];
+    let mut invalid = vec![];
+    for (input_request, shares) in input_requests.iter().zip(signature_shares) {
+        match aggregate_signatures(
+            pubkey_package,
+            std::slice::from_ref(input_request),
+            std::slice::from_ref(shares),
+        ) {
+            Ok(signatures) => group_signatures.extend(signatures),
+            Err(frost_secp256k1_tr::Error::InvalidSignatureShare { culprit }) => {
+                invalid.push(culprit)
+            }
+            Err(err) => return Err(anyhow!(err).context("failed to aggregate signatures")),
+        }
+    }
+
+    if !invalid.is_empty() {
+        invalid.sort();
+        invalid.dedup();
+        let responded = signature_shares
+            .iter()
+            .flat_map(|shares| shares.keys())
+            .unique()
+            .count();
+        return Err(ThresholdError::InvalidShares {
+            threshold,
+            responded,
+            invalid,
+        }
+        .into());
+    }
+    Ok(group_signatures)
+}
+
+/// The witness spending a committee output with a (key path) signature of the committee.
+pub(crate) fn witness_from_signature(
+    group_signature: &frost_secp256k1_tr::Signature,
+    sighash_type: bitcoin::TapSighashType,
+) -> Result<Witness> {
+    let serialized = group_signature.serialize();
+    debug!("- serialized: {:?}", serialized);
+    let sig = secp256k1::schnorr::Signature::from_slice(&serialized[1..])
+        .context("couldn't convert signature type")?;
+
+    let final_signature = taproot::Signature {
+        sig,
+        hash_ty: sighash_type,
+    };
+    let mut witness = Witness::new();
+    witness.push(final_signature.to_vec());
+    Ok(witness)
+}
+
+/// Runs a call to `member` for a FROST `round` in its own span, recording how long the member took to answer.
+async fn member_call<T>(round: u8, member: &Member, call: impl Future<Output = T>) -> T {
+    let span = info_span!(
+        "frost_round",
+        round,
+        member = %member.address,
+        duration_ms = tracing::field::Empty
+    );
+    let start = Instant::now();
+    let res = call.instrument(span.clone()).await;
+    span.record("duration_ms", start.elapsed().as_millis() as u64);
+    res
+}
+
+//
+// Server logic
+//
+
+/// Bob's request to unlock funds from a smart contract.
+async fn unlock_funds(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<BobResponse> {
+    // get bob request
+    let [mut bob_request]: [BobRequest; 1] = params.parse()?;
+
+    // tie everything we (and the committee) log about it together, and let the client quote it in case of errors
+    if let Some(correlation_id) = &bob_request.correlation_id {
+        check_correlation_id(correlation_id).map_err(|e| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "invalid correlation id",
+                Some(format!("{e}")),
+            )
+        })?;
+    }
+    let correlation_id = bob_request
+        .correlation_id
+        .get_or_insert_with(new_correlation_id)
+        .clone();
+    info!("[{correlation_id}] received request: {:?}", bob_request);
+
+    let bob_response = context.handle_request(&bob_request).await.map_err(|e| {
+        if let Some(err) = e.downcast_ref::<InsufficientConfirmations>() {
+            return ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                format!("{err} (correlation id {correlation_id})"),
+                Some(err.clone()),
+            );
+        }
+        if let Some(err) = e.downcast_ref::<ThresholdError>() {
+            return ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                format!("{err} (correlation id {correlation_id})"),
+                Some(err.clone()),
+            );
+        }
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            format!("error while unlocking funds (correlation id {correlation_id})"),
+            Some(format!("the request didn't validate: {e}")),
+        )
+    })?;
+
+    RpcResult::Ok(bob_response)
+}
+
+/// The sighashes the committee would sign for a request (see [BobRequest::sighash_preview]), before any signing happens.
+async fn preview_sighashes(
+    params: Params<'static>,
+    _context: Arc<Orchestrator>,
+) -> RpcResult<SighashPreview> {
+    let [bob_request]: [BobRequest; 1] = params.parse()?;
+    bob_request.sighash_preview().map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while computing the sighashes",
+            Some(format!("{e}")),
+        )
+    })
+}
+
+async fn get_nodes_status(
+    _params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<StatusResponse> {
+    let (online, offline) = {
+        let mss_r = context.member_status.read().unwrap();
+        mss_r.get_status()
+    };
+
+    RpcResult::Ok(StatusResponse {
+        online_members: online,
+        offline_members: offline,
+        blacklisted_members: context.blacklist(),
+        bitcoind_queue_waits: context.chain.as_ref().and_then(|chain| chain.queue_waits()),
+    })
+}
+
+/// Lifts the quarantine of a member, returns `false` if it wasn't quarantined (or failing).
+async fn clear_member_blacklist(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<bool> {
+    let [id]: [Identifier; 1] = params.parse()?;
+    RpcResult::Ok(context.clear_member_blacklist(&id))
+}
+
+/// The status of a zkapp (`null` if it's spent).
+async fn get_zkapp_status(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<Option<ZkappUtxo>> {
+    let [outpoint]: [OutPoint; 1] = params.parse()?;
+
+    context.zkapp_status(outpoint).await.map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while getting the zkapp status",
+            Some(format!("{e}")),
+        )
+    })
+}
+
+async fn get_committee_info(
+    _params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<CommitteeInfo> {
+    CommitteeInfo::new(&context.pubkey_package, &context.committee_cfg).map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while getting committee info",
+            Some(format!("{e}")),
+        )
+    })
+}
+
+/// The hits and misses of the cache of verifier keys.
+async fn vk_cache_stats(
+    _params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<VkCacheStats> {
+    RpcResult::Ok(context.vk_cache.stats())
+}
+
+/// A finished signing session (`null` if it's unknown, or was pruned).
+async fn get_session(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<Option<SessionRecord>> {
+    let [session_id]: [String; 1] = params.parse()?;
+    RpcResult::Ok(context.session(&session_id))
+}
+
+/// Cancels a running signing session, given its id and the token its client sent with its request.
+async fn cancel_session(params: Params<'static>, context: Arc<Orchestrator>) -> RpcResult<bool> {
+    let [session_id, session_token]: [String; 2] = params.parse()?;
+    context
+        .cancel_session(&session_id, &session_token)
+        .map_err(|e| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "error while cancelling the session",
+                Some(format!("{e}")),
+            )
+        })
+}
+
+/// Rebroadcasts the transaction of a session invalidated by a reorg (see [Orchestrator::rebroadcast_session]).
+async fn rebroadcast_session(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<Txid> {
+    let [session_id]: [String; 1] = params.parse()?;
+    context
+        .rebroadcast_session(&session_id)
+        .await
+        .map(|outcome| outcome.txid())
+        .map_err(|e| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "error while rebroadcasting the session",
+                Some(format!("{e:#}")),
+            )
+        })
+}
+
+/// Long-polls the events of a session past a cursor (see [SessionEvents]), `null` if the session is unknown.
+async fn subscribe_session(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<Option<SessionEvents>> {
+    let (session_id, since_event): (String, usize) = params.parse()?;
+    let timeout = Duration::from_secs(SESSION_EVENTS_LONG_POLL_SECONDS);
+    RpcResult::Ok(
+        context
+            .session_events(&session_id, since_event, timeout)
+            .await,
+    )
+}
+
+/// The finished signing sessions matching a filter (see [SessionFilter]).
+async fn list_sessions(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<SessionPage> {
+    let [filter]: [SessionFilter; 1] = params.parse()?;
+    RpcResult::Ok(context.sessions(&filter))
+}
+
+/// The running signing sessions and the most recent finished ones, with their state and age
+/// (also served as `GET /sessions`).
+async fn session_overview(
+    _params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<Vec<SessionSummary>> {
+    RpcResult::Ok(context.session_overview())
+}
+
+/// Reshares the committee key to a new committee (see [Orchestrator::reshare]).
+async fn reshare_committee(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<ReshareTranscript> {
+    let [target]: [ReshareTarget; 1] = params.parse()?;
+    context.reshare(target).await.map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while resharing the committee",
+            Some(format!("{e:#}")),
+        )
+    })
+}
+
+/// How to run the orchestrator of a committee (see [start_server]).
+pub struct OrchestratorConfig {
+    /// A bitcoind node (with the minimum number of confirmations a zkapp needs),
+    /// to check that zkapps are unspent before signing (see [Orchestrator::with_bitcoind]).
+    pub bitcoind: Option<(RpcCtx, u64)>,
+
+    /// Where the reputation of members and the history of signing sessions are persisted (they're kept in memory otherwise).
+    pub state_dir: Option<PathBuf>,
+
+    /// How long requests wait for others to be signed with (see [Orchestrator::with_batch_window]).
+    pub batch_window: Duration,
+
+    /// How long signing sessions are kept in the history.
+    pub session_retention: Duration,
+
+    /// How long signing sessions can run before they're abandoned (see [SessionReaper]).
+    pub session_timeout: Duration,
+
+    /// The service fee spends must pay (see [Orchestrator::with_fee_policy]).
+    pub fee_policy: FeePolicy,
+
+    /// Picks the feerate spends must pay (see [Orchestrator::with_fee_strategy]).
+    pub fee_strategy: FeeStrategy,
+
+    /// The origins browsers can call the orchestrator from (CORS is disabled if not set).
+    pub cors_origins: Option<CorsOrigins>,
+
+    /// How operators are notified of failures (see [Orchestrator::with_alerts]).
+    pub alerts: Alerts,
+
+    /// How many members must answer pings for the orchestrator to report ready (see [crate::committee::readiness]).
+    pub ready_quorum: ReadyQuorum,
+
+    /// The key members can encrypt their signature shares to (see [Orchestrator::with_share_key]).
+    pub share_key: Option<ShareKey>,
+
+    /// Where transactions are broadcast (in order) if the bitcoind node refuses them
+    /// (see [BitcoindBackend::with_broadcast_fallbacks]).
+    pub broadcast_fallbacks: Vec<BroadcastTarget>,
+}
+
+impl Default for OrchestratorConfig {
+    fn default() -> Self {
+        Self {
+            bitcoind: None,
+            state_dir: None,
+            batch_window: Duration::from_millis(SIGNING_BATCH_WINDOW_MS),
+            session_retention: Duration::from_secs(SESSION_HISTORY_RETENTION_DAYS * 24 * 60 * 60),
+            session_timeout: Duration::from_secs(SIGNING_SESSION_TIMEOUT_SECONDS),
+            fee_policy: FeePolicy::default(),
+            fee_strategy: FeeStrategy::default(),
+            cors_origins: None,
+            alerts: Alerts::default(),
+            ready_quorum: ReadyQuorum::default(),
+            share_key: None,
+            broadcast_fallbacks: vec![],
+        }
+    }
+}
+
+/// Runs the orchestrator until it's stopped (see [start_server]).
+pub async fn run_server(
+    address: Option<&str>,
+    pubkey_package: frost::PublicKeyPackage,
+    committee_cfg: CommitteeConfig,
+    config: OrchestratorConfig,
+) -> Result<SocketAddr> {
+    let (addr, handle) = start_server(address, pubkey_package, committee_cfg, config).await?;
+
+    handle.stopped().await;
+
+    Ok(addr)
+}
+
+/// Binds the orchestrator to `address` and starts serving in the background (until the returned handle is stopped or dropped),
+/// returning the address it actually listens on (e.g. when binding to port 0).
+pub async fn start_server(
+    address: Option<&str>,
+    pubkey_package: frost::PublicKeyPackage,
+    committee_cfg: CommitteeConfig,
+    config: OrchestratorConfig,
+) -> Result<(SocketAddr, ServerHandle)> {
+    let OrchestratorConfig {
+        bitcoind,
+        state_dir,
+        batch_window,
+        session_retention,
+        session_timeout,
+        fee_policy,
+        fee_strategy,
+        cors_origins,
+        alerts,
+        ready_quorum,
+        share_key,
+        broadcast_fallbacks,
+    } = config;
+    let address = address.unwrap_or("127.0.0.1:6666");
+    info!("- starting orchestrator at address http://{address}");
+
+    committee_cfg
+        .validate_with_key(&pubkey_package)
+        .context("invalid committee config")?;
+    let readiness = ReadinessCheck::new(&committee_cfg, ready_quorum)?;
+
+    let mut compliance = Compliance::new();
+    // Orchestrator should sync the sanction list before doing anything else
+    compliance.sync().await.expect("sync sanction list");
+
+    // wrap in an Arc after the first sync so it can be used in multiple request contexts
+    let compliance: Arc<Compliance> = Arc::new(compliance);
+
+    let member_status_state = Arc::new(RwLock::new(MemberStatusState::new(&committee_cfg).await));
+    let mss_thread_copy = member_status_state.clone();
+    let alerts = Arc::new(alerts);
+    let alerts_thread_copy = Arc::clone(&alerts);
+    tokio::spawn(async move {
+        MemberStatusState::keepalive_thread(mss_thread_copy, alerts_thread_copy).await
+    });
+
+    let mut ctx = Orchestrator::new(
+        pubkey_package,
+        committee_cfg,
+        member_status_state,
+        Arc::clone(&compliance),
+    )
+    .with_batch_window(batch_window)
+    .with_fee_policy(fee_policy)
+    .with_fee_strategy(fee_strategy)
+    .with_session_timeout(session_timeout)
+    .with_alerts(alerts);
+    info!("- spends must pay a service fee of {fee_policy}");
+    info!("- spends must pay a feerate of {fee_strategy}");
+    if let Some((rpc_ctx, min_zkapp_confirmations)) = bitcoind {
+        for target in &broadcast_fallbacks {
+            info!("- broadcasting to {target} if the RPC node refuses a transaction");
+        }
+        let chain = BitcoindBackend::new(rpc_ctx).with_broadcast_fallbacks(broadcast_fallbacks);
+        ctx = ctx.with_chain_backend(Arc::new(chain), min_zkapp_confirmations);
+    } else {
+        ensure!(
+            broadcast_fallbacks.is_empty(),
+            "broadcast fallbacks need an RPC node to broadcast to first"
+        );
+    }
+    if let Some(share_key) = share_key {
+        info!(
+            "- decrypting signature shares encrypted to {}",
+            share_key.public_key()
+        );
+        ctx = ctx.with_share_key(share_key);
+    }
+    if let Some(state_dir) = &state_dir {
+        info!(
+            "- persisting the reputation of members in {}",
+            state_dir.display()
+        );
+        ctx = ctx.with_reputation(ReputationStore::open(
+            state_dir,
+            MEMBER_MAX_FAILURES,
+            Duration::from_secs(MEMBER_QUARANTINE_SECONDS),
+        )?);
+        ctx = ctx.with_session_history(SessionHistory::open(state_dir, session_retention)?);
+    } else {
+        ctx = ctx.with_session_history(SessionHistory::new(session_retention));
+    }
+
+    // Sync sanction list in a parallel thread
+    compliance.start();
+
+    // answer CORS preflights (if enabled) and readiness probes, compress large payloads (for the clients that support it),
+    // and expose the committee info and the sessions as plain GET endpoints
+    if let Some(cors_origins) = &cors_origins {
+        info!("- allowing browser calls from {cors_origins}");
+    }
+    info!("- ready at {READY_PATH} once a quorum of {ready_quorum} members is healthy");
+    let http_middleware = tower::ServiceBuilder::new()
+        .option_layer(cors_origins.as_ref().map(CorsOrigins::layer))
+        .layer(readiness.layer())
+        .layer(CompressionLayer::new(MAX_REQUEST_BODY_SIZE as usize))
+        .layer(ProxyGetRequestLayer::new(
+            "/committee-info",
+            "committee_info",
+        )?)
+        .layer(ProxyGetRequestLayer::new("/sessions", "session_overview")?);
+    let server = Server::builder()
+        .max_request_body_size(MAX_REQUEST_BODY_SIZE)
+        .set_http_middleware(http_middleware)
+        .build(address.parse::<SocketAddr>()?)
+        .await?;
+    let reaper = ctx.session_reaper();
+    let mut module = DescribedModule::new(ctx);
+    module.register_async_method(
+        Method::new(
+            "unlock_funds",
+            &[("bob_request", "BobRequest")],
+            "BobResponse",
+        ),
+        unlock_funds,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "preview_sighashes",
+            &[("bob_request", "BobRequest")],
+            "SighashPreview",
+        ),
+        preview_sighashes,
+    )?;
+    module.register_async_method(
+        Method::new("status", &[], "StatusResponse"),
+        get_nodes_status,
+    )?;
+    module.register_async_method(
+        Method::new("committee_info", &[], "CommitteeInfo"),
+        get_committee_info,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "zkapp_status",
+            &[("outpoint", "OutPoint")],
+            "Option<ZkappUtxo>",
+        ),
+        get_zkapp_status,
+    )?;
+    module.register_async_method(
+        Method::new("vk_cache_stats", &[], "VkCacheStats"),
+        vk_cache_stats,
+    )?;
+    module.register_async_method(
+        Method::new("clear_member_blacklist", &[("id", "Identifier")], "bool"),
+        clear_member_blacklist,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "get_session",
+            &[("session_id", "String")],
+            "Option<SessionRecord>",
+        ),
+        get_session,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "list_sessions",
+            &[("filter", "SessionFilter")],
+            "SessionPage",
+        ),
+        list_sessions,
+    )?;
+    module.register_async_method(
+        Method::new("session_overview", &[], "Vec<SessionSummary>"),
+        session_overview,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "cancel_session",
+            &[("session_id", "String"), ("session_token", "String")],
+            "bool",
+        ),
+        cancel_session,
+    )?;
+    module.register_async_method(
+        Method::new("rebroadcast_session", &[("session_id", "String")], "Txid"),
+        rebroadcast_session,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "subscribe_session",
+            &[("session_id", "String"), ("since_event", "usize")],
+            "Option<SessionEvents>",
+        ),
+        subscribe_session,
+    )?;
+    module.register_async_method(
+        Method::new(
+            "reshare_committee",
+            &[("target", "ReshareTarget")],
+            "ReshareTranscript",
+        ),
+        reshare_committee,
+    )?;
+    let module = module.finish("orchestrator")?;
+
+    let addr = server.local_addr()?;
+    info!("- orchestrator listening on http://{addr}");
+    let handle = server.start(module);
+
+    // the reaper stops along with the server
+    let stopped = handle.clone().stopped();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = reaper.run(Duration::from_secs(SESSION_REAPER_INTERVAL_SECONDS)) => (),
+            _ = stopped => (),
+        }
+    });
+
+    Ok((addr, handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use bitcoin::{
+        absolute::LockTime, hashes::Hash, transaction::Version, Address, Network, ScriptBuf,
+        Transaction, TxIn, TxOut,
+    };
+
+    use crate::{
+        chain_backend::FakeChain,
+        committee::{node::NodeConfig, session_history::SessionEvents},
+        sighash::KEYSPEND_SIGHASH_TYPE,
+        tx_sanity::FeeLimits,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_committee_info() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members: key_packages
+                .keys()
+                .enumerate()
+                .map(|(idx, id)| {
+                    (
+                        *id,
+                        Member {
+                            address: format!("127.0.0.1:{}", 8888 + idx),
+                            votes: None,
+                        },
+                    )
+                })
+                .collect(),
+            signature: None,
+        };
+
+        let info = CommitteeInfo::new(&pubkey_package, &committee_cfg).unwrap();
+        assert_eq!(info.threshold, 2);
+        assert_eq!(info.members, 3);
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+
+        // derive the address independently from the public key package
+        let secp = secp256k1::Secp256k1::default();
+        let xonly = frost::to_xonly_pubkey(pubkey_package.verifying_key());
+        let expected = Address::p2tr(&secp, xonly, None, get_network());
+        assert_eq!(info.pubkey, xonly.to_string());
+        assert_eq!(info.address, expected.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_zkapp_utxo_cache() {
+        // nothing listens there, so any request to the node fails
+        let rpc_ctx = RpcCtx::builder().url("http://127.0.0.1:1").build().unwrap();
+        let bitcoind = BitcoindBackend::new(rpc_ctx);
+        let outpoint = OutPoint {
+            txid: bitcoin::Txid::all_zeros(),
+            vout: 0,
+        };
+        let zkapp = ZkappUtxo {
+            outpoint,
+            amount: bitcoin::Amount::from_sat(10_000),
+            height: 100,
+            confirmations: 6,
+            op_return_payload: None,
+        };
+
+        // fresh entries are served from the cache
+        let cache = ZkappUtxoCache::new(Duration::from_secs(60));
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .add_entry(outpoint, (Instant::now(), Some(zkapp.clone())));
+        let cached = cache.get(&bitcoind, outpoint).await.unwrap().unwrap();
+        assert_eq!(cached.confirmations, zkapp.confirmations);
+
+        // invalidated entries are fetched again
+        cache.invalidate(&outpoint);
+        assert!(cache.get(&bitcoind, outpoint).await.is_err());
+
+        // so are expired entries
+        let cache = ZkappUtxoCache::new(Duration::ZERO);
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .add_entry(outpoint, (Instant::now(), Some(zkapp)));
+        assert!(cache.get(&bitcoind, outpoint).await.is_err());
+    }
+
+    fn member(address: &str) -> Member {
+        Member {
+            address: address.to_string(),
+            votes: None,
+        }
+    }
+
+    #[test]
+    fn test_duplicate_member_ids_are_rejected() {
+        let id = serde_json::to_string(&Identifier::try_from(1u16).unwrap()).unwrap();
+        let other_id = serde_json::to_string(&Identifier::try_from(2u16).unwrap()).unwrap();
+
+        let config = format!(
+            r#"{{"threshold": 1, "members": {{{id}: {{"address": "127.0.0.1:8891"}}, {other_id}: {{"address": "127.0.0.1:8892"}}}}}}"#
+        );
+        let committee_cfg: CommitteeConfig = serde_json::from_str(&config).unwrap();
+        assert!(committee_cfg.validate().is_ok());
+
+        let config = format!(
+            r#"{{"threshold": 1, "members": {{{id}: {{"address": "127.0.0.1:8891"}}, {id}: {{"address": "127.0.0.1:8892"}}}}}}"#
+        );
+        let err = serde_json::from_str::<CommitteeConfig>(&config).unwrap_err();
+        assert!(err.to_string().contains("duplicate member id"));
+    }
+
+    #[test]
+    fn test_validate_committee_config() {
+        let ids = (1..=3u16)
+            .map(|n| Identifier::try_from(n).unwrap())
+            .collect_vec();
+        let committee_cfg = |threshold, addresses: [&str; 3]| CommitteeConfig {
+            threshold,
+            members: ids
+                .iter()
+                .zip(addresses)
+                .map(|(id, address)| (*id, member(address)))
+                .collect(),
+            signature: None,
+        };
+        let addresses = ["127.0.0.1:8891", "127.0.0.1:8892", "127.0.0.1:8893"];
+
+        assert!(committee_cfg(2, addresses).validate().is_ok());
+        assert!(committee_cfg(0, addresses).validate().is_err());
+        assert!(committee_cfg(4, addresses).validate().is_err());
+
+        // the same address, written differently
+        let addresses = ["127.0.0.1:8891", "127.0.0.1:8892", "http://127.0.0.1:8891/"];
+        let err = committee_cfg(2, addresses).validate().unwrap_err();
+        assert!(err.to_string().contains("same address"));
+
+        // votes count towards the threshold
+        let addresses = ["127.0.0.1:8891", "127.0.0.1:8892", "127.0.0.1:8893"];
+        let mut with_votes = committee_cfg(5, addresses);
+        assert!(with_votes.validate().is_err());
+        with_votes.members.get_mut(&ids[0]).unwrap().votes = Some(3);
+        assert_eq!(with_votes.total_votes(), 5);
+        assert!(with_votes.validate().is_ok());
+        with_votes.members.get_mut(&ids[1]).unwrap().votes = Some(0);
+        let err = with_votes.validate().unwrap_err();
+        assert!(err.to_string().contains("at least 1 vote"));
+    }
+
+    #[tokio::test]
+    async fn test_quorum_votes() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let ids = key_packages.keys().copied().collect_vec();
+
+        // the first member has as many votes as the two others together
+        let committee_cfg = CommitteeConfig {
+            threshold: 3,
+            members: HashMap::from([
+                (
+                    ids[0],
+                    Member {
+                        votes: Some(2),
+                        ..member("127.0.0.1:8891")
+                    },
+                ),
+                (ids[1], member("127.0.0.1:8892")),
+                (ids[2], member("127.0.0.1:8893")),
+            ]),
+            signature: None,
+        };
+        // any quorum has the 2 members the key needs to sign
+        assert_eq!(committee_cfg.smallest_quorum(), 2);
+        committee_cfg.validate_with_key(&pubkey_package).unwrap();
+
+        // a member reaching the threshold alone couldn't sign
+        let mut too_many_votes = committee_cfg.clone();
+        too_many_votes.members.get_mut(&ids[0]).unwrap().votes = Some(3);
+        assert_eq!(too_many_votes.smallest_quorum(), 1);
+        let err = too_many_votes
+            .validate_with_key(&pubkey_package)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("the committee key needs 2 of them"),
+            "{err}"
+        );
+
+        // nor could 2 members with a key needing all 3 of them
+        let (_, three_of_three) = frost::gen_frost_keys(3, 3).unwrap();
+        let err = committee_cfg
+            .validate_with_key(&three_of_three)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("the committee key needs 3 of them"),
+            "{err}"
+        );
+
+        let member_status = MemberStatusState {
+            key_to_addr: committee_cfg
+                .members
+                .iter()
+                .map(|(id, member)| (*id, member.address.clone()))
+                .collect(),
+            status: ids.iter().map(|id| (*id, MemberStatus::Online)).collect(),
+        };
+        let orchestrator = Orchestrator::new(
+            pubkey_package,
+            committee_cfg,
+            Arc::new(RwLock::new(member_status)),
+            Arc::new(Compliance::new()),
+        );
+
+        // the quorum is reached with the first member and any other one
+        orchestrator
+            .member_status
+            .write()
+            .unwrap()
+            .mark_as_offline(&ids[2]);
+        for _ in 0..20 {
+            let signers = orchestrator.select_signers(&BTreeMap::new()).unwrap();
+            let signers = signers.iter().map(|(id, _)| *id).sorted().collect_vec();
+            assert_eq!(signers, [ids[0], ids[1]].into_iter().sorted().collect_vec());
+        }
+
+        // but not by the two others alone
+        {
+            let mut member_status = orchestrator.member_status.write().unwrap();
+            member_status.mark_as_offline(&ids[0]);
+            member_status.status.insert(ids[2], MemberStatus::Online);
+        }
+        let err = orchestrator.select_signers(&BTreeMap::new()).unwrap_err();
+        assert_eq!(
+            err,
+            ThresholdError::NotEnoughResponses {
+                threshold: 3,
+                available: 2,
+                failed: BTreeMap::new(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_addresses_dont_double_count() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let ids = key_packages.keys().copied().collect_vec();
+
+        // two members point to the same node
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members: HashMap::from([
+                (ids[0], member("127.0.0.1:8891")),
+                (ids[1], member("http://127.0.0.1:8891")),
+                (ids[2], member("127.0.0.1:8893")),
+            ]),
+            signature: None,
+        };
+        let member_status = MemberStatusState {
+            key_to_addr: committee_cfg
+                .members
+                .iter()
+                .map(|(id, member)| (*id, member.address.clone()))
+                .collect(),
+            status: ids.iter().map(|id| (*id, MemberStatus::Online)).collect(),
+        };
+        let orchestrator = Orchestrator::new(
+            pubkey_package,
+            committee_cfg,
+            Arc::new(RwLock::new(member_status)),
+            Arc::new(Compliance::new()),
+        );
+
+        // the node behind the duplicate address is only ever picked once
+        for _ in 0..20 {
+            let signers = orchestrator.select_signers(&BTreeMap::new()).unwrap();
+            assert_eq!(signers.len(), 2);
+            assert!(signers.iter().any(|(id, _)| *id == ids[2]));
+        }
+
+        // so a threshold of members can't be reached if the third member goes offline
+        orchestrator
+            .member_status
+            .write()
+            .unwrap()
+            .mark_as_offline(&ids[2]);
+        let err = orchestrator.select_signers(&BTreeMap::new()).unwrap_err();
+        assert!(err.to_string().contains("not enough available signers"));
+    }
+
+    #[tokio::test]
+    async fn test_not_enough_responses() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let ids = key_packages.keys().copied().collect_vec();
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members: HashMap::from([
+                (ids[0], member("127.0.0.1:8891")),
+                (ids[1], member("127.0.0.1:8892")),
+                (ids[2], member("127.0.0.1:8893")),
+            ]),
+            signature: None,
+        };
+        let member_status = MemberStatusState {
+            key_to_addr: committee_cfg
+                .members
+                .iter()
+                .map(|(id, member)| (*id, member.address.clone()))
+                .collect(),
+            status: ids.iter().map(|id| (*id, MemberStatus::Online)).collect(),
+        };
+        let orchestrator = Orchestrator::new(
+            pubkey_package,
+            committee_cfg,
+            Arc::new(RwLock::new(member_status)),
+            Arc::new(Compliance::new()),
+        );
+
+        // two members stopped responding during the session
+        let failed = BTreeMap::from([
+            (ids[0], "round 1: connection refused".to_string()),
+            (ids[1], "round 2: request timeout".to_string()),
+        ]);
+        {
+            let mut member_status = orchestrator.member_status.write().unwrap();
+            member_status.mark_as_disconnected(&ids[0]);
+            member_status.mark_as_offline(&ids[1]);
+        }
+        let err = orchestrator.select_signers(&failed).unwrap_err();
+        assert_eq!(
+            err,
+            ThresholdError::NotEnoughResponses {
+                threshold: 2,
+                available: 1,
+                failed: failed.clone(),
+            }
+        );
+        let message = err.to_string();
+        assert!(message.contains("1 of the 2 needed"), "{message}");
+        assert!(message.contains("request timeout"), "{message}");
+
+        // which clients get as the data of the error
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["kind"], "not_enough_responses");
+        let deserialized: ThresholdError = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized, err);
+    }
+
+    #[test]
+    fn test_invalid_shares() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let signers = key_packages.values().take(2).collect_vec();
+        let rng = &mut rand::thread_rng();
+
+        // round 1
+        let mut nonces = BTreeMap::new();
+        let mut commitments_map = BTreeMap::new();
+        for key_package in &signers {
+            let (member_nonces, commitments) =
+                frost_secp256k1_tr::round1::commit(key_package.signing_share(), rng);
+            nonces.insert(*key_package.identifier(), member_nonces);
+            commitments_map.insert(*key_package.identifier(), commitments);
+        }
+
+        // round 2
+        let input_request = InputSigningRequest {
+            commitments_map: commitments_map.clone(),
+            message: [1; 32],
+            sighash_type: KEYSPEND_SIGHASH_TYPE,
+        };
+        let sign = |key_package: &frost::KeyPackage, message: &[u8]| {
+            let signing_package =
+                frost_secp256k1_tr::SigningPackage::new(commitments_map.clone(), message);
+            frost_secp256k1_tr::round2::sign(
+                &signing_package,
+                &nonces[key_package.identifier()],
+                key_package,
+            )
+            .unwrap()
+        };
+        let valid_shares = signers
+            .iter()
+            .map(|key_package| (*key_package.identifier(), sign(*key_package, &[1; 32])))
+            .collect::<BTreeMap<_, _>>();
+
+        // every member responded with a valid share
+        let signatures = aggregate_or_blame(
+            &pubkey_package,
+            &[input_request.clone()],
+            &[valid_shares.clone()],
+            2,
+        )
+        .unwrap();
+        assert_eq!(signatures.len(), 1);
+
+        // every member responded, but one of them signed something else
+        let cheater = *signers[1].identifier();
+        let mut shares = valid_shares;
+        shares.insert(cheater, sign(signers[1], &[2; 32]));
+        let err = aggregate_or_blame(&pubkey_package, &[input_request], &[shares], 2).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ThresholdError>(),
+            Some(&ThresholdError::InvalidShares {
+                threshold: 2,
+                responded: 2,
+                invalid: vec![cheater],
+            })
+        );
+    }
+
+    /// A request spending a (fake) zkapp.
+    fn bob_request() -> BobRequest {
+        let zkapp_tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: bitcoin::Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(zkapp_tx.txid(), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: bitcoin::Amount::from_sat(9_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        BobRequest {
+            prev_outs: zkapp_tx.output.clone(),
+            tx,
+            zkapp_tx,
+            vk: serde_json::from_str(include_str!("../../examples/circuit/vk.json")).unwrap(),
+            proof: serde_json::from_str(include_str!("../../examples/circuit/proof.json")).unwrap(),
+            update: None,
+            sighash_type: KEYSPEND_SIGHASH_TYPE,
+            session_token: None,
+            correlation_id: None,
+            other_zkapps: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_session() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members: key_packages
+                .keys()
+                .map(|id| (*id, member("http://127.0.0.1:1")))
+                .collect(),
+            signature: None,
+        };
+        let member_status = MemberStatusState {
+            key_to_addr: HashMap::new(),
+            status: HashMap::new(),
+        };
+        let orchestrator = Orchestrator::new(
+            pubkey_package,
+            committee_cfg.clone(),
+            Arc::new(RwLock::new(member_status)),
+            Arc::new(Compliance::new()),
+        );
+        let bob_request = bob_request();
+        let session_token = "bob's secret";
+        let session_id = session_id_from_token(session_token);
+
+        // a session that keeps collecting (e.g. waiting on a slow member) until it's cancelled
+        let collected = AtomicUsize::new(0);
+        let signers = Mutex::new(vec![]);
+        let progress = Arc::new(SessionProgress::default());
+        let collecting = async {
+            *signers.lock().unwrap() = committee_cfg.members.keys().copied().collect();
+            progress.push(SessionEvent::RequestValidated);
+            while collected.fetch_add(1, Ordering::SeqCst) < 1_000 {
+                sleep(Duration::from_millis(5)).await;
+            }
+            Err::<BobResponse, _>(anyhow!("the session was never cancelled"))
+        };
+        let cancelling = async {
+            while collected.load(Ordering::SeqCst) < 3 {
+                sleep(Duration::from_millis(5)).await;
+            }
+            // subscribers can follow the session while it runs
+            let events = orchestrator
+                .session_events(&session_id, 0, Duration::from_secs(10))
+                .await
+                .unwrap();
+            assert_eq!(events.events[0].event, SessionEvent::RequestValidated);
+            assert!(!events.finished);
+            // only the client that started the session can cancel it
+            assert!(orchestrator
+                .cancel_session(&session_id, "not bob's secret")
+                .is_err());
+            assert!(orchestrator
+                .cancel_session(&session_id, session_token)
+                .unwrap());
+        };
+        let (res, ()) = tokio::join!(
+            orchestrator.run_session(&session_id, &bob_request, &signers, &progress, collecting),
+            cancelling
+        );
+        assert!(res.unwrap_err().is::<SessionCancelled>());
+
+        // collection stopped
+        let collected_when_cancelled = collected.load(Ordering::SeqCst);
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(collected.load(Ordering::SeqCst), collected_when_cancelled);
+
+        // and the session is reported as cancelled
+        let session = orchestrator.session(&session_id).unwrap();
+        assert_eq!(session.status, SessionStatus::Cancelled);
+        assert_eq!(session.members.len(), 3);
+
+        // and its events can be looked at after the fact
+        let events = orchestrator
+            .session_events(&session_id, 1, Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert!(events.finished);
+        assert_eq!(
+            events.events[0].event,
+            SessionEvent::Finished {
+                status: SessionStatus::Cancelled,
+                txid: None
+            }
+        );
+        assert!(!orchestrator
+            .cancel_session(&session_id, session_token)
+            .unwrap());
+
+        // the token can't be used for another session
+        let err = orchestrator
+            .run_session(
+                &session_id,
+                &bob_request,
+                &Mutex::new(vec![]),
+                &Arc::new(SessionProgress::default()),
+                async { Err::<BobResponse, _>(anyhow!("unreachable")) },
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("can't be reused"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_sessions() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members: key_packages
+                .keys()
+                .map(|id| (*id, member("http://127.0.0.1:1")))
+                .collect(),
+            signature: None,
+        };
+        let member_status = MemberStatusState {
+            key_to_addr: HashMap::new(),
+            status: HashMap::new(),
+        };
+        let orchestrator = Orchestrator::new(
+            pubkey_package,
+            committee_cfg,
+            Arc::new(RwLock::new(member_status)),
+            Arc::new(Compliance::new()),
+        );
+        let bob_request = bob_request();
+        let outpoint = bob_request.zkapp_outpoint().unwrap();
+        let failing = || async { Err::<BobResponse, _>(anyhow!("a member misbehaved")) };
+
+        // a session that runs until it's told to stop
+        let release = Notify::new();
+        let first = async {
+            release.notified().await;
+            Err::<BobResponse, _>(anyhow!("the first session stopped"))
+        };
+        let conflicting = async {
+            while orchestrator.session_overview().is_empty() {
+                sleep(Duration::from_millis(5)).await;
+            }
+
+            // another session spending the same input is refused while the first one runs
+            let err = orchestrator
+                .run_session(
+                    "second",
+                    &bob_request,
+                    &Mutex::new(vec![]),
+                    &Arc::new(SessionProgress::default()),
+                    failing(),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(err.downcast_ref(), Some(&InputReserved { outpoint }));
+            assert_eq!(
+                err.to_string(),
+                format!("input {outpoint} already reserved by another signing session")
+            );
+            // (and it never ran)
+            assert_eq!(orchestrator.session_overview().len(), 1);
+
+            release.notify_one();
+        };
+        let (res, ()) = tokio::join!(
+            orchestrator.run_session(
+                "first",
+                &bob_request,
+                &Mutex::new(vec![]),
+                &Arc::new(SessionProgress::default()),
+                first
+            ),
+            conflicting
+        );
+        assert_eq!(res.unwrap_err().to_string(), "the first session stopped");
+
+        // once the first session finished, its input is released
+        assert!(orchestrator.reserved_inputs.lock().unwrap().is_empty());
+        let err = orchestrator
+            .run_session(
+                "third",
+                &bob_request,
+                &Mutex::new(vec![]),
+                &Arc::new(SessionProgress::default()),
+                failing(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "a member misbehaved");
+    }
+
+    #[tokio::test]
+    async fn test_chain_backend() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members: key_packages
+                .keys()
+                .map(|id| (*id, member("http://127.0.0.1:1")))
+                .collect(),
+            signature: None,
+        };
+        let member_status = MemberStatusState {
+            key_to_addr: HashMap::new(),
+            status: HashMap::new(),
+        };
+        let chain = Arc::new(FakeChain::default());
+        let orchestrator = Orchestrator::new(
+            pubkey_package,
+            committee_cfg,
+            Arc::new(RwLock::new(member_status)),
+            Arc::new(Compliance::new()),
+        )
+        .with_chain_backend(chain.clone(), 2)
+        .with_broadcast();
+
+        let outpoint = |vout| OutPoint {
+            txid: bitcoin::Txid::all_zeros(),
+            vout,
+        };
+        let spend = |zkapp| Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: zkapp,
+                ..Default::default()
+            }],
+            output: vec![],
+        };
+
+        // a zkapp that was never there (or was spent by a transaction we don't know about)
+        let err = orchestrator.check_zkapps(&[outpoint(0)]).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ZkappAlreadySpent {
+                outpoint: outpoint(0),
+                spent_by: None,
+                confirmations: None,
+            })
+        );
+
+        // a zkapp that isn't deep enough
+        chain.add_zkapp(outpoint(1), bitcoin::Amount::from_sat(10_000), 1);
+        let err = orchestrator.check_zkapps(&[outpoint(1)]).await.unwrap_err();
+        let err = err.downcast_ref::<InsufficientConfirmations>().unwrap();
+        assert_eq!((err.have, err.need), (1, 2));
+
+        // a deep enough zkapp, but the network rejects the spend
+        chain.add_zkapp(outpoint(2), bitcoin::Amount::from_sat(10_000), 6);
+        orchestrator.check_zkapps(&[outpoint(2)]).await.unwrap();
+        chain.reject_broadcasts(Some("min relay fee not met"));
+        let err = orchestrator
+            .broadcast_signed(&spend(outpoint(2)), &[outpoint(2)])
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("min relay fee not met"));
+        assert!(chain.broadcasts().is_empty());
+
+        // the chain is reorganized between the verification and the broadcast
+        chain.reject_broadcasts(None);
+        chain.set_confirmations(outpoint(2), 0);
+        let err = orchestrator
+            .broadcast_signed(&spend(outpoint(2)), &[outpoint(2)])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("reorganized"));
+        assert_eq!(
+            err.downcast_ref::<ZkappReorged>()
+                .map(|err| err.signed_tx.txid()),
+            Some(spend(outpoint(2)).txid())
+        );
+        assert!(chain.broadcasts().is_empty());
+
+        // once it's back, the spend goes through (and the zkapp is spent)
+        chain.set_confirmations(outpoint(2), 3);
+        let outcome = orchestrator
+            .broadcast_signed(&spend(outpoint(2)), &[outpoint(2)])
+            .await
+            .unwrap();
+        assert!(!outcome.is_already_known());
+        assert_eq!(chain.broadcasts(), vec![spend(outpoint(2))]);
+        assert!(chain.zkapp_utxo(outpoint(2)).await.unwrap().is_none());
+
+        // spending it again is refused, pointing to the spend
+        let txid = spend(outpoint(2)).txid();
+        orchestrator.zkapp_cache.invalidate(&outpoint(2));
+        chain.confirm(txid, 2);
+        let err = orchestrator.check_zkapps(&[outpoint(2)]).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ZkappAlreadySpent {
+                outpoint: outpoint(2),
+                spent_by: Some(txid),
+                confirmations: Some(2),
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "the zkapp {} is already spent by {txid} (2 confirmations)",
+                outpoint(2)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reorgs() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members: key_packages
+                .keys()
+                .map(|id| (*id, member("http://127.0.0.1:1")))
+                .collect(),
+            signature: None,
+        };
+        let signer_ids = committee_cfg.members.keys().copied().collect_vec();
+        let member_status = MemberStatusState {
+            key_to_addr: HashMap::new(),
+            status: HashMap::new(),
+        };
+        let chain = Arc::new(FakeChain::default());
+        let (tips, chain_tips) = watch::channel(None);
+        let orchestrator = Orchestrator::new(
+            pubkey_package,
+            committee_cfg,
+            Arc::new(RwLock::new(member_status)),
+            Arc::new(Compliance::new()),
+        )
+        .with_chain_backend(chain.clone(), 1)
+        .with_broadcast()
+        .with_chain_tips(chain_tips);
+
+        let bob_request = bob_request();
+        let zkapp_outpoint = bob_request.zkapp_outpoint().unwrap();
+        let signed_tx = bob_request.tx.clone();
+        let new_tip = |height: u64| {
+            tips.send_replace(Some(ChainTip {
+                height,
+                hash: bitcoin::BlockHash::from_byte_array([height as u8; 32]),
+            }));
+        };
+        // a session that "signs" the transaction, and broadcasts it
+        let session = |session_id: &'static str| {
+            let signers = Mutex::new(signer_ids.clone());
+            let orchestrator = &orchestrator;
+            let bob_request = &bob_request;
+            let signed_tx = signed_tx.clone();
+            async move {
+                let signing = async {
+                    orchestrator
+                        .broadcast_signed(&signed_tx, &[zkapp_outpoint])
+                        .await?;
+                    orchestrator.spawn_reorg_watch(session_id, &signed_tx, vec![zkapp_outpoint]);
+                    Ok::<_, anyhow::Error>(BobResponse {
+                        unlocked_tx: signed_tx.clone(),
+                    })
+                };
+                orchestrator
+                    .run_session(
+                        session_id,
+                        bob_request,
+                        &signers,
+                        &Arc::new(SessionProgress::default()),
+                        signing,
+                    )
+                    .await
+            }
+        };
+        let wait_for_status = |session_id: &'static str, status: SessionStatus| {
+            let orchestrator = &orchestrator;
+            async move {
+                for _ in 0..500 {
+                    if orchestrator.session(session_id).unwrap().status == status {
+                        return;
+                    }
+                    sleep(Duration::from_millis(10)).await;
+                }
+                panic!("session {session_id} never became {status:?}");
+            }
+        };
+
+        // the deployment is reorged out before the broadcast
+        chain.add_zkapp(zkapp_outpoint, bitcoin::Amount::from_sat(10_000), 0);
+        let err = session("first").await.unwrap_err();
+        assert!(err.downcast_ref::<ZkappReorged>().is_some());
+        let record = orchestrator.session("first").unwrap();
+        assert_eq!(record.status, SessionStatus::InvalidatedByReorg);
+        assert_eq!(record.txid, Some(signed_tx.txid()));
+        assert_eq!(record.signed_tx.as_ref(), Some(&signed_tx));
+        assert!(record
+            .events
+            .iter()
+            .any(|record| record.event == SessionEvent::InvalidatedByReorg { zkapp_outpoint }));
+        assert!(chain.broadcasts().is_empty());
+
+        // it can't be rebroadcast until the deployment confirms again
+        let err = orchestrator.rebroadcast_session("first").await.unwrap_err();
+        assert!(err.downcast_ref::<InsufficientConfirmations>().is_some());
+        chain.set_confirmations(zkapp_outpoint, 1);
+        let outcome = orchestrator.rebroadcast_session("first").await.unwrap();
+        assert_eq!(outcome.txid(), signed_tx.txid());
+        assert_eq!(chain.broadcasts(), vec![signed_tx.clone()]);
+        let record = orchestrator.session("first").unwrap();
+        assert_eq!(record.status, SessionStatus::Signed);
+        assert_eq!(record.signed_tx, None);
+        assert!(SessionEvents::since(&record.events, 0).finished);
+
+        // (only sessions invalidated by a reorg can be rebroadcast)
+        assert!(orchestrator.rebroadcast_session("first").await.is_err());
+
+        // the deployment is reorged out after the (re)broadcast
+        chain.forget(zkapp_outpoint.txid);
+        new_tip(101);
+        wait_for_status("first", SessionStatus::InvalidatedByReorg).await;
+        let record = orchestrator.session("first").unwrap();
+        assert_eq!(record.signed_tx.as_ref(), Some(&signed_tx));
+
+        // and confirms again: the signed transaction is rebroadcast, without a new ceremony
+        chain.add_zkapp(zkapp_outpoint, bitcoin::Amount::from_sat(10_000), 1);
+        let outcome = orchestrator.rebroadcast_session("first").await.unwrap();
+        assert!(!outcome.is_already_known());
+        assert_eq!(chain.broadcasts(), vec![signed_tx.clone()]);
+        assert_eq!(
+            orchestrator.session("first").unwrap().status,
+            SessionStatus::Signed
+        );
+
+        // new blocks that keep the deployment don't change anything
+        new_tip(102);
+        new_tip(103);
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            orchestrator.session("first").unwrap().status,
+            SessionStatus::Signed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_reaper() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members: key_packages
+                .keys()
+                .map(|id| (*id, member("http://127.0.0.1:1")))
+                .collect(),
+            signature: None,
+        };
+        let member_status = MemberStatusState {
+            key_to_addr: HashMap::new(),
+            status: HashMap::new(),
+        };
+        let orchestrator = Orchestrator::new(
+            pubkey_package,
+            committee_cfg.clone(),
+            Arc::new(RwLock::new(member_status)),
+            Arc::new(Compliance::new()),
+        )
+        .with_session_history(SessionHistory::new(Duration::from_secs(3600)))
+        .with_session_timeout(Duration::from_secs(60));
+        let reaper = orchestrator.session_reaper();
+        let bob_request = bob_request();
+
+        // a session that finished, and one that got stuck collecting (e.g. waiting on a member that never answers)
+        orchestrator
+            .run_session(
+                "finished",
+                &bob_request,
+                &Mutex::new(committee_cfg.members.keys().copied().collect()),
+                &Arc::new(SessionProgress::default()),
+                async { Err::<BobResponse, _>(anyhow!("a member misbehaved")) },
+            )
+            .await
+            .unwrap_err();
+        let signers = Mutex::new(vec![]);
+        let progress = Arc::new(SessionProgress::default());
+        let stuck = async {
+            *signers.lock().unwrap() = committee_cfg.members.keys().copied().collect();
+            futures::future::pending::<Result<BobResponse>>().await
+        };
+        let reaping = async {
+            while orchestrator.session_overview().len() < 2 {
+                sleep(Duration::from_millis(5)).await;
+            }
+
+            // the listing reflects both sessions
+            let overview = orchestrator.session_overview();
+            assert_eq!(overview[0].session_id, "stuck");
+            assert_eq!(overview[0].state, SessionState::Running);
+            assert_eq!(
+                overview[0].zkapp_outpoint,
+                bob_request.zkapp_outpoint().ok()
+            );
+            assert_eq!(overview[1].session_id, "finished");
+            assert_eq!(overview[1].state, SessionState::Failed);
+
+            // nothing is stale yet
+            let now = now_secs();
+            assert_eq!(reaper.reap_at(now), ReapedSessions::default());
+
+            // past the signing timeout, the stuck session is abandoned
+            assert_eq!(
+                reaper.reap_at(now + 60),
+                ReapedSessions {
+                    pruned: 0,
+                    abandoned: 1
+                }
+            );
+        };
+        let (res, ()) = tokio::join!(
+            orchestrator.run_session("stuck", &bob_request, &signers, &progress, stuck),
+            reaping
+        );
+        assert!(res.unwrap_err().is::<SessionAbandoned>());
+        let session = orchestrator.session("stuck").unwrap();
+        assert_eq!(session.status, SessionStatus::Abandoned);
+        assert_eq!(session.members.len(), 3);
+
+        let overview = orchestrator.session_overview();
+        assert_eq!(
+            overview
+                .iter()
+                .map(|session| (session.session_id.as_str(), session.state))
+                .collect_vec(),
+            [
+                ("stuck", SessionState::Abandoned),
+                ("finished", SessionState::Failed)
+            ]
+        );
+
+        // past the retention period, finished sessions are dropped
+        let now = now_secs();
+        assert_eq!(
+            reaper.reap_at(now + 3601),
+            ReapedSessions {
+                pruned: 2,
+                abandoned: 0
+            }
+        );
+        assert!(orchestrator.session_overview().is_empty());
+        assert!(orchestrator.session("finished").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_observer_config() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let ids = key_packages.keys().copied().collect_vec();
+
+        // only the first member is running
+        let mut members = HashMap::new();
+        for (idx, (id, key_package)) in key_packages.into_iter().enumerate() {
+            let address = if idx == 0 {
+                let (address, handle) = crate::committee::node::start_server(
+                    Some("127.0.0.1:0"),
+                    key_package,
+                    pubkey_package.clone(),
+                    NodeConfig {
+                        fee_limits: FeeLimits::for_network(Network::Regtest),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+                tokio::spawn(handle.stopped());
+                address
+            } else {
+                // nothing listens there
+                std::net::TcpListener::bind("127.0.0.1:0")
+                    .unwrap()
+                    .local_addr()
+                    .unwrap()
+            };
+            members.insert(
+                id,
+                Member {
+                    address: format!("http://{address}"),
+                    votes: None,
+                },
+            );
+        }
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members,
+            signature: None,
+        };
+
+        // export and round-trip through JSON
+        let observer_cfg = committee_cfg.observer_config(&ids[..2]).unwrap();
+        assert_eq!(observer_cfg.members.len(), 2);
+        let json = serde_json::to_string(&observer_cfg).unwrap();
+        assert!(!json.contains("threshold"));
+        let deserialized: ObserverConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, observer_cfg);
+
+        // all members by default, but no strangers
+        assert_eq!(committee_cfg.observer_config(&[]).unwrap().members.len(), 3);
+        let stranger = Identifier::try_from(42u16).unwrap();
+        assert!(committee_cfg.observer_config(&[stranger]).is_err());
+
+        // ping
+        let alive = deserialized.ping().await;
+        assert!(alive[&ids[0]]);
+        assert!(!alive[&ids[1]]);
+    }
+
+    #[tokio::test]
+    async fn test_reshare_through_members() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let ids = key_packages.keys().copied().collect_vec();
+
+        // a new 3-of-4 committee
+        let share_keys: BTreeMap<_, _> = (1..=4u16)
+            .map(|n| (Identifier::try_from(n).unwrap(), ShareKey::generate()))
+            .collect();
+        let target = ReshareTarget {
+            min_signers: 3,
+            recipients: share_keys
+                .iter()
+                .map(|(id, share_key)| {
+                    (
+                        *id,
+                        crate::committee::reshare::ReshareRecipient {
+                            address: format!("http://new-member-{id:?}"),
+                            share_key: share_key.public_key(),
+                        },
+                    )
+                })
+                .collect(),
+        };
+        let mut other_target = target.clone();
+        other_target.min_signers = 2;
+
+        // the operator of the last member approved another reshare
+        let mut members = HashMap::new();
+        let mut handles = vec![];
+        for (idx, (id, key_package)) in key_packages.into_iter().enumerate() {
+            let approved = if idx < 2 { &target } else { &other_target };
+            let (address, handle) = crate::committee::node::start_server(
+                Some("127.0.0.1:0"),
+                key_package,
+                pubkey_package.clone(),
+                NodeConfig {
+                    fee_limits: FeeLimits::for_network(Network::Regtest),
+                    reshare_target: Some(approved.clone()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            handles.push(handle);
+            members.insert(
+                id,
+                Member {
+                    address: format!("http://{address}"),
+                    votes: None,
+                },
+            );
+        }
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members,
+            signature: None,
+        };
+        let member_status = MemberStatusState::new(&committee_cfg).await;
+        let orchestrator = Orchestrator::new(
+            pubkey_package.clone(),
+            committee_cfg,
+            Arc::new(RwLock::new(member_status)),
+            Arc::new(Compliance::new()),
+        );
+
+        // a member that didn't approve the target refuses to deal
+        orchestrator
+            .member_status
+            .write()
+            .unwrap()
+            .mark_as_offline(&ids[0]);
+        let err = orchestrator.reshare(target.clone()).await.unwrap_err();
+        assert!(
+            format!("{err:#}").contains("didn't approve this reshare"),
+            "{err:#}"
+        );
+
+        // the two members that approved it deal their shares
+        {
+            let mut member_status = orchestrator.member_status.write().unwrap();
+            member_status.status.insert(ids[0], MemberStatus::Online);
+            member_status.mark_as_offline(&ids[2]);
+        }
+        let transcript = orchestrator.reshare(target.clone()).await.unwrap();
+        assert_eq!(
+            transcript
+                .deals
+                .iter()
+                .map(|deal| deal.dealer)
+                .sorted()
+                .collect_vec(),
+            ids[..2]
+        );
+
+        // each new member receives its share, and they sign under the original group key
+        let new_pubkey_package = transcript.pubkey_package().unwrap();
+        let new_key_packages = share_keys
+            .iter()
+            .map(|(id, share_key)| transcript.receive(&pubkey_package, id, share_key).unwrap())
+            .collect_vec();
+        let message = b"the same zkBitcoin address";
+        let signers = new_key_packages.iter().skip(1).collect_vec();
+        let signature = frost::sign_with(&signers, &new_pubkey_package, message).unwrap();
+        pubkey_package
+            .verifying_key()
+            .verify(message, &signature)
+            .unwrap();
+
+        // but not below the new threshold
+        assert!(frost::sign_with(&signers[..2], &new_pubkey_package, message).is_err());
+
+        for handle in handles {
+            handle.stop().unwrap();
+        }
+    }
 }
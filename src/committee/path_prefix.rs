@@ -0,0 +1,231 @@
+//! Serving a committee node under a path prefix (e.g. `/committee/node3`),
+//! so that several nodes can sit behind a single reverse proxy, on one hostname.
+//! The orchestrator then reaches each member at its prefix (e.g. `https://committee.example.com/committee/node3`),
+//! as the address of a member is used as is.
+
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::{ensure, Result};
+use hyper::{http::uri::PathAndQuery, Body, Request, Response, StatusCode, Uri};
+
+/// The path a node is served under: `/`-separated segments, starting with a `/` (e.g. `/committee/node3`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPrefix(String);
+
+impl PathPrefix {
+    /// Returns what's left of `path` once the prefix is removed (`/` for the prefix itself),
+    /// or `None` if `path` isn't under the prefix.
+    pub fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let rest = path.strip_prefix(&self.0)?;
+        match rest {
+            "" => Some("/"),
+            rest if rest.starts_with('/') => Some(rest),
+            // e.g. `/committee/node30` isn't under `/committee/node3`
+            _ => None,
+        }
+    }
+
+    /// The layer serving a node under the prefix (requests to other paths get a 404).
+    pub fn layer(&self) -> PathPrefixLayer {
+        PathPrefixLayer {
+            prefix: Arc::new(self.clone()),
+        }
+    }
+}
+
+impl FromStr for PathPrefix {
+    type Err = anyhow::Error;
+
+    /// Parses a prefix like `/committee/node3` (a trailing slash is ignored).
+    fn from_str(s: &str) -> Result<Self> {
+        let prefix = s.trim().trim_end_matches('/');
+        ensure!(
+            prefix.starts_with('/'),
+            "invalid path prefix `{s}`: it must start with a `/` (e.g. /committee/node3)"
+        );
+        ensure!(
+            prefix[1..].split('/').all(|segment| {
+                !segment.is_empty()
+                    && segment
+                        .bytes()
+                        .all(|byte| byte.is_ascii_alphanumeric() || b"-._~".contains(&byte))
+            }),
+            "invalid path prefix `{s}`: segments can only contain letters, digits, `-`, `.`, `_`, and `~`"
+        );
+        Ok(Self(prefix.to_string()))
+    }
+}
+
+impl fmt::Display for PathPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// See [PathPrefix::layer].
+#[derive(Debug, Clone)]
+pub struct PathPrefixLayer {
+    prefix: Arc<PathPrefix>,
+}
+
+impl<S> tower::Layer<S> for PathPrefixLayer {
+    type Service = PathPrefixService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PathPrefixService {
+            inner,
+            prefix: Arc::clone(&self.prefix),
+        }
+    }
+}
+
+/// See [PathPrefix::layer].
+#[derive(Debug, Clone)]
+pub struct PathPrefixService<S> {
+    inner: S,
+    prefix: Arc<PathPrefix>,
+}
+
+impl<S> tower::Service<Request<Body>> for PathPrefixService<S>
+where
+    S: tower::Service<Request<Body>, Response = Response<Body>> + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        let Some(path) = self.prefix.strip(request.uri().path()) else {
+            return Box::pin(async move {
+                let response = Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty())?;
+                Ok(response)
+            });
+        };
+
+        // the node only ever sees the path under the prefix
+        let path_and_query = match request.uri().query() {
+            Some(query) => format!("{path}?{query}"),
+            None => path.to_string(),
+        };
+        let mut parts = request.uri().clone().into_parts();
+        parts.path_and_query = Some(match PathAndQuery::from_str(&path_and_query) {
+            Ok(path_and_query) => path_and_query,
+            Err(err) => return Box::pin(async move { Err(err.into()) }),
+        });
+        *request.uri_mut() = match Uri::from_parts(parts) {
+            Ok(uri) => uri,
+            Err(err) => return Box::pin(async move { Err(err.into()) }),
+        };
+
+        let fut = self.inner.call(request);
+        Box::pin(async move { fut.await.map_err(Into::into) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Network;
+
+    use super::*;
+    use crate::{
+        committee::{
+            node::NodeConfig,
+            orchestrator::{Member, ObserverConfig},
+        },
+        frost,
+        tx_sanity::FeeLimits,
+    };
+
+    #[test]
+    fn test_parse_prefix() {
+        let prefix: PathPrefix = "/committee/node3/".parse().unwrap();
+        assert_eq!(prefix.to_string(), "/committee/node3");
+        assert_eq!(prefix.strip("/committee/node3"), Some("/"));
+        assert_eq!(prefix.strip("/committee/node3/"), Some("/"));
+        assert_eq!(prefix.strip("/committee/node3/describe"), Some("/describe"));
+        assert_eq!(prefix.strip("/committee/node30"), None);
+        assert_eq!(prefix.strip("/"), None);
+
+        assert!("committee/node3".parse::<PathPrefix>().is_err());
+        assert!("/".parse::<PathPrefix>().is_err());
+        assert!("/committee//node3".parse::<PathPrefix>().is_err());
+        assert!("/committee/node 3".parse::<PathPrefix>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_node_under_prefix() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let ids = key_packages.keys().copied().collect::<Vec<_>>();
+        let key_package = key_packages.into_values().next().unwrap();
+        let (address, handle) = crate::committee::node::start_server(
+            Some("127.0.0.1:0"),
+            key_package,
+            pubkey_package,
+            NodeConfig {
+                fee_limits: FeeLimits::for_network(Network::Regtest),
+                path_prefix: Some("/committee/node3".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        tokio::spawn(handle.stopped());
+
+        // the orchestrator reaches the node at its prefix, but not elsewhere
+        let observer_cfg = ObserverConfig {
+            members: ids
+                .iter()
+                .zip(["/committee/node3/", "", "/committee/node4"])
+                .map(|(id, path)| {
+                    let address = format!("http://{address}{path}");
+                    (
+                        *id,
+                        Member {
+                            address,
+                            votes: None,
+                        },
+                    )
+                })
+                .collect(),
+        };
+        let alive = observer_cfg.ping().await;
+        assert!(alive[&ids[0]]);
+        assert!(!alive[&ids[1]]);
+        assert!(!alive[&ids[2]]);
+
+        // un-prefixed paths don't exist
+        let ping = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "ping", "params": [7]});
+        let client = reqwest::Client::new();
+        for path in ["", "/committee/node3"] {
+            let response = client
+                .post(format!("http://{address}{path}"))
+                .json(&ping)
+                .send()
+                .await
+                .unwrap();
+            let expected = if path.is_empty() {
+                reqwest::StatusCode::NOT_FOUND
+            } else {
+                reqwest::StatusCode::OK
+            };
+            assert_eq!(response.status(), expected);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Serving a JSON-RPC method at a plain HTTP path (e.g. `POST /verify`), for clients that aren't JSON-RPC clients.
+//! The body of the request is passed as the (only) parameter of the method,
+//! and its result is returned as is (or its error, with a 400).
+
+use std::{
+    error::Error,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use hyper::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    Body, Method, Request, Response, StatusCode,
+};
+use serde::Deserialize;
+
+/// Serves the JSON-RPC `method` at `POST path` (other requests are passed to the wrapped service).
+#[derive(Debug, Clone)]
+pub struct ProxyPostRequestLayer {
+    path: Arc<str>,
+    method: Arc<str>,
+}
+
+impl ProxyPostRequestLayer {
+    pub fn new(path: &str, method: &str) -> Self {
+        Self {
+            path: path.into(),
+            method: method.into(),
+        }
+    }
+}
+
+impl<S> tower::Layer<S> for ProxyPostRequestLayer {
+    type Service = ProxyPostRequestService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ProxyPostRequestService {
+            inner,
+            path: Arc::clone(&self.path),
+            method: Arc::clone(&self.method),
+        }
+    }
+}
+
+/// See [ProxyPostRequestLayer].
+#[derive(Debug, Clone)]
+pub struct ProxyPostRequestService<S> {
+    inner: S,
+    path: Arc<str>,
+    method: Arc<str>,
+}
+
+impl<S> tower::Service<Request<Body>> for ProxyPostRequestService<S>
+where
+    S: tower::Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // the inner service is ready (not its clone), so it's the one we call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        if request.method() != Method::POST || request.uri().path() != &*self.path {
+            let fut = inner.call(request);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        }
+
+        let method = Arc::clone(&self.method);
+        Box::pin(async move {
+            // wrap the body in a JSON-RPC call
+            let (mut parts, body) = request.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            let params = match serde_json::from_slice::<serde_json::Value>(&body) {
+                Ok(params) => params,
+                Err(err) => {
+                    return json_response(
+                        StatusCode::BAD_REQUEST,
+                        serde_json::json!({ "message": format!("invalid JSON: {err}") }),
+                    )
+                }
+            };
+            let call = serde_json::to_vec(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": &*method,
+                "params": [params],
+            }))?;
+            parts.uri = "/".parse()?;
+            parts
+                .headers
+                .insert(CONTENT_TYPE, "application/json".parse()?);
+            parts.headers.insert(CONTENT_LENGTH, call.len().into());
+            let response = inner
+                .call(Request::from_parts(parts, Body::from(call)))
+                .await
+                .map_err(Into::into)?;
+
+            // and unwrap its response
+            #[derive(Deserialize)]
+            struct JsonRpcResponse {
+                result: Option<serde_json::Value>,
+                error: Option<serde_json::Value>,
+            }
+
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            match serde_json::from_slice::<JsonRpcResponse>(&body) {
+                Ok(JsonRpcResponse {
+                    result: Some(result),
+                    ..
+                }) => json_response(StatusCode::OK, result),
+                Ok(JsonRpcResponse {
+                    error: Some(error), ..
+                }) => json_response(StatusCode::BAD_REQUEST, error),
+                // e.g. a request that was too large
+                _ => Ok(Response::builder().status(status).body(Body::from(body))?),
+            }
+        })
+    }
+}
+
+fn json_response(
+    status: StatusCode,
+    body: serde_json::Value,
+) -> Result<Response<Body>, Box<dyn Error + Send + Sync + 'static>> {
+    let response = Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(&body)?))?;
+    Ok(response)
+}
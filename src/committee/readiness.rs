@@ -0,0 +1,345 @@
+//! The readiness probe of the orchestrator (served at `GET /ready`), for load balancers.
+//! The orchestrator is only ready when it could complete a signing session,
+//! i.e. when a quorum of members (see [ReadyQuorum]) answers a ping. It answers with a 503 until then.
+
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::{ensure, Context as _, Result};
+use hyper::{header::CONTENT_TYPE, Body, Method, Request, Response, StatusCode};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::committee::orchestrator::{normalize_address, CommitteeConfig, ObserverConfig};
+
+/// The path of the readiness probe.
+pub const READY_PATH: &str = "/ready";
+
+/// How many healthy members the orchestrator needs to be ready.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadyQuorum {
+    /// Just enough members to sign.
+    #[default]
+    Threshold,
+
+    /// More members than needed to sign, so that a session can still complete if some of them fail during it.
+    ThresholdPlus(usize),
+}
+
+impl ReadyQuorum {
+    /// The number of healthy members needed, for a committee with `threshold`.
+    pub fn required(&self, threshold: usize) -> usize {
+        match self {
+            Self::Threshold => threshold,
+            Self::ThresholdPlus(buffer) => threshold + buffer,
+        }
+    }
+}
+
+impl FromStr for ReadyQuorum {
+    type Err = anyhow::Error;
+
+    /// Parses `threshold` or `threshold+<buffer>` (e.g. `threshold+1`).
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s == "threshold" {
+            return Ok(Self::Threshold);
+        }
+        let buffer = s
+            .strip_prefix("threshold+")
+            .with_context(|| format!("invalid quorum {s:?} (expected threshold or threshold+N)"))?;
+        let buffer = buffer
+            .parse()
+            .with_context(|| format!("invalid quorum {s:?} (expected threshold or threshold+N)"))?;
+        Ok(match buffer {
+            0 => Self::Threshold,
+            buffer => Self::ThresholdPlus(buffer),
+        })
+    }
+}
+
+impl fmt::Display for ReadyQuorum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Threshold => write!(f, "threshold"),
+            Self::ThresholdPlus(buffer) => write!(f, "threshold+{buffer}"),
+        }
+    }
+}
+
+/// What the readiness probe returns (with a 200 if ready, and a 503 otherwise).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Readiness {
+    pub ready: bool,
+
+    /// The (votes of the) members that answered (members sharing an address only count once).
+    pub healthy: usize,
+
+    /// The (votes of the) members needed (see [ReadyQuorum]).
+    pub required: usize,
+}
+
+/// Pings the members to tell if the orchestrator is ready.
+#[derive(Debug, Clone)]
+pub struct ReadinessCheck {
+    members: ObserverConfig,
+    required: usize,
+}
+
+impl ReadinessCheck {
+    pub fn new(committee_cfg: &CommitteeConfig, quorum: ReadyQuorum) -> Result<Self> {
+        let required = quorum.required(committee_cfg.threshold);
+        ensure!(
+            required <= committee_cfg.total_votes(),
+            "the ready quorum ({quorum}) needs {required} votes, but the committee only has {}",
+            committee_cfg.total_votes()
+        );
+        Ok(Self {
+            members: committee_cfg.observer_config(&[])?,
+            required,
+        })
+    }
+
+    pub async fn check(&self) -> Readiness {
+        let alive = self.members.ping().await;
+        let healthy = alive
+            .iter()
+            .filter(|(_, alive)| **alive)
+            .map(|(id, _)| &self.members.members[id])
+            .unique_by(|member| normalize_address(&member.address))
+            .map(|member| member.votes())
+            .sum();
+        Readiness {
+            ready: healthy >= self.required,
+            healthy,
+            required: self.required,
+        }
+    }
+
+    /// The layer serving the readiness probe (other requests are passed to the wrapped service).
+    pub fn layer(self) -> ReadinessLayer {
+        ReadinessLayer {
+            check: Arc::new(self),
+        }
+    }
+}
+
+/// See [ReadinessCheck::layer].
+#[derive(Debug, Clone)]
+pub struct ReadinessLayer {
+    check: Arc<ReadinessCheck>,
+}
+
+impl<S> tower::Layer<S> for ReadinessLayer {
+    type Service = ReadinessService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ReadinessService {
+            inner,
+            check: Arc::clone(&self.check),
+        }
+    }
+}
+
+/// See [ReadinessCheck::layer].
+#[derive(Debug, Clone)]
+pub struct ReadinessService<S> {
+    inner: S,
+    check: Arc<ReadinessCheck>,
+}
+
+impl<S> tower::Service<Request<Body>> for ReadinessService<S>
+where
+    S: tower::Service<Request<Body>, Response = Response<Body>> + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        if request.method() != Method::GET || request.uri().path() != READY_PATH {
+            let fut = self.inner.call(request);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        }
+
+        let check = Arc::clone(&self.check);
+        Box::pin(async move {
+            let readiness = check.check().await;
+            debug!(
+                "- ready check: {}/{} members healthy",
+                readiness.healthy, readiness.required
+            );
+            let status = if readiness.ready {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            let response = Response::builder()
+                .status(status)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&readiness)?))?;
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, convert::Infallible};
+
+    use bitcoin::Network;
+    use tower::{Layer, ServiceExt};
+
+    use super::*;
+    use crate::{
+        committee::{node::NodeConfig, orchestrator::Member},
+        frost,
+        tx_sanity::FeeLimits,
+    };
+
+    async fn ready(check: &ReadinessCheck) -> (StatusCode, Readiness) {
+        let service = tower::service_fn(|_: Request<Body>| async {
+            Ok::<_, Infallible>(Response::new(Body::from("{}")))
+        });
+        let request = Request::get(READY_PATH).body(Body::empty()).unwrap();
+        let response = check
+            .clone()
+            .layer()
+            .layer(service)
+            .oneshot(request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_parse_quorum() {
+        assert_eq!(
+            "threshold".parse::<ReadyQuorum>().unwrap(),
+            ReadyQuorum::Threshold
+        );
+        assert_eq!(
+            "threshold+2".parse::<ReadyQuorum>().unwrap(),
+            ReadyQuorum::ThresholdPlus(2)
+        );
+        assert_eq!(
+            "threshold+0".parse::<ReadyQuorum>().unwrap(),
+            ReadyQuorum::Threshold
+        );
+        assert!("2".parse::<ReadyQuorum>().is_err());
+        assert!("threshold+".parse::<ReadyQuorum>().is_err());
+        assert_eq!(ReadyQuorum::ThresholdPlus(1).to_string(), "threshold+1");
+    }
+
+    #[tokio::test]
+    async fn test_ready_quorum() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+
+        let ids = key_packages.keys().copied().collect::<Vec<_>>();
+
+        // two members are running
+        let mut running = vec![];
+        for key_package in key_packages.into_values().take(2) {
+            let (address, handle) = crate::committee::node::start_server(
+                Some("127.0.0.1:0"),
+                key_package,
+                pubkey_package.clone(),
+                NodeConfig {
+                    fee_limits: FeeLimits::for_network(Network::Regtest),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            tokio::spawn(handle.stopped());
+            running.push(format!("http://{address}"));
+        }
+        // nothing listens there
+        let stopped = (0..2)
+            .map(|_| {
+                let address = std::net::TcpListener::bind("127.0.0.1:0")
+                    .unwrap()
+                    .local_addr()
+                    .unwrap();
+                format!("http://{address}")
+            })
+            .collect::<Vec<_>>();
+        let committee_cfg = |addresses: [&String; 3]| CommitteeConfig {
+            threshold: 2,
+            members: ids
+                .iter()
+                .zip(addresses)
+                .map(|(id, address)| {
+                    (
+                        *id,
+                        Member {
+                            address: address.clone(),
+                            votes: None,
+                        },
+                    )
+                })
+                .collect::<HashMap<_, _>>(),
+            signature: None,
+        };
+
+        // fewer healthy members than the threshold
+        let unhealthy = committee_cfg([&running[0], &stopped[0], &stopped[1]]);
+        let check = ReadinessCheck::new(&unhealthy, ReadyQuorum::Threshold).unwrap();
+        let (status, readiness) = ready(&check).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            readiness,
+            Readiness {
+                ready: false,
+                healthy: 1,
+                required: 2
+            }
+        );
+
+        // a quorum of healthy members
+        let healthy = committee_cfg([&running[0], &running[1], &stopped[0]]);
+        let check = ReadinessCheck::new(&healthy, ReadyQuorum::Threshold).unwrap();
+        let (status, readiness) = ready(&check).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(readiness.ready);
+        assert_eq!(readiness.healthy, 2);
+
+        // but not with a buffer
+        let check = ReadinessCheck::new(&healthy, ReadyQuorum::ThresholdPlus(1)).unwrap();
+        let (status, readiness) = ready(&check).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(readiness.required, 3);
+
+        // which can't be larger than the committee
+        assert!(ReadinessCheck::new(&healthy, ReadyQuorum::ThresholdPlus(2)).is_err());
+
+        // other requests go through
+        let service = tower::service_fn(|_: Request<Body>| async {
+            Ok::<_, Infallible>(Response::new(Body::from("{}")))
+        });
+        let response = check
+            .layer()
+            .layer(service)
+            .oneshot(Request::post("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,305 @@
+//! The reputation of the committee members, as seen by the orchestrator.
+//! Members that fail several signing sessions in a row (they time out, or send bad shares)
+//! are quarantined for a while, during which they are not selected for new signing sessions.
+//! Reputations can optionally be persisted to disk so that they survive restarts.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use frost_secp256k1_tr::Identifier;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{MEMBER_MAX_FAILURES, MEMBER_QUARANTINE_SECONDS};
+
+/// The file (in the state directory) where reputations are persisted.
+const REPUTATION_FILE: &str = "reputation.json";
+
+/// The reputation of a member.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberReputation {
+    /// The number of signing sessions the member failed in a row.
+    pub failure_streak: u32,
+
+    /// Until when the member is quarantined (in seconds since the UNIX epoch).
+    pub quarantined_until: Option<u64>,
+}
+
+pub struct ReputationStore {
+    /// The number of failures in a row after which a member is quarantined.
+    max_failures: u32,
+
+    /// How long a member is quarantined for.
+    quarantine: Duration,
+
+    members: BTreeMap<Identifier, MemberReputation>,
+
+    /// Where reputations are persisted (if anywhere).
+    path: Option<PathBuf>,
+}
+
+impl Default for ReputationStore {
+    fn default() -> Self {
+        Self::new(
+            MEMBER_MAX_FAILURES,
+            Duration::from_secs(MEMBER_QUARANTINE_SECONDS),
+        )
+    }
+}
+
+impl ReputationStore {
+    /// Creates an in-memory store.
+    pub fn new(max_failures: u32, quarantine: Duration) -> Self {
+        Self {
+            max_failures,
+            quarantine,
+            members: BTreeMap::new(),
+            path: None,
+        }
+    }
+
+    /// Creates a store persisted in `state_dir` (reloading the reputations persisted there, if any).
+    pub fn open(state_dir: &Path, max_failures: u32, quarantine: Duration) -> Result<Self> {
+        std::fs::create_dir_all(state_dir).context("couldn't create the state directory")?;
+        let path = state_dir.join(REPUTATION_FILE);
+
+        let members = if path.exists() {
+            let file = std::fs::File::open(&path)
+                .with_context(|| format!("couldn't open {}", path.display()))?;
+            serde_json::from_reader(file)
+                .with_context(|| format!("couldn't deserialize {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            members,
+            path: Some(path),
+            ..Self::new(max_failures, quarantine)
+        })
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    /// Records that a member took part in a successful signing session.
+    pub fn record_success(&mut self, id: &Identifier) {
+        if let Some(reputation) = self.members.get_mut(id) {
+            if reputation.failure_streak > 0 {
+                reputation.failure_streak = 0;
+                self.persist();
+            }
+        }
+    }
+
+    /// Records that a member failed a signing session (it timed out, or sent something invalid).
+    /// Returns `true` if the member got quarantined.
+    pub fn record_failure(&mut self, id: &Identifier) -> bool {
+        self.record_failure_at(id, Self::now())
+    }
+
+    fn record_failure_at(&mut self, id: &Identifier, now: u64) -> bool {
+        let reputation = self.members.entry(*id).or_default();
+        reputation.failure_streak += 1;
+
+        let quarantined = reputation.failure_streak >= self.max_failures;
+        if quarantined {
+            let until = now + self.quarantine.as_secs();
+            warn!(
+                "{id:?} failed {} signing sessions in a row, quarantining it for {} seconds",
+                reputation.failure_streak,
+                self.quarantine.as_secs()
+            );
+            reputation.failure_streak = 0;
+            reputation.quarantined_until = Some(until);
+        }
+
+        self.persist();
+        quarantined
+    }
+
+    pub fn is_quarantined(&self, id: &Identifier) -> bool {
+        self.is_quarantined_at(id, Self::now())
+    }
+
+    fn is_quarantined_at(&self, id: &Identifier, now: u64) -> bool {
+        self.members
+            .get(id)
+            .and_then(|reputation| reputation.quarantined_until)
+            .map(|until| now < until)
+            .unwrap_or(false)
+    }
+
+    /// The members currently quarantined, and until when (in seconds since the UNIX epoch).
+    pub fn blacklist(&self) -> BTreeMap<Identifier, u64> {
+        self.blacklist_at(Self::now())
+    }
+
+    fn blacklist_at(&self, now: u64) -> BTreeMap<Identifier, u64> {
+        self.members
+            .iter()
+            .filter_map(|(id, reputation)| {
+                reputation
+                    .quarantined_until
+                    .filter(|until| now < *until)
+                    .map(|until| (*id, until))
+            })
+            .collect()
+    }
+
+    /// Lifts the quarantine of a member (and resets its failure streak).
+    /// Returns `false` if we didn't know anything bad about the member.
+    pub fn clear(&mut self, id: &Identifier) -> bool {
+        let cleared = self.members.remove(id).is_some();
+        if cleared {
+            info!("cleared the reputation of {id:?}");
+            self.persist();
+        }
+        cleared
+    }
+
+    /// Filters quarantined members out of `candidates`.
+    /// If that would leave less than `threshold` members, quarantined members are used anyway (with a warning),
+    /// after all the other ones (so that truncating the result to `threshold` prefers healthy members).
+    pub fn select<T>(
+        &self,
+        candidates: Vec<(Identifier, T)>,
+        threshold: usize,
+    ) -> Vec<(Identifier, T)> {
+        self.select_at(candidates, threshold, Self::now())
+    }
+
+    fn select_at<T>(
+        &self,
+        candidates: Vec<(Identifier, T)>,
+        threshold: usize,
+        now: u64,
+    ) -> Vec<(Identifier, T)> {
+        let (mut healthy, quarantined): (Vec<_>, Vec<_>) = candidates
+            .into_iter()
+            .partition(|(id, _)| !self.is_quarantined_at(id, now));
+
+        if healthy.len() < threshold && !quarantined.is_empty() {
+            warn!(
+                "only {} members are not quarantined (threshold is {threshold}), using quarantined members anyway",
+                healthy.len()
+            );
+            healthy.extend(quarantined);
+        }
+
+        healthy
+    }
+
+    /// Persists the reputations (if enabled).
+    /// As this is only a best effort, failing to persist them is only logged.
+    fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        // write to a temporary file first, so that we never leave a truncated file behind
+        let tmp_path = path.with_extension("json.tmp");
+        let res = std::fs::File::create(&tmp_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| Ok(serde_json::to_writer_pretty(file, &self.members)?))
+            .and_then(|_| Ok(std::fs::rename(&tmp_path, path)?));
+        if let Err(err) = res {
+            warn!("couldn't persist reputations to {}: {err}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn id(n: u16) -> Identifier {
+        Identifier::try_from(n).unwrap()
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let mut store = ReputationStore::new(3, Duration::from_secs(60));
+        let now = 1_000;
+
+        // a success resets the failure streak
+        assert!(!store.record_failure_at(&id(1), now));
+        assert!(!store.record_failure_at(&id(1), now));
+        store.record_success(&id(1));
+        assert!(!store.record_failure_at(&id(1), now));
+        assert!(!store.record_failure_at(&id(1), now));
+        assert!(!store.is_quarantined_at(&id(1), now));
+
+        // the third failure in a row gets the member quarantined
+        assert!(store.record_failure_at(&id(1), now));
+        assert!(store.is_quarantined_at(&id(1), now));
+        assert!(!store.is_quarantined_at(&id(2), now));
+        assert_eq!(store.blacklist_at(now), BTreeMap::from([(id(1), now + 60)]));
+
+        // until the quarantine expires
+        assert!(store.is_quarantined_at(&id(1), now + 59));
+        assert!(!store.is_quarantined_at(&id(1), now + 60));
+        assert!(store.blacklist_at(now + 60).is_empty());
+
+        // or gets cleared
+        store.record_failure_at(&id(2), now);
+        store.record_failure_at(&id(2), now);
+        store.record_failure_at(&id(2), now);
+        assert!(store.is_quarantined_at(&id(2), now));
+        assert!(store.clear(&id(2)));
+        assert!(!store.is_quarantined_at(&id(2), now));
+        assert!(!store.clear(&id(2)));
+    }
+
+    #[test]
+    fn test_select() {
+        let mut store = ReputationStore::new(1, Duration::from_secs(60));
+        let now = 1_000;
+        store.record_failure_at(&id(2), now);
+
+        let candidates = || vec![(id(1), "a"), (id(2), "b"), (id(3), "c")];
+
+        // quarantined members are not selected
+        let selected = store.select_at(candidates(), 2, now);
+        assert_eq!(selected, vec![(id(1), "a"), (id(3), "c")]);
+
+        // unless we would drop below the threshold, in which case they come last
+        let selected = store.select_at(candidates(), 3, now);
+        assert_eq!(selected, vec![(id(1), "a"), (id(3), "c"), (id(2), "b")]);
+
+        // once the quarantine expires, they are selected again
+        let selected = store.select_at(candidates(), 2, now + 60);
+        assert_eq!(selected, candidates());
+    }
+
+    #[test]
+    fn test_persistence() {
+        let tmp_dir = TempDir::new("zkbitcoin_reputation").unwrap();
+        let quarantine = Duration::from_secs(3600);
+
+        {
+            let mut store = ReputationStore::open(tmp_dir.path(), 1, quarantine).unwrap();
+            store.record_failure(&id(1));
+            assert!(store.is_quarantined(&id(1)));
+        }
+
+        // the quarantine survives a restart
+        let mut store = ReputationStore::open(tmp_dir.path(), 1, quarantine).unwrap();
+        assert!(store.is_quarantined(&id(1)));
+
+        // and so does clearing it
+        store.clear(&id(1));
+        let store = ReputationStore::open(tmp_dir.path(), 1, quarantine).unwrap();
+        assert!(!store.is_quarantined(&id(1)));
+    }
+}
@@ -0,0 +1,521 @@
+//! Resharing the committee key among a new set of members (and/or with a new threshold),
+//! without changing the group key (and thus the zkBitcoin address), and without ever reconstructing it.
+//!
+//! A threshold of the current members (the dealers) each split their own share, multiplied by its Lagrange coefficient,
+//! with a fresh random polynomial of the new threshold (see [deal]).
+//! They send a sub-share to each new member, encrypted to its key (see [crate::committee::share_encryption]),
+//! along with commitments to the coefficients of their polynomial.
+//! The orchestrator only collects the deals into a [ReshareTranscript].
+//! Each new member then sums the sub-shares it received into its key share (see [ReshareTranscript::receive]),
+//! after checking them against the commitments.
+//! Anyone can compute the new public key package from the commitments (see [ReshareTranscript::pubkey_package]).
+//!
+//! The commitment to the constant term of each dealer must match its verification share (times its Lagrange coefficient),
+//! so a dealer can't deal anything else than its actual share.
+//! Dealers only take part in a reshare towards the [ReshareTarget] their operator approved.
+
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::secp256k1::PublicKey;
+use frost_secp256k1_tr::{
+    keys::{KeyPackage, PublicKeyPackage, SigningShare, VerifyingShare},
+    Identifier, VerifyingKey,
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    committee::{
+        session_history::call_orchestrator,
+        share_encryption::{encrypt_to, EncryptedShares, ShareKey},
+    },
+    frost::{self, Element, Scalar},
+};
+
+/// Domain separation of the sub-shares, so that they can't be mistaken for encrypted signature shares.
+const RESHARE_CONTEXT: &[u8] = b"zkbitcoin/reshare/v1";
+
+/// A member of the new committee.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReshareRecipient {
+    /// The address the member will be reached at (for the new committee configuration).
+    pub address: String,
+
+    /// The key its sub-shares are encrypted to (see [ShareKey::public_key]).
+    pub share_key: PublicKey,
+}
+
+/// The new committee: its threshold, and its members.
+/// Each dealer must approve it before taking part in the reshare.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReshareTarget {
+    pub min_signers: u16,
+    pub recipients: BTreeMap<Identifier, ReshareRecipient>,
+}
+
+impl ReshareTarget {
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("couldn't open {}", path.display()))?;
+        let target: Self = serde_json::from_reader(file)
+            .with_context(|| format!("couldn't deserialize {}", path.display()))?;
+        target.validate()?;
+        Ok(target)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.min_signers >= 2,
+            "the new threshold must be at least 2"
+        );
+        ensure!(
+            self.min_signers as usize <= self.recipients.len(),
+            "the new threshold ({}) is larger than the number of new members ({})",
+            self.min_signers,
+            self.recipients.len()
+        );
+        Ok(())
+    }
+}
+
+/// What the orchestrator asks each dealer for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReshareRequest {
+    pub target: ReshareTarget,
+
+    /// The current members taking part (a threshold of them, at least).
+    pub dealers: Vec<Identifier>,
+}
+
+/// The contribution of a dealer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReshareDeal {
+    pub dealer: Identifier,
+
+    /// The commitments to the coefficients of the dealer's polynomial (compressed points, in hex).
+    pub commitments: Vec<String>,
+
+    /// The sub-share of each new member, encrypted to its key.
+    pub sub_shares: BTreeMap<Identifier, EncryptedShares>,
+}
+
+impl ReshareDeal {
+    fn commitments(&self) -> Result<Vec<Element>> {
+        self.commitments
+            .iter()
+            .map(|commitment| frost::deserialize_element(&hex::decode(commitment)?))
+            .collect::<Result<_>>()
+            .with_context(|| format!("invalid commitments from {:?}", self.dealer))
+    }
+}
+
+/// Deals the share of `key_package` to the new members of the request: run by each dealer, with its own share only.
+pub fn deal(key_package: &KeyPackage, request: &ReshareRequest) -> Result<ReshareDeal> {
+    request.target.validate()?;
+    let dealer = *key_package.identifier();
+    ensure!(
+        request.dealers.contains(&dealer),
+        "{dealer:?} is not one of the dealers"
+    );
+    ensure!(
+        request.dealers.iter().all_unique(),
+        "the dealers must be distinct"
+    );
+    ensure!(
+        request.dealers.len() >= *key_package.min_signers() as usize,
+        "{} dealers can't reshare a key needing {} of them",
+        request.dealers.len(),
+        key_package.min_signers()
+    );
+
+    // our part of the group secret, as one of these dealers
+    let xs = request
+        .dealers
+        .iter()
+        .map(frost::identifier_scalar)
+        .collect_vec();
+    let share = frost::deserialize_scalar(&key_package.signing_share().serialize())?;
+    let secret = share * frost::lagrange_coefficient(&frost::identifier_scalar(&dealer), &xs)?;
+
+    // shared with a random polynomial of the new threshold
+    let coefficients = std::iter::once(secret)
+        .chain((1..request.target.min_signers).map(|_| frost::random_scalar()))
+        .collect_vec();
+    let commitments = coefficients
+        .iter()
+        .map(|coefficient| {
+            hex::encode(frost::serialize_element(
+                &(frost::generator() * *coefficient),
+            ))
+        })
+        .collect();
+
+    let group_key = key_package.verifying_key();
+    let sub_shares = request
+        .target
+        .recipients
+        .iter()
+        .map(|(id, recipient)| {
+            let sub_share = evaluate(&coefficients, &frost::identifier_scalar(id));
+            let encrypted = encrypt_to(
+                &recipient.share_key,
+                &associated_data(group_key, &dealer, id),
+                &frost::serialize_scalar(&sub_share),
+            )?;
+            Ok((*id, encrypted))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(ReshareDeal {
+        dealer,
+        commitments,
+        sub_shares,
+    })
+}
+
+/// The deals of a reshare, collected by the orchestrator. It doesn't contain anything secret
+/// (the sub-shares are encrypted to the new members), and is handed to each of them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReshareTranscript {
+    /// The group key, which stays the same.
+    pub verifying_key: VerifyingKey,
+
+    pub target: ReshareTarget,
+
+    pub deals: Vec<ReshareDeal>,
+}
+
+impl ReshareTranscript {
+    /// Checks the deals against the current committee: a threshold of its members dealt their actual share
+    /// to all the new members, with a polynomial of the new threshold.
+    pub fn verify(&self, pubkey_package: &PublicKeyPackage) -> Result<()> {
+        self.target.validate()?;
+        ensure!(
+            &self.verifying_key == pubkey_package.verifying_key(),
+            "the reshare is for another committee key"
+        );
+
+        let dealers = self.deals.iter().map(|deal| deal.dealer).collect_vec();
+        ensure!(dealers.iter().all_unique(), "a dealer dealt twice");
+        let min_signers = frost::key_threshold(pubkey_package)?;
+        ensure!(
+            dealers.len() >= min_signers,
+            "only {} members dealt their share, but the key needs {min_signers} of them",
+            dealers.len()
+        );
+
+        let xs = dealers.iter().map(frost::identifier_scalar).collect_vec();
+        let mut group_key = frost::identity();
+        for deal in &self.deals {
+            let commitments = deal.commitments()?;
+            ensure!(
+                commitments.len() == self.target.min_signers as usize,
+                "{:?} dealt with a polynomial of the wrong degree",
+                deal.dealer
+            );
+            ensure!(
+                deal.sub_shares.keys().eq(self.target.recipients.keys()),
+                "{:?} didn't deal a sub-share to each new member",
+                deal.dealer
+            );
+
+            let verifying_share = pubkey_package
+                .verifying_shares()
+                .get(&deal.dealer)
+                .with_context(|| format!("{:?} is not a member of the committee", deal.dealer))?;
+            let coefficient =
+                frost::lagrange_coefficient(&frost::identifier_scalar(&deal.dealer), &xs)?;
+            ensure!(
+                commitments[0] == frost::verifying_share_element(verifying_share)? * coefficient,
+                "{:?} didn't deal its key share",
+                deal.dealer
+            );
+            group_key = group_key + commitments[0];
+        }
+        ensure!(
+            group_key == self.verifying_key.element(),
+            "the deals don't add up to the group key"
+        );
+
+        Ok(())
+    }
+
+    /// The public key package of the new committee (with the same group key).
+    pub fn pubkey_package(&self) -> Result<PublicKeyPackage> {
+        let commitments = self
+            .deals
+            .iter()
+            .map(ReshareDeal::commitments)
+            .collect::<Result<Vec<_>>>()?;
+        let verifying_shares = self
+            .target
+            .recipients
+            .keys()
+            .map(|id| {
+                let x = frost::identifier_scalar(id);
+                let share = commitments
+                    .iter()
+                    .map(|commitments| frost::evaluate_commitments(commitments, &x))
+                    .reduce(|acc, share| acc + share)
+                    .context("no deals")?;
+                let share = VerifyingShare::deserialize(frost::serialize_element(&share))
+                    .map_err(|err| anyhow::anyhow!("invalid verification share: {err}"))?;
+                Ok((*id, share))
+            })
+            .collect::<Result<_>>()?;
+        Ok(PublicKeyPackage::new(verifying_shares, self.verifying_key))
+    }
+
+    /// The key share of the new member `id`, from the sub-shares encrypted to `share_key`: run by each new member.
+    /// The transcript is checked against the current committee first, and each sub-share against its dealer's commitments.
+    pub fn receive(
+        &self,
+        pubkey_package: &PublicKeyPackage,
+        id: &Identifier,
+        share_key: &ShareKey,
+    ) -> Result<KeyPackage> {
+        self.verify(pubkey_package)?;
+        let recipient = self
+            .target
+            .recipients
+            .get(id)
+            .with_context(|| format!("{id:?} is not a member of the new committee"))?;
+        ensure!(
+            recipient.share_key == share_key.public_key(),
+            "the sub-shares of {id:?} are encrypted to another key"
+        );
+
+        let x = frost::identifier_scalar(id);
+        let mut share: Option<Scalar> = None;
+        for deal in &self.deals {
+            let encrypted = &deal.sub_shares[id];
+            let sub_share = share_key
+                .decrypt(
+                    encrypted,
+                    &associated_data(&self.verifying_key, &deal.dealer, id),
+                )
+                .and_then(|plaintext| frost::deserialize_scalar(&plaintext))
+                .with_context(|| format!("couldn't decrypt the sub-share of {:?}", deal.dealer))?;
+            if frost::generator() * sub_share
+                != frost::evaluate_commitments(&deal.commitments()?, &x)
+            {
+                bail!(
+                    "the sub-share of {:?} doesn't match its commitments",
+                    deal.dealer
+                );
+            }
+            share = Some(match share {
+                Some(share) => share + sub_share,
+                None => sub_share,
+            });
+        }
+        let share = share.context("no deals")?;
+
+        let signing_share = SigningShare::deserialize(frost::serialize_scalar(&share))
+            .map_err(|err| anyhow::anyhow!("invalid key share: {err}"))?;
+        let key_package = KeyPackage::new(
+            *id,
+            signing_share,
+            VerifyingShare::from(signing_share),
+            self.verifying_key,
+            self.target.min_signers,
+        );
+        frost::check_key_package(&key_package, &self.pubkey_package()?)?;
+        Ok(key_package)
+    }
+}
+
+/// Asks the orchestrator to reshare the committee key to `target` (see [crate::committee::orchestrator::Orchestrator::reshare]).
+/// The transcript is checked against the current committee before being returned.
+pub async fn request_reshare(
+    orchestrator_address: &str,
+    target: &ReshareTarget,
+    pubkey_package: &PublicKeyPackage,
+) -> Result<ReshareTranscript> {
+    let transcript: ReshareTranscript = call_orchestrator(
+        orchestrator_address,
+        "reshare_committee",
+        &[serde_json::value::to_raw_value(target)?],
+    )
+    .await?;
+    ensure!(
+        &transcript.target == target,
+        "the orchestrator reshared to another committee"
+    );
+    transcript.verify(pubkey_package)?;
+    Ok(transcript)
+}
+
+/// Evaluates a polynomial at `x`.
+fn evaluate(coefficients: &[Scalar], x: &Scalar) -> Scalar {
+    let mut coefficients = coefficients.iter().rev();
+    let highest = *coefficients.next().expect("at least one coefficient");
+    coefficients.fold(highest, |acc, coefficient| acc * *x + *coefficient)
+}
+
+/// What a sub-share is bound to: the committee, its dealer, and its recipient.
+fn associated_data(
+    group_key: &VerifyingKey,
+    dealer: &Identifier,
+    recipient: &Identifier,
+) -> Vec<u8> {
+    let mut aad = RESHARE_CONTEXT.to_vec();
+    aad.extend_from_slice(&group_key.serialize());
+    aad.extend_from_slice(&dealer.serialize());
+    aad.extend_from_slice(&recipient.serialize());
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A new committee of `n` members (with their share keys), any `min_signers` of which can sign.
+    fn target(n: u16, min_signers: u16) -> (ReshareTarget, BTreeMap<Identifier, ShareKey>) {
+        let share_keys: BTreeMap<_, _> = (1..=n)
+            .map(|n| (Identifier::try_from(n).unwrap(), ShareKey::generate()))
+            .collect();
+        let recipients = share_keys
+            .iter()
+            .enumerate()
+            .map(|(idx, (id, share_key))| {
+                (
+                    *id,
+                    ReshareRecipient {
+                        address: format!("http://127.0.0.1:{}", 9000 + idx),
+                        share_key: share_key.public_key(),
+                    },
+                )
+            })
+            .collect();
+        (
+            ReshareTarget {
+                min_signers,
+                recipients,
+            },
+            share_keys,
+        )
+    }
+
+    #[test]
+    fn test_reshare() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+
+        // a member leaves, and the committee grows to a 3-of-4
+        let (target, share_keys) = target(4, 3);
+        let dealers = key_packages.values().skip(1).collect_vec();
+        let request = ReshareRequest {
+            target: target.clone(),
+            dealers: dealers
+                .iter()
+                .map(|key_package| *key_package.identifier())
+                .collect(),
+        };
+        let transcript = ReshareTranscript {
+            verifying_key: *pubkey_package.verifying_key(),
+            target,
+            deals: dealers
+                .iter()
+                .map(|key_package| deal(key_package, &request).unwrap())
+                .collect(),
+        };
+        transcript.verify(&pubkey_package).unwrap();
+
+        // the transcript doesn't contain the shares in clear
+        let json = serde_json::to_string(&transcript).unwrap();
+        for key_package in key_packages.values() {
+            assert!(!json.contains(&hex::encode(key_package.signing_share().serialize())));
+        }
+
+        // each new member gets its key share, under the same group key
+        let new_pubkey_package = transcript.pubkey_package().unwrap();
+        assert_eq!(
+            new_pubkey_package.verifying_key(),
+            pubkey_package.verifying_key()
+        );
+        assert_eq!(frost::key_threshold(&new_pubkey_package).unwrap(), 3);
+        let new_key_packages: BTreeMap<_, _> = share_keys
+            .iter()
+            .map(|(id, share_key)| {
+                (
+                    *id,
+                    transcript.receive(&pubkey_package, id, share_key).unwrap(),
+                )
+            })
+            .collect();
+        frost::verify_key_packages(&new_key_packages, &new_pubkey_package).unwrap();
+
+        // the new shares sign under the original group key
+        let message = b"hello";
+        let signers = new_key_packages.values().take(3).collect_vec();
+        let signature = frost::sign_with(&signers, &new_pubkey_package, message).unwrap();
+        pubkey_package
+            .verifying_key()
+            .verify(message, &signature)
+            .unwrap();
+
+        // a member can't read the sub-shares of another one
+        let (id, _) = share_keys.iter().next().unwrap();
+        let other_key = share_keys.values().last().unwrap();
+        assert!(transcript.receive(&pubkey_package, id, other_key).is_err());
+    }
+
+    #[test]
+    fn test_cheating_dealers() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let (target, share_keys) = target(3, 2);
+        let dealers = key_packages.values().take(2).collect_vec();
+        let request = ReshareRequest {
+            target: target.clone(),
+            dealers: dealers
+                .iter()
+                .map(|key_package| *key_package.identifier())
+                .collect(),
+        };
+        let transcript = ReshareTranscript {
+            verifying_key: *pubkey_package.verifying_key(),
+            target: target.clone(),
+            deals: dealers
+                .iter()
+                .map(|key_package| deal(key_package, &request).unwrap())
+                .collect(),
+        };
+
+        // not enough dealers
+        let mut missing = transcript.clone();
+        missing.deals.pop();
+        let err = missing.verify(&pubkey_package).unwrap_err();
+        assert!(err.to_string().contains("needs 2 of them"), "{err}");
+
+        // a dealer dealing something else than its share
+        let (other_key_packages, _) = frost::gen_frost_keys(3, 2).unwrap();
+        let impostor = &other_key_packages[key_packages.keys().next().unwrap()];
+        let mut forged = transcript.clone();
+        forged.deals[0] = deal(impostor, &request).unwrap();
+        let err = forged.verify(&pubkey_package).unwrap_err();
+        assert!(
+            err.to_string().contains("didn't deal its key share"),
+            "{err}"
+        );
+
+        // a sub-share that doesn't match the commitments of its dealer
+        let (id, share_key) = share_keys.iter().next().unwrap();
+        let mut tampered = transcript.clone();
+        let other_deal = deal(dealers[0], &request).unwrap();
+        tampered.deals[0]
+            .sub_shares
+            .insert(*id, other_deal.sub_shares[id].clone());
+        let err = tampered
+            .receive(&pubkey_package, id, share_key)
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("doesn't match its commitments"),
+            "{err}"
+        );
+
+        // a dealer that isn't one of the dealers of the request
+        let outsider = key_packages.values().last().unwrap();
+        assert!(deal(outsider, &request).is_err());
+    }
+}
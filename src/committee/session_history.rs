@@ -0,0 +1,995 @@
+//! The history of the signing sessions run by the orchestrator,
+//! so that users can find out what happened to their request (e.g. the txid of the transaction the committee signed)
+//! after the fact. Sessions are kept for a while (see [SessionHistory::open]),
+//! and can optionally be persisted to disk so that they survive restarts.
+//!
+//! Users can also pick the id of their session, by sending a secret session token with their request (see [session_id_from_token]).
+//! The token is what allows them to cancel the session while it's running (see [cancel_session]).
+//!
+//! While a session runs, clients can follow its progress (see [SessionEvent]) by long-polling the orchestrator
+//! (see [follow_session]). The events are kept in the history too, so they can also be looked at after the fact.
+
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use bitcoin::{
+    hashes::{sha256, Hash},
+    OutPoint, Transaction, Txid,
+};
+use frost_secp256k1_tr::Identifier;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::watch, time::sleep};
+
+use crate::{
+    bob_request::BobRequest,
+    constants::{SESSION_HISTORY_MAX_PAGE_SIZE, SESSION_HISTORY_RETENTION_DAYS},
+    fee_strategy::{FeeRateChoice, FeeRateSource},
+    json_rpc_stuff::{json_rpc_request, RpcCtx},
+};
+
+/// The file (in the state directory) where sessions are persisted, one JSON object per line.
+const SESSION_HISTORY_FILE: &str = "sessions.jsonl";
+
+/// How a signing session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStatus {
+    /// The committee signed the transaction.
+    Signed,
+
+    /// The session failed (see [SessionRecord::error]).
+    Failed,
+
+    /// The client cancelled the session before it finished.
+    Cancelled,
+
+    /// The session ran past the signing timeout, and the orchestrator gave up on it
+    /// (see [crate::committee::orchestrator::SessionReaper]).
+    Abandoned,
+
+    /// The committee signed the transaction, but the zkapp it spends was reorganized out of the chain
+    /// before (or right after) the orchestrator broadcast it. The signed transaction is kept (see [SessionRecord::signed_tx]),
+    /// and can be rebroadcast once the zkapp confirms again (see [crate::committee::orchestrator::Orchestrator::rebroadcast_session]).
+    #[serde(rename = "invalidated_by_reorg")]
+    InvalidatedByReorg,
+}
+
+/// The id of the session started by a request carrying `session_token` (see [BobRequest::session_token]).
+/// Only the client knows the token, so it can prove it started the session by revealing it.
+pub fn session_id_from_token(session_token: &str) -> String {
+    sha256::Hash::hash(session_token.as_bytes()).to_string()
+}
+
+/// The error of a session that was cancelled by its client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionCancelled;
+
+impl std::fmt::Display for SessionCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the session was cancelled by the client")
+    }
+}
+
+impl std::error::Error for SessionCancelled {}
+
+/// The error of a session that ran past the signing timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionAbandoned {
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for SessionAbandoned {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the session was abandoned after running for more than {}s",
+            self.timeout.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for SessionAbandoned {}
+
+/// The error of a session spending a committee input that another running session already spends
+/// (signing both would give two conflicting transactions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputReserved {
+    pub outpoint: OutPoint,
+}
+
+impl std::fmt::Display for InputReserved {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "input {} already reserved by another signing session",
+            self.outpoint
+        )
+    }
+}
+
+impl std::error::Error for InputReserved {}
+
+/// The error of a session whose zkapp changed (e.g. it was reorganized out of the chain)
+/// between its verification and the broadcast of the transaction the committee signed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZkappReorged {
+    pub zkapp_outpoint: OutPoint,
+
+    /// The transaction the committee signed, which can be rebroadcast once the zkapp confirms again.
+    pub signed_tx: Transaction,
+
+    /// What changed.
+    pub reason: String,
+}
+
+impl std::fmt::Display for ZkappReorged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the zkapp {} changed since it was verified (was the chain reorganized?): {}. The signed transaction {} can be rebroadcast once the zkapp confirms again",
+            self.zkapp_outpoint,
+            self.reason,
+            self.signed_tx.txid()
+        )
+    }
+}
+
+impl std::error::Error for ZkappReorged {}
+
+/// What was asked of the committee.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestSummary {
+    /// The number of inputs of the transaction to sign.
+    pub inputs: usize,
+
+    /// The number of outputs of the transaction to sign.
+    pub outputs: usize,
+
+    /// The total value of the outputs (in satoshis).
+    pub output_value: u64,
+
+    /// The total value of the inputs (in satoshis), i.e. what pays for the outputs and the fee
+    /// (unknown if the request didn't come with a prevout for each input).
+    #[serde(default)]
+    pub input_value: Option<u64>,
+}
+
+impl RequestSummary {
+    pub fn new(bob_request: &BobRequest) -> Self {
+        Self {
+            inputs: bob_request.tx.input.len(),
+            outputs: bob_request.tx.output.len(),
+            output_value: bob_request
+                .tx
+                .output
+                .iter()
+                .map(|output| output.value.to_sat())
+                .sum(),
+            input_value: (bob_request.prev_outs.len() == bob_request.tx.input.len()).then(|| {
+                bob_request
+                    .prev_outs
+                    .iter()
+                    .map(|prevout| prevout.value.to_sat())
+                    .sum()
+            }),
+        }
+    }
+}
+
+/// A step of a signing session.
+/// Unless the orchestrator broadcasts the transactions it signs (see [crate::committee::orchestrator::Orchestrator::with_broadcast]),
+/// clients do, so a session ends with the signed transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SessionEvent {
+    /// The request passed the compliance checks.
+    RequestValidated,
+
+    /// The feerate the transaction must pay was chosen (see [crate::fee_strategy::FeeStrategy]).
+    FeeRateChosen {
+        sat_per_vb: u64,
+        source: FeeRateSource,
+    },
+
+    /// The proofs of the zkapps verified, and their spend passed the sanity, fee, and confirmation checks.
+    ProofVerified,
+
+    /// Round 1 completed with these members.
+    /// If a member drops out, the ceremony is retried from round 1 (and this event is repeated).
+    Round1Complete {
+        members: Vec<Identifier>,
+    },
+
+    Round2Complete,
+
+    /// The signature shares were aggregated into valid signatures.
+    SignatureVerified,
+
+    /// The zkapp was reorganized out of the chain after the committee signed the transaction
+    /// (see [SessionStatus::InvalidatedByReorg]). This can happen after the session finished, in which case it finishes again.
+    InvalidatedByReorg {
+        zkapp_outpoint: OutPoint,
+    },
+
+    /// The signed transaction was broadcast again, after the zkapp confirmed again.
+    Rebroadcast {
+        txid: Txid,
+    },
+
+    /// The session finished (with the txid of the signed transaction, if the committee signed it).
+    Finished {
+        status: SessionStatus,
+        txid: Option<Txid>,
+    },
+}
+
+/// The feerate chosen during a session (see [SessionEvent::FeeRateChosen]), if it got that far.
+pub fn chosen_fee_rate(events: &[SessionEventRecord]) -> Option<FeeRateChoice> {
+    events.iter().find_map(|record| match record.event {
+        SessionEvent::FeeRateChosen { sat_per_vb, source } => {
+            Some(FeeRateChoice { sat_per_vb, source })
+        }
+        _ => None,
+    })
+}
+
+/// An event of a session, with its index (events are numbered from 0, in the order they happened).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionEventRecord {
+    pub index: usize,
+
+    /// When it happened (in seconds since the UNIX epoch).
+    pub at: u64,
+
+    #[serde(flatten)]
+    pub event: SessionEvent,
+}
+
+/// The events of a session past a cursor (see [SessionProgress::wait_since]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionEvents {
+    pub events: Vec<SessionEventRecord>,
+
+    /// The cursor to ask for the next events with.
+    pub next_event: usize,
+
+    /// Whether the session finished (in which case there won't be any more events).
+    pub finished: bool,
+}
+
+impl SessionEvents {
+    /// The events past `since_event` of a list of events.
+    pub fn since(events: &[SessionEventRecord], since_event: usize) -> Self {
+        Self {
+            events: events.iter().skip(since_event).cloned().collect(),
+            next_event: events.len().max(since_event),
+            finished: matches!(
+                events.last(),
+                Some(SessionEventRecord {
+                    event: SessionEvent::Finished { .. },
+                    ..
+                })
+            ),
+        }
+    }
+}
+
+/// The events of a running session, which subscribers can wait on.
+pub struct SessionProgress {
+    events: Mutex<Vec<SessionEventRecord>>,
+
+    /// The number of events so far.
+    count: watch::Sender<usize>,
+}
+
+impl Default for SessionProgress {
+    fn default() -> Self {
+        Self {
+            events: Mutex::new(vec![]),
+            count: watch::channel(0).0,
+        }
+    }
+}
+
+impl SessionProgress {
+    /// Records an event, waking up the subscribers.
+    pub fn push(&self, event: SessionEvent) {
+        let mut events = self.events.lock().unwrap();
+        events.push(SessionEventRecord {
+            index: events.len(),
+            at: SessionHistory::now(),
+            event,
+        });
+        self.count.send_replace(events.len());
+    }
+
+    /// The events so far.
+    pub fn events(&self) -> Vec<SessionEventRecord> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Waits (up to `timeout`) for events past `since_event`, and returns them (if any).
+    pub async fn wait_since(&self, since_event: usize, timeout: Duration) -> SessionEvents {
+        let mut count = self.count.subscribe();
+        // timing out only means that there's nothing new yet
+        let _ = tokio::time::timeout(timeout, count.wait_for(|count| *count > since_event)).await;
+        SessionEvents::since(&self.events(), since_event)
+    }
+}
+
+/// A finished signing session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub session_id: String,
+
+    /// The correlation id of the request (see [BobRequest::correlation_id]), to look the session up in logs.
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+
+    pub request: RequestSummary,
+
+    /// The zkapp the transaction spends.
+    pub zkapp_outpoint: OutPoint,
+
+    /// The txid of the signed transaction (if the committee signed it).
+    pub txid: Option<Txid>,
+
+    /// The signed transaction, kept while it waits to be rebroadcast (see [SessionStatus::InvalidatedByReorg]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_tx: Option<Transaction>,
+
+    /// The members that took part in the (last attempt of the) ceremony.
+    pub members: Vec<Identifier>,
+
+    /// When the session started and finished (in seconds since the UNIX epoch).
+    pub started_at: u64,
+    pub finished_at: u64,
+
+    pub status: SessionStatus,
+
+    /// Why the session failed (if it did).
+    pub error: Option<String>,
+
+    /// The feerate the transaction had to pay (if the session got that far).
+    #[serde(default)]
+    pub fee_rate: Option<FeeRateChoice>,
+
+    /// What happened during the session.
+    #[serde(default)]
+    pub events: Vec<SessionEventRecord>,
+}
+
+impl SessionRecord {
+    /// Records an event that happened after the session finished (e.g. a reorg), followed by the new status of the session.
+    pub fn push_event(&mut self, event: SessionEvent) {
+        for event in [
+            event,
+            SessionEvent::Finished {
+                status: self.status,
+                txid: self.txid,
+            },
+        ] {
+            self.events.push(SessionEventRecord {
+                index: self.events.len(),
+                at: SessionHistory::now(),
+                event,
+            });
+        }
+    }
+}
+
+/// Which sessions to list (most recent first), see [SessionHistory::list].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionFilter {
+    pub status: Option<SessionStatus>,
+
+    pub zkapp_outpoint: Option<OutPoint>,
+
+    /// The number of (matching) sessions to skip.
+    pub offset: usize,
+
+    /// The maximum number of sessions to return (capped at [SESSION_HISTORY_MAX_PAGE_SIZE]).
+    pub limit: Option<usize>,
+}
+
+impl SessionFilter {
+    fn matches(&self, session: &SessionRecord) -> bool {
+        self.status.map_or(true, |status| session.status == status)
+            && self
+                .zkapp_outpoint
+                .map_or(true, |outpoint| session.zkapp_outpoint == outpoint)
+    }
+}
+
+/// A page of sessions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionPage {
+    pub sessions: Vec<SessionRecord>,
+
+    /// The total number of sessions matching the filter.
+    pub total: usize,
+}
+
+/// Where a session is at (see [SessionSummary]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    Running,
+    Signed,
+    Failed,
+    Cancelled,
+    Abandoned,
+    InvalidatedByReorg,
+}
+
+impl From<SessionStatus> for SessionState {
+    fn from(status: SessionStatus) -> Self {
+        match status {
+            SessionStatus::Signed => Self::Signed,
+            SessionStatus::Failed => Self::Failed,
+            SessionStatus::Cancelled => Self::Cancelled,
+            SessionStatus::Abandoned => Self::Abandoned,
+            SessionStatus::InvalidatedByReorg => Self::InvalidatedByReorg,
+        }
+    }
+}
+
+/// A running or finished session, as listed by the `/sessions` endpoint of the orchestrator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub state: SessionState,
+
+    /// The zkapp being spent (unknown if the request didn't make sense).
+    pub zkapp_outpoint: Option<OutPoint>,
+
+    /// When the session started (in seconds since the epoch).
+    pub started_at: u64,
+
+    /// How long ago the session started (in seconds).
+    pub age_secs: u64,
+}
+
+impl SessionSummary {
+    pub fn new(session: &SessionRecord, now: u64) -> Self {
+        Self {
+            session_id: session.session_id.clone(),
+            state: session.status.into(),
+            zkapp_outpoint: Some(session.zkapp_outpoint),
+            started_at: session.started_at,
+            age_secs: now.saturating_sub(session.started_at),
+        }
+    }
+}
+
+pub struct SessionHistory {
+    /// How long sessions are kept for.
+    retention: Duration,
+
+    /// The sessions, oldest first.
+    sessions: VecDeque<SessionRecord>,
+
+    /// Where sessions are persisted (if anywhere).
+    path: Option<PathBuf>,
+}
+
+impl Default for SessionHistory {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(
+            SESSION_HISTORY_RETENTION_DAYS * 24 * 60 * 60,
+        ))
+    }
+}
+
+impl SessionHistory {
+    /// Creates an in-memory history.
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            retention,
+            sessions: VecDeque::new(),
+            path: None,
+        }
+    }
+
+    /// Creates a history persisted in `state_dir` (reloading the sessions persisted there, if any).
+    pub fn open(state_dir: &Path, retention: Duration) -> Result<Self> {
+        std::fs::create_dir_all(state_dir).context("couldn't create the state directory")?;
+        let path = state_dir.join(SESSION_HISTORY_FILE);
+
+        let mut sessions = VecDeque::new();
+        if path.exists() {
+            let file = std::fs::File::open(&path)
+                .with_context(|| format!("couldn't open {}", path.display()))?;
+            for (idx, line) in BufReader::new(file).lines().enumerate() {
+                let line = line.with_context(|| format!("couldn't read {}", path.display()))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // a crash can leave a truncated last line behind, which we can live without
+                match serde_json::from_str(&line) {
+                    Ok(session) => sessions.push_back(session),
+                    Err(err) => warn!(
+                        "skipping invalid session on line {} of {}: {err}",
+                        idx + 1,
+                        path.display()
+                    ),
+                }
+            }
+        }
+
+        let mut history = Self {
+            sessions,
+            path: Some(path),
+            ..Self::new(retention)
+        };
+        history.prune();
+        info!(
+            "- loaded {} signing sessions from the history",
+            history.sessions.len()
+        );
+        Ok(history)
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    /// Records a finished session (and drops the sessions older than the retention period).
+    pub fn record(&mut self, session: SessionRecord) {
+        self.append(&session);
+        self.sessions.push_back(session);
+        self.prune();
+    }
+
+    /// Returns a session by its id.
+    pub fn get(&self, session_id: &str) -> Option<&SessionRecord> {
+        self.sessions
+            .iter()
+            .find(|session| session.session_id == session_id)
+    }
+
+    /// Updates a session (e.g. after a reorg), returns the updated session (`None` if it's not in the history).
+    pub fn update(
+        &mut self,
+        session_id: &str,
+        f: impl FnOnce(&mut SessionRecord),
+    ) -> Option<SessionRecord> {
+        let session = self
+            .sessions
+            .iter_mut()
+            .find(|session| session.session_id == session_id)?;
+        f(session);
+        let session = session.clone();
+        self.rewrite();
+        Some(session)
+    }
+
+    /// Returns the transaction the committee signed to spend a zkapp, if a session did (the most recent one).
+    pub fn spent_by(&self, zkapp_outpoint: &OutPoint) -> Option<Txid> {
+        self.sessions
+            .iter()
+            .rev()
+            .filter(|session| {
+                session.status == SessionStatus::Signed && session.zkapp_outpoint == *zkapp_outpoint
+            })
+            .find_map(|session| session.txid)
+    }
+
+    /// Lists the sessions matching the filter, most recent first.
+    pub fn list(&self, filter: &SessionFilter) -> SessionPage {
+        let matching = self
+            .sessions
+            .iter()
+            .rev()
+            .filter(|session| filter.matches(session));
+        let limit = filter
+            .limit
+            .unwrap_or(SESSION_HISTORY_MAX_PAGE_SIZE)
+            .min(SESSION_HISTORY_MAX_PAGE_SIZE);
+
+        SessionPage {
+            sessions: matching
+                .clone()
+                .skip(filter.offset)
+                .take(limit)
+                .cloned()
+                .collect(),
+            total: matching.count(),
+        }
+    }
+
+    /// Drops the sessions older than the retention period, returns how many were dropped.
+    pub fn prune(&mut self) -> usize {
+        self.prune_at(Self::now())
+    }
+
+    pub(crate) fn prune_at(&mut self, now: u64) -> usize {
+        let cutoff = now.saturating_sub(self.retention.as_secs());
+        let before = self.sessions.len();
+        self.sessions
+            .retain(|session| session.finished_at >= cutoff);
+
+        let pruned = before - self.sessions.len();
+        if pruned > 0 {
+            self.rewrite();
+        }
+        pruned
+    }
+
+    /// Appends a session to the file (if enabled).
+    /// As this is only a best effort, failing to persist it is only logged.
+    fn append(&self, session: &SessionRecord) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        let res = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut file| {
+                let mut line = serde_json::to_vec(session)?;
+                line.push(b'\n');
+                Ok(file.write_all(&line)?)
+            });
+        if let Err(err) = res {
+            warn!("couldn't persist session to {}: {err}", path.display());
+        }
+    }
+
+    /// Rewrites the whole file (if enabled), e.g. after pruning.
+    fn rewrite(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        // write to a temporary file first, so that we never leave a truncated file behind
+        let tmp_path = path.with_extension("jsonl.tmp");
+        let res = std::fs::File::create(&tmp_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut file| {
+                for session in &self.sessions {
+                    let mut line = serde_json::to_vec(session)?;
+                    line.push(b'\n');
+                    file.write_all(&line)?;
+                }
+                Ok(())
+            })
+            .and_then(|_| Ok(std::fs::rename(&tmp_path, path)?));
+        if let Err(err) = res {
+            warn!("couldn't persist sessions to {}: {err}", path.display());
+        }
+    }
+}
+
+//
+// Client
+//
+
+pub(crate) async fn call_orchestrator<T: serde::de::DeserializeOwned>(
+    orchestrator_address: &str,
+    method: &'static str,
+    params: &[Box<serde_json::value::RawValue>],
+) -> Result<T> {
+    let rpc_ctx = RpcCtx::builder()
+        .version("2.0")
+        .url(orchestrator_address)
+        .build()?;
+    let resp = json_rpc_request(&rpc_ctx, method, params)
+        .await
+        .with_context(|| format!("couldn't reach the orchestrator at {orchestrator_address}"))?;
+    let response: bitcoincore_rpc::jsonrpc::Response =
+        serde_json::from_str(&resp).context("couldn't deserialize the orchestrator's response")?;
+    Ok(response.result()?)
+}
+
+/// Asks the orchestrator for a session (`None` if it doesn't know it).
+pub async fn fetch_session(
+    orchestrator_address: &str,
+    session_id: &str,
+) -> Result<Option<SessionRecord>> {
+    call_orchestrator(
+        orchestrator_address,
+        "get_session",
+        &[serde_json::value::to_raw_value(session_id)?],
+    )
+    .await
+}
+
+/// Asks the orchestrator for the sessions matching `filter`.
+pub async fn fetch_sessions(
+    orchestrator_address: &str,
+    filter: &SessionFilter,
+) -> Result<SessionPage> {
+    call_orchestrator(
+        orchestrator_address,
+        "list_sessions",
+        &[serde_json::value::to_raw_value(filter)?],
+    )
+    .await
+}
+
+/// Asks the orchestrator for its running sessions and its most recent finished ones (see [SessionSummary]).
+pub async fn fetch_session_overview(orchestrator_address: &str) -> Result<Vec<SessionSummary>> {
+    call_orchestrator(orchestrator_address, "session_overview", &[]).await
+}
+
+/// Asks the orchestrator to cancel a running session, returns `false` if it's not running (anymore).
+pub async fn cancel_session(
+    orchestrator_address: &str,
+    session_id: &str,
+    session_token: &str,
+) -> Result<bool> {
+    call_orchestrator(
+        orchestrator_address,
+        "cancel_session",
+        &[
+            serde_json::value::to_raw_value(session_id)?,
+            serde_json::value::to_raw_value(session_token)?,
+        ],
+    )
+    .await
+}
+
+/// Asks the orchestrator to rebroadcast the transaction of a session invalidated by a reorg
+/// (see [SessionStatus::InvalidatedByReorg]), returns its txid.
+pub async fn rebroadcast_session(orchestrator_address: &str, session_id: &str) -> Result<Txid> {
+    call_orchestrator(
+        orchestrator_address,
+        "rebroadcast_session",
+        &[serde_json::value::to_raw_value(session_id)?],
+    )
+    .await
+}
+
+/// Waits (for a while) for the events of a session past `since_event`, see [SessionEvents].
+/// Returns `None` if the orchestrator doesn't know the session (e.g. it didn't start yet).
+pub async fn fetch_session_events(
+    orchestrator_address: &str,
+    session_id: &str,
+    since_event: usize,
+) -> Result<Option<SessionEvents>> {
+    call_orchestrator(
+        orchestrator_address,
+        "subscribe_session",
+        &[
+            serde_json::value::to_raw_value(session_id)?,
+            serde_json::value::to_raw_value(&since_event)?,
+        ],
+    )
+    .await
+}
+
+/// Follows a session until it finishes, calling `on_event` for each of its events (e.g. to update a progress spinner).
+/// As a client usually starts following its session while sending its request,
+/// a session the orchestrator doesn't know yet is waited for (and thus this should be raced against the request).
+pub async fn follow_session(
+    orchestrator_address: &str,
+    session_id: &str,
+    mut on_event: impl FnMut(&SessionEventRecord),
+) -> Result<SessionRecord> {
+    let mut next_event = 0;
+    loop {
+        let Some(events) =
+            fetch_session_events(orchestrator_address, session_id, next_event).await?
+        else {
+            sleep(Duration::from_secs(1)).await;
+            continue;
+        };
+        events.events.iter().for_each(&mut on_event);
+        next_event = events.next_event;
+        if !events.finished {
+            continue;
+        }
+
+        // requests rejected before reaching the committee finish without being recorded
+        return fetch_session(orchestrator_address, session_id)
+            .await?
+            .context("the request was rejected before reaching the committee");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn session(n: u32, finished_at: u64, status: SessionStatus) -> SessionRecord {
+        SessionRecord {
+            session_id: format!("session-{n}"),
+            correlation_id: Some(format!("correlation-{n}")),
+            request: RequestSummary {
+                inputs: 2,
+                outputs: 2,
+                output_value: 1_000,
+                input_value: Some(1_200),
+            },
+            zkapp_outpoint: OutPoint::new(Txid::all_zeros(), n % 2),
+            txid: (status == SessionStatus::Signed).then(Txid::all_zeros),
+            signed_tx: None,
+            members: vec![Identifier::try_from(1).unwrap()],
+            started_at: finished_at - 1,
+            finished_at,
+            status,
+            error: (status == SessionStatus::Failed).then(|| "oops".to_string()),
+            fee_rate: None,
+            events: vec![],
+        }
+    }
+
+    #[test]
+    fn test_list_sessions() {
+        let mut history = SessionHistory::new(Duration::from_secs(3600));
+        let now = SessionHistory::now();
+        for n in 0..10 {
+            let status = if n < 7 {
+                SessionStatus::Signed
+            } else {
+                SessionStatus::Failed
+            };
+            history.record(session(n, now + n as u64, status));
+        }
+
+        assert_eq!(
+            history.get("session-3"),
+            Some(&session(3, now + 3, SessionStatus::Signed))
+        );
+        assert_eq!(history.get("session-42"), None);
+
+        // most recent first, with pagination
+        let page = history.list(&SessionFilter {
+            offset: 2,
+            limit: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(page.total, 10);
+        let ids = page
+            .sessions
+            .iter()
+            .map(|session| session.session_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["session-7", "session-6", "session-5"]);
+
+        // filters
+        let page = history.list(&SessionFilter {
+            status: Some(SessionStatus::Failed),
+            ..Default::default()
+        });
+        assert_eq!(page.total, 3);
+        let page = history.list(&SessionFilter {
+            status: Some(SessionStatus::Signed),
+            zkapp_outpoint: Some(OutPoint::new(Txid::all_zeros(), 1)),
+            ..Default::default()
+        });
+        assert_eq!(page.total, 3);
+
+        // the transactions that spent a zkapp
+        assert_eq!(
+            history.spent_by(&OutPoint::new(Txid::all_zeros(), 1)),
+            Some(Txid::all_zeros())
+        );
+        assert_eq!(history.spent_by(&OutPoint::new(Txid::all_zeros(), 2)), None);
+    }
+
+    #[tokio::test]
+    async fn test_session_progress() {
+        let progress = SessionProgress::default();
+        progress.push(SessionEvent::RequestValidated);
+
+        // events already there are returned right away
+        let events = progress.wait_since(0, Duration::from_secs(10)).await;
+        assert_eq!(events.events.len(), 1);
+        assert_eq!(events.next_event, 1);
+        assert!(!events.finished);
+
+        // otherwise we wait for the next one
+        let (events, ()) = tokio::join!(progress.wait_since(1, Duration::from_secs(10)), async {
+            sleep(Duration::from_millis(20)).await;
+            progress.push(SessionEvent::ProofVerified);
+        });
+        assert_eq!(events.events[0].index, 1);
+        assert_eq!(events.events[0].event, SessionEvent::ProofVerified);
+
+        // or time out
+        let events = progress.wait_since(2, Duration::from_millis(20)).await;
+        assert!(events.events.is_empty());
+        assert_eq!(events.next_event, 2);
+
+        progress.push(SessionEvent::Finished {
+            status: SessionStatus::Failed,
+            txid: None,
+        });
+        let events = progress.wait_since(2, Duration::from_secs(10)).await;
+        assert!(events.finished);
+
+        // events survive in the history
+        let mut record = session(1, SessionHistory::now(), SessionStatus::Failed);
+        record.events = progress.events();
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains(r#""event":"proof_verified""#), "{json}");
+        let record: SessionRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(SessionEvents::since(&record.events, 1).events.len(), 2);
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut history = SessionHistory::new(Duration::from_secs(100));
+        history
+            .sessions
+            .push_back(session(1, 1_000, SessionStatus::Signed));
+        history
+            .sessions
+            .push_back(session(2, 1_050, SessionStatus::Signed));
+
+        assert_eq!(history.prune_at(1_100), 0);
+        assert_eq!(history.prune_at(1_101), 1);
+        assert!(history.get("session-1").is_none());
+        assert!(history.get("session-2").is_some());
+    }
+
+    #[test]
+    fn test_persistence() {
+        let tmp_dir = TempDir::new("zkbitcoin_sessions").unwrap();
+        let retention = Duration::from_secs(3600);
+        let now = SessionHistory::now();
+
+        {
+            let mut history = SessionHistory::open(tmp_dir.path(), retention).unwrap();
+            history.record(session(1, now - 10, SessionStatus::Signed));
+            history.record(session(2, now - 10, SessionStatus::Failed));
+        }
+
+        // sessions survive a restart
+        let mut history = SessionHistory::open(tmp_dir.path(), retention).unwrap();
+        assert_eq!(history.list(&SessionFilter::default()).total, 2);
+        assert_eq!(
+            history.get("session-2"),
+            Some(&session(2, now - 10, SessionStatus::Failed))
+        );
+
+        // and so do updates
+        let updated = history
+            .update("session-1", |session| {
+                session.status = SessionStatus::InvalidatedByReorg;
+                session.push_event(SessionEvent::InvalidatedByReorg {
+                    zkapp_outpoint: session.zkapp_outpoint,
+                });
+            })
+            .unwrap();
+        assert!(history.update("session-42", |_| ()).is_none());
+        let history = SessionHistory::open(tmp_dir.path(), retention).unwrap();
+        assert_eq!(history.get("session-1"), Some(&updated));
+        assert_eq!(history.list(&SessionFilter::default()).total, 2);
+        let events = SessionEvents::since(&updated.events, 0);
+        assert!(events.finished);
+        assert_eq!(
+            events.events.last().unwrap().event,
+            SessionEvent::Finished {
+                status: SessionStatus::InvalidatedByReorg,
+                txid: Some(Txid::all_zeros()),
+            }
+        );
+        assert!(serde_json::to_string(&updated)
+            .unwrap()
+            .contains(r#""status":"invalidated_by_reorg""#));
+
+        // expired sessions are dropped when reopening
+        let history = SessionHistory::open(tmp_dir.path(), Duration::from_secs(5)).unwrap();
+        assert!(history.list(&SessionFilter::default()).sessions.is_empty());
+        let history = SessionHistory::open(tmp_dir.path(), retention).unwrap();
+        assert_eq!(history.list(&SessionFilter::default()).total, 0);
+    }
+}
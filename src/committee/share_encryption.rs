@@ -0,0 +1,334 @@
+//! Encryption of signature shares for the orchestrator, on top of TLS.
+//! TLS is often terminated by a reverse proxy in front of committee nodes, which then sees the shares in clear.
+//! Nodes configured with the public key of the orchestrator (see [ShareKey]) encrypt their round 2 shares to it,
+//! so that only the orchestrator can read them:
+//! each reply uses a fresh ephemeral key, agreed with the orchestrator's key through ECDH,
+//! and is bound to the signing session and the member (so that it can't be replayed for another one).
+//! The same encryption carries the sub-shares of a reshare to the new members (see [crate::committee::reshare]).
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine};
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    secp256k1::{ecdh::SharedSecret, PublicKey, Secp256k1, SecretKey},
+    Txid,
+};
+use chacha20poly1305::{
+    aead::{Aead, Payload},
+    ChaCha20Poly1305, KeyInit, Nonce,
+};
+use frost_secp256k1_tr::{round2::SignatureShare, Identifier};
+use rand::RngCore;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::utils::secret_file::write_secret_json;
+
+/// Domain separation of the keys derived for share encryption.
+const SHARE_ENCRYPTION_CONTEXT: &[u8] = b"zkbitcoin/share-encryption/v1";
+
+/// The key of the orchestrator that signature shares are encrypted to.
+/// Its public key is given to every member (see [ShareKey::public_key]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareKey {
+    secret_key: SecretKey,
+}
+
+impl ShareKey {
+    pub fn generate() -> Self {
+        Self {
+            secret_key: SecretKey::new(&mut rand::thread_rng()),
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.secret_key.public_key(&Secp256k1::new())
+    }
+
+    /// Reads a key written by [ShareKey::write].
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("couldn't open {}", path.display()))?;
+        serde_json::from_reader(file)
+            .with_context(|| format!("couldn't deserialize {}", path.display()))
+    }
+
+    /// Writes the key to a secret file (see [write_secret_json]).
+    pub fn write(&self, path: &Path, force: bool) -> Result<()> {
+        write_secret_json(path, self, force)
+    }
+
+    /// Decrypts the shares that `member` sent for the signing session of `txid`.
+    pub fn decrypt_shares(
+        &self,
+        txid: &Txid,
+        member: &Identifier,
+        encrypted: &EncryptedShares,
+    ) -> Result<Vec<SignatureShare>> {
+        let plaintext = self
+            .decrypt(encrypted, &associated_data(txid, member))
+            .with_context(|| {
+                format!(
+                    "couldn't decrypt the signature shares of {member:?} (encrypted to another key, or for another session)"
+                )
+            })?;
+        serde_json::from_slice(&plaintext).context("couldn't deserialize the decrypted shares")
+    }
+
+    /// Decrypts what was encrypted to us with [encrypt_to], for the same associated data.
+    pub(crate) fn decrypt(&self, encrypted: &EncryptedShares, aad: &[u8]) -> Result<Vec<u8>> {
+        let cipher = cipher(
+            &SharedSecret::new(&encrypted.ephemeral_key, &self.secret_key),
+            &encrypted.ephemeral_key,
+            &self.public_key(),
+        );
+        cipher
+            .decrypt(
+                Nonce::from_slice(&encrypted.nonce),
+                Payload {
+                    msg: &encrypted.ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("decryption failed"))
+    }
+}
+
+/// Shares encrypted to a [ShareKey]: signature shares encrypted to the orchestrator (see [encrypt_shares]),
+/// or the sub-shares of a reshare encrypted to a new member.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedShares {
+    /// The ephemeral key of the member (the shares are encrypted with the key it agreed with the orchestrator's).
+    pub ephemeral_key: PublicKey,
+
+    /// (Nonces of another length are refused when deserializing.)
+    #[serde(with = "base64_nonce")]
+    pub nonce: [u8; 12],
+
+    #[serde(with = "base64_bytes")]
+    pub ciphertext: Vec<u8>,
+}
+
+/// Encrypts the signature shares of `member` for the signing session of `txid`,
+/// so that only the holder of the secret key of `orchestrator_key` can read them.
+pub fn encrypt_shares(
+    orchestrator_key: &PublicKey,
+    txid: &Txid,
+    member: &Identifier,
+    shares: &[SignatureShare],
+) -> Result<EncryptedShares> {
+    encrypt_to(
+        orchestrator_key,
+        &associated_data(txid, member),
+        &serde_json::to_vec(shares)?,
+    )
+    .context("couldn't encrypt the signature shares")
+}
+
+/// Encrypts `plaintext` so that only the holder of the secret key of `recipient` can read it (see [ShareKey::decrypt]),
+/// bound to `aad`.
+pub(crate) fn encrypt_to(
+    recipient: &PublicKey,
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<EncryptedShares> {
+    let secp = Secp256k1::new();
+    let ephemeral_secret = SecretKey::new(&mut rand::thread_rng());
+    let ephemeral_key = ephemeral_secret.public_key(&secp);
+    let cipher = cipher(
+        &SharedSecret::new(recipient, &ephemeral_secret),
+        &ephemeral_key,
+        recipient,
+    );
+
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+
+    Ok(EncryptedShares {
+        ephemeral_key,
+        nonce,
+        ciphertext,
+    })
+}
+
+/// Parses the (hex-encoded, compressed) public key of the orchestrator that members encrypt shares to.
+pub fn parse_share_key(public_key: &str) -> Result<PublicKey> {
+    let public_key = public_key
+        .trim()
+        .parse::<PublicKey>()
+        .with_context(|| format!("invalid share encryption key `{public_key}`"))?;
+    Ok(public_key)
+}
+
+/// The cipher for a shared secret, bound to both public keys.
+fn cipher(
+    shared_secret: &SharedSecret,
+    ephemeral_key: &PublicKey,
+    recipient_key: &PublicKey,
+) -> ChaCha20Poly1305 {
+    let mut engine = sha256::Hash::engine();
+    engine.input(SHARE_ENCRYPTION_CONTEXT);
+    engine.input(&shared_secret.secret_bytes());
+    engine.input(&ephemeral_key.serialize());
+    engine.input(&recipient_key.serialize());
+    let key = sha256::Hash::from_engine(engine);
+    ChaCha20Poly1305::new(key.as_byte_array().into())
+}
+
+/// What the ciphertext is bound to: the signing session, and the member.
+fn associated_data(txid: &Txid, member: &Identifier) -> Vec<u8> {
+    let mut aad = txid.to_byte_array().to_vec();
+    aad.extend_from_slice(&member.serialize());
+    aad
+}
+
+/// Serializes bytes as base64.
+mod base64_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&general_purpose::STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serializes a ChaCha20Poly1305 nonce as base64, refusing nonces of another length.
+mod base64_nonce {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(nonce: &[u8; 12], serializer: S) -> Result<S::Ok, S::Error> {
+        base64_bytes::serialize(nonce, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 12], D::Error> {
+        let nonce = base64_bytes::deserialize(deserializer)?;
+        nonce.as_slice().try_into().map_err(|_| {
+            serde::de::Error::custom(format!(
+                "invalid nonce length {} (expected 12 bytes)",
+                nonce.len()
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use frost_secp256k1_tr::round1;
+
+    use super::*;
+    use crate::frost;
+
+    /// Real signature shares, from a signing session of a 2-of-3 committee.
+    fn signature_shares() -> (Identifier, Vec<SignatureShare>) {
+        let (key_packages, _) = frost::gen_frost_keys(3, 2).unwrap();
+        let mut rng = rand::thread_rng();
+        let mut nonces = BTreeMap::new();
+        let mut commitments = BTreeMap::new();
+        for (id, key_package) in key_packages.iter().take(2) {
+            let (nonce, commitment) = round1::commit(key_package.signing_share(), &mut rng);
+            nonces.insert(*id, nonce);
+            commitments.insert(*id, commitment);
+        }
+        let signing_package = frost_secp256k1_tr::SigningPackage::new(commitments, &[7; 32]);
+        let (id, key_package) = key_packages.iter().next().unwrap();
+        let share =
+            frost_secp256k1_tr::round2::sign(&signing_package, &nonces[id], key_package).unwrap();
+        (*id, vec![share])
+    }
+
+    #[test]
+    fn test_share_encryption() {
+        let orchestrator_key = ShareKey::generate();
+        let txid = Txid::from_byte_array([1; 32]);
+        let (member, shares) = signature_shares();
+
+        let encrypted =
+            encrypt_shares(&orchestrator_key.public_key(), &txid, &member, &shares).unwrap();
+
+        // what goes through a proxy doesn't contain the shares in clear
+        let intercepted = serde_json::to_string(&encrypted).unwrap();
+        let in_clear = serde_json::to_string(&shares).unwrap();
+        assert!(!intercepted.contains(in_clear.trim_matches(|c| c == '[' || c == ']')));
+
+        // and can't be decrypted without the orchestrator's key
+        let intercepted: EncryptedShares = serde_json::from_str(&intercepted).unwrap();
+        let proxy_key = ShareKey::generate();
+        assert!(proxy_key
+            .decrypt_shares(&txid, &member, &intercepted)
+            .is_err());
+
+        // the orchestrator decrypts them
+        assert_eq!(
+            orchestrator_key
+                .decrypt_shares(&txid, &member, &intercepted)
+                .unwrap(),
+            shares
+        );
+
+        // but only for the session and the member they were sent for
+        let other_txid = Txid::from_byte_array([2; 32]);
+        assert!(orchestrator_key
+            .decrypt_shares(&other_txid, &member, &intercepted)
+            .is_err());
+        let other_member = Identifier::try_from(42u16).unwrap();
+        assert!(orchestrator_key
+            .decrypt_shares(&txid, &other_member, &intercepted)
+            .is_err());
+
+        // a tampered ciphertext is rejected
+        let mut tampered = intercepted.clone();
+        tampered.ciphertext[0] ^= 1;
+        assert!(orchestrator_key
+            .decrypt_shares(&txid, &member, &tampered)
+            .is_err());
+    }
+
+    #[test]
+    fn test_short_nonce_is_refused() {
+        let orchestrator_key = ShareKey::generate();
+        let txid = Txid::from_byte_array([1; 32]);
+        let (member, shares) = signature_shares();
+        let encrypted =
+            encrypt_shares(&orchestrator_key.public_key(), &txid, &member, &shares).unwrap();
+
+        // a member (or a proxy) sending a one-byte nonce gets an error, instead of crashing the orchestrator
+        let mut json = serde_json::to_value(&encrypted).unwrap();
+        json["nonce"] = general_purpose::STANDARD.encode([0u8]).into();
+        let err = serde_json::from_value::<EncryptedShares>(json).unwrap_err();
+        assert!(err.to_string().contains("invalid nonce length 1"), "{err}");
+    }
+
+    #[test]
+    fn test_share_key_file() {
+        let dir = tempdir::TempDir::new("share_key").unwrap();
+        let path = dir.path().join("share-key.json");
+        let key = ShareKey::generate();
+        key.write(&path, false).unwrap();
+        assert_eq!(ShareKey::read(&path).unwrap(), key);
+        assert!(key.write(&path, false).is_err());
+
+        assert_eq!(
+            parse_share_key(&key.public_key().to_string()).unwrap(),
+            key.public_key()
+        );
+        assert!(parse_share_key("not a key").is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//! Signatures of committee configurations.
+//! Anyone who can write to the host of the orchestrator could otherwise point a member at another endpoint,
+//! so the committee can sign its configuration with the group key,
+//! and the orchestrator and nodes can then check it against the public key package (see [check_config_signature]).
+//! Once a committee runs, its members sign the configuration their operators approved (see [sign_config_with_members]),
+//! without the key shares ever leaving them. [sign_config] is only for whoever holds all the shares (e.g. when generating them).
+//!
+//! The signature covers a canonical serialization of the threshold and the members (see [config_message]),
+//! tagged with [CONFIG_SIGNATURE_TAG] so that it can't be mistaken for a transaction or smoke test signature.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use log::warn;
+use serde::Serialize;
+
+use crate::{
+    committee::{
+        orchestrator::{CommitteeConfig, Member},
+        smoke_test::{check_identities, run_frost_ceremony, Ceremony},
+    },
+    frost,
+};
+
+/// The tag of the messages signed for committee configurations.
+pub const CONFIG_SIGNATURE_TAG: &str = "zkBitcoin/committee-config";
+
+/// What gets signed: the config, without its signature, with members sorted by id.
+#[derive(Serialize)]
+struct CanonicalConfig<'a> {
+    threshold: usize,
+    members: BTreeMap<&'a frost::Identifier, &'a Member>,
+}
+
+/// The message signed for a config: a tagged hash (as in BIP 340) of its canonical serialization.
+pub fn config_message(committee_cfg: &CommitteeConfig) -> Result<[u8; 32]> {
+    let canonical = CanonicalConfig {
+        threshold: committee_cfg.threshold,
+        members: committee_cfg.members.iter().collect(),
+    };
+    let serialized = serde_json::to_vec(&canonical).context("couldn't serialize the config")?;
+
+    let tag = sha256::Hash::hash(CONFIG_SIGNATURE_TAG.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    engine.input(&serialized);
+    Ok(sha256::Hash::from_engine(engine).to_byte_array())
+}
+
+/// Signs the config with a threshold of the given key shares (which must all belong to the committee).
+/// Returns the ids of the members who signed.
+pub fn sign_config(
+    committee_cfg: &mut CommitteeConfig,
+    key_packages: &BTreeMap<frost::Identifier, frost::KeyPackage>,
+    pubkey_package: &frost::PublicKeyPackage,
+) -> Result<Vec<frost::Identifier>> {
+    let min_signers = *key_packages
+        .values()
+        .next()
+        .context("no key shares were given")?
+        .min_signers() as usize;
+    ensure!(
+        key_packages.len() >= min_signers,
+        "only {} key shares were given, but {min_signers} are needed to sign",
+        key_packages.len()
+    );
+    for key_package in key_packages.values() {
+        frost::check_key_package(key_package, pubkey_package)?;
+    }
+
+    let signers = key_packages.values().take(min_signers).collect::<Vec<_>>();
+    let message = config_message(committee_cfg)?;
+    let signature = frost::sign_with(&signers, pubkey_package, &message)
+        .map_err(|err| anyhow::anyhow!("couldn't sign the config: {err}"))?;
+    committee_cfg.signature = Some(signature);
+
+    Ok(signers.iter().map(|signer| *signer.identifier()).collect())
+}
+
+/// The methods of the members signing the config they approved (see [sign_config_with_members]).
+const SIGN_CONFIG_CEREMONY: Ceremony = Ceremony {
+    round_1: "sign_config_round_1",
+    round_2: "sign_config_round_2",
+    discard: "discard_config_signing",
+};
+
+/// Asks a threshold of the members of the config (the first ones, by id, who answer as who they claim to be)
+/// to sign it. Each of them only signs if their operator approved this exact config (see `--approve-config`).
+/// Returns the ids of the members who signed.
+pub async fn sign_config_with_members(
+    committee_cfg: &mut CommitteeConfig,
+    pubkey_package: &frost::PublicKeyPackage,
+) -> Result<Vec<frost::Identifier>> {
+    let min_signers = frost::key_threshold(pubkey_package)?;
+    let identities = check_identities(committee_cfg, pubkey_package).await;
+    let mut signers = vec![];
+    for (id, identity) in identities {
+        match identity {
+            Ok(()) => signers.push(id),
+            Err(err) => warn!("{id:?} can't sign the config: {err:#}"),
+        }
+    }
+    ensure!(
+        signers.len() >= min_signers,
+        "only {} members are available, but {min_signers} are needed to sign",
+        signers.len()
+    );
+    signers.truncate(min_signers);
+
+    let message = config_message(committee_cfg)?;
+    let signature = run_frost_ceremony(
+        &SIGN_CONFIG_CEREMONY,
+        committee_cfg,
+        pubkey_package,
+        &signers,
+        message,
+        &message,
+    )
+    .await
+    .context("couldn't sign the config")?;
+    pubkey_package
+        .verifying_key()
+        .verify(&message, &signature)
+        .map_err(|_| anyhow::anyhow!("the members produced an invalid signature"))?;
+    committee_cfg.signature = Some(signature);
+
+    Ok(signers)
+}
+
+/// Checks that the config is signed by the committee of the public key package.
+pub fn verify_config_signature(
+    committee_cfg: &CommitteeConfig,
+    pubkey_package: &frost::PublicKeyPackage,
+) -> Result<()> {
+    let signature = committee_cfg
+        .signature
+        .as_ref()
+        .context("the committee config is not signed")?;
+    let message = config_message(committee_cfg)?;
+    pubkey_package
+        .verifying_key()
+        .verify(&message, signature)
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "the signature of the committee config is invalid (was it modified after being signed?)"
+            )
+        })
+}
+
+/// Checks the signature of a config when loading it: an invalid signature is always an error,
+/// while a missing signature is only an error if `require_signature` is set (and a warning otherwise).
+pub fn check_config_signature(
+    committee_cfg: &CommitteeConfig,
+    pubkey_package: &frost::PublicKeyPackage,
+    require_signature: bool,
+) -> Result<()> {
+    if committee_cfg.signature.is_none() {
+        if require_signature {
+            bail!("the committee config is not signed (see `zkbtc-admin sign-config`)");
+        }
+        warn!("the committee config is not signed (see `zkbtc-admin sign-config`)");
+        return Ok(());
+    }
+    verify_config_signature(committee_cfg, pubkey_package)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn committee() -> (
+        CommitteeConfig,
+        BTreeMap<frost::Identifier, frost::KeyPackage>,
+        frost::PublicKeyPackage,
+    ) {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let members = key_packages
+            .keys()
+            .enumerate()
+            .map(|(idx, id)| {
+                (
+                    *id,
+                    Member {
+                        address: format!("http://127.0.0.1:889{idx}"),
+                        votes: None,
+                    },
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members,
+            signature: None,
+        };
+        (committee_cfg, key_packages, pubkey_package)
+    }
+
+    #[test]
+    fn test_sign_config() {
+        let (mut committee_cfg, key_packages, pubkey_package) = committee();
+
+        // unsigned configs are only accepted if no signature is required
+        check_config_signature(&committee_cfg, &pubkey_package, false).unwrap();
+        assert!(check_config_signature(&committee_cfg, &pubkey_package, true).is_err());
+
+        // a threshold of members is enough to sign
+        let two_shares = key_packages.clone().into_iter().take(2).collect();
+        let signers = sign_config(&mut committee_cfg, &two_shares, &pubkey_package).unwrap();
+        assert_eq!(signers.len(), 2);
+        check_config_signature(&committee_cfg, &pubkey_package, true).unwrap();
+
+        // the signature survives a round trip
+        let json = serde_json::to_string(&committee_cfg).unwrap();
+        let deserialized: CommitteeConfig = serde_json::from_str(&json).unwrap();
+        verify_config_signature(&deserialized, &pubkey_package).unwrap();
+
+        // but not tampering, even when no signature is required
+        let mut tampered = committee_cfg.clone();
+        let id = *tampered.members.keys().next().unwrap();
+        tampered.members.get_mut(&id).unwrap().address = "http://evil.com:8891".to_string();
+        let err = check_config_signature(&tampered, &pubkey_package, false).unwrap_err();
+        assert!(err.to_string().contains("invalid"), "{err}");
+
+        // nor another committee
+        let (_, _, other_pubkey_package) = committee();
+        assert!(verify_config_signature(&committee_cfg, &other_pubkey_package).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sign_config_with_members() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+
+        // the config has to be known before the members start, as they approve it
+        let listeners = key_packages
+            .keys()
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect::<Vec<_>>();
+        let addresses = listeners
+            .iter()
+            .map(|listener| listener.local_addr().unwrap())
+            .collect::<Vec<_>>();
+        drop(listeners);
+        let members = key_packages
+            .keys()
+            .zip(&addresses)
+            .map(|(id, address)| {
+                (
+                    *id,
+                    Member {
+                        address: format!("http://{address}"),
+                        votes: None,
+                    },
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let mut committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members,
+            signature: None,
+        };
+        let mut other_cfg = CommitteeConfig {
+            threshold: 3,
+            ..committee_cfg.clone()
+        };
+
+        // the operator of the last member approved another config
+        let mut handles = vec![];
+        for (idx, (key_package, address)) in key_packages.into_values().zip(&addresses).enumerate()
+        {
+            let approved = if idx < 2 { &committee_cfg } else { &other_cfg };
+            let (_, handle) = crate::committee::node::start_server(
+                Some(&address.to_string()),
+                key_package,
+                pubkey_package.clone(),
+                crate::committee::node::NodeConfig {
+                    fee_limits: crate::tx_sanity::FeeLimits::for_network(bitcoin::Network::Regtest),
+                    approved_config: Some(approved.clone()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            handles.push(handle);
+        }
+
+        // the first two members sign the config they approved
+        let signers = sign_config_with_members(&mut committee_cfg, &pubkey_package)
+            .await
+            .unwrap();
+        assert_eq!(signers.len(), 2);
+        verify_config_signature(&committee_cfg, &pubkey_package).unwrap();
+
+        // but not another one
+        let err = sign_config_with_members(&mut other_cfg, &pubkey_package)
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("didn't approve this committee configuration"),
+            "{err:#}"
+        );
+        assert!(other_cfg.signature.is_none());
+    }
+
+    #[test]
+    fn test_sign_config_needs_threshold() {
+        let (mut committee_cfg, key_packages, pubkey_package) = committee();
+        let one_share = key_packages.into_iter().take(1).collect();
+        let err = sign_config(&mut committee_cfg, &one_share, &pubkey_package).unwrap_err();
+        assert!(err.to_string().contains("needed to sign"), "{err}");
+        assert!(committee_cfg.signature.is_none());
+    }
+}
@@ -0,0 +1,366 @@
+//! Smoke tests of a committee: checks that the members are reachable, that they hold the keys we expect,
+//! and that a threshold of them can run a FROST ceremony together.
+//! The ceremony signs a throwaway message derived from a random challenge with a dedicated tag
+//! (see [smoke_test_message]), so that it can never produce a signature for a transaction.
+
+use std::collections::BTreeMap;
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    secp256k1::XOnlyPublicKey,
+};
+use frost_secp256k1_tr::{keys::VerifyingShare, round1::SigningCommitments, Identifier};
+use futures::future::join_all;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use crate::{
+    committee::{
+        node::{Round1Response, Round2Response},
+        orchestrator::CommitteeConfig,
+    },
+    frost,
+    json_rpc_stuff::{json_rpc_request, RpcCtx},
+};
+
+/// The tag of the messages signed during smoke tests.
+pub const SMOKE_TEST_TAG: &str = "zkBitcoin/smoke-test";
+
+/// Who a member is, as returned by its `identity` method.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeIdentity {
+    pub identifier: Identifier,
+    pub verifying_share: VerifyingShare,
+
+    /// The key signing its audit log, if it keeps one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_key: Option<XOnlyPublicKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmokeTestRound2Request {
+    /// The challenge of the smoke test (members sign [smoke_test_message] of it),
+    /// or the message of the committee configuration being signed (see [crate::committee::signed_config]).
+    pub challenge: [u8; 32],
+
+    pub commitments_map: BTreeMap<Identifier, SigningCommitments>,
+}
+
+/// The result of a smoke test ceremony.
+#[derive(Debug, Clone)]
+pub struct SmokeTestSignature {
+    pub challenge: [u8; 32],
+    pub message: [u8; 32],
+    pub signature: frost_secp256k1_tr::Signature,
+}
+
+/// The message signed for a challenge: a tagged hash (as in BIP 340) with [SMOKE_TEST_TAG],
+/// which can't collide with a transaction sighash (tagged with `TapSighash`).
+pub fn smoke_test_message(challenge: &[u8; 32]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(SMOKE_TEST_TAG.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    engine.input(challenge);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Calls a method of a member.
+pub(crate) async fn call_member<T: DeserializeOwned>(
+    address: &str,
+    method: &'static str,
+    params: &[Box<RawValue>],
+) -> Result<T> {
+    let rpc_ctx = RpcCtx::builder()
+        .version("2.0")
+        .url(address)
+        .compress_requests(true)
+        .build()?;
+    let resp = json_rpc_request(&rpc_ctx, method, params).await?;
+    let response: bitcoincore_rpc::jsonrpc::Response =
+        serde_json::from_str(&resp).context("couldn't deserialize the response")?;
+    Ok(response.result()?)
+}
+
+/// Checks that the member behind `address` is `id`, and holds the key share the public key package expects.
+pub async fn check_identity(
+    id: &Identifier,
+    address: &str,
+    pubkey_package: &frost::PublicKeyPackage,
+) -> Result<()> {
+    let identity: NodeIdentity = call_member(address, "identity", &[]).await?;
+    ensure!(
+        identity.identifier == *id,
+        "{address} is {:?}, not {id:?}",
+        identity.identifier
+    );
+
+    let expected = pubkey_package
+        .verifying_shares()
+        .get(id)
+        .with_context(|| format!("{id:?} is not part of the public key package"))?;
+    ensure!(
+        identity.verifying_share == *expected,
+        "the key share of {id:?} doesn't match the public key package"
+    );
+
+    Ok(())
+}
+
+/// Checks the identity of all the members (see [check_identity]).
+pub async fn check_identities(
+    committee_cfg: &CommitteeConfig,
+    pubkey_package: &frost::PublicKeyPackage,
+) -> BTreeMap<Identifier, Result<()>> {
+    let checks = committee_cfg.members.iter().map(|(id, member)| async move {
+        (
+            *id,
+            check_identity(id, &member.address, pubkey_package).await,
+        )
+    });
+    join_all(checks).await.into_iter().collect()
+}
+
+/// The methods of the members running a FROST ceremony over a tagged message,
+/// which the members compute (or check) themselves from a challenge, so that it can't be used to sign anything else.
+pub(crate) struct Ceremony {
+    /// Takes the challenge, and returns a [Round1Response].
+    pub round_1: &'static str,
+
+    /// Takes a [SmokeTestRound2Request], and returns a [Round2Response].
+    pub round_2: &'static str,
+
+    /// Takes the challenge, and forgets the nonces committed to for it.
+    pub discard: &'static str,
+}
+
+/// The ceremony of smoke tests (see [run_ceremony]).
+const SMOKE_TEST_CEREMONY: Ceremony = Ceremony {
+    round_1: "smoke_test_round_1",
+    round_2: "smoke_test_round_2",
+    discard: "discard_smoke_test",
+};
+
+/// Asks members to forget the nonces they committed to for a challenge (best effort, as nonces are never reused anyway).
+async fn discard_ceremony(ceremony: &Ceremony, addresses: &[&str], challenge: &[u8; 32]) {
+    let Ok(params) = serde_json::value::to_raw_value(challenge) else {
+        return;
+    };
+    let params = [params];
+    join_all(
+        addresses
+            .iter()
+            .map(|address| call_member::<bool>(address, ceremony.discard, &params)),
+    )
+    .await;
+}
+
+/// Runs a FROST ceremony with the given members over the message of a random challenge,
+/// and returns the aggregated signature (which still needs to be verified).
+/// If the ceremony fails, the members that are left holding nonces are asked to discard them.
+pub async fn run_ceremony(
+    committee_cfg: &CommitteeConfig,
+    pubkey_package: &frost::PublicKeyPackage,
+    signers: &[Identifier],
+) -> Result<SmokeTestSignature> {
+    let challenge: [u8; 32] = rand::random();
+    let message = smoke_test_message(&challenge);
+    let signature = run_frost_ceremony(
+        &SMOKE_TEST_CEREMONY,
+        committee_cfg,
+        pubkey_package,
+        signers,
+        challenge,
+        &message,
+    )
+    .await?;
+
+    Ok(SmokeTestSignature {
+        challenge,
+        message,
+        signature,
+    })
+}
+
+/// Runs `ceremony` with the given members over `challenge`, for which they sign `message`,
+/// and returns the aggregated signature (which still needs to be verified).
+pub(crate) async fn run_frost_ceremony(
+    ceremony: &Ceremony,
+    committee_cfg: &CommitteeConfig,
+    pubkey_package: &frost::PublicKeyPackage,
+    signers: &[Identifier],
+    challenge: [u8; 32],
+    message: &[u8; 32],
+) -> Result<frost_secp256k1_tr::Signature> {
+    let addresses = signers
+        .iter()
+        .map(|id| {
+            let member = committee_cfg
+                .members
+                .get(id)
+                .with_context(|| format!("{id:?} is not a member of the committee"))?;
+            Ok((*id, member.address.as_str()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // round 1
+    let params = [serde_json::value::to_raw_value(&challenge)?];
+    let responses = join_all(
+        addresses
+            .iter()
+            .map(|(_, address)| call_member::<Round1Response>(address, ceremony.round_1, &params)),
+    )
+    .await;
+    let mut commitments_map = BTreeMap::new();
+    let mut round_1_error = None;
+    for ((id, address), resp) in addresses.iter().zip(responses) {
+        match resp {
+            Ok(resp) => {
+                commitments_map.insert(*id, resp.commitments);
+            }
+            Err(err) => {
+                round_1_error
+                    .get_or_insert(err.context(format!("round 1 failed with {id:?} ({address})")));
+            }
+        }
+    }
+    if let Some(err) = round_1_error {
+        let committed = addresses
+            .iter()
+            .filter(|(id, _)| commitments_map.contains_key(id))
+            .map(|(_, address)| *address)
+            .collect::<Vec<_>>();
+        discard_ceremony(ceremony, &committed, &challenge).await;
+        return Err(err);
+    }
+
+    // round 2
+    let request = SmokeTestRound2Request {
+        challenge,
+        commitments_map: commitments_map.clone(),
+    };
+    let params = [serde_json::value::to_raw_value(&request)?];
+    let responses = join_all(
+        addresses
+            .iter()
+            .map(|(_, address)| call_member::<Round2Response>(address, ceremony.round_2, &params)),
+    )
+    .await;
+    let mut signature_shares = BTreeMap::new();
+    let mut round_2_error = None;
+    let mut failed = vec![];
+    for ((id, address), resp) in addresses.iter().zip(responses) {
+        match resp {
+            Ok(resp) => {
+                signature_shares.insert(*id, resp.signature_share);
+            }
+            Err(err) => {
+                failed.push(*address);
+                round_2_error
+                    .get_or_insert(err.context(format!("round 2 failed with {id:?} ({address})")));
+            }
+        }
+    }
+    if let Some(err) = round_2_error {
+        // the members that signed already forgot their nonces
+        discard_ceremony(ceremony, &failed, &challenge).await;
+        return Err(err);
+    }
+
+    // aggregate
+    let signing_package = frost_secp256k1_tr::SigningPackage::new(commitments_map, message);
+    frost_secp256k1_tr::aggregate(&signing_package, &signature_shares, pubkey_package)
+        .context("failed to aggregate signature shares")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::Network;
+    use itertools::Itertools;
+
+    use crate::{
+        committee::{node::NodeConfig, orchestrator::Member},
+        tx_sanity::FeeLimits,
+    };
+
+    use super::*;
+
+    /// Starts a node for each key package, and returns the config of the committee.
+    async fn start_committee(
+        key_packages: BTreeMap<Identifier, frost::KeyPackage>,
+        pubkey_package: &frost::PublicKeyPackage,
+        threshold: usize,
+    ) -> CommitteeConfig {
+        let mut members = HashMap::new();
+        for (id, key_package) in key_packages {
+            let (address, handle) = crate::committee::node::start_server(
+                Some("127.0.0.1:0"),
+                key_package,
+                pubkey_package.clone(),
+                NodeConfig {
+                    fee_limits: FeeLimits::for_network(Network::Regtest),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            // the node stops once its handle is dropped
+            tokio::spawn(handle.stopped());
+            members.insert(
+                id,
+                Member {
+                    address: format!("http://{address}"),
+                    votes: None,
+                },
+            );
+        }
+        CommitteeConfig {
+            threshold,
+            members,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn test_smoke_test_message() {
+        let challenge = [1u8; 32];
+        let message = smoke_test_message(&challenge);
+        assert_eq!(message, smoke_test_message(&challenge));
+        assert_ne!(message, smoke_test_message(&[2u8; 32]));
+        assert_ne!(message, challenge);
+    }
+
+    #[tokio::test]
+    async fn test_smoke_test() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let ids = key_packages.keys().copied().collect_vec();
+        let mut committee_cfg = start_committee(key_packages, &pubkey_package, 2).await;
+
+        // all members are who they claim to be
+        let identities = check_identities(&committee_cfg, &pubkey_package).await;
+        assert!(identities.values().all(Result::is_ok));
+
+        // a ceremony with a threshold of them produces a valid signature
+        let res = run_ceremony(&committee_cfg, &pubkey_package, &ids[..2])
+            .await
+            .unwrap();
+        assert_eq!(res.message, smoke_test_message(&res.challenge));
+        pubkey_package
+            .verifying_key()
+            .verify(&res.message, &res.signature)
+            .unwrap();
+
+        // mixed up addresses are caught
+        let first = committee_cfg.members[&ids[0]].clone();
+        let second = committee_cfg.members[&ids[1]].clone();
+        committee_cfg.members.insert(ids[0], second);
+        committee_cfg.members.insert(ids[1], first);
+        let identities = check_identities(&committee_cfg, &pubkey_package).await;
+        assert!(identities[&ids[0]].is_err());
+        assert!(identities[&ids[1]].is_err());
+        assert!(identities[&ids[2]].is_ok());
+    }
+}
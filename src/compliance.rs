@@ -165,4 +165,13 @@ impl Compliance {
         let sanctioned_addresses = self.sanctioned_addresses.read().await;
         sanctioned_addresses.contains_key(&address.to_string())
     }
+
+    /// Adds an address to the sanction list (without syncing it).
+    #[cfg(test)]
+    pub(crate) async fn sanction(&self, address: &Address) {
+        self.sanctioned_addresses
+            .write()
+            .await
+            .insert(address.to_string(), true);
+    }
 }
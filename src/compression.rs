@@ -0,0 +1,479 @@
+//! Compression of the bodies exchanged between the orchestrator and committee nodes
+//! (proofs and batches of signing requests can get large).
+//!
+//! Servers wrap their JSON RPC service in a [CompressionLayer]:
+//! - requests with a `Content-Encoding` of gzip or deflate are decoded (other encodings are refused with a 415),
+//!   as long as they don't exceed the request size limit of the server, compressed or not,
+//! - responses are compressed if the client accepts it (see its `Accept-Encoding` header), and sent as-is otherwise,
+//! - every response advertises the encodings accepted for requests (in an `Accept-Encoding` header, see RFC 7694),
+//!   so that clients only compress requests to servers that can decode them (see [crate::json_rpc_stuff::RpcCtx::with_request_compression]).
+
+use std::{
+    error::Error,
+    future::Future,
+    io::{self, Read, Write},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use flate2::{
+    read::{DeflateDecoder, GzDecoder},
+    write::{DeflateEncoder, GzEncoder},
+    Compression,
+};
+use hyper::{
+    body::HttpBody,
+    header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, UPGRADE, VARY},
+    Body, Request, Response, StatusCode,
+};
+use log::debug;
+
+use crate::constants::MAX_REQUEST_BODY_SIZE;
+
+/// The encodings we can decode, as advertised in `Accept-Encoding` headers.
+pub const SUPPORTED_ENCODINGS: &str = "gzip, deflate";
+
+/// Bodies smaller than this (in bytes) aren't worth compressing.
+pub const MIN_COMPRESSED_SIZE: usize = 1024;
+
+/// A (supported) content encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// The name of the encoding, as used in headers.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    /// The encoding named `name` (if we support it).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Picks the encoding to use given an `Accept-Encoding` header, if any of them is accepted
+    /// (gzip is preferred, and encodings with a quality of 0 are refused).
+    pub fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let accepted = accept_encoding
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let name = parts.next()?.trim();
+                let refused = parts.any(|param| {
+                    param
+                        .trim()
+                        .strip_prefix("q=")
+                        .and_then(|q| q.trim().parse::<f32>().ok())
+                        .map_or(false, |q| q == 0.0)
+                });
+                (!refused).then_some(name)
+            })
+            .collect::<Vec<_>>();
+        [Self::Gzip, Self::Deflate].into_iter().find(|encoding| {
+            accepted
+                .iter()
+                .any(|name| *name == "*" || Self::from_name(name) == Some(*encoding))
+        })
+    }
+
+    pub fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Self::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    /// Decodes `data`, failing if it decompresses to more than `max_size` bytes.
+    pub fn decode(&self, data: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+        let decoder: Box<dyn Read> = match self {
+            Self::Gzip => Box::new(GzDecoder::new(data)),
+            Self::Deflate => Box::new(DeflateDecoder::new(data)),
+        };
+        let mut decoded = Vec::new();
+        decoder
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut decoded)?;
+        if decoded.len() > max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the body decompresses to more than {max_size} bytes"),
+            ));
+        }
+        Ok(decoded)
+    }
+}
+
+/// Compresses the requests and responses of the wrapped service (see the [module documentation](self)).
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionLayer {
+    max_decompressed_size: usize,
+}
+
+impl CompressionLayer {
+    /// Compressed requests are refused if they exceed `max_request_size` (in bytes) before or after decompression:
+    /// this should be the limit the server enforces on plain requests (see [MAX_REQUEST_BODY_SIZE]).
+    pub fn new(max_request_size: usize) -> Self {
+        Self {
+            max_decompressed_size: max_request_size,
+        }
+    }
+}
+
+impl Default for CompressionLayer {
+    fn default() -> Self {
+        Self::new(MAX_REQUEST_BODY_SIZE as usize)
+    }
+}
+
+impl<S> tower::Layer<S> for CompressionLayer {
+    type Service = CompressionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CompressionService {
+            inner,
+            max_decompressed_size: self.max_decompressed_size,
+        }
+    }
+}
+
+/// See [CompressionLayer].
+#[derive(Debug, Clone)]
+pub struct CompressionService<S> {
+    inner: S,
+    max_decompressed_size: usize,
+}
+
+impl<S> tower::Service<Request<Body>> for CompressionService<S>
+where
+    S: tower::Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // the inner service is ready (not its clone), so it's the one we call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        // websocket upgrades (e.g. subscriptions) are passed through untouched
+        if request.headers().contains_key(UPGRADE) {
+            let fut = inner.call(request);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        }
+
+        let max_decompressed_size = self.max_decompressed_size;
+        Box::pin(async move {
+            let accepted = request
+                .headers()
+                .get(ACCEPT_ENCODING)
+                .and_then(|value| value.to_str().ok())
+                .and_then(ContentEncoding::negotiate);
+
+            let request = match decompress_request(request, max_decompressed_size).await {
+                Ok(request) => request,
+                Err(refusal) => return Ok(refusal),
+            };
+            let response = inner.call(request).await.map_err(Into::into)?;
+            compress_response(response, accepted).await
+        })
+    }
+}
+
+/// A plain response (e.g. to refuse a request before it reaches the inner service).
+fn plain_response(status: StatusCode, message: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(message));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        ACCEPT_ENCODING,
+        HeaderValue::from_static(SUPPORTED_ENCODINGS),
+    );
+    response
+}
+
+/// Decodes the body of a compressed request, or returns the response refusing it.
+/// Neither the compressed body nor the decoded one can exceed `max_size` bytes,
+/// and decoding happens on the blocking pool (so that large requests don't stall the runtime).
+async fn decompress_request(
+    request: Request<Body>,
+    max_size: usize,
+) -> Result<Request<Body>, Response<Body>> {
+    let Some(content_encoding) = request.headers().get(CONTENT_ENCODING) else {
+        return Ok(request);
+    };
+    let content_encoding = content_encoding.to_str().unwrap_or_default().to_string();
+    if content_encoding.trim().eq_ignore_ascii_case("identity") {
+        return Ok(request);
+    }
+    let Some(encoding) = ContentEncoding::from_name(&content_encoding) else {
+        return Err(plain_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("unsupported content encoding `{content_encoding}` (expected one of {SUPPORTED_ENCODINGS})"),
+        ));
+    };
+
+    let too_large = || {
+        plain_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("the request is larger than {max_size} bytes"),
+        )
+    };
+    if request.body().size_hint().lower() > max_size as u64 {
+        return Err(too_large());
+    }
+
+    let (mut parts, mut body) = request.into_parts();
+    let mut compressed = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|err| {
+            plain_response(
+                StatusCode::BAD_REQUEST,
+                format!("couldn't read the request: {err}"),
+            )
+        })?;
+        if compressed.len() + chunk.len() > max_size {
+            return Err(too_large());
+        }
+        compressed.extend_from_slice(&chunk);
+    }
+
+    let (compressed, decoded) = tokio::task::spawn_blocking(move || {
+        let decoded = encoding.decode(&compressed, max_size);
+        (compressed, decoded)
+    })
+    .await
+    .map_err(|err| {
+        plain_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("couldn't decode the request: {err}"),
+        )
+    })?;
+    let decoded = decoded.map_err(|err| {
+        plain_response(
+            StatusCode::BAD_REQUEST,
+            format!("couldn't decode the {} request: {err}", encoding.name()),
+        )
+    })?;
+    debug!(
+        "- decoded a {} request ({} bytes, {} decompressed)",
+        encoding.name(),
+        compressed.len(),
+        decoded.len()
+    );
+
+    parts.headers.remove(CONTENT_ENCODING);
+    parts
+        .headers
+        .insert(CONTENT_LENGTH, HeaderValue::from(decoded.len()));
+    Ok(Request::from_parts(parts, Body::from(decoded)))
+}
+
+/// Compresses a response with the encoding accepted by the client (if any),
+/// and advertises the encodings accepted for requests.
+async fn compress_response(
+    response: Response<Body>,
+    encoding: Option<ContentEncoding>,
+) -> Result<Response<Body>, Box<dyn Error + Send + Sync + 'static>> {
+    let (mut parts, body) = response.into_parts();
+    parts.headers.insert(
+        ACCEPT_ENCODING,
+        HeaderValue::from_static(SUPPORTED_ENCODINGS),
+    );
+    let Some(encoding) = encoding else {
+        return Ok(Response::from_parts(parts, body));
+    };
+    if parts.headers.contains_key(CONTENT_ENCODING) {
+        return Ok(Response::from_parts(parts, body));
+    }
+    parts
+        .headers
+        .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+    let body = hyper::body::to_bytes(body).await?;
+    if body.len() < MIN_COMPRESSED_SIZE {
+        return Ok(Response::from_parts(parts, Body::from(body)));
+    }
+    let encoded = encoding.encode(&body)?;
+    parts
+        .headers
+        .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+    parts
+        .headers
+        .insert(CONTENT_LENGTH, HeaderValue::from(encoded.len()));
+    Ok(Response::from_parts(parts, Body::from(encoded)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{Layer, ServiceExt};
+
+    use super::*;
+
+    async fn call(request: Request<Body>) -> Response<Body> {
+        // echoes the body of requests
+        let echo = tower::service_fn(|request: Request<Body>| async move {
+            Ok::<_, Infallible>(Response::new(request.into_body()))
+        });
+        CompressionLayer::default()
+            .layer(echo)
+            .oneshot(request)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(
+            ContentEncoding::negotiate("gzip, deflate, br"),
+            Some(ContentEncoding::Gzip)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate("deflate"),
+            Some(ContentEncoding::Deflate)
+        );
+        assert_eq!(
+            ContentEncoding::negotiate("gzip;q=0, deflate;q=0.5"),
+            Some(ContentEncoding::Deflate)
+        );
+        assert_eq!(ContentEncoding::negotiate("*"), Some(ContentEncoding::Gzip));
+        assert_eq!(ContentEncoding::negotiate("br"), None);
+        assert_eq!(ContentEncoding::negotiate(""), None);
+    }
+
+    #[test]
+    fn test_decode_is_bounded() {
+        let data = vec![0; 10_000];
+        for encoding in [ContentEncoding::Gzip, ContentEncoding::Deflate] {
+            let encoded = encoding.encode(&data).unwrap();
+            assert!(encoded.len() < data.len());
+            assert_eq!(encoding.decode(&encoded, data.len()).unwrap(), data);
+            assert!(encoding.decode(&encoded, data.len() - 1).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compressed_round_trip() {
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "round_1_signing",
+            "params": ["ab".repeat(5_000)],
+        })
+        .to_string();
+
+        // a compressed request is decoded, and the response is compressed
+        let request = Request::post("/")
+            .header(CONTENT_ENCODING, "gzip")
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::from(
+                ContentEncoding::Gzip.encode(body.as_bytes()).unwrap(),
+            ))
+            .unwrap();
+        let response = call(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[ACCEPT_ENCODING], SUPPORTED_ENCODINGS);
+        let compressed = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(compressed.len() < body.len());
+        let decoded = ContentEncoding::Gzip
+            .decode(&compressed, MAX_REQUEST_BODY_SIZE as usize)
+            .unwrap();
+        assert_eq!(decoded, body.as_bytes());
+
+        // a client that doesn't accept compression gets a plain response
+        let request = Request::post("/")
+            .header(CONTENT_ENCODING, "deflate")
+            .body(Body::from(
+                ContentEncoding::Deflate.encode(body.as_bytes()).unwrap(),
+            ))
+            .unwrap();
+        let response = call(request).await;
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+        let plain = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(plain, body.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_refused_requests() {
+        // unsupported encoding
+        let request = Request::post("/")
+            .header(CONTENT_ENCODING, "br")
+            .body(Body::from("whatever"))
+            .unwrap();
+        let response = call(request).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(response.headers()[ACCEPT_ENCODING], SUPPORTED_ENCODINGS);
+
+        // not actually gzip
+        let request = Request::post("/")
+            .header(CONTENT_ENCODING, "gzip")
+            .body(Body::from("not gzip"))
+            .unwrap();
+        assert_eq!(call(request).await.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_request_size_limit() {
+        let echo = tower::service_fn(|request: Request<Body>| async move {
+            Ok::<_, Infallible>(Response::new(request.into_body()))
+        });
+        let service = CompressionLayer::new(1_000).layer(echo);
+        let compressed_request = |data: &[u8]| {
+            Request::post("/")
+                .header(CONTENT_ENCODING, "gzip")
+                .body(Body::from(ContentEncoding::Gzip.encode(data).unwrap()))
+                .unwrap()
+        };
+
+        // within the limit
+        let response = service
+            .clone()
+            .oneshot(compressed_request(&[1; 1_000]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // a compressed body over the limit isn't even read
+        let random: Vec<u8> = (0..2_000).map(|_| rand::random()).collect();
+        let response = service
+            .clone()
+            .oneshot(compressed_request(&random))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // nor can a small body decompress past it
+        let response = service
+            .oneshot(compressed_request(&[1; 1_001]))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
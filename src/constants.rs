@@ -15,12 +15,34 @@ pub const ZKBITCOIN_FEE_PUBKEY: &str =
 /// Number of confirmation required for a transaction to be considered final.
 pub const MINIMUM_CONFIRMATIONS: usize = 0; // TODO: bad in prod?
 
+/// Number of confirmations a zkapp needs on mainnet before the committee signs a spend of it
+/// (so that it doesn't sign against a zkapp that later gets reorged out).
+pub const MIN_ZKAPP_CONFIRMATIONS_MAINNET: u64 = 6;
+
+/// Number of confirmations a zkapp needs on other networks (testnet, regtest) before the committee signs a spend of it.
+pub const MIN_ZKAPP_CONFIRMATIONS_TESTNET: u64 = 1;
+
 /// The JSON-RPC version to use with bitcoind.
 pub const BITCOIN_JSON_RPC_VERSION: &str = "1.0";
 
 /// The fee payable to the zkBitcoin fund.
 pub const FEE_ZKBITCOIN_SAT: u64 = 546; // see https://whattodevnow.medium.com/how-to-calculate-the-real-minimum-satoshis-amount-for-a-utxo-5941628ad3e8
 
+/// The minimum feerate (in sat/vB) committee nodes accept to co-sign a transaction with (except on regtest).
+pub const DEFAULT_MIN_FEERATE_SAT_VB: u64 = 1;
+
+/// The maximum fee (in satoshis) committee nodes accept to co-sign a transaction with (except on regtest).
+pub const DEFAULT_MAX_FEE_ABSOLUTE_SAT: u64 = 100_000;
+
+/// The number of blocks the orchestrator's fee estimates target, unless configured otherwise (see [crate::fee_strategy::FeeStrategy]).
+pub const DEFAULT_FEE_ESTIMATE_CONF_TARGET: u16 = 6;
+
+/// The highest feerate (in sat/vB) the orchestrator requires when following the fee estimates of its node.
+pub const DEFAULT_MAX_ESTIMATED_FEERATE_SAT_VB: u64 = 200;
+
+/// The maximum number of bytes that can be pushed in an OP_RETURN output for it to be standard (and relayed).
+pub const MAX_OP_RETURN_DATA_LEN: usize = 80;
+
 pub const ORCHESTRATOR_ADDRESS: &str = "http://64.23.148.42:8891";
 
 pub const CIRCOM_ETH_PRIME: &str =
@@ -38,6 +60,12 @@ pub const STATELESS_ZKAPP_PUBLIC_INPUT_LEN: usize = 1 /* truncated txid */;
 /// The expected number of public inputs for a stateful zkapp.
 pub const STATEFUL_ZKAPP_PUBLIC_INPUT_LEN: usize = 2 /* new state + prev state */ + 1 /* truncated txid */ + 1 /* amount_out */ + 1 /* amount_in */;
 
+/// The default maximum size (in bytes, serialized as JSON) of a proof a node verifies (a plonk proof is a few KB).
+pub const DEFAULT_MAX_PROOF_SIZE: usize = 64 * 1024;
+
+/// The default maximum number of public inputs of a proof a node verifies.
+pub const DEFAULT_MAX_PUBLIC_INPUTS: usize = 64;
+
 /// The number of seconds to sleep between orchestrator-node keepalive requests
 pub const KEEPALIVE_WAIT_SECONDS: u64 = 5;
 
@@ -45,3 +73,74 @@ pub const KEEPALIVE_WAIT_SECONDS: u64 = 5;
 pub const KEEPALIVE_MAX_RETRIES: u8 = 10;
 
 pub const MAX_SIGNING_TASK: usize = 100;
+
+/// The maximum size (in bytes) of a request to a committee node or the orchestrator,
+/// before and after decompression (see [crate::compression]).
+pub const MAX_REQUEST_BODY_SIZE: u32 = 10 * 1024 * 1024;
+
+/// The number of seconds a command printing the key package of a node can take (see [crate::committee::key_source]).
+pub const KEY_SOURCE_COMMAND_TIMEOUT_SECONDS: u64 = 30;
+
+/// The number of signing sessions in a row a member can fail before the orchestrator quarantines it.
+pub const MEMBER_MAX_FAILURES: u32 = 3;
+
+/// The number of seconds a member is quarantined for (see [MEMBER_MAX_FAILURES]).
+pub const MEMBER_QUARANTINE_SECONDS: u64 = 10 * 60;
+
+/// How long the orchestrator holds back calls to a member, to batch them with the calls of other signing sessions.
+pub const SIGNING_BATCH_WINDOW_MS: u64 = 50;
+
+/// The maximum number of items in a batch of calls to a member (members refuse larger batches).
+/// Members don't keep more signing tasks than that anyway.
+pub const MAX_SIGNING_BATCH: usize = MAX_SIGNING_TASK;
+
+/// The number of items of a batch a member processes at the same time.
+pub const SIGNING_BATCH_CONCURRENCY: usize = 8;
+
+/// The number of days the orchestrator keeps finished signing sessions for.
+pub const SESSION_HISTORY_RETENTION_DAYS: u64 = 30;
+
+/// How long (in seconds) a signing session can run before the orchestrator abandons it
+/// (e.g. when a member never answers).
+pub const SIGNING_SESSION_TIMEOUT_SECONDS: u64 = 5 * 60;
+
+/// How often (in seconds) the orchestrator garbage-collects stale signing sessions.
+pub const SESSION_REAPER_INTERVAL_SECONDS: u64 = 30;
+
+/// The number of signing sessions failing in a row after which the orchestrator alerts operators.
+pub const ALERT_CONSECUTIVE_FAILURES: usize = 3;
+
+/// How long (in seconds) the orchestrator waits for an alert webhook to answer.
+pub const ALERT_WEBHOOK_TIMEOUT_SECONDS: u64 = 10;
+
+/// How long (in milliseconds) the CLIs wait for the latest release before starting without checking their version.
+pub const VERSION_CHECK_TIMEOUT_MS: u64 = 1000;
+
+/// How long (in seconds) the result of a version check is reused before checking again.
+pub const VERSION_CHECK_INTERVAL_SECONDS: u64 = 24 * 60 * 60;
+
+/// The maximum length of the correlation id of a request (see [crate::bob_request::check_correlation_id]).
+pub const MAX_CORRELATION_ID_LEN: usize = 64;
+
+/// The maximum number of signing sessions the orchestrator returns at once.
+pub const SESSION_HISTORY_MAX_PAGE_SIZE: usize = 100;
+
+/// How long the orchestrator holds a request for the progress of a session, waiting for something new to happen
+/// (kept below the timeout of JSON-RPC clients).
+pub const SESSION_EVENTS_LONG_POLL_SECONDS: u64 = 20;
+
+/// The number of seconds the orchestrator caches the status of a zkapp for.
+pub const ZKAPP_UTXO_CACHE_TTL_SECONDS: u64 = 10;
+
+/// The maximum number of zkapps the orchestrator caches the status of.
+pub const ZKAPP_UTXO_CACHE_SIZE: usize = 1000;
+
+/// The default number of verifier keys that the orchestrator and the nodes keep ready to verify proofs (see [crate::vk_cache::VkCache]).
+pub const DEFAULT_VK_CACHE_SIZE: usize = 64;
+
+/// How often the tip of the chain is polled (see [crate::chain_tip::ChainTipTracker]).
+pub const CHAIN_TIP_POLL_SECONDS: u64 = 10;
+
+/// For how many blocks the orchestrator watches the transactions it broadcast, in case a reorg invalidates their zkapp
+/// (see [crate::committee::orchestrator::Orchestrator::with_chain_tips]).
+pub const REORG_WATCH_BLOCKS: u64 = 6;
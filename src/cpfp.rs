@@ -0,0 +1,149 @@
+//! Bumping the fee of unconfirmed transactions with a child paying for the package (CPFP).
+//! Transactions signed by the committee can't be replaced without another signing ceremony, but they can be bumped this way.
+
+use anyhow::{Context, Result};
+use bitcoin::{Amount, OutPoint, Transaction, TxIn, Txid};
+use tracing::info;
+
+use crate::json_rpc_stuff::{
+    get_mempool_entry, get_new_address, list_unspent, send_raw_transaction, sign_transaction,
+    RpcCtx, TransactionOrHex,
+};
+
+/// The result of a [cpfp_bump].
+#[derive(Debug, Clone)]
+pub struct CpfpBump {
+    /// The child transaction.
+    pub txid: Txid,
+
+    /// The fee paid by the child transaction.
+    pub child_fee: Amount,
+
+    /// The fee rate (in sat/vB) of the parent and child together.
+    pub package_feerate: f64,
+}
+
+/// Returns the fee rate (in sat/vB) of a package of transactions.
+pub fn package_feerate(fees: Amount, vsize: u64) -> f64 {
+    fees.to_sat() as f64 / vsize as f64
+}
+
+/// Computes the fee a child transaction must pay so that the package (parent + child)
+/// pays `target_feerate` (in sat/vB). The child always pays at least 1 sat/vB for itself.
+pub fn cpfp_child_fee(
+    parent_vsize: u64,
+    parent_fee: Amount,
+    child_vsize: u64,
+    target_feerate: u64,
+) -> Result<Amount> {
+    let package_fee = Amount::from_sat(target_feerate * (parent_vsize + child_vsize));
+    let child_fee = package_fee
+        .checked_sub(parent_fee)
+        .filter(|fee| *fee > Amount::ZERO)
+        .with_context(|| {
+            format!(
+                "the parent already pays {:.2} sat/vB, which is more than {target_feerate} sat/vB",
+                package_feerate(parent_fee, parent_vsize)
+            )
+        })?;
+    Ok(child_fee.max(Amount::from_sat(child_vsize)))
+}
+
+/// Bumps the fee of an unconfirmed transaction by spending one of its outputs (that must belong to the wallet)
+/// with a child paying enough fees for the package to reach `target_feerate` (in sat/vB).
+/// This is useful for transactions signed by the committee, which can't be replaced without another signing ceremony.
+pub async fn cpfp_bump(ctx: &RpcCtx, parent_txid: Txid, target_feerate: u64) -> Result<CpfpBump> {
+    let parent = get_mempool_entry(ctx, parent_txid)
+        .await
+        .with_context(|| format!("{parent_txid} is not in the mempool (is it confirmed?)"))?;
+
+    // use the largest output of the parent that the wallet can spend
+    let utxo = list_unspent(ctx)
+        .await?
+        .into_iter()
+        .filter(|utxo| utxo.txid == parent_txid && utxo.spendable)
+        .max_by_key(|utxo| utxo.amount)
+        .with_context(|| {
+            format!("none of the outputs of {parent_txid} belong to the wallet, can't bump it")
+        })?;
+    let outpoint = OutPoint {
+        txid: utxo.txid,
+        vout: utxo.vout,
+    };
+
+    // the child sends the output back to the wallet, minus the fee
+    let address = get_new_address(ctx).await?;
+    let mut tx = Transaction {
+        version: bitcoin::transaction::Version::TWO,
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            sequence: bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME,
+            ..Default::default()
+        }],
+        output: vec![bitcoin::TxOut {
+            value: utxo.amount,
+            script_pubkey: address.script_pubkey(),
+        }],
+    };
+
+    // sign once to learn the size of the child
+    let (_, signed_tx) = sign_transaction(ctx, TransactionOrHex::Transaction(&tx)).await?;
+    let child_fee = cpfp_child_fee(
+        parent.vsize,
+        parent.fees.base,
+        signed_tx.vsize() as u64,
+        target_feerate,
+    )?;
+    let dust_threshold = tx.output[0].script_pubkey.dust_value();
+    tx.output[0].value = utxo
+        .amount
+        .checked_sub(child_fee)
+        .filter(|value| *value >= dust_threshold)
+        .with_context(|| {
+            format!(
+                "the output {outpoint} ({}) can't pay a child fee of {child_fee}",
+                utxo.amount
+            )
+        })?;
+
+    let (_, signed_tx) = sign_transaction(ctx, TransactionOrHex::Transaction(&tx)).await?;
+    let txid = send_raw_transaction(ctx, TransactionOrHex::Transaction(&signed_tx)).await?;
+
+    let package_feerate = package_feerate(
+        parent.fees.base + child_fee,
+        parent.vsize + signed_tx.vsize() as u64,
+    );
+    info!(
+        "- bumped {parent_txid} with child {txid} (package fee rate: {package_feerate:.2} sat/vB)"
+    );
+
+    Ok(CpfpBump {
+        txid,
+        child_fee,
+        package_feerate,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpfp_child_fee() {
+        // parent: 200 vB paying 1 sat/vB, child: 110 vB, target: 10 sat/vB
+        let child_fee = cpfp_child_fee(200, Amount::from_sat(200), 110, 10).unwrap();
+        assert_eq!(child_fee, Amount::from_sat(10 * 310 - 200));
+        assert_eq!(
+            package_feerate(Amount::from_sat(200) + child_fee, 310),
+            10.0
+        );
+
+        // the child pays for itself at least
+        let child_fee = cpfp_child_fee(200, Amount::from_sat(2_100), 110, 7).unwrap();
+        assert_eq!(child_fee, Amount::from_sat(110));
+
+        // the parent already pays enough
+        assert!(cpfp_child_fee(200, Amount::from_sat(4_000), 110, 10).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! The service fee paid to the zkBitcoin fund (see [ZKBITCOIN_FEE_PUBKEY]) when a zkapp is spent.
+//! The fee is deducted from the amount leaving the zkapps, and the orchestrator refuses to sign spends that don't pay it.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::{Amount, PublicKey, ScriptBuf, Transaction, TxOut};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::{FEE_ZKBITCOIN_SAT, ZKBITCOIN_FEE_PUBKEY},
+    mpc_sign_tx::committee_inputs,
+    p2tr_script_to, zkbitcoin_pubkey,
+};
+
+/// The number of basis points in 100%.
+const BASIS_POINTS: u64 = 10_000;
+
+/// How much a spend pays to the zkBitcoin fund.
+/// The fee is never below [FEE_ZKBITCOIN_SAT], which is what committee nodes accept at the very least.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeePolicy {
+    /// A fixed fee (in satoshis).
+    Flat(u64),
+
+    /// A fraction of the spent amount, in basis points (1/100th of a percent), rounded up.
+    Percentage(u64),
+}
+
+impl Default for FeePolicy {
+    fn default() -> Self {
+        Self::Flat(FEE_ZKBITCOIN_SAT)
+    }
+}
+
+impl fmt::Display for FeePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flat(sats) => write!(f, "{sats} sat"),
+            Self::Percentage(basis_points) => write!(f, "{}%", *basis_points as f64 / 100.0),
+        }
+    }
+}
+
+/// Parses a flat fee in satoshis (e.g. `1000`), or a percentage of the spent amount (e.g. `0.5%`).
+impl FromStr for FeePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(percentage) = s.strip_suffix('%') {
+            let percentage: f64 = percentage
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid percentage: {s}"))?;
+            ensure!(
+                (0.0..=100.0).contains(&percentage),
+                "the fee must be between 0% and 100% (got {s})"
+            );
+            let basis_points = (percentage * 100.0).round() as u64;
+            return Ok(Self::Percentage(basis_points));
+        }
+        let sats = s
+            .strip_suffix("sat")
+            .unwrap_or(s)
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid fee (expected satoshis or a percentage): {s}"))?;
+        Ok(Self::Flat(sats))
+    }
+}
+
+/// The script of the zkBitcoin fund, which the fee is paid to.
+pub fn fee_script() -> ScriptBuf {
+    p2tr_script_to(PublicKey::from_str(ZKBITCOIN_FEE_PUBKEY).unwrap())
+}
+
+/// The amount leaving the committee in a transaction: what its committee inputs hold, minus what goes back to the committee
+/// (e.g. the new state of a stateful zkapp).
+pub fn spent_amount(tx: &Transaction, prev_outs: &[TxOut]) -> Result<Amount> {
+    let committee_script = p2tr_script_to(zkbitcoin_pubkey()?);
+    let unlocked: Amount = committee_inputs(prev_outs)?
+        .into_iter()
+        .map(|idx| prev_outs[idx].value)
+        .sum();
+    let relocked: Amount = tx
+        .output
+        .iter()
+        .filter(|output| output.script_pubkey == committee_script)
+        .map(|output| output.value)
+        .sum();
+    Ok(unlocked.checked_sub(relocked).unwrap_or(Amount::ZERO))
+}
+
+impl FeePolicy {
+    /// The fee for spending `spent` out of the zkapps.
+    pub fn fee(&self, spent: Amount) -> Amount {
+        let fee = match self {
+            Self::Flat(sats) => *sats,
+            Self::Percentage(basis_points) => {
+                let fee = spent.to_sat() as u128 * *basis_points as u128;
+                fee.div_ceil(BASIS_POINTS as u128) as u64
+            }
+        };
+        Amount::from_sat(fee.max(FEE_ZKBITCOIN_SAT))
+    }
+
+    /// Splits `spent` between the fee and what's left for the recipient (paid to `recipient`),
+    /// failing if what's left would be dust (and the output wouldn't relay).
+    pub fn deduct(&self, spent: Amount, recipient: &ScriptBuf) -> Result<(Amount, Amount)> {
+        let fee = self.fee(spent);
+        let Some(remaining) = spent.checked_sub(fee) else {
+            bail!("the fee of {fee} ({self}) is more than the spent amount of {spent}");
+        };
+        let dust = recipient.dust_value();
+        ensure!(
+            remaining >= dust,
+            "after the fee of {fee} ({self}), only {remaining} is left of {spent}, which is dust (below {dust})"
+        );
+        Ok((fee, remaining))
+    }
+
+    /// Checks that a transaction (spending `prev_outs`) pays the fee to the zkBitcoin fund,
+    /// and that what's left of the spent amount isn't dust. Returns the fee.
+    pub fn check_spend(&self, tx: &Transaction, prev_outs: &[TxOut]) -> Result<Amount> {
+        let fee_script = fee_script();
+        let spent = spent_amount(tx, prev_outs)?;
+        let fee = self.fee(spent);
+        let paid: Amount = tx
+            .output
+            .iter()
+            .filter(|output| output.script_pubkey == fee_script)
+            .map(|output| output.value)
+            .sum();
+        ensure!(
+            paid >= fee,
+            "the transaction pays {paid} to the zkBitcoin fund, but the fee policy ({self}) requires {fee} for spending {spent}"
+        );
+
+        // nothing is left for the recipient if it's only a deposit (which the wallet pays the fee of)
+        if spent > Amount::ZERO {
+            self.deduct(spent, &fee_script)?;
+        }
+
+        Ok(fee)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, transaction::Version, TxIn};
+
+    use super::*;
+
+    fn spend_tx(outputs: Vec<TxOut>) -> (Transaction, Vec<TxOut>) {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: outputs,
+        };
+        let prev_outs = vec![TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: p2tr_script_to(zkbitcoin_pubkey().unwrap()),
+        }];
+        (tx, prev_outs)
+    }
+
+    #[test]
+    fn test_parse_fee_policy() {
+        assert_eq!("1000".parse::<FeePolicy>().unwrap(), FeePolicy::Flat(1000));
+        assert_eq!(
+            "1000 sat".parse::<FeePolicy>().unwrap(),
+            FeePolicy::Flat(1000)
+        );
+        assert_eq!(
+            "0.5%".parse::<FeePolicy>().unwrap(),
+            FeePolicy::Percentage(50)
+        );
+        assert!("101%".parse::<FeePolicy>().is_err());
+        assert!("a lot".parse::<FeePolicy>().is_err());
+        assert_eq!(FeePolicy::Percentage(50).to_string(), "0.5%");
+    }
+
+    #[test]
+    fn test_flat_fee() {
+        let policy = FeePolicy::Flat(1_000);
+        assert_eq!(
+            policy.fee(Amount::from_sat(100_000)),
+            Amount::from_sat(1_000)
+        );
+        assert_eq!(policy.fee(Amount::ZERO), Amount::from_sat(1_000));
+
+        // never below what nodes accept
+        assert_eq!(
+            FeePolicy::Flat(1).fee(Amount::from_sat(100_000)),
+            Amount::from_sat(FEE_ZKBITCOIN_SAT)
+        );
+
+        let (fee, remaining) = policy
+            .deduct(Amount::from_sat(100_000), &fee_script())
+            .unwrap();
+        assert_eq!(fee, Amount::from_sat(1_000));
+        assert_eq!(remaining, Amount::from_sat(99_000));
+    }
+
+    #[test]
+    fn test_percentage_fee() {
+        let policy = FeePolicy::Percentage(150);
+        assert_eq!(
+            policy.fee(Amount::from_sat(100_000)),
+            Amount::from_sat(1_500)
+        );
+
+        // rounded up
+        assert_eq!(
+            policy.fee(Amount::from_sat(100_001)),
+            Amount::from_sat(1_501)
+        );
+
+        // and never below what nodes accept
+        assert_eq!(
+            policy.fee(Amount::from_sat(1_000)),
+            Amount::from_sat(FEE_ZKBITCOIN_SAT)
+        );
+    }
+
+    #[test]
+    fn test_dust_after_fee() {
+        let policy = FeePolicy::Flat(1_000);
+        let recipient = fee_script();
+
+        // just enough is left
+        let spent = Amount::from_sat(1_000) + recipient.dust_value();
+        policy.deduct(spent, &recipient).unwrap();
+
+        // dust is left
+        let err = policy
+            .deduct(spent - Amount::from_sat(1), &recipient)
+            .unwrap_err();
+        assert!(err.to_string().contains("dust"), "{err}");
+
+        // nothing is left
+        assert!(policy.deduct(Amount::from_sat(500), &recipient).is_err());
+    }
+
+    #[test]
+    fn test_check_spend() {
+        let policy = FeePolicy::Percentage(100);
+        let recipient = TxOut {
+            value: Amount::from_sat(99_000),
+            script_pubkey: ScriptBuf::new(),
+        };
+
+        // 1% of the 100_000 sats spent
+        let (tx, prev_outs) = spend_tx(vec![
+            TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: fee_script(),
+            },
+            recipient.clone(),
+        ]);
+        assert_eq!(
+            spent_amount(&tx, &prev_outs).unwrap(),
+            Amount::from_sat(100_000)
+        );
+        assert_eq!(
+            policy.check_spend(&tx, &prev_outs).unwrap(),
+            Amount::from_sat(1_000)
+        );
+
+        // too little
+        let (tx, prev_outs) = spend_tx(vec![
+            TxOut {
+                value: Amount::from_sat(FEE_ZKBITCOIN_SAT),
+                script_pubkey: fee_script(),
+            },
+            recipient.clone(),
+        ]);
+        assert!(policy.check_spend(&tx, &prev_outs).is_err());
+
+        // what goes back to the committee isn't spent
+        let (tx, prev_outs) = spend_tx(vec![
+            TxOut {
+                value: Amount::from_sat(FEE_ZKBITCOIN_SAT),
+                script_pubkey: fee_script(),
+            },
+            TxOut {
+                value: Amount::from_sat(90_000),
+                script_pubkey: p2tr_script_to(zkbitcoin_pubkey().unwrap()),
+            },
+            recipient,
+        ]);
+        assert_eq!(
+            spent_amount(&tx, &prev_outs).unwrap(),
+            Amount::from_sat(10_000)
+        );
+        policy.check_spend(&tx, &prev_outs).unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+//! The feerate the orchestrator requires of the transactions it signs: a fixed one,
+//! or the estimate of its chain backend (see [ChainBackend::estimate_fee_rate]) within bounds.
+//! The feerate chosen for a session, and where it comes from, is recorded in the session history
+//! (see [crate::committee::session_history::SessionEvent::FeeRateChosen]).
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use bitcoin::{Amount, FeeRate, Transaction, TxOut};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chain_backend::ChainBackend,
+    constants::{
+        DEFAULT_FEE_ESTIMATE_CONF_TARGET, DEFAULT_MAX_ESTIMATED_FEERATE_SAT_VB,
+        DEFAULT_MIN_FEERATE_SAT_VB,
+    },
+    get_network,
+    json_rpc_stuff::EstimateMode,
+    tx_sanity::{check_fee_limits, FeeLimits, FeeRefusal},
+};
+
+/// How the orchestrator picks the feerate that the transactions it signs must pay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeStrategy {
+    /// Always the same feerate.
+    Fixed(FeeRate),
+
+    /// The estimate of the node for confirming within `conf_target` blocks, capped at `max`.
+    /// If the node has no estimate (e.g. on a fresh regtest chain), or can't be reached, `fallback` is used.
+    Estimate {
+        conf_target: u16,
+        mode: EstimateMode,
+        max: FeeRate,
+        fallback: FeeRate,
+    },
+}
+
+/// The same minimum feerate as committee nodes (see [FeeLimits::for_network]).
+impl Default for FeeStrategy {
+    fn default() -> Self {
+        Self::Fixed(FeeLimits::for_network(get_network()).min_feerate)
+    }
+}
+
+impl fmt::Display for FeeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed(fee_rate) => write!(f, "{} sat/vB", fee_rate.to_sat_per_vb_ceil()),
+            Self::Estimate {
+                conf_target,
+                mode,
+                max,
+                fallback,
+            } => write!(
+                f,
+                "estimated for {conf_target} blocks ({mode:?}), at most {} sat/vB, {} sat/vB without an estimate",
+                max.to_sat_per_vb_ceil(),
+                fallback.to_sat_per_vb_ceil()
+            ),
+        }
+    }
+}
+
+/// A feerate in sat/vB.
+fn parse_sat_per_vb(s: &str) -> Result<FeeRate> {
+    let sat_per_vb: u64 = s
+        .trim()
+        .strip_suffix("sat/vB")
+        .unwrap_or(s)
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid feerate (expected sat/vB): {s}"))?;
+    FeeRate::from_sat_per_vb(sat_per_vb).with_context(|| format!("the feerate {s} is too high"))
+}
+
+/// Parses a fixed feerate in sat/vB (e.g. `5`), or `estimate[:<conf_target>[:<mode>]]` (e.g. `estimate:3:conservative`)
+/// to follow the estimates of the node, up to [DEFAULT_MAX_ESTIMATED_FEERATE_SAT_VB],
+/// and falling back to [DEFAULT_MIN_FEERATE_SAT_VB] (see [FeeStrategy::with_bounds] to change these).
+impl FromStr for FeeStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().split(':');
+        if parts.next() != Some("estimate") {
+            return Ok(Self::Fixed(parse_sat_per_vb(s)?));
+        }
+        let conf_target = match parts.next() {
+            Some(conf_target) => conf_target
+                .parse()
+                .with_context(|| format!("invalid confirmation target: {conf_target}"))?,
+            None => DEFAULT_FEE_ESTIMATE_CONF_TARGET,
+        };
+        let mode = match parts.next() {
+            Some(mode) => mode.parse()?,
+            None => EstimateMode::default(),
+        };
+        if parts.next().is_some() {
+            bail!("invalid fee strategy {s:?} (expected estimate[:<conf_target>[:<mode>]])");
+        }
+        Ok(Self::Estimate {
+            conf_target,
+            mode,
+            max: FeeRate::from_sat_per_vb(DEFAULT_MAX_ESTIMATED_FEERATE_SAT_VB).unwrap(),
+            fallback: FeeRate::from_sat_per_vb(DEFAULT_MIN_FEERATE_SAT_VB).unwrap(),
+        })
+    }
+}
+
+/// Where the feerate of a session comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeRateSource {
+    /// The fixed feerate of the orchestrator.
+    Fixed,
+
+    /// The estimate of the node.
+    Estimate,
+
+    /// The estimate of the node was above the maximum, so the maximum was used.
+    Clamped,
+
+    /// The node had no estimate, so the fallback feerate was used.
+    Fallback,
+}
+
+/// The feerate chosen for a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeRateChoice {
+    /// In sat/vB (rounded up).
+    pub sat_per_vb: u64,
+
+    pub source: FeeRateSource,
+}
+
+impl FeeStrategy {
+    /// Changes the maximum and/or the fallback feerate of an estimate strategy (fixed strategies are left as they are).
+    pub fn with_bounds(mut self, max: Option<FeeRate>, fallback: Option<FeeRate>) -> Self {
+        if let Self::Estimate {
+            max: current_max,
+            fallback: current_fallback,
+            ..
+        } = &mut self
+        {
+            *current_max = max.unwrap_or(*current_max);
+            *current_fallback = fallback.unwrap_or(*current_fallback);
+        }
+        self
+    }
+
+    /// Picks the feerate, given the estimate of the node (if any).
+    pub fn choose(&self, estimate: Option<FeeRate>) -> FeeRateChoice {
+        let (fee_rate, source) = match *self {
+            Self::Fixed(fee_rate) => (fee_rate, FeeRateSource::Fixed),
+            Self::Estimate { max, fallback, .. } => match estimate {
+                None => (fallback, FeeRateSource::Fallback),
+                Some(estimate) if estimate > max => (max, FeeRateSource::Clamped),
+                Some(estimate) => (estimate, FeeRateSource::Estimate),
+            },
+        };
+        FeeRateChoice {
+            sat_per_vb: fee_rate.to_sat_per_vb_ceil(),
+            source,
+        }
+    }
+
+    /// Picks the feerate, asking the chain (if any) for an estimate.
+    pub async fn resolve(&self, chain: Option<&dyn ChainBackend>) -> FeeRateChoice {
+        let estimate = match (self, chain) {
+            (
+                Self::Estimate {
+                    conf_target, mode, ..
+                },
+                Some(chain),
+            ) => match chain.estimate_fee_rate(*conf_target, *mode).await {
+                Ok(estimate) => estimate,
+                Err(err) => {
+                    warn!("- couldn't estimate the feerate, using the fallback: {err:#}");
+                    None
+                }
+            },
+            _ => None,
+        };
+        self.choose(estimate)
+    }
+}
+
+impl FeeRateChoice {
+    pub fn fee_rate(&self) -> FeeRate {
+        FeeRate::from_sat_per_vb(self.sat_per_vb).unwrap_or(FeeRate::MAX)
+    }
+
+    /// Checks that a transaction (spending `prev_outs`) pays at least the chosen feerate.
+    pub fn check(&self, tx: &Transaction, prev_outs: &[TxOut]) -> Result<(), FeeRefusal> {
+        let limits = FeeLimits {
+            min_feerate: self.fee_rate(),
+            max_fee_absolute: Amount::MAX_MONEY,
+        };
+        check_fee_limits(tx, prev_outs, &limits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, transaction::Version, ScriptBuf, TxIn};
+
+    use super::*;
+    use crate::{
+        chain_backend::{BitcoindBackend, FakeChain},
+        json_rpc_stuff::RpcCtx,
+    };
+
+    fn sat_per_vb(sat_per_vb: u64) -> FeeRate {
+        FeeRate::from_sat_per_vb(sat_per_vb).unwrap()
+    }
+
+    fn estimate_strategy() -> FeeStrategy {
+        FeeStrategy::Estimate {
+            conf_target: 6,
+            mode: EstimateMode::Economical,
+            max: sat_per_vb(50),
+            fallback: sat_per_vb(2),
+        }
+    }
+
+    #[test]
+    fn test_parse_fee_strategy() {
+        assert_eq!(
+            "5".parse::<FeeStrategy>().unwrap(),
+            FeeStrategy::Fixed(sat_per_vb(5))
+        );
+        assert_eq!(
+            "5 sat/vB".parse::<FeeStrategy>().unwrap(),
+            FeeStrategy::Fixed(sat_per_vb(5))
+        );
+        assert_eq!(
+            "estimate".parse::<FeeStrategy>().unwrap(),
+            FeeStrategy::Estimate {
+                conf_target: DEFAULT_FEE_ESTIMATE_CONF_TARGET,
+                mode: EstimateMode::Unset,
+                max: sat_per_vb(DEFAULT_MAX_ESTIMATED_FEERATE_SAT_VB),
+                fallback: sat_per_vb(DEFAULT_MIN_FEERATE_SAT_VB),
+            }
+        );
+        assert_eq!(
+            "estimate:6:economical"
+                .parse::<FeeStrategy>()
+                .unwrap()
+                .with_bounds(Some(sat_per_vb(50)), Some(sat_per_vb(2))),
+            estimate_strategy()
+        );
+        assert!("estimate:soon".parse::<FeeStrategy>().is_err());
+        assert!("estimate:6:cheap".parse::<FeeStrategy>().is_err());
+        assert!("cheap".parse::<FeeStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_fixed() {
+        let choice = FeeStrategy::Fixed(sat_per_vb(3)).choose(Some(sat_per_vb(10)));
+        assert_eq!(
+            choice,
+            FeeRateChoice {
+                sat_per_vb: 3,
+                source: FeeRateSource::Fixed
+            }
+        );
+    }
+
+    #[test]
+    fn test_estimate_is_clamped() {
+        let strategy = estimate_strategy();
+        assert_eq!(
+            strategy.choose(Some(sat_per_vb(12))),
+            FeeRateChoice {
+                sat_per_vb: 12,
+                source: FeeRateSource::Estimate
+            }
+        );
+        assert_eq!(
+            strategy.choose(Some(sat_per_vb(50))).source,
+            FeeRateSource::Estimate
+        );
+        assert_eq!(
+            strategy.choose(Some(sat_per_vb(300))),
+            FeeRateChoice {
+                sat_per_vb: 50,
+                source: FeeRateSource::Clamped
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let strategy = estimate_strategy();
+        let fallback = FeeRateChoice {
+            sat_per_vb: 2,
+            source: FeeRateSource::Fallback,
+        };
+        assert_eq!(strategy.choose(None), fallback);
+
+        // without a node, or with a node that can't be reached
+        assert_eq!(strategy.resolve(None).await, fallback);
+        let unreachable =
+            BitcoindBackend::new(RpcCtx::builder().url("http://127.0.0.1:1").build().unwrap());
+        assert_eq!(strategy.resolve(Some(&unreachable)).await, fallback);
+
+        // or without an estimate
+        let chain = FakeChain::default();
+        assert_eq!(strategy.resolve(Some(&chain)).await, fallback);
+        chain.set_fee_rate(Some(sat_per_vb(10)));
+        assert_eq!(strategy.resolve(Some(&chain)).await.sat_per_vb, 10);
+    }
+
+    #[test]
+    fn test_check() {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: Amount::from_sat(9_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let prev_outs = [TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new(),
+        }];
+
+        // 1000 sat for a transaction of less than 100 vB
+        let choice = FeeStrategy::Fixed(sat_per_vb(10)).choose(None);
+        choice.check(&tx, &prev_outs).unwrap();
+        let choice = FeeStrategy::Fixed(sat_per_vb(100)).choose(None);
+        let refusal = choice.check(&tx, &prev_outs).unwrap_err();
+        assert_eq!(refusal.min_feerate, 100.0);
+    }
+}
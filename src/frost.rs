@@ -1,13 +1,63 @@
-use bitcoin::sighash::{Prevouts, SighashCache};
-use bitcoin::{TapSighashType, Transaction, TxOut};
+use bitcoin::{Transaction, TxOut};
 use frost_secp256k1_tr as frost;
-use frost_secp256k1_tr::Signature;
+use frost_secp256k1_tr::{Field, Group, Signature};
+use itertools::Itertools;
 use rand::thread_rng;
 use secp256k1::XOnlyPublicKey;
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::Deref,
+};
+use zeroize::Zeroizing;
+
+use crate::{redact::Redacted, sighash::compute_keyspend_sighash};
 
 pub use frost::keys::{KeyPackage, PublicKeyPackage};
-use secp256k1::hashes::Hash;
+pub use frost::Identifier;
+
+//
+// Secret material
+//
+
+/// The key package of a committee member, wiped from memory when dropped.
+/// Its `Debug` output never includes the signing share.
+#[derive(Clone)]
+pub struct SecretKeyPackage(Zeroizing<KeyPackage>);
+
+impl SecretKeyPackage {
+    pub fn new(key_package: KeyPackage) -> Self {
+        Self(Zeroizing::new(key_package))
+    }
+}
+
+impl From<KeyPackage> for SecretKeyPackage {
+    fn from(key_package: KeyPackage) -> Self {
+        Self::new(key_package)
+    }
+}
+
+impl Deref for SecretKeyPackage {
+    type Target = KeyPackage;
+
+    fn deref(&self) -> &KeyPackage {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretKeyPackage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretKeyPackage")
+            .field("identifier", self.0.identifier())
+            .field("signing_share", &Redacted(self.0.signing_share()))
+            .field("verifying_share", self.0.verifying_share())
+            .finish()
+    }
+}
+
+/// The nonces of a signing session, wiped from memory when dropped.
+/// They must only ever be used once (in round 2), and dropped right after.
+pub type SecretNonces = Zeroizing<frost::round1::SigningNonces>;
 
 //
 // Functions to test our flow
@@ -151,7 +201,7 @@ pub fn to_xonly_pubkey(verifying_key: &frost::VerifyingKey) -> XOnlyPublicKey {
     XOnlyPublicKey::from_slice(&serialized_pubkey[1..]).unwrap()
 }
 
-fn sign(
+pub(crate) fn sign(
     key_packages: &BTreeMap<frost::Identifier, frost::keys::KeyPackage>,
     pubkey_package: &frost::keys::PublicKeyPackage,
     message: &[u8],
@@ -231,28 +281,8 @@ pub fn sign_transaction_frost(
     prevouts: &[TxOut],
 ) -> secp256k1::schnorr::Signature {
     // the first input is the taproot UTXO we want to spend
-    let tx_ind = 0;
-
-    // the sighash flag is always ALL
-    let hash_ty = TapSighashType::All;
-
-    // sighash
-    let mut cache = SighashCache::new(tx);
-    let mut sig_msg = Vec::new();
-    cache
-        .taproot_encode_signing_data_to(
-            &mut sig_msg,
-            tx_ind,
-            &Prevouts::All(prevouts),
-            None,
-            None,
-            hash_ty,
-        )
-        .unwrap();
-    let sighash = cache
-        .taproot_signature_hash(tx_ind, &Prevouts::All(prevouts), None, None, hash_ty)
-        .unwrap();
-    let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+    let sighash = compute_keyspend_sighash(tx, 0, prevouts).unwrap();
+    let msg = secp256k1::Message::from_digest(sighash);
 
     // secp.sign_schnorr_with_aux_rand(&msg, &tweaked_keypair, &[0u8; 32])
 
@@ -262,6 +292,232 @@ pub fn sign_transaction_frost(
     secp256k1::schnorr::Signature::from_slice(signature.serialize()[1..].as_ref()).unwrap()
 }
 
+//
+// Functions to check key material
+//
+
+/// Produces a signature over `message` using exactly the given signers.
+pub fn sign_with(
+    signers: &[&frost::keys::KeyPackage],
+    pubkey_package: &frost::keys::PublicKeyPackage,
+    message: &[u8],
+) -> Result<Signature, frost::Error> {
+    let rng = &mut thread_rng();
+
+    // round 1
+    let mut nonces_map = BTreeMap::new();
+    let mut commitments_map = BTreeMap::new();
+    for key_package in signers {
+        let (nonces, commitments) = frost::round1::commit(key_package.signing_share(), rng);
+        nonces_map.insert(*key_package.identifier(), nonces);
+        commitments_map.insert(*key_package.identifier(), commitments);
+    }
+
+    // round 2
+    let signing_package = frost::SigningPackage::new(commitments_map, message);
+    let mut signature_shares = BTreeMap::new();
+    for key_package in signers {
+        let nonces = &nonces_map[key_package.identifier()];
+        let signature_share = frost::round2::sign(&signing_package, nonces, key_package)?;
+        signature_shares.insert(*key_package.identifier(), signature_share);
+    }
+
+    // aggregate (also verifies the signature shares)
+    frost::aggregate(&signing_package, &signature_shares, pubkey_package)
+}
+
+/// Checks that a key share belongs to the committee described by the public key package
+/// (and not, for example, to another key generation ceremony).
+pub fn check_key_package(
+    key_package: &frost::keys::KeyPackage,
+    pubkey_package: &frost::keys::PublicKeyPackage,
+) -> anyhow::Result<()> {
+    let id = key_package.identifier();
+    anyhow::ensure!(
+        key_package.verifying_key() == pubkey_package.verifying_key(),
+        "key share does not match public key package: the group keys differ"
+    );
+
+    let expected_verifying_share = pubkey_package.verifying_shares().get(id).ok_or_else(|| {
+        anyhow::anyhow!(
+            "key share does not match public key package: {id:?} is not part of the committee"
+        )
+    })?;
+    anyhow::ensure!(
+        key_package.verifying_share() == expected_verifying_share,
+        "key share does not match public key package: the verification shares of {id:?} differ"
+    );
+
+    let derived_verifying_share = frost::keys::VerifyingShare::from(*key_package.signing_share());
+    anyhow::ensure!(
+        &derived_verifying_share == key_package.verifying_share(),
+        "key share {id:?} is corrupted: its signing share doesn't match its verification share"
+    );
+
+    Ok(())
+}
+
+/// Checks that the given key packages all belong to the committee described by the public key package:
+/// each share must match its verification share in the public key package,
+/// and any `min_signers` of them must be able to produce a signature that verifies under the group key.
+/// The error returned points at the share that failed.
+pub fn verify_key_packages(
+    key_packages: &BTreeMap<frost::Identifier, frost::keys::KeyPackage>,
+    pubkey_package: &frost::keys::PublicKeyPackage,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(!key_packages.is_empty(), "no key shares were given");
+
+    // check each share individually
+    let mut min_signers = None;
+    for (id, key_package) in key_packages {
+        anyhow::ensure!(
+            key_package.identifier() == id,
+            "share {id:?}: identifier doesn't match (found {:?})",
+            key_package.identifier()
+        );
+
+        check_key_package(key_package, pubkey_package)
+            .map_err(|err| anyhow::anyhow!("share {id:?}: {err}"))?;
+
+        match min_signers {
+            None => min_signers = Some(*key_package.min_signers()),
+            Some(min_signers) => anyhow::ensure!(
+                min_signers == *key_package.min_signers(),
+                "share {id:?}: threshold ({}) differs from other shares ({min_signers})",
+                key_package.min_signers()
+            ),
+        }
+    }
+
+    // check that any threshold of them can sign
+    let min_signers = min_signers.unwrap() as usize;
+    anyhow::ensure!(
+        key_packages.len() >= min_signers,
+        "only {} shares were given, but {min_signers} are needed to sign",
+        key_packages.len()
+    );
+
+    let message = b"zkBitcoin key verification";
+    for signers in key_packages.values().combinations(min_signers) {
+        let ids = signers.iter().map(|k| *k.identifier()).collect::<Vec<_>>();
+        let signature = match sign_with(&signers, pubkey_package, message) {
+            Ok(signature) => signature,
+            Err(frost::Error::InvalidSignatureShare { culprit }) => {
+                anyhow::bail!("share {culprit:?}: produced an invalid signature share")
+            }
+            Err(err) => anyhow::bail!("couldn't sign with shares {ids:?}: {err}"),
+        };
+
+        anyhow::ensure!(
+            pubkey_package
+                .verifying_key()
+                .verify(message, &signature)
+                .is_ok(),
+            "the signature of shares {ids:?} does not verify under the group key"
+        );
+    }
+
+    Ok(())
+}
+
+/// The group of the ciphersuite, and its scalar field.
+type G = <frost::Secp256K1Sha256 as frost::Ciphersuite>::Group;
+type F = <G as Group>::Field;
+
+pub(crate) type Scalar = <F as Field>::Scalar;
+pub(crate) type Element = <G as Group>::Element;
+
+/// The scalar of an identifier (its x coordinate in the secret sharing).
+pub(crate) fn identifier_scalar(id: &frost::Identifier) -> Scalar {
+    F::deserialize(&id.serialize()).expect("identifiers are valid scalars")
+}
+
+/// The Lagrange coefficient of `x_i` at 0, for the points at `xs` (which must include `x_i`).
+pub(crate) fn lagrange_coefficient(x_i: &Scalar, xs: &[Scalar]) -> anyhow::Result<Scalar> {
+    let mut numerator = F::one();
+    let mut denominator = F::one();
+    for x_j in xs.iter().filter(|x_j| *x_j != x_i) {
+        numerator = numerator * *x_j;
+        denominator = denominator * (*x_j - *x_i);
+    }
+    let inverse = F::invert(&denominator).map_err(|_| anyhow::anyhow!("duplicate identifiers"))?;
+    Ok(numerator * inverse)
+}
+
+/// Evaluates, at `x`, the polynomial (in the exponent) whose coefficients are committed to by `commitments`.
+pub(crate) fn evaluate_commitments(commitments: &[Element], x: &Scalar) -> Element {
+    commitments
+        .iter()
+        .rev()
+        .fold(G::identity(), |acc, commitment| acc * *x + *commitment)
+}
+
+pub(crate) fn generator() -> Element {
+    G::generator()
+}
+
+pub(crate) fn identity() -> Element {
+    G::identity()
+}
+
+pub(crate) fn random_scalar() -> Scalar {
+    F::random(&mut thread_rng())
+}
+
+pub(crate) fn serialize_scalar(scalar: &Scalar) -> [u8; 32] {
+    F::serialize(scalar)
+}
+
+pub(crate) fn deserialize_scalar(bytes: &[u8]) -> anyhow::Result<Scalar> {
+    let bytes = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("invalid scalar length {}", bytes.len()))?;
+    F::deserialize(&bytes).map_err(|err| anyhow::anyhow!("invalid scalar: {err}"))
+}
+
+pub(crate) fn serialize_element(element: &Element) -> [u8; 33] {
+    G::serialize(element)
+}
+
+pub(crate) fn deserialize_element(bytes: &[u8]) -> anyhow::Result<Element> {
+    let bytes = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("invalid point length {}", bytes.len()))?;
+    G::deserialize(&bytes).map_err(|err| anyhow::anyhow!("invalid point: {err}"))
+}
+
+/// The point behind a verification share.
+pub(crate) fn verifying_share_element(
+    share: &frost::keys::VerifyingShare,
+) -> anyhow::Result<Element> {
+    deserialize_element(&share.serialize())
+}
+
+/// The threshold of a committee key (the `min_signers` of its shares), which the public key package doesn't record:
+/// it's the smallest number of verification shares that interpolate (in the exponent) to the group key.
+pub fn key_threshold(pubkey_package: &frost::keys::PublicKeyPackage) -> anyhow::Result<usize> {
+    let shares = pubkey_package
+        .verifying_shares()
+        .iter()
+        .map(|(id, share)| Ok((identifier_scalar(id), verifying_share_element(share)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let group_key = pubkey_package.verifying_key().element();
+
+    for threshold in 1..=shares.len() {
+        let points = &shares[..threshold];
+        let xs = points.iter().map(|(x, _)| *x).collect_vec();
+        let mut interpolated = G::identity();
+        for (x_i, y_i) in points {
+            interpolated = interpolated + *y_i * lagrange_coefficient(x_i, &xs)?;
+        }
+        if interpolated == group_key {
+            return Ok(threshold);
+        }
+    }
+
+    anyhow::bail!("the verification shares don't match the group key")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -394,4 +650,78 @@ mod tests {
         let message = "message to sign".as_bytes();
         sign(&key_packages, &pubkey_package, message).unwrap();
     }
+
+    #[test]
+    fn test_verify_key_packages() {
+        let (key_packages, pubkey_package) = gen_frost_keys(3, 2).unwrap();
+        verify_key_packages(&key_packages, &pubkey_package).unwrap();
+    }
+
+    #[test]
+    fn test_verify_tampered_key_packages() {
+        let (mut key_packages, pubkey_package) = gen_frost_keys(3, 2).unwrap();
+        let ids = key_packages.keys().cloned().collect::<Vec<_>>();
+
+        // swap the signing share of the second member with the one of the first member
+        let tampered = {
+            let mut tampered = serde_json::to_value(&key_packages[&ids[1]]).unwrap();
+            let other = serde_json::to_value(&key_packages[&ids[0]]).unwrap();
+            tampered["signing_share"] = other["signing_share"].clone();
+            serde_json::from_value(tampered).unwrap()
+        };
+        key_packages.insert(ids[1], tampered);
+
+        let err = verify_key_packages(&key_packages, &pubkey_package).unwrap_err();
+        assert!(err.to_string().contains(&format!("{:?}", ids[1])));
+    }
+
+    #[test]
+    fn test_check_key_package() {
+        let (key_packages, pubkey_package) = gen_frost_keys(3, 2).unwrap();
+        let (other_key_packages, other_pubkey_package) = gen_frost_keys(3, 2).unwrap();
+        for key_package in key_packages.values() {
+            check_key_package(key_package, &pubkey_package).unwrap();
+        }
+
+        // a key share from another ceremony
+        let key_package = other_key_packages.values().next().unwrap();
+        let err = check_key_package(key_package, &pubkey_package).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("key share does not match public key package"));
+        check_key_package(key_package, &other_pubkey_package).unwrap();
+    }
+
+    #[test]
+    fn test_key_threshold() {
+        for (max_signers, min_signers) in [(3, 2), (5, 3), (5, 5)] {
+            let (_, pubkey_package) = gen_frost_keys(max_signers, min_signers).unwrap();
+            assert_eq!(
+                key_threshold(&pubkey_package).unwrap(),
+                min_signers as usize
+            );
+        }
+
+        // the shares of another key don't interpolate to the group key
+        let (_, pubkey_package) = gen_frost_keys(3, 2).unwrap();
+        let (_, other) = gen_frost_keys(3, 2).unwrap();
+        let mixed = frost::keys::PublicKeyPackage::new(
+            other.verifying_shares().clone(),
+            *pubkey_package.verifying_key(),
+        );
+        assert!(key_threshold(&mixed).is_err());
+    }
+
+    #[test]
+    fn test_secret_key_package_debug() {
+        let (key_packages, _) = gen_frost_keys(3, 2).unwrap();
+        let key_package = key_packages.into_values().next().unwrap();
+        let signing_share = hex::encode(key_package.signing_share().serialize());
+
+        let secret = SecretKeyPackage::new(key_package.clone());
+        let debug = format!("{secret:?}");
+        assert!(debug.contains("<redacted>"), "{debug}");
+        assert!(!debug.contains(&signing_share), "{debug}");
+        assert_eq!(*secret, key_package);
+    }
 }
@@ -0,0 +1,339 @@
+#![allow(non_snake_case)]
+
+//! Groth16 proofs (in the snarkjs JSON format), verified natively over BN254 (which snarkjs calls bn128).
+
+use std::str::FromStr;
+
+use anyhow::{ensure, Context, Result};
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ff::PrimeField;
+use ark_groth16::{prepare_verifying_key, Groth16};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+/// A point of G1, as snarkjs serializes it: projective coordinates `[x, y, z]`.
+type G1Json = [String; 3];
+
+/// A point of G2, as snarkjs serializes it: projective coordinates in Fq2, each as `[c0, c1]`.
+type G2Json = [[String; 2]; 3];
+
+/// The snarkjs groth16 verifier key format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifierKey {
+    protocol: String, // "groth16"
+    curve: String,    // "bn128"
+    pub nPublic: usize,
+    vk_alpha_1: G1Json,
+    vk_beta_2: G2Json,
+    vk_gamma_2: G2Json,
+    vk_delta_2: G2Json,
+    // (snarkjs also exports `vk_alphabeta_12`, which can be recomputed, and isn't part of the hash)
+    IC: Vec<G1Json>,
+}
+
+impl VerifierKey {
+    /// hashes a verifier key.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Keccak256::new();
+        hasher.update(serde_json::to_string(&self).unwrap());
+        hasher.finalize().into()
+    }
+
+    fn to_ark(&self) -> Result<ark_groth16::VerifyingKey<Bn254>> {
+        ensure!(
+            self.protocol == "groth16" && self.curve == "bn128",
+            "expected a groth16 verifier key over bn128, not {} over {}",
+            self.protocol,
+            self.curve
+        );
+        ensure!(
+            self.IC.len() == self.nPublic + 1,
+            "the verifier key declares {} public inputs, but has {} IC points (instead of {})",
+            self.nPublic,
+            self.IC.len(),
+            self.nPublic + 1
+        );
+        Ok(ark_groth16::VerifyingKey {
+            alpha_g1: g1(&self.vk_alpha_1).context("invalid vk_alpha_1")?,
+            beta_g2: g2(&self.vk_beta_2).context("invalid vk_beta_2")?,
+            gamma_g2: g2(&self.vk_gamma_2).context("invalid vk_gamma_2")?,
+            delta_g2: g2(&self.vk_delta_2).context("invalid vk_delta_2")?,
+            gamma_abc_g1: self
+                .IC
+                .iter()
+                .enumerate()
+                .map(|(i, point)| g1(point).with_context(|| format!("invalid IC point #{i}")))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// A snarkjs groth16 proof.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proof {
+    pi_a: G1Json,
+    pi_b: G2Json,
+    pi_c: G1Json,
+    protocol: String, // "groth16"
+    curve: String,    // "bn128"
+}
+
+impl Proof {
+    /// Hashes a proof.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Keccak256::new();
+        hasher.update(serde_json::to_string(&self).unwrap());
+        hasher.finalize().into()
+    }
+
+    fn to_ark(&self) -> Result<ark_groth16::Proof<Bn254>> {
+        ensure!(
+            self.protocol == "groth16" && self.curve == "bn128",
+            "expected a groth16 proof over bn128, not {} over {}",
+            self.protocol,
+            self.curve
+        );
+        Ok(ark_groth16::Proof {
+            a: g1(&self.pi_a).context("invalid pi_a")?,
+            b: g2(&self.pi_b).context("invalid pi_b")?,
+            c: g1(&self.pi_c).context("invalid pi_c")?,
+        })
+    }
+}
+
+/// A verifier key with its points parsed (and checked), and its pairings precomputed,
+/// ready to verify any number of proofs (see [VerifierKey::prepare]).
+#[derive(Debug, Clone)]
+pub struct PreparedVerifierKey {
+    n_public: usize,
+    pvk: ark_groth16::PreparedVerifyingKey<Bn254>,
+}
+
+impl VerifierKey {
+    /// Parses the points of the verifier key, and precomputes what can be.
+    pub fn prepare(&self) -> Result<PreparedVerifierKey> {
+        Ok(PreparedVerifierKey {
+            n_public: self.nPublic,
+            pvk: prepare_verifying_key(&self.to_ark()?),
+        })
+    }
+}
+
+impl PreparedVerifierKey {
+    /// Verifies a groth16 proof, natively (unlike PLONK proofs, see [crate::snarkjs::verify_proof]).
+    pub fn verify(&self, public_inputs: &[String], proof: &Proof) -> Result<()> {
+        ensure!(
+            public_inputs.len() == self.n_public,
+            "the verifier key expects {} public inputs, but {} were given",
+            self.n_public,
+            public_inputs.len()
+        );
+        let public_inputs = public_inputs
+            .iter()
+            .map(|input| {
+                field::<Fr>(input).with_context(|| format!("invalid public input `{input}`"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let valid = Groth16::<Bn254>::verify_proof(&self.pvk, &proof.to_ark()?, &public_inputs)
+            .map_err(|err| anyhow::anyhow!("couldn't verify the proof: {err}"))?;
+        ensure!(valid, "failed to verify proof");
+        Ok(())
+    }
+}
+
+/// Verifies a groth16 proof (see [PreparedVerifierKey::verify] to verify several proofs with the same key).
+pub fn verify_proof(vk: &VerifierKey, public_inputs: &[String], proof: &Proof) -> Result<()> {
+    vk.prepare()?.verify(public_inputs, proof)
+}
+
+/// Parses a (canonical) field element given in decimal.
+fn field<F: PrimeField>(decimal: &str) -> Result<F> {
+    let value = BigUint::from_str(decimal)?;
+    ensure!(
+        value < F::MODULUS.into(),
+        "the field element is larger than the modulus"
+    );
+    Ok(F::from(value))
+}
+
+/// Parses a point of G1 (in affine form, as snarkjs exports them).
+fn g1([x, y, z]: &G1Json) -> Result<G1Affine> {
+    ensure!(z == "1", "the point is not in affine form");
+    let point = G1Affine::new_unchecked(field::<Fq>(x)?, field::<Fq>(y)?);
+    ensure!(
+        point.is_on_curve() && point.is_in_correct_subgroup_assuming_on_curve(),
+        "the point is not on the curve"
+    );
+    Ok(point)
+}
+
+/// Parses a point of G2 (in affine form, as snarkjs exports them).
+fn g2([x, y, z]: &G2Json) -> Result<G2Affine> {
+    ensure!(
+        z[0] == "1" && z[1] == "0",
+        "the point is not in affine form"
+    );
+    let fq2 = |[c0, c1]: &[String; 2]| -> Result<Fq2> {
+        Ok(Fq2::new(field::<Fq>(c0)?, field::<Fq>(c1)?))
+    };
+    let point = G2Affine::new_unchecked(fq2(x)?, fq2(y)?);
+    ensure!(
+        point.is_on_curve() && point.is_in_correct_subgroup_assuming_on_curve(),
+        "the point is not on the curve"
+    );
+    Ok(point)
+}
+
+/// A circuit with a single public input that it doesn't constrain, so that it proves any input:
+/// tests use it to prove the public inputs of the requests they build (e.g. the truncated txid of a spend).
+#[cfg(test)]
+pub(crate) mod test_circuit {
+    use ark_groth16::ProvingKey;
+    use ark_relations::{
+        lc,
+        r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError, Variable},
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+
+    struct AnyInput(Fr);
+
+    impl ConstraintSynthesizer<Fr> for AnyInput {
+        fn generate_constraints(
+            self,
+            cs: ConstraintSystemRef<Fr>,
+        ) -> std::result::Result<(), SynthesisError> {
+            let input = cs.new_input_variable(|| Ok(self.0))?;
+            cs.enforce_constraint(lc!() + input, lc!() + Variable::One, lc!() + input)
+        }
+    }
+
+    /// Runs the setup of the circuit, and returns its verifier key (in the snarkjs format) and its proving key.
+    pub(crate) fn setup() -> (VerifierKey, ProvingKey<Bn254>) {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        let pk = Groth16::<Bn254>::generate_random_parameters_with_reduction(
+            AnyInput(Fr::from(0u64)),
+            &mut rng,
+        )
+        .unwrap();
+        let vk = VerifierKey {
+            protocol: "groth16".to_string(),
+            curve: "bn128".to_string(),
+            nPublic: pk.vk.gamma_abc_g1.len() - 1,
+            vk_alpha_1: g1_json(&pk.vk.alpha_g1),
+            vk_beta_2: g2_json(&pk.vk.beta_g2),
+            vk_gamma_2: g2_json(&pk.vk.gamma_g2),
+            vk_delta_2: g2_json(&pk.vk.delta_g2),
+            IC: pk.vk.gamma_abc_g1.iter().map(g1_json).collect(),
+        };
+        (vk, pk)
+    }
+
+    /// Proves `public_input` (a field element in decimal).
+    pub(crate) fn prove(pk: &ProvingKey<Bn254>, public_input: &str) -> Proof {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let input = field::<Fr>(public_input).unwrap();
+        let proof =
+            Groth16::<Bn254>::create_random_proof_with_reduction(AnyInput(input), pk, &mut rng)
+                .unwrap();
+        Proof {
+            pi_a: g1_json(&proof.a),
+            pi_b: g2_json(&proof.b),
+            pi_c: g1_json(&proof.c),
+            protocol: "groth16".to_string(),
+            curve: "bn128".to_string(),
+        }
+    }
+
+    fn fq_json(x: &Fq) -> String {
+        BigUint::from(x.into_bigint()).to_string()
+    }
+
+    fn g1_json(point: &G1Affine) -> G1Json {
+        [fq_json(&point.x), fq_json(&point.y), "1".to_string()]
+    }
+
+    fn g2_json(point: &G2Affine) -> G2Json {
+        [
+            [fq_json(&point.x.c0), fq_json(&point.x.c1)],
+            [fq_json(&point.y.c0), fq_json(&point.y.c1)],
+            ["1".to_string(), "0".to_string()],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A proof (of a single public input) in the snarkjs format (see examples/circuit/groth16/README.md).
+    fn example() -> (VerifierKey, Vec<String>, Proof) {
+        (
+            serde_json::from_str(include_str!("../examples/circuit/groth16/vk.json")).unwrap(),
+            serde_json::from_str(include_str!(
+                "../examples/circuit/groth16/public_inputs.json"
+            ))
+            .unwrap(),
+            serde_json::from_str(include_str!("../examples/circuit/groth16/proof.json")).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_verify_groth16() {
+        let (vk, public_inputs, proof) = example();
+
+        // the output of the example circuit (3 * 11)
+        assert_eq!(public_inputs, vec!["33".to_string()]);
+        verify_proof(&vk, &public_inputs, &proof).unwrap();
+
+        // another public input
+        let other_inputs = vec!["1".to_string()];
+        assert!(verify_proof(&vk, &other_inputs, &proof).is_err());
+
+        // the wrong number of public inputs
+        assert!(verify_proof(&vk, &[], &proof).is_err());
+
+        // a non-canonical public input (the same one, plus the modulus)
+        let modulus: BigUint = Fr::MODULUS.into();
+        let aliased = vec![(BigUint::from_str(&public_inputs[0]).unwrap() + modulus).to_string()];
+        assert!(verify_proof(&vk, &aliased, &proof).is_err());
+
+        // a tampered proof
+        let mut tampered = proof.clone();
+        tampered.pi_c = proof.pi_a.clone();
+        assert!(verify_proof(&vk, &public_inputs, &tampered).is_err());
+
+        // points off the curve
+        let mut off_curve = proof.clone();
+        off_curve.pi_a[1] = "1".to_string();
+        let err = verify_proof(&vk, &public_inputs, &off_curve).unwrap_err();
+        assert!(format!("{err:#}").contains("invalid pi_a"), "{err:#}");
+    }
+
+    #[test]
+    fn test_test_circuit() {
+        let (vk, pk) = test_circuit::setup();
+        let proof = test_circuit::prove(&pk, "42");
+        verify_proof(&vk, &["42".to_string()], &proof).unwrap();
+        assert!(verify_proof(&vk, &["43".to_string()], &proof).is_err());
+    }
+
+    #[test]
+    fn test_groth16_hash() {
+        let (vk, _, proof) = example();
+
+        // the hashes don't depend on the fields that aren't part of the format
+        let mut json: serde_json::Value =
+            serde_json::from_str(include_str!("../examples/circuit/groth16/vk.json")).unwrap();
+        json["vk_alphabeta_12"] = serde_json::json!([]);
+        let with_extra: VerifierKey = serde_json::from_value(json).unwrap();
+        assert_eq!(with_extra.hash(), vk.hash());
+
+        assert_ne!(proof.hash(), [0; 32]);
+        assert_ne!(vk.hash(), proof.hash());
+    }
+}
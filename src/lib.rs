@@ -4,15 +4,38 @@ use anyhow::Context;
 use secp256k1::hashes::Hash;
 
 pub mod capped_hashmap;
+pub mod chain_backend;
+pub mod chain_tip;
+pub mod coin_selection;
 pub mod committee;
 pub mod compliance;
+pub mod compression;
 pub mod constants;
+pub mod cpfp;
+pub mod fee_policy;
+pub mod fee_strategy;
 pub mod frost;
+pub mod groth16;
 pub mod json_rpc_stuff;
+pub mod logging;
 pub mod plonk;
+pub mod proof_system;
+pub mod public_inputs;
+pub mod redact;
+pub mod sighash;
 pub mod snarkjs;
 pub mod srs;
+pub mod store;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tx_sanity;
+pub mod tx_template;
 pub mod utils;
+pub mod vk_cache;
+pub mod vk_commitment;
+pub mod watch_wallet;
+#[cfg(feature = "witness")]
+pub mod witness;
 
 /// 1. Alice signs a transaction to deploy a smart contract.
 pub mod alice_sign_tx;
@@ -41,15 +64,41 @@ pub fn zkbitcoin_folder() -> std::path::PathBuf {
     zkbitcoin_dir
 }
 
-/// Returns the current network (mainnet or testnet).
+/// Returns the current network (mainnet, regtest, or testnet).
 pub fn get_network() -> bitcoin::Network {
+    #[cfg(feature = "testing")]
+    if let Some((_, network)) = testing::local_committee() {
+        return network;
+    }
     if std::env::var("MAINNET").is_ok() {
         bitcoin::Network::Bitcoin
+    } else if std::env::var("REGTEST").is_ok() {
+        bitcoin::Network::Regtest
     } else {
         bitcoin::Network::Testnet
     }
 }
 
+/// Returns the public key of zkBitcoin ([constants::ZKBITCOIN_PUBKEY]).
+/// Only test builds (with the `testing` feature) can point it at a local committee:
+/// explicitly (see [testing::set_local_committee]), or with the `ZKBITCOIN_PUBKEY` env var on regtest.
+pub fn zkbitcoin_pubkey() -> anyhow::Result<bitcoin::PublicKey> {
+    #[cfg(feature = "testing")]
+    {
+        if let Some((pubkey, _)) = testing::local_committee() {
+            return Ok(pubkey);
+        }
+        if get_network() == bitcoin::Network::Regtest {
+            if let Ok(pubkey) = std::env::var("ZKBITCOIN_PUBKEY") {
+                return <bitcoin::PublicKey as std::str::FromStr>::from_str(&pubkey)
+                    .context("invalid ZKBITCOIN_PUBKEY");
+            }
+        }
+    }
+    <bitcoin::PublicKey as std::str::FromStr>::from_str(constants::ZKBITCOIN_PUBKEY)
+        .context("invalid zkBitcoin public key")
+}
+
 /// Truncates a transaction ID so that it can fit in a field element in Circom.
 pub fn truncate_txid(txid: bitcoin::Txid) -> String {
     //    let mut bytes = vec![];
@@ -96,19 +145,63 @@ pub fn circom_field_from_bytes(bytes: &[u8]) -> anyhow::Result<String> {
     Ok(big.to_str_radix(10))
 }
 
+/// Checks that some data fits in a standard OP_RETURN (otherwise the transaction wouldn't be relayed).
+pub fn check_op_return_data_len(data: &[u8]) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        data.len() <= constants::MAX_OP_RETURN_DATA_LEN,
+        "the OP_RETURN data is too large ({} bytes, the standard limit is {}): \
+         commit to a hash of the data instead (e.g. a 32-byte digest) to keep the transaction relayable",
+        data.len(),
+        constants::MAX_OP_RETURN_DATA_LEN
+    );
+    Ok(())
+}
+
+/// Returns the data stored in the OP_RETURN of a zkapp: the hash of its verifier key, followed by its state (if any).
+pub fn op_return_data_for(vk_hash: &[u8; 32], state: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let mut data = vk_hash.to_vec();
+    if let Some(state) = state {
+        data.extend(circom_field_to_bytes(state).context("incorrect state given")?);
+    }
+    check_op_return_data_len(&data)?;
+    Ok(data)
+}
+
 pub fn op_return_script_for(
     vk_hash: &[u8; 32],
     initial_state: Option<&str>,
 ) -> anyhow::Result<bitcoin::ScriptBuf> {
-    let mut data = vk_hash.to_vec();
-    if let Some(initial_state) = initial_state {
-        data.extend(circom_field_to_bytes(initial_state).context("incorrect initial state given")?);
-        assert!(data.len() < 64);
-    }
+    let data = op_return_data_for(vk_hash, initial_state)?;
     let thing: &bitcoin::script::PushBytes = data.as_slice().try_into().unwrap();
     Ok(bitcoin::ScriptBuf::new_op_return(thing))
 }
 
+/// Same as [op_return_script_for], for a zkapp verified with proofs of `scheme`.
+/// PLONK zkapps keep the original format (a single push), others push the tag of their scheme first:
+/// `OP_RETURN <tag> <vk_hash || state>` (see [bob_request::parse_op_return_data_with_scheme]).
+pub fn op_return_script_for_scheme(
+    scheme: proof_system::ProofScheme,
+    vk_hash: &[u8; 32],
+    initial_state: Option<&str>,
+) -> anyhow::Result<bitcoin::ScriptBuf> {
+    if scheme == proof_system::ProofScheme::Plonk {
+        return op_return_script_for(vk_hash, initial_state);
+    }
+    let data = op_return_data_for(vk_hash, initial_state)?;
+    let data: &bitcoin::script::PushBytes = data.as_slice().try_into().unwrap();
+    let script = bitcoin::script::Builder::new()
+        .push_opcode(bitcoin::opcodes::all::OP_RETURN)
+        .push_slice([scheme.tag()])
+        .push_slice(data)
+        .into_script();
+    anyhow::ensure!(
+        script.len() <= 3 + constants::MAX_OP_RETURN_DATA_LEN,
+        "the OP_RETURN script is too large ({} bytes) to be relayed",
+        script.len()
+    );
+    Ok(script)
+}
+
 pub fn taproot_addr_from(pubkey_str: &str) -> anyhow::Result<bitcoin::Address> {
     let pubkey = <bitcoin::PublicKey as std::str::FromStr>::from_str(pubkey_str)?;
     let internal_key = bitcoin::key::UntweakedPublicKey::from(pubkey);
@@ -116,3 +209,219 @@ pub fn taproot_addr_from(pubkey_str: &str) -> anyhow::Result<bitcoin::Address> {
     let taproot_address = bitcoin::Address::p2tr(&secp, internal_key, None, get_network());
     Ok(taproot_address)
 }
+
+/// Same as [taproot_addr_from], but the address also commits to a tree of (tapscript) leaves,
+/// e.g. a timelocked recovery script to fall back on if the committee permanently loses its quorum.
+/// Leaves are spent with [sighash::ScriptPathSpend::from_spend_info], and are all given the same weight (so the tree is balanced).
+///
+/// Note that key-path spends of such an address must be signed with the key tweaked by the merkle root of the tree
+/// (see [bitcoin::taproot::TaprootSpendInfo::merkle_root]), which the FROST ciphersuite we use doesn't support:
+/// it always tweaks the group key without a merkle root. So the committee can't (yet) sign key-path spends of these addresses.
+pub fn taproot_addr_from_with_scripts(
+    pubkey: bitcoin::PublicKey,
+    scripts: &[bitcoin::ScriptBuf],
+) -> anyhow::Result<(bitcoin::Address, bitcoin::taproot::TaprootSpendInfo)> {
+    anyhow::ensure!(
+        !scripts.is_empty(),
+        "the script tree needs at least one script (see taproot_addr_from otherwise)"
+    );
+    let secp = secp256k1::Secp256k1::default();
+    let internal_key = bitcoin::key::UntweakedPublicKey::from(pubkey);
+    let spend_info = bitcoin::taproot::TaprootBuilder::with_huffman_tree(
+        scripts.iter().map(|script| (1, script.clone())),
+    )
+    .context("couldn't build the script tree")?
+    .finalize(&secp, internal_key)
+    .map_err(|_| anyhow::anyhow!("couldn't finalize the script tree"))?;
+    let taproot_address = bitcoin::Address::p2tr_tweaked(spend_info.output_key(), get_network());
+    Ok((taproot_address, spend_info))
+}
+
+/// The output descriptor of the (key-path only) taproot address of `pubkey` (see [taproot_addr_from]),
+/// without its checksum.
+pub fn taproot_descriptor_from(pubkey: bitcoin::PublicKey) -> String {
+    let internal_key = bitcoin::key::UntweakedPublicKey::from(pubkey);
+    format!("tr({internal_key})")
+}
+
+/// Computes the checksum of an output descriptor (see BIP 380), as bitcoind does with `getdescriptorinfo`.
+pub fn descriptor_checksum(descriptor: &str) -> anyhow::Result<String> {
+    const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+    const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    const GENERATORS: [u64; 5] = [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ];
+
+    fn polymod(c: u64, value: u64) -> u64 {
+        let c0 = c >> 35;
+        let mut c = ((c & 0x7ffffffff) << 5) ^ value;
+        for (i, generator) in GENERATORS.iter().enumerate() {
+            if (c0 >> i) & 1 == 1 {
+                c ^= generator;
+            }
+        }
+        c
+    }
+
+    let mut c = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET
+            .find(ch)
+            .with_context(|| format!("invalid character `{ch}` in descriptor"))?
+            as u64;
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+
+    Ok((0..8)
+        .map(|i| CHECKSUM_CHARSET[((c >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect())
+}
+
+/// Same as [taproot_descriptor_from], with its checksum (e.g. to import it in a watch-only wallet with `importdescriptors`).
+pub fn checksummed_taproot_descriptor_from(pubkey: bitcoin::PublicKey) -> String {
+    let descriptor = taproot_descriptor_from(pubkey);
+    let checksum =
+        descriptor_checksum(&descriptor).expect("descriptors of keys only use valid characters");
+    format!("{descriptor}#{checksum}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor_checksum() {
+        // from BIP 380
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(
+            descriptor_checksum(
+                "tr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd)"
+            )
+            .unwrap(),
+            "dh4fyxrd"
+        );
+        assert!(descriptor_checksum("raw(deadbeef)\n").is_err());
+
+        // the descriptor of the committee encodes its x-only key
+        let pubkey = zkbitcoin_pubkey().unwrap();
+        let descriptor = checksummed_taproot_descriptor_from(pubkey);
+        let (body, checksum) = descriptor.split_once('#').unwrap();
+        assert_eq!(descriptor_checksum(body).unwrap(), checksum);
+        let xonly = bitcoin::key::XOnlyPublicKey::from(pubkey.inner);
+        assert_eq!(body, format!("tr({})", hex::encode(xonly.serialize())));
+    }
+
+    #[test]
+    fn test_op_return_data_len() {
+        // at the limit
+        assert!(check_op_return_data_len(&[1; constants::MAX_OP_RETURN_DATA_LEN]).is_ok());
+
+        // over the limit
+        let err =
+            check_op_return_data_len(&[1; constants::MAX_OP_RETURN_DATA_LEN + 1]).unwrap_err();
+        assert!(err.to_string().contains("commit to a hash"));
+    }
+
+    #[test]
+    fn test_op_return_script_for() {
+        // a verifier key hash and a state always fit
+        let max_state = <num_bigint::BigUint as num_traits::Num>::from_str_radix(
+            constants::CIRCOM_ETH_PRIME,
+            10,
+        )
+        .unwrap()
+            - 1u8;
+        let script = op_return_script_for(&[1; 32], Some(&max_state.to_str_radix(10))).unwrap();
+        assert!(script.is_op_return());
+        assert!(script.len() <= 2 + constants::MAX_OP_RETURN_DATA_LEN);
+
+        // the scheme is only tagged for non-PLONK zkapps
+        let state = max_state.to_str_radix(10);
+        for scheme in [
+            proof_system::ProofScheme::Plonk,
+            proof_system::ProofScheme::Groth16,
+        ] {
+            let script = op_return_script_for_scheme(scheme, &[1; 32], Some(&state)).unwrap();
+            assert!(script.is_op_return());
+            let (parsed_scheme, data) =
+                bob_request::parse_op_return_data_with_scheme(&script).unwrap();
+            assert_eq!(parsed_scheme, scheme);
+            assert_eq!(data, op_return_data_for(&[1; 32], Some(&state)).unwrap());
+        }
+        assert_eq!(
+            op_return_script_for_scheme(proof_system::ProofScheme::Plonk, &[1; 32], None).unwrap(),
+            op_return_script_for(&[1; 32], None).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_taproot_addr_from_with_scripts() {
+        let pubkey = zkbitcoin_pubkey().unwrap();
+        let scripts = (0..3)
+            .map(|n| {
+                bitcoin::script::Builder::new()
+                    .push_int(n)
+                    .push_opcode(bitcoin::opcodes::all::OP_DROP)
+                    .push_x_only_key(&bitcoin::key::XOnlyPublicKey::from(pubkey.inner))
+                    .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+                    .into_script()
+            })
+            .collect::<Vec<_>>();
+        let (address, spend_info) = taproot_addr_from_with_scripts(pubkey, &scripts).unwrap();
+
+        // the address commits to the tree, so it's not the key-path only one
+        assert_eq!(
+            address.script_pubkey(),
+            bitcoin::ScriptBuf::new_p2tr_tweaked(spend_info.output_key())
+        );
+        assert!(spend_info.merkle_root().is_some());
+        assert_ne!(
+            address.script_pubkey(),
+            p2tr_script_to(pubkey),
+            "the script tree isn't committed to"
+        );
+
+        // every leaf can be spent, and the tree is balanced
+        let secp = secp256k1::Secp256k1::verification_only();
+        for script in scripts {
+            let spend =
+                sighash::ScriptPathSpend::from_spend_info(&spend_info, script.clone()).unwrap();
+            assert!(spend.control_block.merkle_branch.len() <= 2);
+            assert!(spend.control_block.verify_taproot_commitment(
+                &secp,
+                spend_info.output_key().to_inner(),
+                &script
+            ));
+        }
+
+        assert!(taproot_addr_from_with_scripts(pubkey, &[]).is_err());
+    }
+
+    #[test]
+    fn test_taproot_descriptor_from() {
+        let pubkey = zkbitcoin_pubkey().unwrap();
+        let descriptor = taproot_descriptor_from(pubkey);
+        let xonly = bitcoin::key::XOnlyPublicKey::from(pubkey.inner);
+        assert_eq!(descriptor, format!("tr({xonly})"));
+    }
+}
@@ -0,0 +1,32 @@
+//! How the binaries log.
+//! The signing pipeline logs through [tracing], with a span per signing session, per FROST round (and member),
+//! per proof verification, and per RPC call, whose durations are logged when they close.
+//! Everything still logged with the `log` crate is forwarded to the same subscriber,
+//! and the verbosity is set with `RUST_LOG` as with `env_logger` (`info` by default).
+
+use anyhow::{anyhow, Result};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+/// The verbosity when `RUST_LOG` isn't set.
+const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Installs the global subscriber, logging to stderr: human-readable lines,
+/// or JSON lines if `json` is set (e.g. to ship them to Loki or ELK).
+pub fn init(json: bool) -> Result<()> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE);
+    let res = if json {
+        builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init()
+    } else {
+        builder.try_init()
+    };
+    res.map_err(|err| anyhow!("couldn't install the logger: {err}"))
+}
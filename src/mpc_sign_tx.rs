@@ -1,26 +1,21 @@
 use anyhow::{ensure, Context, Result};
-use bitcoin::{
-    sighash::{Prevouts, SighashCache},
-    TapSighashType, TxOut,
+use bitcoin::{TapSighashType, Transaction, TxOut};
+
+use crate::{
+    bob_request::{send_bob_request, BobRequest, SmartContract},
+    json_rpc_stuff::{sign_transaction, RpcCtx, TransactionOrHex},
+    p2tr_script_to,
+    sighash::compute_keyspend_sighash_with_type,
+    zkbitcoin_pubkey,
 };
-use secp256k1::hashes::Hash;
 
-use crate::bob_request::SmartContract;
-
-/// Gets the digest to hash for signing a transaction containing a zkapp.
+/// Gets the digest to hash for signing a transaction containing a zkapp, with the given sighash type.
 pub fn get_digest_to_hash(
     prev_outs: &[TxOut],
     transaction: &bitcoin::Transaction,
     smart_contract: &SmartContract,
+    sighash_type: TapSighashType,
 ) -> Result<[u8; 32]> {
-    // TODO: return Result
-    // the sighash flag is always ALL
-    let hash_ty = TapSighashType::All;
-
-    // sighash
-    let mut cache = SighashCache::new(transaction);
-    let mut sig_msg = Vec::new();
-
     // input to sign is the one containing the zkapp
     let (input_idx, _) = transaction
         .input
@@ -32,24 +27,77 @@ pub fn get_digest_to_hash(
         })
         .context("could not find a zkapp being used in the given transaction")?;
 
-    // sanity check
+    compute_keyspend_sighash_with_type(transaction, input_idx, prev_outs, sighash_type)
+}
+
+/// Returns the indices of the inputs that spend an output of the committee.
+/// These can only be signed by the committee (see [sign_with_committee]), never by a wallet.
+pub fn committee_inputs(prev_outs: &[TxOut]) -> Result<Vec<usize>> {
+    let committee_script = p2tr_script_to(zkbitcoin_pubkey()?);
+    Ok(prev_outs
+        .iter()
+        .enumerate()
+        .filter(|(_, prev_out)| prev_out.script_pubkey == committee_script)
+        .map(|(idx, _)| idx)
+        .collect())
+}
+
+/// Gets the committee to sign the zkapp inputs of Bob's transaction (via the orchestrator at `orchestrator_address`),
+/// and returns the transaction (its other inputs still need to be signed, see [sign_wallet_inputs]).
+pub async fn sign_with_committee(
+    orchestrator_address: &str,
+    bob_request: BobRequest,
+) -> Result<Transaction> {
+    let zkapp_outpoints = bob_request
+        .zkapp_requests()?
+        .iter()
+        .map(BobRequest::zkapp_outpoint)
+        .collect::<Result<Vec<_>>>()?;
+    let bob_response = send_bob_request(orchestrator_address, bob_request)
+        .await
+        .context("error while sending request to orchestrator")?;
+
+    let tx = bob_response.unlocked_tx;
+    for zkapp_outpoint in zkapp_outpoints {
+        let zkapp_input = tx
+            .input
+            .iter()
+            .find(|input| input.previous_output == zkapp_outpoint)
+            .with_context(|| {
+                format!("the committee returned a transaction that doesn't spend the zkapp {zkapp_outpoint}")
+            })?;
+        ensure!(
+            !zkapp_input.witness.is_empty(),
+            "the committee returned a transaction without signing the zkapp {zkapp_outpoint}"
+        );
+    }
+
+    Ok(tx)
+}
+
+/// Signs the inputs of a transaction that belong to the wallet of the node.
+/// Inputs spending an output of the committee must already have been signed by the committee (see [sign_with_committee]),
+/// as the wallet can't sign them (and would leave them unsigned).
+pub async fn sign_wallet_inputs(
+    ctx: &RpcCtx,
+    tx: &Transaction,
+    prev_outs: &[TxOut],
+) -> Result<(String, Transaction)> {
     ensure!(
-        prev_outs.len() == transaction.input.len(),
-        "the given prev_out parameter doesn't match the number of inputs in the given transaction"
+        tx.input.len() == prev_outs.len(),
+        "expected {} previous outputs, got {}",
+        tx.input.len(),
+        prev_outs.len()
     );
+    for idx in committee_inputs(prev_outs)? {
+        ensure!(
+            !tx.input[idx].witness.is_empty(),
+            "input {idx} ({}) belongs to the committee: it must be signed with FROST (see sign_with_committee), not by the wallet",
+            tx.input[idx].previous_output
+        );
+    }
 
-    // get hash
-    let prev_outs = Prevouts::All(prev_outs);
-    cache.taproot_encode_signing_data_to(
-        &mut sig_msg,
-        input_idx,
-        &prev_outs,
-        None,
-        None,
-        hash_ty,
-    )?;
-    let sighash = cache.taproot_signature_hash(input_idx, &prev_outs, None, None, hash_ty)?;
-    Ok(sighash.to_byte_array())
+    sign_transaction(ctx, TransactionOrHex::Transaction(tx)).await
 }
 
 #[cfg(test)]
@@ -78,6 +126,7 @@ mod tests {
             fund_raw_transaction, send_raw_transaction, sign_transaction, TransactionOrHex,
         },
         p2tr_script_to,
+        sighash::KEYSPEND_SIGHASH_TYPE,
     };
     use crate::{constants::ZKBITCOIN_PUBKEY, json_rpc_stuff::RpcCtx};
 
@@ -302,6 +351,7 @@ mod tests {
             vk_hash: [0; 32],
             state: None,
             vout_of_zkbitcoin_utxo: 0,
+            scheme: Default::default(),
         };
         let mut tx = create_transaction(&smart_contract, txid, bob_address);
 
@@ -312,7 +362,7 @@ mod tests {
         let sig = sign_transaction_frost(&key_packages, &pubkey_package, &tx, prevouts);
 
         // place signature in witness
-        let hash_ty = TapSighashType::All;
+        let hash_ty = KEYSPEND_SIGHASH_TYPE;
         let final_signature = taproot::Signature { sig, hash_ty };
         let mut witness = Witness::new();
         witness.push(final_signature.to_vec());
@@ -344,6 +394,7 @@ mod tests {
             vk_hash: [0; 32],
             state: None,
             vout_of_zkbitcoin_utxo: 0,
+            scheme: Default::default(),
         };
         let mut tx = create_transaction(&smart_contract, txid, bob_address);
 
@@ -391,4 +442,41 @@ mod tests {
 
         println!("{:?}", address.script_pubkey());
     }
+
+    #[tokio::test]
+    async fn test_wallet_doesnt_sign_committee_inputs() {
+        let wallet_prev_out = TxOut {
+            value: Amount::from_sat(1000),
+            script_pubkey: ScriptBuf::new_op_return(&[]),
+        };
+        let committee_prev_out = TxOut {
+            value: Amount::from_sat(1000),
+            script_pubkey: p2tr_script_to(crate::zkbitcoin_pubkey().unwrap()),
+        };
+        let prev_outs = vec![wallet_prev_out, committee_prev_out];
+        assert_eq!(super::committee_inputs(&prev_outs).unwrap(), vec![1]);
+
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default(), TxIn::default()],
+            output: vec![],
+        };
+
+        // (there's no node there, we should fail before talking to it)
+        let ctx = RpcCtx::builder().url("http://127.0.0.1:1").build().unwrap();
+
+        // the committee input hasn't been signed by the committee yet, the wallet would leave it unsigned
+        let err = super::sign_wallet_inputs(&ctx, &tx, &prev_outs)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("must be signed with FROST"));
+
+        // once the committee signed it, the wallet is asked to sign the rest
+        tx.input[1].witness.push([0u8; 64]);
+        let err = super::sign_wallet_inputs(&ctx, &tx, &prev_outs)
+            .await
+            .unwrap_err();
+        assert!(!err.to_string().contains("must be signed with FROST"));
+    }
 }
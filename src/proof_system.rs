@@ -0,0 +1,276 @@
+//! The proof systems zkapps can be deployed with: PLONK (verified with snarkjs),
+//! and Groth16 (verified natively, see [crate::groth16]).
+//! The scheme of a zkapp is recorded when it's deployed (see [crate::op_return_script_for_scheme]),
+//! so that a zkapp can only be unlocked with proofs of that scheme.
+
+use std::fmt;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{groth16, plonk, snarkjs};
+
+/// A proof system.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProofScheme {
+    /// The scheme of zkapps deployed without a scheme tag.
+    #[default]
+    Plonk,
+    Groth16,
+}
+
+impl ProofScheme {
+    /// The byte identifying the scheme in the OP_RETURN of a zkapp.
+    pub fn tag(&self) -> u8 {
+        match self {
+            Self::Plonk => 0,
+            Self::Groth16 => 1,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Result<Self> {
+        match tag {
+            0 => Ok(Self::Plonk),
+            1 => Ok(Self::Groth16),
+            _ => bail!("unknown proof scheme tag {tag}"),
+        }
+    }
+}
+
+impl fmt::Display for ProofScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plonk => write!(f, "PLONK"),
+            Self::Groth16 => write!(f, "Groth16"),
+        }
+    }
+}
+
+/// A verifier key, in the snarkjs JSON format of its scheme.
+/// (It serializes as the key of the scheme, so the hash of PLONK keys is unchanged.)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum VerifierKey {
+    Plonk(plonk::VerifierKey),
+    Groth16(groth16::VerifierKey),
+}
+
+impl VerifierKey {
+    pub fn scheme(&self) -> ProofScheme {
+        match self {
+            Self::Plonk(_) => ProofScheme::Plonk,
+            Self::Groth16(_) => ProofScheme::Groth16,
+        }
+    }
+
+    /// hashes a verifier key (this is what a zkapp commits to).
+    pub fn hash(&self) -> [u8; 32] {
+        match self {
+            Self::Plonk(vk) => vk.hash(),
+            Self::Groth16(vk) => vk.hash(),
+        }
+    }
+
+    /// The number of public inputs of the circuit.
+    pub fn num_public_inputs(&self) -> usize {
+        match self {
+            Self::Plonk(vk) => vk.nPublic,
+            Self::Groth16(vk) => vk.nPublic,
+        }
+    }
+
+    /// Gets the verifier key ready to verify proofs (for Groth16, this parses and checks its points).
+    pub fn prepare(&self) -> Result<PreparedVerifierKey> {
+        Ok(match self {
+            Self::Plonk(vk) => PreparedVerifierKey::Plonk(vk.clone()),
+            Self::Groth16(vk) => PreparedVerifierKey::Groth16(vk.prepare()?),
+        })
+    }
+}
+
+/// A verifier key ready to verify proofs (see [VerifierKey::prepare]).
+/// (PLONK keys are given as-is to snarkjs.)
+#[derive(Debug, Clone)]
+pub enum PreparedVerifierKey {
+    Plonk(plonk::VerifierKey),
+    Groth16(groth16::PreparedVerifierKey),
+}
+
+impl PreparedVerifierKey {
+    pub fn scheme(&self) -> ProofScheme {
+        match self {
+            Self::Plonk(_) => ProofScheme::Plonk,
+            Self::Groth16(_) => ProofScheme::Groth16,
+        }
+    }
+
+    /// Verifies a proof with the verifier of its scheme.
+    pub fn verify(&self, public_inputs: &[String], proof: &Proof) -> Result<()> {
+        match (self, proof) {
+            (Self::Plonk(vk), Proof::Plonk(proof)) => {
+                snarkjs::verify_proof(vk, public_inputs, proof)
+            }
+            (Self::Groth16(vk), Proof::Groth16(proof)) => vk.verify(public_inputs, proof),
+            _ => bail!(
+                "the verifier key is for {} but the proof is a {} proof",
+                self.scheme(),
+                proof.scheme()
+            ),
+        }
+    }
+}
+
+impl From<plonk::VerifierKey> for VerifierKey {
+    fn from(vk: plonk::VerifierKey) -> Self {
+        Self::Plonk(vk)
+    }
+}
+
+impl From<groth16::VerifierKey> for VerifierKey {
+    fn from(vk: groth16::VerifierKey) -> Self {
+        Self::Groth16(vk)
+    }
+}
+
+/// A proof, in the snarkjs JSON format of its scheme.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Proof {
+    Plonk(plonk::Proof),
+    Groth16(groth16::Proof),
+}
+
+impl Proof {
+    pub fn scheme(&self) -> ProofScheme {
+        match self {
+            Self::Plonk(_) => ProofScheme::Plonk,
+            Self::Groth16(_) => ProofScheme::Groth16,
+        }
+    }
+
+    /// Hashes a proof.
+    pub fn hash(&self) -> [u8; 32] {
+        match self {
+            Self::Plonk(proof) => proof.hash(),
+            Self::Groth16(proof) => proof.hash(),
+        }
+    }
+}
+
+impl From<plonk::Proof> for Proof {
+    fn from(proof: plonk::Proof) -> Self {
+        Self::Plonk(proof)
+    }
+}
+
+impl From<groth16::Proof> for Proof {
+    fn from(proof: groth16::Proof) -> Self {
+        Self::Groth16(proof)
+    }
+}
+
+/// A verifier key or a proof of another scheme than the one the zkapp was deployed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchemeMismatch {
+    pub deployed: ProofScheme,
+    pub supplied: ProofScheme,
+
+    /// What was supplied with the wrong scheme.
+    pub what: &'static str,
+}
+
+impl fmt::Display for SchemeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "zkapp was deployed with {} but a {} {} was supplied",
+            self.deployed, self.supplied, self.what
+        )
+    }
+}
+
+impl std::error::Error for SchemeMismatch {}
+
+/// Checks that a verifier key and a proof are of the scheme a zkapp was deployed with.
+pub fn check_scheme(
+    deployed: ProofScheme,
+    vk: &VerifierKey,
+    proof: &Proof,
+) -> Result<(), SchemeMismatch> {
+    for (supplied, what) in [(proof.scheme(), "proof"), (vk.scheme(), "verifier key")] {
+        if supplied != deployed {
+            return Err(SchemeMismatch {
+                deployed,
+                supplied,
+                what,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Verifies a proof with the verifier of its scheme.
+pub fn verify_proof(vk: &VerifierKey, public_inputs: &[String], proof: &Proof) -> Result<()> {
+    vk.prepare()?.verify(public_inputs, proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_both_schemes() {
+        let plonk_vk: VerifierKey =
+            serde_json::from_str(include_str!("../examples/circuit/vk.json")).unwrap();
+        let plonk_proof: Proof =
+            serde_json::from_str(include_str!("../examples/circuit/proof.json")).unwrap();
+        let groth16_vk: VerifierKey =
+            serde_json::from_str(include_str!("../examples/circuit/groth16/vk.json")).unwrap();
+        let groth16_proof: Proof =
+            serde_json::from_str(include_str!("../examples/circuit/groth16/proof.json")).unwrap();
+        assert_eq!(plonk_vk.scheme(), ProofScheme::Plonk);
+        assert_eq!(plonk_proof.scheme(), ProofScheme::Plonk);
+        assert_eq!(groth16_vk.scheme(), ProofScheme::Groth16);
+        assert_eq!(groth16_proof.scheme(), ProofScheme::Groth16);
+
+        // the hash of PLONK keys didn't change
+        let plonk: plonk::VerifierKey =
+            serde_json::from_str(include_str!("../examples/circuit/vk.json")).unwrap();
+        assert_eq!(plonk_vk.hash(), plonk.hash());
+
+        // proofs are checked against the scheme of the zkapp
+        assert_eq!(
+            check_scheme(ProofScheme::Plonk, &plonk_vk, &plonk_proof),
+            Ok(())
+        );
+        let err = check_scheme(ProofScheme::Plonk, &plonk_vk, &groth16_proof).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "zkapp was deployed with PLONK but a Groth16 proof was supplied"
+        );
+        let err = check_scheme(ProofScheme::Groth16, &plonk_vk, &groth16_proof).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "zkapp was deployed with Groth16 but a PLONK verifier key was supplied"
+        );
+
+        // and a proof is never given to the verifier of another scheme
+        assert!(verify_proof(&plonk_vk, &[], &groth16_proof).is_err());
+
+        // groth16 proofs verify natively
+        let public_inputs: Vec<String> = serde_json::from_str(include_str!(
+            "../examples/circuit/groth16/public_inputs.json"
+        ))
+        .unwrap();
+        verify_proof(&groth16_vk, &public_inputs, &groth16_proof).unwrap();
+    }
+
+    #[test]
+    fn test_scheme_tags() {
+        for scheme in [ProofScheme::Plonk, ProofScheme::Groth16] {
+            assert_eq!(ProofScheme::from_tag(scheme.tag()).unwrap(), scheme);
+        }
+        assert!(ProofScheme::from_tag(7).is_err());
+    }
+}
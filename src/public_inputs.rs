@@ -0,0 +1,256 @@
+//! Validation of the public inputs of proofs, before they're given to the verifier.
+//! A proof that doesn't verify says nothing about why, so the inputs are first checked one by one
+//! (their number, that they're field elements, and that the ones zkBitcoin binds to the transaction match it),
+//! and every failure names the input and what was expected.
+//! The orchestrator and the nodes run the same checks (see [crate::bob_request::BobRequest::validate_request_with]).
+
+use std::fmt;
+
+use num_bigint::BigUint;
+use num_traits::Num;
+
+use crate::constants::{CIRCOM_ETH_PRIME, STATEFUL_ZKAPP_PUBLIC_INPUT_LEN};
+
+/// The public inputs of a stateful zkapp, in order (see [crate::plonk::PublicInputs]).
+pub const STATEFUL_INPUT_NAMES: [&str; STATEFUL_ZKAPP_PUBLIC_INPUT_LEN] = [
+    "new_state",
+    "prev_state",
+    "truncated_txid",
+    "amount_out",
+    "amount_in",
+];
+
+/// The public inputs of a stateless zkapp.
+pub const STATELESS_INPUT_NAMES: [&str; 1] = ["truncated_txid"];
+
+/// A public input that doesn't fit the circuit or the transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicInputError {
+    /// The verifier key declares another number of public inputs.
+    Count {
+        expected: usize,
+        supplied: usize,
+        names: &'static [&'static str],
+    },
+
+    /// An input isn't a (canonical) field element.
+    Malformed {
+        index: usize,
+        name: &'static str,
+        supplied: String,
+        reason: String,
+    },
+
+    /// An input bound to the transaction doesn't match it.
+    Mismatch {
+        index: usize,
+        name: &'static str,
+        expected: String,
+        supplied: String,
+
+        /// Where the expected value comes from.
+        source: &'static str,
+    },
+}
+
+impl fmt::Display for PublicInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Count {
+                expected,
+                supplied,
+                names,
+            } => write!(
+                f,
+                "the verifier key expects {expected} public inputs, but the zkapp uses {supplied} ({})",
+                names.join(", ")
+            ),
+            Self::Malformed {
+                index,
+                name,
+                supplied,
+                reason,
+            } => write!(
+                f,
+                "public input #{index} ({name}) `{supplied}` is not a valid field element: {reason}"
+            ),
+            Self::Mismatch {
+                index,
+                name,
+                expected,
+                supplied,
+                source,
+            } => write!(
+                f,
+                "public input #{index} ({name}) is {supplied}, but {expected} was expected ({source})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PublicInputError {}
+
+/// Parses an element of the BN254 scalar field (the circom field), given in decimal or in 0x-prefixed hex.
+/// Values that aren't smaller than the modulus are refused, rather than reduced.
+pub fn parse_field_element(input: &str) -> Result<BigUint, String> {
+    let (digits, radix) = match input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+    {
+        Some(hex) => (hex, 16),
+        None => (input, 10),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(
+            if radix == 10 && !input.is_empty() && input.chars().all(|c| c.is_ascii_hexdigit()) {
+                "it looks like hex, which must be prefixed with 0x".to_string()
+            } else if radix == 10 {
+                "expected a decimal number, or a hex number prefixed with 0x".to_string()
+            } else {
+                "expected hex digits after 0x".to_string()
+            },
+        );
+    }
+    let value = BigUint::from_str_radix(digits, radix).map_err(|err| err.to_string())?;
+
+    let modulus = BigUint::from_str_radix(CIRCOM_ETH_PRIME, 10).unwrap();
+    if value >= modulus {
+        return Err(format!(
+            "it is not smaller than the BN254 scalar field modulus {CIRCOM_ETH_PRIME} (it would wrap around to {})",
+            value % modulus
+        ));
+    }
+    Ok(value)
+}
+
+/// Parses a public input (see [parse_field_element]), and returns it in decimal (as the verifiers expect it).
+pub fn normalize(
+    index: usize,
+    name: &'static str,
+    input: &str,
+) -> Result<String, PublicInputError> {
+    parse_field_element(input)
+        .map(|value| value.to_str_radix(10))
+        .map_err(|reason| PublicInputError::Malformed {
+            index,
+            name,
+            supplied: input.to_string(),
+            reason,
+        })
+}
+
+/// Checks the public inputs of a proof against the number the verifier key declares, and normalizes them.
+pub fn validate(
+    declared: usize,
+    names: &'static [&'static str],
+    inputs: &[String],
+) -> Result<Vec<String>, PublicInputError> {
+    if declared != inputs.len() {
+        return Err(PublicInputError::Count {
+            expected: declared,
+            supplied: inputs.len(),
+            names,
+        });
+    }
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| normalize(index, names.get(index).unwrap_or(&"?"), input))
+        .collect()
+}
+
+/// Checks that a public input bound to the transaction matches the value computed from it
+/// (both being compared as field elements).
+pub fn check_reserved(
+    index: usize,
+    name: &'static str,
+    expected: &str,
+    supplied: &str,
+    source: &'static str,
+) -> Result<(), PublicInputError> {
+    let matches = match (parse_field_element(expected), parse_field_element(supplied)) {
+        (Ok(expected), Ok(supplied)) => expected == supplied,
+        _ => false,
+    };
+    if matches {
+        return Ok(());
+    }
+    Err(PublicInputError::Mismatch {
+        index,
+        name,
+        expected: expected.to_string(),
+        supplied: supplied.to_string(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_field_element() {
+        assert_eq!(parse_field_element("42").unwrap(), BigUint::from(42u8));
+        assert_eq!(parse_field_element("0x2a").unwrap(), BigUint::from(42u8));
+        assert_eq!(parse_field_element("0X2A").unwrap(), BigUint::from(42u8));
+        assert_eq!(parse_field_element("0").unwrap(), BigUint::from(0u8));
+
+        // hex without its prefix
+        let err = parse_field_element("2a").unwrap_err();
+        assert!(err.contains("prefixed with 0x"), "{err}");
+
+        // not a number
+        for input in ["", "0x", "-1", "1.5", " 1", "0xzz"] {
+            assert!(parse_field_element(input).is_err(), "{input}");
+        }
+
+        // the modulus (and anything larger) is refused rather than reduced
+        let err = parse_field_element(CIRCOM_ETH_PRIME).unwrap_err();
+        assert!(err.contains("wrap around to 0"), "{err}");
+        let largest = BigUint::from_str_radix(CIRCOM_ETH_PRIME, 10).unwrap() - 1u8;
+        assert_eq!(parse_field_element(&largest.to_string()).unwrap(), largest);
+    }
+
+    #[test]
+    fn test_validate() {
+        let inputs = ["1", "0x2", "3", "0x04", "5"].map(String::from);
+        assert_eq!(
+            validate(5, &STATEFUL_INPUT_NAMES, &inputs).unwrap(),
+            ["1", "2", "3", "4", "5"]
+        );
+
+        // the wrong count
+        let err = validate(1, &STATEFUL_INPUT_NAMES, &inputs).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the verifier key expects 1 public inputs, but the zkapp uses 5 (new_state, prev_state, truncated_txid, amount_out, amount_in)"
+        );
+
+        // a malformed input is named
+        let mut malformed = inputs.clone();
+        malformed[3] = "ff".to_string();
+        let err = validate(5, &STATEFUL_INPUT_NAMES, &malformed).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                PublicInputError::Malformed {
+                    index: 3,
+                    name: "amount_out",
+                    ..
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_check_reserved() {
+        check_reserved(1, "prev_state", "42", "0x2a", "the state of the zkapp").unwrap();
+        let err =
+            check_reserved(1, "prev_state", "42", "43", "the state of the zkapp").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "public input #1 (prev_state) is 43, but 42 was expected (the state of the zkapp)"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Keeps secrets out of the logs.
+//! Anything sensitive (RPC credentials, key shares, nonces) is wrapped in [Redacted] before it reaches a log macro,
+//! and is then logged as [REDACTED]. Redaction can be turned off to debug (see [set_redaction]).
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// What a secret is logged as.
+pub const REDACTED: &str = "<redacted>";
+
+/// The RPC methods whose parameters carry secrets (private keys, wallet passphrases).
+const SENSITIVE_RPC_METHODS: &[&str] = &[
+    "createwallet",
+    "dumpprivkey",
+    "encryptwallet",
+    "importprivkey",
+    "signrawtransactionwithkey",
+    "walletpassphrase",
+    "walletpassphrasechange",
+];
+
+static REDACTION: AtomicBool = AtomicBool::new(true);
+
+/// Turns redaction on (the default) or off for the whole process.
+pub fn set_redaction(enabled: bool) {
+    REDACTION.store(enabled, Ordering::Relaxed);
+}
+
+pub fn redaction_enabled() -> bool {
+    REDACTION.load(Ordering::Relaxed)
+}
+
+/// A value that is only shown in the logs if redaction is off.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Redacted<T>(pub T);
+
+impl<T: fmt::Debug> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if redaction_enabled() {
+            f.write_str(REDACTED)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if redaction_enabled() {
+            f.write_str(REDACTED)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+/// Masks the parameters of a JSON RPC request body (see [SENSITIVE_RPC_METHODS]) before it's logged.
+pub fn redact_rpc_body(method: &str, mut body: serde_json::Value) -> serde_json::Value {
+    if redaction_enabled() && SENSITIVE_RPC_METHODS.contains(&method) {
+        if let Some(params) = body.get_mut("params") {
+            *params = REDACTED.into();
+        }
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Credentials {
+        user: String,
+        auth: Redacted<String>,
+    }
+
+    #[test]
+    fn test_redacted_by_default() {
+        let credentials = Credentials {
+            user: "satoshi".to_string(),
+            auth: Redacted("satoshi:hunter2".to_string()),
+        };
+        let logged = format!("{credentials:?}");
+        assert_eq!(
+            logged,
+            r#"Credentials { user: "satoshi", auth: <redacted> }"#
+        );
+        assert!(!logged.contains("hunter2"));
+        assert_eq!(credentials.auth.to_string(), REDACTED);
+    }
+
+    #[test]
+    fn test_redact_rpc_body() {
+        let body = json!({"jsonrpc": "1.0", "id": "1", "method": "walletpassphrase", "params": ["hunter2", 60]});
+        assert_eq!(
+            redact_rpc_body("walletpassphrase", body),
+            json!({"jsonrpc": "1.0", "id": "1", "method": "walletpassphrase", "params": REDACTED})
+        );
+
+        // other methods are logged as is
+        let body = json!({"jsonrpc": "1.0", "id": "1", "method": "getblockcount", "params": []});
+        assert_eq!(redact_rpc_body("getblockcount", body.clone()), body);
+    }
+}
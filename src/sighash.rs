@@ -0,0 +1,439 @@
+//! The sighash of the zkapp input, which is what the committee signs.
+//! The orchestrator (to build the signing package) and the nodes (to check what they sign)
+//! must compute it the exact same way, so they both go through [compute_keyspend_sighash].
+//! By default the sighash commits to all the inputs and outputs, but Bob can ask for another sighash type
+//! (see [check_sighash_type]), in which case everyone uses [compute_keyspend_sighash_with_type].
+//!
+//! Zkapps locked to a taproot script tree (for example, with a timelocked recovery leaf next to the committee key)
+//! can also be spent through one of their leaves, see [ScriptPathSpend] and [compute_script_spend_sighash].
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::{
+    sighash::{Prevouts, SighashCache},
+    taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootSpendInfo},
+    ScriptBuf, TapSighashType, Transaction, TxOut, Witness,
+};
+use secp256k1::hashes::Hash;
+
+/// The sighash type used to spend zkapps by default (it commits to all the inputs and outputs).
+/// Signatures using it are 64 bytes (no sighash flag appended).
+pub const KEYSPEND_SIGHASH_TYPE: TapSighashType = TapSighashType::Default;
+
+/// Used as a serde default, for requests that don't specify a sighash type.
+pub fn default_sighash_type() -> TapSighashType {
+    KEYSPEND_SIGHASH_TYPE
+}
+
+/// Checks that the committee can sign a zkapp input with `sighash_type`.
+/// Only types committing to all the outputs are accepted: with `NONE` or `SINGLE` types,
+/// the outputs checked before signing (the fee to zkBitcoinFund, the new state of a stateful zkapp, the change)
+/// could be dropped or replaced by whoever holds the signed input.
+pub fn check_sighash_type(sighash_type: TapSighashType) -> Result<()> {
+    match sighash_type {
+        TapSighashType::Default | TapSighashType::All | TapSighashType::AllPlusAnyoneCanPay => {
+            Ok(())
+        }
+        TapSighashType::None
+        | TapSighashType::NonePlusAnyoneCanPay
+        | TapSighashType::Single
+        | TapSighashType::SinglePlusAnyoneCanPay => {
+            bail!("sighash type {sighash_type} doesn't commit to all the outputs")
+        }
+    }
+}
+
+/// The code separator position to use when the leaf script has no `OP_CODESEPARATOR` (see BIP 342).
+const NO_CODE_SEPARATOR: u32 = 0xFFFFFFFF;
+
+/// A taproot script-path spend: the leaf script being executed,
+/// and the control block (leaf version, internal key, and merkle path) proving that it's part of the script tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptPathSpend {
+    pub leaf_script: ScriptBuf,
+    pub control_block: ControlBlock,
+}
+
+impl ScriptPathSpend {
+    pub fn new(leaf_script: ScriptBuf, control_block: ControlBlock) -> Self {
+        Self {
+            leaf_script,
+            control_block,
+        }
+    }
+
+    /// Builds the spend of a (tapscript) leaf of the given script tree.
+    pub fn from_spend_info(spend_info: &TaprootSpendInfo, leaf_script: ScriptBuf) -> Result<Self> {
+        let control_block = spend_info
+            .control_block(&(leaf_script.clone(), LeafVersion::TapScript))
+            .context("the leaf script is not part of the script tree")?;
+        Ok(Self::new(leaf_script, control_block))
+    }
+
+    pub fn leaf_version(&self) -> LeafVersion {
+        self.control_block.leaf_version
+    }
+
+    pub fn leaf_hash(&self) -> TapLeafHash {
+        TapLeafHash::from_script(&self.leaf_script, self.leaf_version())
+    }
+
+    /// The witness of the input, given the elements satisfying the leaf script (e.g. a signature).
+    pub fn witness(&self, script_inputs: &[&[u8]]) -> Witness {
+        let mut witness = Witness::new();
+        for script_input in script_inputs {
+            witness.push(script_input);
+        }
+        witness.push(self.leaf_script.as_bytes());
+        witness.push(self.control_block.serialize());
+        witness
+    }
+}
+
+/// Computes the taproot key-spend sighash of the input at `input_index`.
+/// As taproot sighashes commit to all the prevouts, `prevouts` must contain one output per input of `tx` (in order).
+pub fn compute_keyspend_sighash(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+) -> Result<[u8; 32]> {
+    compute_keyspend_sighash_with_type(tx, input_index, prevouts, KEYSPEND_SIGHASH_TYPE)
+}
+
+/// Same as [compute_keyspend_sighash], but with the given sighash type.
+/// The signature must then be serialized with the same type (see [bitcoin::taproot::Signature]).
+pub fn compute_keyspend_sighash_with_type(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    sighash_type: TapSighashType,
+) -> Result<[u8; 32]> {
+    compute_sighash(tx, input_index, prevouts, None, sighash_type)
+}
+
+/// Computes the taproot script-spend sighash of the input at `input_index`, spent through the leaf of `spend`.
+/// As for [compute_keyspend_sighash], `prevouts` must contain one output per input of `tx` (in order).
+pub fn compute_script_spend_sighash(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    spend: &ScriptPathSpend,
+) -> Result<[u8; 32]> {
+    compute_sighash(
+        tx,
+        input_index,
+        prevouts,
+        Some(spend.leaf_hash()),
+        KEYSPEND_SIGHASH_TYPE,
+    )
+}
+
+fn compute_sighash(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    leaf_hash: Option<TapLeafHash>,
+    sighash_type: TapSighashType,
+) -> Result<[u8; 32]> {
+    ensure!(
+        input_index < tx.input.len(),
+        "input index {input_index} is out of range (the transaction has {} inputs)",
+        tx.input.len()
+    );
+    ensure!(
+        prevouts.len() == tx.input.len(),
+        "missing prevouts: got {} prevouts for {} inputs",
+        prevouts.len(),
+        tx.input.len()
+    );
+
+    let mut cache = SighashCache::new(tx);
+    let sighash = cache
+        .taproot_signature_hash(
+            input_index,
+            &Prevouts::All(prevouts),
+            None,
+            leaf_hash.map(|leaf_hash| (leaf_hash, NO_CODE_SEPARATOR)),
+            sighash_type,
+        )
+        .context("couldn't compute the sighash")?;
+    Ok(sighash.to_byte_array())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        absolute::LockTime,
+        key::UntweakedPublicKey,
+        opcodes::all::{OP_CHECKSIG, OP_CSV, OP_DROP},
+        script::Builder,
+        taproot::TaprootBuilder,
+        transaction::Version,
+        Amount, OutPoint, Sequence, TxIn, Txid,
+    };
+    use secp256k1::{Keypair, Message, Secp256k1, SecretKey};
+
+    use super::*;
+
+    fn script(hex: &str) -> ScriptBuf {
+        ScriptBuf::from_hex(hex).unwrap()
+    }
+
+    fn p2tr_a() -> ScriptBuf {
+        script(&format!("5120{}", "aa".repeat(32)))
+    }
+
+    fn p2tr_b() -> ScriptBuf {
+        script(&format!("5120{}", "bb".repeat(32)))
+    }
+
+    fn p2wpkh() -> ScriptBuf {
+        script(&format!("0014{}", "cc".repeat(20)))
+    }
+
+    fn input(txid_byte: u8, vout: u32) -> TxIn {
+        TxIn {
+            previous_output: OutPoint {
+                txid: Txid::from_byte_array([txid_byte; 32]),
+                vout,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }
+    }
+
+    fn output(value: u64, script_pubkey: ScriptBuf) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey,
+        }
+    }
+
+    /// A transaction spending a zkapp (first input) and a wallet UTXO (second input).
+    fn multi_input_tx() -> (Transaction, Vec<TxOut>) {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![input(1, 0), input(2, 1)],
+            output: vec![output(120_000, p2tr_b()), output(29_000, p2wpkh())],
+        };
+        let prevouts = vec![output(100_000, p2tr_a()), output(50_000, p2wpkh())];
+        (tx, prevouts)
+    }
+
+    #[test]
+    fn test_single_input_vector() {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![input(1, 0)],
+            output: vec![output(120_000, p2tr_b())],
+        };
+        let prevouts = vec![output(100_000, p2tr_a())];
+        let sighash = compute_keyspend_sighash(&tx, 0, &prevouts).unwrap();
+        assert_eq!(
+            hex::encode(sighash),
+            "8635ac611cd411223d9d51ee308a8f593939d91853e1560086fc73382a6ad5fd"
+        );
+    }
+
+    #[test]
+    fn test_multi_input_vector() {
+        let (tx, prevouts) = multi_input_tx();
+        let zkapp_sighash = compute_keyspend_sighash(&tx, 0, &prevouts).unwrap();
+        assert_eq!(
+            hex::encode(zkapp_sighash),
+            "5789bf688cceab085944111bd8a5170908c061bc2bf4c0037dfaeb02423c35b9"
+        );
+
+        // the sighash of the other input is different
+        let other_sighash = compute_keyspend_sighash(&tx, 1, &prevouts).unwrap();
+        assert_eq!(
+            hex::encode(other_sighash),
+            "6e3d08846ccd12c65dc06896663de472a40b0ce842f203d3d3263f800c7be205"
+        );
+    }
+
+    #[test]
+    fn test_lock_time_and_sequence_are_signed() {
+        let (tx, prevouts) = multi_input_tx();
+        let sighash = compute_keyspend_sighash(&tx, 0, &prevouts).unwrap();
+
+        let mut time_locked = tx.clone();
+        time_locked.lock_time = LockTime::from_height(850_000).unwrap();
+        let time_locked_sighash = compute_keyspend_sighash(&time_locked, 0, &prevouts).unwrap();
+        assert_ne!(sighash, time_locked_sighash);
+
+        // the sequence of any input is signed (not just the one of the input being signed)
+        let mut replaceable = tx.clone();
+        replaceable.input[1].sequence = Sequence::MAX;
+        let replaceable_sighash = compute_keyspend_sighash(&replaceable, 0, &prevouts).unwrap();
+        assert_ne!(sighash, replaceable_sighash);
+    }
+
+    #[test]
+    fn test_script_path_spend() {
+        let secp = Secp256k1::new();
+        let (internal_key, _): (UntweakedPublicKey, _) =
+            Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[2; 32]).unwrap())
+                .x_only_public_key();
+        let recovery_keypair =
+            Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let (recovery_key, _) = recovery_keypair.x_only_public_key();
+
+        // a script tree with a committee leaf and a timelocked recovery leaf
+        let committee_leaf = Builder::new()
+            .push_x_only_key(&internal_key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let recovery_leaf = Builder::new()
+            .push_int(144)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_x_only_key(&recovery_key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(1, committee_leaf.clone())
+            .unwrap()
+            .add_leaf(1, recovery_leaf.clone())
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+        let output_key = spend_info.output_key().to_inner();
+
+        let (mut tx, mut prevouts) = multi_input_tx();
+        prevouts[0].script_pubkey = ScriptBuf::new_p2tr_tweaked(spend_info.output_key());
+        tx.input[0].sequence = Sequence::from_height(144);
+
+        // spend through the recovery leaf
+        let spend = ScriptPathSpend::from_spend_info(&spend_info, recovery_leaf.clone()).unwrap();
+        assert_eq!(spend.leaf_version(), LeafVersion::TapScript);
+        assert_eq!(spend.control_block.merkle_branch.len(), 1);
+        assert!(spend
+            .control_block
+            .verify_taproot_commitment(&secp, output_key, &recovery_leaf));
+
+        // the sighash matches the reference computation
+        let sighash = compute_script_spend_sighash(&tx, 0, &prevouts, &spend).unwrap();
+        let expected = SighashCache::new(&tx)
+            .taproot_script_spend_signature_hash(
+                0,
+                &Prevouts::All(&prevouts),
+                TapLeafHash::from_script(&recovery_leaf, LeafVersion::TapScript),
+                KEYSPEND_SIGHASH_TYPE,
+            )
+            .unwrap();
+        assert_eq!(sighash, expected.to_byte_array());
+
+        // it's different from the key-spend sighash, and from the sighash of the other leaf
+        assert_ne!(
+            sighash,
+            compute_keyspend_sighash(&tx, 0, &prevouts).unwrap()
+        );
+        let committee_spend =
+            ScriptPathSpend::from_spend_info(&spend_info, committee_leaf).unwrap();
+        assert_ne!(
+            sighash,
+            compute_script_spend_sighash(&tx, 0, &prevouts, &committee_spend).unwrap()
+        );
+
+        // the witness is [signature, leaf script, control block]
+        let sig = secp
+            .sign_schnorr_no_aux_rand(&Message::from_digest(sighash), &recovery_keypair)
+            .serialize();
+        let witness = spend.witness(&[&sig[..]]);
+        assert_eq!(witness.len(), 3);
+        assert_eq!(witness.nth(0).unwrap(), &sig[..]);
+        assert_eq!(witness.tapscript().unwrap(), recovery_leaf.as_script());
+        assert_eq!(witness.nth(2).unwrap(), spend.control_block.serialize());
+
+        // a leaf that is not in the tree can't be spent
+        let unknown_leaf = Builder::new().push_opcode(OP_CHECKSIG).into_script();
+        assert!(ScriptPathSpend::from_spend_info(&spend_info, unknown_leaf).is_err());
+    }
+
+    #[test]
+    fn test_non_default_sighash_type() {
+        let (key_packages, pubkey_package) = crate::frost::gen_frost_keys(3, 2).unwrap();
+        let (mut tx, mut prevouts) = multi_input_tx();
+        let xonly = crate::frost::to_xonly_pubkey(pubkey_package.verifying_key());
+        prevouts[0].script_pubkey = ScriptBuf::new_p2tr(&Secp256k1::new(), xonly, None);
+
+        // ANYONECANPAY only commits to the zkapp input
+        let sighash_type = TapSighashType::AllPlusAnyoneCanPay;
+        check_sighash_type(sighash_type).unwrap();
+        let sighash = compute_keyspend_sighash_with_type(&tx, 0, &prevouts, sighash_type).unwrap();
+        assert_ne!(
+            sighash,
+            compute_keyspend_sighash(&tx, 0, &prevouts).unwrap()
+        );
+        let expected = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), sighash_type)
+            .unwrap();
+        assert_eq!(sighash, expected.to_byte_array());
+
+        // the committee signs it, and the signature carries the sighash flag
+        let signature = crate::frost::sign(&key_packages, &pubkey_package, &sighash).unwrap();
+        let sig = secp256k1::schnorr::Signature::from_slice(&signature.serialize()[1..]).unwrap();
+        let final_signature = bitcoin::taproot::Signature {
+            sig,
+            hash_ty: sighash_type,
+        };
+        let serialized = final_signature.to_vec();
+        assert_eq!(serialized.len(), 65);
+        assert_eq!(serialized[64], sighash_type as u8);
+
+        // which verifies under the output key, as it would in the script interpreter
+        let parsed = bitcoin::taproot::Signature::from_slice(&serialized).unwrap();
+        assert_eq!(parsed.hash_ty, sighash_type);
+        let output_key = prevouts[0].script_pubkey.as_bytes()[2..].to_vec();
+        let output_key = secp256k1::XOnlyPublicKey::from_slice(&output_key).unwrap();
+        Secp256k1::verification_only()
+            .verify_schnorr(&parsed.sig, &Message::from_digest(sighash), &output_key)
+            .unwrap();
+
+        // other inputs can be added without invalidating the signature
+        tx.input.push(input(3, 0));
+        prevouts.push(output(10_000, p2wpkh()));
+        assert_eq!(
+            compute_keyspend_sighash_with_type(&tx, 0, &prevouts, sighash_type).unwrap(),
+            sighash
+        );
+    }
+
+    #[test]
+    fn test_check_sighash_type() {
+        for sighash_type in [
+            TapSighashType::Default,
+            TapSighashType::All,
+            TapSighashType::AllPlusAnyoneCanPay,
+        ] {
+            check_sighash_type(sighash_type).unwrap();
+        }
+
+        // with SINGLE, the signature would only cover the output at the index of the zkapp input
+        for sighash_type in [
+            TapSighashType::None,
+            TapSighashType::NonePlusAnyoneCanPay,
+            TapSighashType::Single,
+            TapSighashType::SinglePlusAnyoneCanPay,
+        ] {
+            let err = check_sighash_type(sighash_type).unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("doesn't commit to all the outputs"));
+        }
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let (tx, prevouts) = multi_input_tx();
+
+        let err = compute_keyspend_sighash(&tx, 2, &prevouts).unwrap_err();
+        assert!(err.to_string().contains("out of range"));
+
+        let err = compute_keyspend_sighash(&tx, 0, &prevouts[..1]).unwrap_err();
+        assert!(err.to_string().contains("missing prevouts"));
+    }
+}
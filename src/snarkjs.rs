@@ -135,7 +135,7 @@ pub async fn prove(
     let proof_path = tmp_dir.path().join("proof.json");
     let full_public_inputs_path = tmp_dir.path().join("full_public_inputs.json");
 
-    // create witness using circom
+    // create witness using circom's witness calculator (in-process with the `witness` feature, with node otherwise)
     {
         let circuit_name = circom_circuit_path
             .file_stem()
@@ -147,54 +147,88 @@ pub async fn prove(
         let circuit_wasm_path =
             output_folder.join(format!("{}.wasm", circuit_name.to_string_lossy()));
 
-        // node output/circuit_js/generate_witness.js output/circuit_js/circuit.wasm public_input.json output/witness.wtns
-        let output = Command::new("node")
-            .current_dir(&tmp_dir)
-            .arg(generate_witness_path)
-            .arg(circuit_wasm_path)
-            .arg(&public_inputs_path)
-            .arg(&witness_path)
-            .output()
-            .expect("failed to execute process");
-
-        info!("{}", String::from_utf8_lossy(&output.stdout));
+        #[cfg(feature = "witness")]
+        {
+            let _ = generate_witness_path;
+            let inputs = match serde_json::to_value(proof_inputs)? {
+                serde_json::Value::Object(inputs) => inputs,
+                _ => unreachable!("the proof inputs are a map"),
+            };
+            let wtns = crate::witness::WitnessCalculator::from_file(&circuit_wasm_path)?
+                .calculate_wtns(&inputs)
+                .context("couldn't create witness")?;
+            std::fs::write(&witness_path, wtns).context("couldn't write witness")?;
+        }
 
-        if !output.status.success() {
-            info!("{}", String::from_utf8_lossy(&output.stderr));
-            bail!("couldn't create witness");
+        #[cfg(not(feature = "witness"))]
+        {
+            // node output/circuit_js/generate_witness.js output/circuit_js/circuit.wasm public_input.json output/witness.wtns
+            let output = Command::new("node")
+                .current_dir(&tmp_dir)
+                .arg(generate_witness_path)
+                .arg(circuit_wasm_path)
+                .arg(&public_inputs_path)
+                .arg(&witness_path)
+                .output()
+                .expect("failed to execute process");
+
+            info!("{}", String::from_utf8_lossy(&output.stdout));
+
+            if !output.status.success() {
+                info!("{}", String::from_utf8_lossy(&output.stderr));
+                bail!("couldn't create witness");
+            }
         }
     }
 
     // create proof using snarkjs
-    {
-        let output = Command::new("snarkjs")
-            .current_dir(&tmp_dir)
-            .arg("plonk")
-            .arg("prove")
-            .arg(prover_key_path)
-            .arg(&witness_path)
-            .arg(&proof_path)
-            .arg(&full_public_inputs_path)
-            .output()
-            .expect("failed to execute process");
+    let (proof, full_public_inputs) = prove_with_witness(
+        tmp_dir.path(),
+        &prover_key_path,
+        &witness_path,
+        &proof_path,
+        &full_public_inputs_path,
+    )?;
 
-        info!("{}", String::from_utf8_lossy(&output.stdout));
+    Ok((proof, full_public_inputs, verifier_key))
+}
 
-        if !output.status.success() {
-            bail!("couldn't create proof");
-        }
+/// Creates a PLONK proof from a witness (in the `.wtns` format) with snarkjs,
+/// writing the proof and the public inputs to the given paths.
+pub fn prove_with_witness(
+    working_dir: &Path,
+    prover_key_path: &Path,
+    witness_path: &Path,
+    proof_path: &Path,
+    public_inputs_path: &Path,
+) -> Result<(plonk::Proof, plonk::PublicInputs)> {
+    // snarkjs plonk prove circuit_final.zkey witness.wtns proof.json public.json
+    let output = Command::new("snarkjs")
+        .current_dir(working_dir)
+        .arg("plonk")
+        .arg("prove")
+        .arg(prover_key_path)
+        .arg(witness_path)
+        .arg(proof_path)
+        .arg(public_inputs_path)
+        .output()
+        .expect("failed to execute process");
+
+    info!("{}", String::from_utf8_lossy(&output.stdout));
+
+    if !output.status.success() {
+        bail!("couldn't create proof");
     }
 
     // parse proof and full public inputs
     let proof_file = File::open(proof_path).expect("file creation failed");
     let proof: plonk::Proof = serde_json::from_reader(proof_file).expect("read failed");
 
-    let full_public_inputs_file =
-        File::open(full_public_inputs_path).expect("file creation failed");
+    let full_public_inputs_file = File::open(public_inputs_path).expect("file creation failed");
     let full_public_inputs: plonk::PublicInputs =
         serde_json::from_reader(full_public_inputs_file).expect("read failed");
 
-    Ok((proof, full_public_inputs, verifier_key))
+    Ok((proof, full_public_inputs))
 }
 
 pub fn verify_proof(
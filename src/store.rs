@@ -0,0 +1,280 @@
+//! A small key-value store that the subsystems needing durable state can share,
+//! instead of each of them coming up with its own file format.
+//!
+//! Keys live in namespaces (one per subsystem, e.g. `sessions`), so that subsystems never step on each other's keys.
+//! [FileStore] is the default implementation (one file per key, written atomically),
+//! and [MemoryStore] is useful for tests and for nodes that don't need to persist anything.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{ensure, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A key-value store, with keys grouped in namespaces.
+pub trait Store: Send + Sync {
+    /// Returns the value of a key (if it exists).
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Sets the value of a key (overwriting its previous value, if any).
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<()>;
+
+    /// Deletes a key, returns `false` if it didn't exist.
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool>;
+
+    /// Returns the keys of a namespace starting with `prefix` (and their values), sorted by key.
+    fn scan(&self, namespace: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>>;
+}
+
+impl dyn Store + '_ {
+    /// Returns the value of a key, deserialized from JSON.
+    pub fn get_json<T: DeserializeOwned>(&self, namespace: &str, key: &str) -> Result<Option<T>> {
+        self.get(namespace, key)?
+            .map(|value| {
+                serde_json::from_slice(&value)
+                    .with_context(|| format!("couldn't deserialize {namespace}/{key}"))
+            })
+            .transpose()
+    }
+
+    /// Sets the value of a key, serialized to JSON.
+    pub fn put_json<T: Serialize>(&self, namespace: &str, key: &str, value: &T) -> Result<()> {
+        self.put(namespace, key, &serde_json::to_vec(value)?)
+    }
+}
+
+/// Namespaces end up as directory names, so they are kept simple.
+fn check_namespace(namespace: &str) -> Result<()> {
+    ensure!(
+        !namespace.is_empty()
+            && namespace
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-'),
+        "invalid namespace {namespace:?} (only lowercase letters, digits, '_' and '-' are allowed)"
+    );
+    Ok(())
+}
+
+/// A store kept in memory (and thus lost on restart).
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<BTreeMap<(String, String), Vec<u8>>>,
+}
+
+impl Store for MemoryStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        check_namespace(namespace)?;
+        let entries = self.entries.lock().unwrap();
+        Ok(entries
+            .get(&(namespace.to_string(), key.to_string()))
+            .cloned())
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        check_namespace(namespace)?;
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert((namespace.to_string(), key.to_string()), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool> {
+        check_namespace(namespace)?;
+        let mut entries = self.entries.lock().unwrap();
+        Ok(entries
+            .remove(&(namespace.to_string(), key.to_string()))
+            .is_some())
+    }
+
+    fn scan(&self, namespace: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        check_namespace(namespace)?;
+        let entries = self.entries.lock().unwrap();
+        Ok(entries
+            .iter()
+            .filter(|((ns, key), _)| ns == namespace && key.starts_with(prefix))
+            .map(|((_, key), value)| (key.clone(), value.clone()))
+            .collect())
+    }
+}
+
+/// A store persisted in a directory: one directory per namespace, and one file per key.
+/// File names are the hex encoding of the keys (so that any key is a valid file name),
+/// and files are written to a temporary file first, so that a crash never leaves a truncated value behind.
+pub struct FileStore {
+    dir: PathBuf,
+
+    /// Serializes writes, so that concurrent writers of the same key don't race on its temporary file.
+    write_lock: Mutex<()>,
+}
+
+impl FileStore {
+    /// Opens the store in `dir` (creating it if needed).
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("couldn't create the store directory {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    fn namespace_dir(&self, namespace: &str) -> Result<PathBuf> {
+        check_namespace(namespace)?;
+        Ok(self.dir.join(namespace))
+    }
+
+    fn key_path(&self, namespace: &str, key: &str) -> Result<PathBuf> {
+        Ok(self.namespace_dir(namespace)?.join(hex::encode(key)))
+    }
+}
+
+impl Store for FileStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.key_path(namespace, key)?;
+        match std::fs::read(&path) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("couldn't read {}", path.display())),
+        }
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        let path = self.key_path(namespace, key)?;
+        let _lock = self.write_lock.lock().unwrap();
+        std::fs::create_dir_all(path.parent().unwrap())
+            .with_context(|| format!("couldn't create the directory of namespace {namespace}"))?;
+
+        let tmp_path = path.with_extension("tmp");
+        let res = std::fs::write(&tmp_path, value)
+            .and_then(|_| std::fs::rename(&tmp_path, &path))
+            .with_context(|| format!("couldn't write {}", path.display()));
+        if res.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        res
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool> {
+        let path = self.key_path(namespace, key)?;
+        let _lock = self.write_lock.lock().unwrap();
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).with_context(|| format!("couldn't delete {}", path.display())),
+        }
+    }
+
+    fn scan(&self, namespace: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let dir = self.namespace_dir(namespace)?;
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut entries = vec![];
+        let read_dir =
+            std::fs::read_dir(&dir).with_context(|| format!("couldn't list {}", dir.display()))?;
+        for entry in read_dir {
+            let path = entry?.path();
+            // skip leftover temporary files (and anything else that isn't a key)
+            let Some(key) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| hex::decode(name).ok())
+                .and_then(|key| String::from_utf8(key).ok())
+            else {
+                continue;
+            };
+            if !key.starts_with(prefix) {
+                continue;
+            }
+            // the key might have been deleted since we listed it
+            match std::fs::read(&path) {
+                Ok(value) => entries.push((key, value)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    return Err(err).with_context(|| format!("couldn't read {}", path.display()))
+                }
+            }
+        }
+        entries.sort();
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    /// Runs the same checks against any store.
+    fn check_store(store: &dyn Store) {
+        assert_eq!(store.get("sessions", "a").unwrap(), None);
+        store.put("sessions", "a", b"1").unwrap();
+        store.put("sessions", "a/b", b"2").unwrap();
+        store.put("sessions", "c", b"3").unwrap();
+        assert_eq!(store.get("sessions", "a").unwrap(), Some(b"1".to_vec()));
+
+        // overwriting
+        store.put("sessions", "a", b"4").unwrap();
+        assert_eq!(store.get("sessions", "a").unwrap(), Some(b"4".to_vec()));
+
+        // scanning by prefix, sorted
+        let keys = store
+            .scan("sessions", "a")
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["a", "a/b"]);
+        assert_eq!(store.scan("sessions", "").unwrap().len(), 3);
+
+        // deleting
+        assert!(store.delete("sessions", "c").unwrap());
+        assert!(!store.delete("sessions", "c").unwrap());
+        assert_eq!(store.get("sessions", "c").unwrap(), None);
+
+        // namespaces are isolated
+        assert_eq!(store.get("nonces", "a").unwrap(), None);
+        store.put("nonces", "a", b"5").unwrap();
+        assert_eq!(store.get("sessions", "a").unwrap(), Some(b"4".to_vec()));
+        assert_eq!(store.scan("nonces", "").unwrap().len(), 1);
+        assert!(store.scan("audit", "").unwrap().is_empty());
+
+        // and must be simple names
+        assert!(store.put("../escape", "a", b"6").is_err());
+        assert!(store.get("", "a").is_err());
+
+        // JSON helpers
+        store.put_json("sessions", "json", &vec![1, 2, 3]).unwrap();
+        assert_eq!(
+            store.get_json::<Vec<u32>>("sessions", "json").unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert!(store.get_json::<String>("sessions", "json").is_err());
+    }
+
+    #[test]
+    fn test_memory_store() {
+        check_store(&MemoryStore::default());
+    }
+
+    #[test]
+    fn test_file_store() {
+        let tmp_dir = TempDir::new("zkbitcoin_store").unwrap();
+        check_store(&FileStore::open(tmp_dir.path()).unwrap());
+
+        // everything survives reopening the store
+        let store = FileStore::open(tmp_dir.path()).unwrap();
+        assert_eq!(store.get("sessions", "a").unwrap(), Some(b"4".to_vec()));
+        assert_eq!(store.get("sessions", "c").unwrap(), None);
+        assert_eq!(store.scan("sessions", "").unwrap().len(), 3);
+        assert_eq!(store.get("nonces", "a").unwrap(), Some(b"5".to_vec()));
+
+        // leftover temporary files are ignored
+        std::fs::write(tmp_dir.path().join("nonces").join("61.tmp"), b"").unwrap();
+        assert_eq!(store.scan("nonces", "").unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,261 @@
+//! A full deposit → spend cycle on a regtest node, with an in-process committee:
+//! a committee is generated, a zkapp is deployed to its address, and a spend of it is proven, signed by the committee,
+//! broadcast, and confirmed. Used by the `e2e-demo` command of `zkbtc-admin` and by the end-to-end tests.
+//! Proving requires circom and snarkjs.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::{Amount, BlockHash, Network, OutPoint, Txid};
+use jsonrpsee::server::ServerHandle;
+use log::info;
+use serde::Serialize;
+use tempdir::TempDir;
+
+use crate::{
+    alice_sign_tx::generate_and_broadcast_transaction,
+    bob_request::{verify_with_node, BobRequest},
+    committee::{
+        node::NodeConfig,
+        orchestrator::{CommitteeConfig, Member, MemberStatusState, Orchestrator},
+    },
+    compliance::Compliance,
+    frost,
+    json_rpc_stuff::{
+        send_raw_transaction, wait_for_confirmation, ConfirmationStatus, TransactionOrHex,
+    },
+    mpc_sign_tx::sign_wallet_inputs,
+    snarkjs::{self, CompilationResult},
+    taproot_addr_from,
+    testing::regtest::{find_executable, Regtest},
+    tx_sanity::FeeLimits,
+};
+
+/// The amount (in satoshis) deposited in the zkapp.
+pub const DEMO_DEPOSIT_SATS: u64 = 10_000;
+
+/// How long we wait for the spend to confirm.
+const SPEND_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The stateless example circuit, unlocked by the preimage `1`.
+pub fn stateless_circuit() -> (PathBuf, HashMap<String, Vec<String>>) {
+    let circuit_path =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples/circuit/stateless.circom");
+    let proof_inputs = HashMap::from([("preimage".to_string(), vec!["1".to_string()])]);
+    (circuit_path, proof_inputs)
+}
+
+/// Checks that the tools needed to prove are installed.
+pub fn check_prover() -> Result<()> {
+    for tool in ["circom", "snarkjs"] {
+        if find_executable(tool).is_none() {
+            bail!("{tool} is needed to prove (it wasn't found in the PATH)");
+        }
+    }
+    Ok(())
+}
+
+/// A 2-of-3 committee running in the current process.
+pub struct InProcessCommittee {
+    /// The group public key (which the zkBitcoin address is derived from).
+    pub pubkey: bitcoin::PublicKey,
+
+    /// The orchestrator, driven directly (it doesn't listen for requests).
+    pub orchestrator: Orchestrator,
+
+    /// The addresses of the nodes.
+    pub node_addresses: Vec<String>,
+
+    /// The nodes, which stop when the committee is dropped.
+    _nodes: Vec<ServerHandle>,
+}
+
+impl InProcessCommittee {
+    /// Generates a committee and starts its nodes on ports picked by the OS.
+    /// As the zkBitcoin address is global, this points it (and the network) at the new committee
+    /// (see [crate::testing::set_local_committee]),
+    /// and tracks the committee's outputs in the committee wallet of the node (see [Regtest::track_committee]).
+    pub async fn start(regtest: &Regtest) -> Result<Self> {
+        // the committee's public key must start with 0x02 (it's used as a taproot internal key)
+        let (key_packages, pubkey_package) = loop {
+            let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2)?;
+            if pubkey_package.verifying_key().serialize()[0] == 2 {
+                break (key_packages, pubkey_package);
+            }
+        };
+        let pubkey = bitcoin::PublicKey::from_slice(&pubkey_package.verifying_key().serialize())?;
+        crate::testing::set_local_committee(pubkey, Network::Regtest);
+        regtest.track_committee(pubkey).await?;
+
+        let mut members = HashMap::new();
+        let mut nodes = vec![];
+        let mut node_addresses = vec![];
+        for (id, key_package) in key_packages {
+            let (address, handle) = crate::committee::node::start_server(
+                Some("127.0.0.1:0"),
+                key_package,
+                pubkey_package.clone(),
+                NodeConfig {
+                    fee_limits: FeeLimits::for_network(Network::Regtest),
+                    ..Default::default()
+                },
+            )
+            .await?;
+            nodes.push(handle);
+            node_addresses.push(format!("http://{address}"));
+            members.insert(
+                id,
+                Member {
+                    address: format!("http://{address}"),
+                    votes: None,
+                },
+            );
+        }
+
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members,
+            signature: None,
+        };
+        let member_status = Arc::new(RwLock::new(MemberStatusState::new(&committee_cfg).await));
+        let orchestrator = Orchestrator::new(
+            pubkey_package,
+            committee_cfg,
+            member_status,
+            Arc::new(Compliance::new()),
+        )
+        .with_bitcoind(regtest.node_ctx(), 1);
+
+        Ok(Self {
+            pubkey,
+            orchestrator,
+            node_addresses,
+            _nodes: nodes,
+        })
+    }
+}
+
+/// What happened during a [deposit_and_spend].
+#[derive(Debug, Clone, Serialize)]
+pub struct E2eDemoReport {
+    pub committee_address: String,
+
+    /// The hash of the verifier key of the zkapp.
+    pub vk_hash: String,
+
+    pub zkapp_outpoint: OutPoint,
+    pub deposit: Amount,
+
+    /// The balance of the committee before the deposit, after it, and after the spend.
+    pub committee_balances: [Amount; 3],
+
+    pub spend_txid: Txid,
+    pub spend_block_hash: BlockHash,
+
+    /// Who the zkapp was unlocked to, and how much they received.
+    pub recipient: String,
+    pub received: Amount,
+}
+
+/// Deploys the zkapp of `circuit_path` to the committee, proves a spend of it with `proof_inputs`,
+/// gets the committee to sign the spend, and broadcasts it. Fails unless the spend confirms, and the funds moved.
+pub async fn deposit_and_spend(
+    regtest: &Regtest,
+    committee: &InProcessCommittee,
+    circuit_path: &Path,
+    proof_inputs: HashMap<String, Vec<String>>,
+) -> Result<E2eDemoReport> {
+    let ctx = &regtest.rpc_ctx;
+    let committee_address = taproot_addr_from(&committee.pubkey.to_string())?.to_string();
+    let balance_before = regtest.balance(&regtest.committee_ctx).await?;
+
+    // deposit
+    let vk_hash = {
+        let tmp_dir = TempDir::new("zkbitcoin_").context("couldn't create tmp dir")?;
+        let CompilationResult { verifier_key, .. } =
+            snarkjs::compile(&tmp_dir, circuit_path).await?;
+        verifier_key.hash()
+    };
+    let zkapp_txid =
+        generate_and_broadcast_transaction(ctx, &vk_hash, None, DEMO_DEPOSIT_SATS).await?;
+    regtest.mine_blocks(1).await?;
+    info!("- deposited {DEMO_DEPOSIT_SATS} sats to {committee_address} in {zkapp_txid}");
+
+    let balance_after_deposit = regtest.balance(&regtest.committee_ctx).await?;
+    let deposit = Amount::from_sat(DEMO_DEPOSIT_SATS);
+    ensure!(
+        balance_after_deposit == balance_before + deposit,
+        "the committee's balance went from {balance_before} to {balance_after_deposit} after the deposit"
+    );
+
+    // prove and get the committee to sign the spend
+    let recipient = regtest.get_new_address().await?;
+    let bob_request = BobRequest::new(
+        ctx,
+        recipient.clone(),
+        zkapp_txid,
+        circuit_path,
+        proof_inputs,
+    )
+    .await?;
+    let zkapp_outpoint = bob_request.zkapp_outpoint()?;
+    let verified = verify_with_node(&committee.node_addresses[0], &bob_request).await?;
+    ensure!(
+        verified.accepted,
+        "a member wouldn't sign the spend: {}",
+        verified.reason.unwrap_or_default()
+    );
+    let bob_response = committee.orchestrator.handle_request(&bob_request).await?;
+    info!("- the committee signed the spend of {zkapp_outpoint}");
+
+    // sign the wallet inputs, broadcast, and wait for the spend to confirm
+    let (signed_tx_hex, _) =
+        sign_wallet_inputs(ctx, &bob_response.unlocked_tx, &bob_request.prev_outs).await?;
+    let spend_txid = send_raw_transaction(ctx, TransactionOrHex::Hex(signed_tx_hex)).await?;
+    regtest.mine_blocks(1).await?;
+    let spend_block_hash =
+        match wait_for_confirmation(ctx, &spend_txid, 1, SPEND_CONFIRMATION_TIMEOUT).await? {
+            ConfirmationStatus::Confirmed { block_hash, .. } => block_hash,
+            status => bail!("the spend {spend_txid} didn't confirm: {status:?}"),
+        };
+    info!("- the spend {spend_txid} confirmed in {spend_block_hash}");
+
+    // the funds moved
+    let balance_after_spend = regtest.balance(&regtest.committee_ctx).await?;
+    ensure!(
+        balance_after_spend == balance_before,
+        "the committee's balance is {balance_after_spend} after the spend (instead of {balance_before})"
+    );
+    let received = regtest.received_by(&recipient).await?;
+    ensure!(
+        received > Amount::ZERO,
+        "{recipient} didn't receive anything"
+    );
+
+    Ok(E2eDemoReport {
+        committee_address,
+        vk_hash: hex::encode(vk_hash),
+        zkapp_outpoint,
+        deposit,
+        committee_balances: [balance_before, balance_after_deposit, balance_after_spend],
+        spend_txid,
+        spend_block_hash,
+        recipient: recipient.to_string(),
+        received,
+    })
+}
+
+/// Runs a full deposit → spend cycle of the stateless example zkapp, with a new in-process committee.
+/// Returns the committee (e.g. to keep using it) along with the report.
+pub async fn run_e2e_demo(regtest: &Regtest) -> Result<(InProcessCommittee, E2eDemoReport)> {
+    check_prover()?;
+    let committee = InProcessCommittee::start(regtest).await?;
+    let (circuit_path, proof_inputs) = stateless_circuit();
+    let report = deposit_and_spend(regtest, &committee, &circuit_path, proof_inputs).await?;
+    Ok((committee, report))
+}
@@ -0,0 +1,431 @@
+//! A mock bitcoind JSON RPC server, to test code that talks to a node without running one.
+//! Tests register the responses to return for each method (see [MockRpc::on], [MockRpc::on_error], and [MockRpc::on_raw]),
+//! and get an [RpcCtx] pointing at the server (see [MockRpc::ctx]).
+//! The requests received are recorded (see [MockRpc::requests]), and the server can be made slow (see [MockRpc::delay])
+//! or picky about credentials (see [MockRpc::require_auth]).
+//!
+//! ```ignore
+//! let mock = MockRpc::start()?;
+//! mock.on("getblockcount", 42);
+//! let resp = json_rpc_request(&mock.ctx(), "getblockcount", &[]).await?;
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine};
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::json_rpc_stuff::RpcCtx;
+
+/// The credentials of the mock server (any credentials are accepted).
+const MOCK_RPC_AUTH: &str = "mock:mock";
+
+/// The JSON RPC error code returned by bitcoind for unknown methods.
+pub const RPC_METHOD_NOT_FOUND: i64 = -32601;
+
+/// A canned response.
+#[derive(Debug, Clone)]
+enum MockResponse {
+    Result(Value),
+    Error {
+        code: i64,
+        message: String,
+    },
+
+    /// Sent as is (e.g. a malformed body).
+    Raw {
+        status: u16,
+        body: String,
+    },
+}
+
+/// A request received by the mock server.
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    /// The path of the request (e.g. `/wallet/mywallet` for wallet calls).
+    pub path: String,
+    pub method: String,
+    pub params: Value,
+
+    /// The `user:password` of the request (if it came with basic auth).
+    pub auth: Option<String>,
+}
+
+#[derive(Default)]
+struct MockState {
+    /// The responses to return for each method, in order (the last one is returned forever).
+    responses: HashMap<String, VecDeque<MockResponse>>,
+
+    /// The requests received so far.
+    requests: Vec<MockRequest>,
+
+    /// How long to wait before responding to each method.
+    delays: HashMap<String, Duration>,
+
+    /// The only credentials accepted (if set).
+    required_auth: Option<String>,
+}
+
+impl MockState {
+    fn respond(&mut self, request: MockRequest) -> MockResponse {
+        if self.required_auth.is_some() && request.auth != self.required_auth {
+            // like bitcoind, with an empty body
+            self.requests.push(request);
+            return MockResponse::Raw {
+                status: 401,
+                body: String::new(),
+            };
+        }
+
+        let response = match self.responses.get_mut(&request.method) {
+            Some(queue) if queue.len() > 1 => queue.pop_front(),
+            Some(queue) => queue.front().cloned(),
+            None => None,
+        };
+        let response = response.unwrap_or_else(|| MockResponse::Error {
+            code: RPC_METHOD_NOT_FOUND,
+            message: "Method not found".to_string(),
+        });
+        self.requests.push(request);
+        response
+    }
+}
+
+/// A mock bitcoind, listening on a random local port until it is dropped.
+pub struct MockRpc {
+    address: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockRpc {
+    /// Starts the server (in a background thread).
+    pub fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").context("couldn't bind the mock server")?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState::default()));
+
+        // the server only holds a weak reference, so that it stops accepting connections once the mock is dropped
+        let weak_state = Arc::downgrade(&state);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Some(state) = weak_state.upgrade() else {
+                    return;
+                };
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                std::thread::spawn(move || serve_connection(stream, state));
+            }
+        });
+
+        Ok(Self { address, state })
+    }
+
+    /// The URL of the server.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// Returns a context to talk to the server.
+    pub fn ctx(&self) -> RpcCtx {
+        RpcCtx::builder()
+            .url(self.url())
+            .auth_userpass(MOCK_RPC_AUTH)
+            .build()
+            .expect("the mock server has a valid URL")
+    }
+
+    /// Responds to `method` with `result`.
+    /// Responses registered for the same method are returned in order, and the last one is then returned forever.
+    pub fn on(&self, method: &str, result: impl Serialize) -> &Self {
+        let result = serde_json::to_value(result).expect("couldn't serialize mock result");
+        self.push(method, MockResponse::Result(result))
+    }
+
+    /// Responds to `method` with an error (see [Self::on] for the order of responses).
+    pub fn on_error(&self, method: &str, code: i64, message: &str) -> &Self {
+        self.push(
+            method,
+            MockResponse::Error {
+                code,
+                message: message.to_string(),
+            },
+        )
+    }
+
+    /// Responds to `method` with an HTTP `status` and `body` as is, e.g. to send malformed JSON
+    /// (see [Self::on] for the order of responses).
+    pub fn on_raw(&self, method: &str, status: u16, body: &str) -> &Self {
+        self.push(
+            method,
+            MockResponse::Raw {
+                status,
+                body: body.to_string(),
+            },
+        )
+    }
+
+    /// Waits for `delay` before responding to `method` (e.g. to test timeouts).
+    pub fn delay(&self, method: &str, delay: Duration) -> &Self {
+        self.state
+            .lock()
+            .unwrap()
+            .delays
+            .insert(method.to_string(), delay);
+        self
+    }
+
+    /// Only accepts requests with the `user:password` credentials (the others get a 401, like with bitcoind).
+    /// Note that [Self::ctx] uses other credentials.
+    pub fn require_auth(&self, auth: &str) -> &Self {
+        self.state.lock().unwrap().required_auth = Some(auth.to_string());
+        self
+    }
+
+    fn push(&self, method: &str, response: MockResponse) -> &Self {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .entry(method.to_string())
+            .or_default()
+            .push_back(response);
+        self
+    }
+
+    /// The requests received so far.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The requests received so far for `method`.
+    pub fn requests_for(&self, method: &str) -> Vec<MockRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.method == method)
+            .collect()
+    }
+}
+
+/// Serves the requests of a connection until it is closed.
+fn serve_connection(mut stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    while let Some((path, auth, body)) = read_request(&mut stream) {
+        let (status, body) = match serde_json::from_slice::<Value>(&body) {
+            Ok(request) => {
+                let id = request.get("id").cloned().unwrap_or(Value::Null);
+                let request = MockRequest {
+                    path,
+                    method: request["method"].as_str().unwrap_or_default().to_string(),
+                    params: request.get("params").cloned().unwrap_or(json!([])),
+                    auth,
+                };
+                let delay = state.lock().unwrap().delays.get(&request.method).copied();
+                if let Some(delay) = delay {
+                    std::thread::sleep(delay);
+                }
+                // like bitcoind, errors come with a 404 (unknown method) or a 500
+                let response = state.lock().unwrap().respond(request);
+                match response {
+                    MockResponse::Result(result) => (
+                        200,
+                        json!({ "result": result, "error": null, "id": id }).to_string(),
+                    ),
+                    MockResponse::Error { code, message } => (
+                        if code == RPC_METHOD_NOT_FOUND { 404 } else { 500 },
+                        json!({ "result": null, "error": { "code": code, "message": message }, "id": id }).to_string(),
+                    ),
+                    MockResponse::Raw { status, body } => (status, body),
+                }
+            }
+            Err(err) => (
+                500,
+                json!({ "result": null, "error": { "code": -32700, "message": format!("Parse error: {err}") }, "id": null }).to_string(),
+            ),
+        };
+
+        let reason = StatusCode::from_u16(status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("Unknown");
+        let response = format!(
+            "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        if stream.write_all(response.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads an HTTP request, and returns its path, its basic auth credentials (if any), and its body
+/// (or `None` once the connection is closed).
+fn read_request(stream: &mut TcpStream) -> Option<(String, Option<String>, Vec<u8>)> {
+    let mut request = vec![];
+    let mut buf = [0u8; 4096];
+    loop {
+        if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&request[..end]).into_owned();
+            let path = headers
+                .lines()
+                .next()?
+                .split_whitespace()
+                .nth(1)?
+                .to_string();
+            let content_length = headers
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            let auth = headers.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                let encoded = name
+                    .eq_ignore_ascii_case("authorization")
+                    .then(|| value.trim().strip_prefix("Basic "))??;
+                let decoded = general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+                String::from_utf8(decoded).ok()
+            });
+
+            let body_start = end + 4;
+            while request.len() < body_start + content_length {
+                let read = stream.read(&mut buf).ok().filter(|read| *read > 0)?;
+                request.extend_from_slice(&buf[..read]);
+            }
+            return Some((
+                path,
+                auth,
+                request[body_start..body_start + content_length].to_vec(),
+            ));
+        }
+
+        let read = stream.read(&mut buf).ok().filter(|read| *read > 0)?;
+        request.extend_from_slice(&buf[..read]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json_rpc_stuff::json_rpc_request;
+
+    use super::*;
+
+    async fn call(ctx: &RpcCtx, method: &'static str, params: &[Value]) -> Result<Value> {
+        let params = params
+            .iter()
+            .map(serde_json::value::to_raw_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        let resp = json_rpc_request(ctx, method, &params).await?;
+        let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&resp)?;
+        Ok(response.result()?)
+    }
+
+    #[tokio::test]
+    async fn test_mock_results() {
+        let mock = MockRpc::start().unwrap();
+        mock.on("getblockcount", 42).on("getbestblockhash", "00ff");
+        let ctx = mock.ctx();
+
+        assert_eq!(call(&ctx, "getblockcount", &[]).await.unwrap(), json!(42));
+        assert_eq!(
+            call(&ctx, "getbestblockhash", &[]).await.unwrap(),
+            json!("00ff")
+        );
+
+        // the requests are recorded
+        call(&ctx, "getblockcount", &[json!(true)]).await.unwrap();
+        let requests = mock.requests_for("getblockcount");
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].params, json!([true]));
+        assert_eq!(mock.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_mock_sequence() {
+        let mock = MockRpc::start().unwrap();
+        mock.on("getblockcount", 1).on("getblockcount", 2);
+        let ctx = mock.ctx();
+
+        // in order, then the last one forever
+        for expected in [1, 2, 2] {
+            assert_eq!(
+                call(&ctx, "getblockcount", &[]).await.unwrap(),
+                json!(expected)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_errors() {
+        let mock = MockRpc::start().unwrap();
+        mock.on_error(
+            "getrawtransaction",
+            -5,
+            "No such mempool or blockchain transaction",
+        );
+        let ctx = mock.ctx();
+
+        let err = call(&ctx, "getrawtransaction", &[]).await.unwrap_err();
+        assert!(err.to_string().contains("No such mempool"), "{err}");
+
+        // unknown methods
+        let err = call(&ctx, "getblockcount", &[]).await.unwrap_err();
+        assert!(err.to_string().contains("Method not found"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_mock_wallet_path() {
+        let mock = MockRpc::start().unwrap();
+        mock.on("getbalance", 1.5);
+        let ctx = RpcCtx::builder()
+            .url(mock.url())
+            .wallet("mywallet")
+            .build()
+            .unwrap();
+
+        assert_eq!(call(&ctx, "getbalance", &[]).await.unwrap(), json!(1.5));
+        assert_eq!(mock.requests()[0].path, "/wallet/mywallet");
+    }
+
+    #[tokio::test]
+    async fn test_mock_raw_auth_and_delay() {
+        let mock = MockRpc::start().unwrap();
+        mock.on_raw("getblockcount", 200, "{not json")
+            .on("getbestblockhash", "00ff")
+            .delay("getbestblockhash", std::time::Duration::from_millis(200));
+        let ctx = mock.ctx();
+
+        // malformed responses
+        assert!(call(&ctx, "getblockcount", &[]).await.is_err());
+
+        // slow responses
+        let start = std::time::Instant::now();
+        call(&ctx, "getbestblockhash", &[]).await.unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+
+        // the credentials are recorded, and can be checked
+        assert_eq!(mock.requests()[0].auth.as_deref(), Some(MOCK_RPC_AUTH));
+        mock.require_auth("alice:secret");
+        let err = call(&ctx, "getbestblockhash", &[]).await.unwrap_err();
+        assert!(err.to_string().contains("HTTP 401"), "{err}");
+        let ctx = RpcCtx::builder()
+            .url(mock.url())
+            .auth_userpass("alice:secret")
+            .build()
+            .unwrap();
+        assert_eq!(
+            call(&ctx, "getbestblockhash", &[]).await.unwrap(),
+            json!("00ff")
+        );
+    }
+}
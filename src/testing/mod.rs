@@ -0,0 +1,22 @@
+//! Utilities to test zkBitcoin end-to-end.
+//! Only available with the `testing` feature.
+
+use std::sync::RwLock;
+
+pub mod e2e_demo;
+pub mod mock_rpc;
+pub mod regtest;
+
+/// The public key and network of a committee running locally, if any (see [set_local_committee]).
+static LOCAL_COMMITTEE: RwLock<Option<(bitcoin::PublicKey, bitcoin::Network)>> = RwLock::new(None);
+
+/// Points the zkBitcoin address ([crate::zkbitcoin_pubkey]) and the network ([crate::get_network])
+/// of this process at a committee running locally (e.g. [e2e_demo::InProcessCommittee] on regtest).
+pub fn set_local_committee(pubkey: bitcoin::PublicKey, network: bitcoin::Network) {
+    *LOCAL_COMMITTEE.write().unwrap() = Some((pubkey, network));
+}
+
+/// The committee set with [set_local_committee].
+pub(crate) fn local_committee() -> Option<(bitcoin::PublicKey, bitcoin::Network)> {
+    *LOCAL_COMMITTEE.read().unwrap()
+}
@@ -0,0 +1,626 @@
+//! A local `bitcoind -regtest` harness.
+//! The path to the bitcoind binary can be given via the [BITCOIND_EXE_ENV] env var,
+//! otherwise we look for `bitcoind` in the `PATH`.
+
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use bitcoin::{Address, Amount, BlockHash, Txid};
+use serde::de::DeserializeOwned;
+use tempdir::TempDir;
+use tokio::time::sleep;
+
+use crate::{
+    json_rpc_stuff::{generate_blocks, get_new_address, json_rpc_request, RpcCtx},
+    taproot_descriptor_from,
+    watch_wallet::import_committee_descriptor,
+};
+
+/// The env var that can be used to point to a bitcoind binary.
+pub const BITCOIND_EXE_ENV: &str = "BITCOIND_EXE";
+
+/// The name of the wallet created on the regtest node.
+const WALLET_NAME: &str = "zkbitcoin";
+
+/// The name of the watch-only wallet tracking the committee's outputs.
+const COMMITTEE_WALLET_NAME: &str = "zkbitcoin-committee";
+
+/// The RPC credentials used by the regtest node.
+const RPC_AUTH: (&str, &str) = ("zkbitcoin", "zkbitcoin");
+
+/// Number of blocks a coinbase output needs before it can be spent.
+const COINBASE_MATURITY: u64 = 100;
+
+/// Number of times we poll bitcoind before giving up on it.
+const READY_RETRIES: usize = 120;
+
+//
+// Helpers
+//
+
+/// Returns the path to an executable by looking at the `PATH`.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Returns the path to the bitcoind binary, if it can be found.
+pub fn bitcoind_exe() -> Option<PathBuf> {
+    match std::env::var(BITCOIND_EXE_ENV) {
+        Ok(path) => Some(PathBuf::from(path)).filter(|path| path.is_file()),
+        Err(_) => find_executable("bitcoind"),
+    }
+}
+
+/// Returns a port that is currently free on the local machine.
+pub fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// Sends a JSON RPC request and deserializes its result.
+async fn call<T: DeserializeOwned>(
+    ctx: &RpcCtx,
+    method: &'static str,
+    params: &[serde_json::Value],
+) -> Result<T> {
+    let params = params
+        .iter()
+        .map(serde_json::value::to_raw_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    let response = json_rpc_request(ctx, method, &params)
+        .await
+        .with_context(|| format!("{method} error"))?;
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    Ok(response.result()?)
+}
+
+//
+// Harness
+//
+
+/// A running `bitcoind -regtest` node with a funded wallet.
+/// The node is killed when this is dropped.
+pub struct Regtest {
+    process: Child,
+
+    /// Keep the data directory around for the lifetime of the node.
+    _datadir: TempDir,
+
+    /// The address of the RPC endpoint.
+    pub address: String,
+
+    /// A context pointing at the funded wallet of the node.
+    pub rpc_ctx: RpcCtx,
+
+    /// A context pointing at a watch-only wallet tracking the committee's outputs (e.g. the zkapps),
+    /// once [Regtest::track_committee] was called.
+    pub committee_ctx: RpcCtx,
+}
+
+impl Regtest {
+    /// Spawns a regtest node, creates a wallet, and mines enough blocks to fund it.
+    /// Returns `None` if bitcoind isn't installed.
+    pub async fn start() -> Result<Option<Self>> {
+        let Some(bitcoind_exe) = bitcoind_exe() else {
+            return Ok(None);
+        };
+
+        let datadir = TempDir::new("zkbitcoin_regtest").context("couldn't create tmp dir")?;
+        let rpc_port = free_port()?;
+        let p2p_port = free_port()?;
+
+        let process = Command::new(&bitcoind_exe)
+            .arg("-regtest")
+            .arg("-server=1")
+            .arg("-listen=0")
+            .arg("-fallbackfee=0.00001")
+            .arg(format!("-datadir={}", datadir.path().display()))
+            .arg(format!("-rpcport={rpc_port}"))
+            .arg(format!("-port={p2p_port}"))
+            .arg(format!("-rpcuser={}", RPC_AUTH.0))
+            .arg(format!("-rpcpassword={}", RPC_AUTH.1))
+            .stdout(Stdio::null())
+            .spawn()
+            .with_context(|| format!("couldn't spawn {}", bitcoind_exe.display()))?;
+
+        let address = format!("http://127.0.0.1:{rpc_port}");
+        let wallet_ctx = |wallet| {
+            RpcCtx::builder()
+                .url(address.clone())
+                .wallet(wallet)
+                .auth_userpass(format!("{}:{}", RPC_AUTH.0, RPC_AUTH.1))
+                .build()
+        };
+        let rpc_ctx = wallet_ctx(WALLET_NAME)?;
+        let committee_ctx = wallet_ctx(COMMITTEE_WALLET_NAME)?;
+
+        // from now on, the process gets killed if anything fails
+        let mut regtest = Self {
+            process,
+            _datadir: datadir,
+            address,
+            rpc_ctx,
+            committee_ctx,
+        };
+
+        regtest.wait_until_ready().await?;
+
+        // create a (descriptor) wallet and fund it
+        regtest.create_wallet(WALLET_NAME, false).await?;
+        regtest.mine_blocks(COINBASE_MATURITY + 1).await?;
+
+        // a watch-only wallet for the committee's outputs (see [Regtest::track_committee])
+        regtest.create_wallet(COMMITTEE_WALLET_NAME, true).await?;
+
+        Ok(Some(regtest))
+    }
+
+    /// Tracks the outputs of the committee with public key `pubkey` in the committee's wallet (see [Regtest::committee_ctx]).
+    pub async fn track_committee(&self, pubkey: bitcoin::PublicKey) -> Result<()> {
+        let descriptor = taproot_descriptor_from(pubkey);
+        import_committee_descriptor(&self.committee_ctx, &descriptor, true).await
+    }
+
+    /// The balance of a wallet (only counting confirmed outputs).
+    pub async fn balance(&self, wallet_ctx: &RpcCtx) -> Result<Amount> {
+        let balance: f64 = call(
+            wallet_ctx,
+            "getbalance",
+            &[serde_json::json!("*"), serde_json::json!(1)],
+        )
+        .await?;
+        Ok(Amount::from_btc(balance)?)
+    }
+
+    /// The amount received by an address of the wallet (only counting confirmed outputs).
+    pub async fn received_by(&self, address: &Address) -> Result<Amount> {
+        let received: f64 = call(
+            &self.rpc_ctx,
+            "getreceivedbyaddress",
+            &[serde_json::json!(address.to_string()), serde_json::json!(1)],
+        )
+        .await?;
+        Ok(Amount::from_btc(received)?)
+    }
+
+    /// A context pointing at the node itself (and not at a specific wallet).
+    pub fn node_ctx(&self) -> RpcCtx {
+        let mut builder = RpcCtx::builder().url(self.address.clone());
+        if let Some(auth) = self.rpc_ctx.auth() {
+            builder = builder.auth_userpass(auth);
+        }
+        builder
+            .build()
+            .expect("the settings of the wallet context were already validated")
+    }
+
+    /// Creates a descriptor wallet (a blank, watch-only one if `watch_only` is set).
+    async fn create_wallet(&self, name: &str, watch_only: bool) -> Result<()> {
+        let _: serde_json::Value = call(
+            &self.node_ctx(),
+            "createwallet",
+            &[
+                serde_json::json!(name),
+                // disable_private_keys
+                serde_json::json!(watch_only),
+                // blank
+                serde_json::json!(watch_only),
+                // passphrase
+                serde_json::json!(""),
+                // avoid_reuse
+                serde_json::json!(false),
+                // descriptors
+                serde_json::json!(true),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn wait_until_ready(&mut self) -> Result<()> {
+        let ctx = self.node_ctx();
+        for _ in 0..READY_RETRIES {
+            if let Some(status) = self.process.try_wait()? {
+                bail!("bitcoind exited before being ready ({status})");
+            }
+
+            if call::<serde_json::Value>(&ctx, "getblockchaininfo", &[])
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+
+            sleep(Duration::from_millis(250)).await;
+        }
+
+        bail!("bitcoind was not ready in time")
+    }
+
+    /// Returns a new address from the wallet.
+    pub async fn get_new_address(&self) -> Result<Address> {
+        get_new_address(&self.rpc_ctx).await
+    }
+
+    /// Mines `n` blocks, sending the rewards to the wallet.
+    pub async fn mine_blocks(&self, n: u64) -> Result<Vec<BlockHash>> {
+        generate_blocks(&self.rpc_ctx, n).await
+    }
+
+    /// Disconnects a block (and all its descendants) from the chain, as in a reorg.
+    /// Its transactions go back to the mempool.
+    pub async fn invalidate_block(&self, block_hash: BlockHash) -> Result<()> {
+        call::<serde_json::Value>(
+            &self.rpc_ctx,
+            "invalidateblock",
+            &[serde_json::json!(block_hash.to_string())],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Sends `amount` from the wallet to the given address.
+    pub async fn fund_address(&self, address: &Address, amount: Amount) -> Result<Txid> {
+        call(
+            &self.rpc_ctx,
+            "sendtoaddress",
+            &[
+                serde_json::json!(address.to_string()),
+                serde_json::json!(amount.to_btc()),
+            ],
+        )
+        .await
+    }
+}
+
+impl Drop for Regtest {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        key::TapTweak,
+        opcodes::all::{OP_CHECKSIG, OP_CSV, OP_DROP},
+        script::Builder,
+        Network, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness,
+    };
+    use itertools::Itertools;
+    use secp256k1::{Keypair, Message, Secp256k1, SecretKey};
+
+    use crate::{
+        alice_sign_tx::generate_and_broadcast_transaction,
+        bob_request::{get_zkapp_utxo, BobRequest, InsufficientConfirmations, ZkappAlreadySpent},
+        committee::session_history::SessionFilter,
+        cpfp::cpfp_bump,
+        json_rpc_stuff::{
+            fund_raw_transaction, fund_raw_transaction_with_options, get_mempool_entry,
+            get_raw_transaction, send_raw_transaction, sign_transaction, unlock_unspent,
+            FundOptions, TransactionOrHex,
+        },
+        sighash::{compute_keyspend_sighash, compute_script_spend_sighash, ScriptPathSpend},
+        taproot_addr_from_with_scripts,
+        testing::e2e_demo::{deposit_and_spend, stateless_circuit, InProcessCommittee},
+        tx_template::{check_lock_time, TxTemplate},
+    };
+
+    use super::*;
+
+    /// Two concurrent funding calls on the same wallet shouldn't pick the same UTXOs.
+    #[tokio::test]
+    async fn test_concurrent_funding_with_locked_unspents() {
+        let Some(regtest) = Regtest::start().await.unwrap() else {
+            println!("skipping: bitcoind not found (you can set {BITCOIND_EXE_ENV})");
+            return;
+        };
+        let ctx = &regtest.rpc_ctx;
+
+        // split the funds of the wallet into several UTXOs
+        for _ in 0..4 {
+            let address = regtest.get_new_address().await.unwrap();
+            regtest
+                .fund_address(&address, Amount::from_btc(2.0).unwrap())
+                .await
+                .unwrap();
+        }
+        regtest.mine_blocks(1).await.unwrap();
+
+        // fund two transactions concurrently
+        let address = regtest.get_new_address().await.unwrap();
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: Amount::from_btc(1.0).unwrap(),
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        let options = FundOptions {
+            lock_unspents: true,
+            ..Default::default()
+        };
+        let (res1, res2) = tokio::join!(
+            fund_raw_transaction_with_options(ctx, TransactionOrHex::Transaction(&tx), &options),
+            fund_raw_transaction_with_options(ctx, TransactionOrHex::Transaction(&tx), &options),
+        );
+        let (_, tx1, _) = res1.unwrap();
+        let (_, tx2, _) = res2.unwrap();
+
+        let outpoints1 = tx1.input.iter().map(|i| i.previous_output).collect_vec();
+        let outpoints2 = tx2.input.iter().map(|i| i.previous_output).collect_vec();
+        assert!(outpoints1.iter().all(|o| !outpoints2.contains(o)));
+
+        // release everything
+        unlock_unspent(ctx, &outpoints1).await.unwrap();
+        unlock_unspent(ctx, &outpoints2).await.unwrap();
+        let locked: Vec<serde_json::Value> = call(ctx, "listlockunspent", &[]).await.unwrap();
+        assert!(locked.is_empty());
+    }
+
+    /// A transaction paying a low fee can be bumped by spending one of its outputs.
+    #[tokio::test]
+    async fn test_cpfp_bump() {
+        let Some(regtest) = Regtest::start().await.unwrap() else {
+            println!("skipping: bitcoind not found (you can set {BITCOIND_EXE_ENV})");
+            return;
+        };
+        let ctx = &regtest.rpc_ctx;
+
+        // broadcast a parent paying 1 sat/vB to the wallet
+        let address = regtest.get_new_address().await.unwrap();
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: Amount::from_btc(1.0).unwrap(),
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        let options = FundOptions {
+            fee_rate: Some(1),
+            ..Default::default()
+        };
+        let (_, tx, _) =
+            fund_raw_transaction_with_options(ctx, TransactionOrHex::Transaction(&tx), &options)
+                .await
+                .unwrap();
+        let (_, tx) = sign_transaction(ctx, TransactionOrHex::Transaction(&tx))
+            .await
+            .unwrap();
+        let parent_txid = send_raw_transaction(ctx, TransactionOrHex::Transaction(&tx))
+            .await
+            .unwrap();
+
+        // bump it
+        let bump = cpfp_bump(ctx, parent_txid, 10).await.unwrap();
+        assert!(bump.package_feerate >= 9.9);
+        let child = get_mempool_entry(ctx, bump.txid).await.unwrap();
+        assert_eq!(child.fees.base, bump.child_fee);
+
+        // can't bump a transaction that isn't in the mempool anymore
+        regtest.mine_blocks(1).await.unwrap();
+        assert!(cpfp_bump(ctx, parent_txid, 20).await.is_err());
+    }
+
+    /// A transaction with a lock time in the future is only accepted once the chain reaches it.
+    #[tokio::test]
+    async fn test_lock_time() {
+        let Some(regtest) = Regtest::start().await.unwrap() else {
+            println!("skipping: bitcoind not found (you can set {BITCOIND_EXE_ENV})");
+            return;
+        };
+        let ctx = &regtest.rpc_ctx;
+
+        let height: u32 = call(ctx, "getblockcount", &[]).await.unwrap();
+        let lock_time = bitcoin::absolute::LockTime::from_height(height + 5).unwrap();
+        let address = regtest.get_new_address().await.unwrap();
+        let tx = TxTemplate::new()
+            .add_output(&address, Amount::from_btc(1.0).unwrap())
+            .lock_time(lock_time)
+            .build()
+            .unwrap();
+        let (_, tx, _) = fund_raw_transaction(ctx, TransactionOrHex::Transaction(&tx))
+            .await
+            .unwrap();
+        assert_eq!(tx.lock_time, lock_time);
+        check_lock_time(&tx).unwrap();
+        let (_, tx) = sign_transaction(ctx, TransactionOrHex::Transaction(&tx))
+            .await
+            .unwrap();
+
+        // too early
+        let err = send_raw_transaction(ctx, TransactionOrHex::Transaction(&tx))
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("non-final"));
+
+        // the next block is past the lock time
+        regtest.mine_blocks(5).await.unwrap();
+        let txid = send_raw_transaction(ctx, TransactionOrHex::Transaction(&tx))
+            .await
+            .unwrap();
+        assert_eq!(txid, tx.txid());
+    }
+
+    /// Coins sent to an address with a script tree can be spent through the key path, and through a (timelocked) leaf.
+    #[tokio::test]
+    async fn test_script_tree_spends() {
+        let Some(regtest) = Regtest::start().await.unwrap() else {
+            println!("skipping: bitcoind not found (you can set {BITCOIND_EXE_ENV})");
+            return;
+        };
+        let ctx = &regtest.rpc_ctx;
+
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[3; 32]).unwrap());
+        let recovery_keypair =
+            Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[4; 32]).unwrap());
+        let recovery_leaf = Builder::new()
+            .push_int(2)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_x_only_key(&recovery_keypair.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let (_, spend_info) = taproot_addr_from_with_scripts(
+            bitcoin::PublicKey::new(keypair.public_key()),
+            &[recovery_leaf.clone()],
+        )
+        .unwrap();
+        let address = Address::p2tr_tweaked(spend_info.output_key(), Network::Regtest);
+
+        // fund the address twice, and wait for the timelock of the recovery leaf
+        let mut funded = vec![];
+        for _ in 0..2 {
+            let txid = regtest
+                .fund_address(&address, Amount::from_sat(100_000))
+                .await
+                .unwrap();
+            let tx = get_raw_transaction(ctx, txid).await.unwrap();
+            let vout = tx
+                .output
+                .iter()
+                .position(|output| output.script_pubkey == address.script_pubkey())
+                .unwrap();
+            funded.push((OutPoint::new(txid, vout as u32), tx.output[vout].clone()));
+        }
+        regtest.mine_blocks(2).await.unwrap();
+
+        let destination = regtest.get_new_address().await.unwrap();
+        let spend_tx = |outpoint: OutPoint, sequence: Sequence| Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                sequence,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(99_000),
+                script_pubkey: destination.script_pubkey(),
+            }],
+        };
+
+        // key path, with the key tweaked by the merkle root of the tree
+        let (outpoint, prevout) = &funded[0];
+        let mut tx = spend_tx(*outpoint, Sequence::MAX);
+        let sighash = compute_keyspend_sighash(&tx, 0, &[prevout.clone()]).unwrap();
+        let tweaked = keypair.tap_tweak(&secp, spend_info.merkle_root());
+        let sig =
+            secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash), &tweaked.to_inner());
+        tx.input[0].witness = Witness::from_slice(&[&sig.serialize()[..]]);
+        send_raw_transaction(ctx, TransactionOrHex::Transaction(&tx))
+            .await
+            .unwrap();
+
+        // script path, through the recovery leaf
+        let (outpoint, prevout) = &funded[1];
+        let mut tx = spend_tx(*outpoint, Sequence::from_height(2));
+        let spend = ScriptPathSpend::from_spend_info(&spend_info, recovery_leaf).unwrap();
+        let sighash = compute_script_spend_sighash(&tx, 0, &[prevout.clone()], &spend).unwrap();
+        let sig = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash), &recovery_keypair);
+        tx.input[0].witness = spend.witness(&[&sig.serialize()[..]]);
+        send_raw_transaction(ctx, TransactionOrHex::Transaction(&tx))
+            .await
+            .unwrap();
+    }
+
+    /// Deploys a stateless zkapp to an in-process 2-of-3 committee and unlocks it.
+    #[tokio::test]
+    async fn test_end_to_end_unlock() {
+        let Some(regtest) = Regtest::start().await.unwrap() else {
+            println!("skipping: bitcoind not found (you can set {BITCOIND_EXE_ENV})");
+            return;
+        };
+        if find_executable("circom").is_none() || find_executable("snarkjs").is_none() {
+            println!("skipping: circom and snarkjs are needed to prove");
+            return;
+        }
+
+        // deposit to an in-process committee, and spend
+        let committee = InProcessCommittee::start(&regtest).await.unwrap();
+        let orchestrator = &committee.orchestrator;
+        let (circom_circuit_path, proof_inputs) = stateless_circuit();
+        let report = deposit_and_spend(
+            &regtest,
+            &committee,
+            &circom_circuit_path,
+            proof_inputs.clone(),
+        )
+        .await
+        .unwrap();
+        let ctx = &regtest.rpc_ctx;
+        let vk_hash: [u8; 32] = hex::decode(&report.vk_hash).unwrap().try_into().unwrap();
+
+        // the session was recorded, along with the txid of the transaction
+        let sessions = orchestrator.sessions(&SessionFilter {
+            zkapp_outpoint: Some(report.zkapp_outpoint),
+            ..Default::default()
+        });
+        assert_eq!(sessions.total, 1);
+        assert_eq!(sessions.sessions[0].txid, Some(report.spend_txid));
+
+        // the orchestrator shouldn't sign a spend of the same zkapp again
+        assert!(orchestrator
+            .zkapp_status(report.zkapp_outpoint)
+            .await
+            .unwrap()
+            .is_none());
+        let bob_request = BobRequest::new(
+            ctx,
+            regtest.get_new_address().await.unwrap(),
+            report.zkapp_outpoint.txid,
+            &circom_circuit_path,
+            proof_inputs.clone(),
+        )
+        .await
+        .unwrap();
+        let err = orchestrator.handle_request(&bob_request).await.unwrap_err();
+        let err = err.downcast_ref::<ZkappAlreadySpent>().unwrap();
+        assert_eq!(err.outpoint, report.zkapp_outpoint);
+        assert_eq!(err.spent_by, Some(report.spend_txid));
+        assert!(err.confirmations.is_some());
+
+        // deploy another zkapp, which then gets reorged back into the mempool
+        let zkapp_txid = generate_and_broadcast_transaction(ctx, &vk_hash, None, 10_000)
+            .await
+            .unwrap();
+        let block_hashes = regtest.mine_blocks(1).await.unwrap();
+        let bob_request = BobRequest::new(
+            ctx,
+            regtest.get_new_address().await.unwrap(),
+            zkapp_txid,
+            &circom_circuit_path,
+            proof_inputs,
+        )
+        .await
+        .unwrap();
+        let zkapp_outpoint = bob_request.zkapp_outpoint().unwrap();
+        let zkapp = get_zkapp_utxo(ctx, zkapp_outpoint).await.unwrap().unwrap();
+        assert_eq!(zkapp.confirmations, 1);
+
+        regtest.invalidate_block(block_hashes[0]).await.unwrap();
+        let zkapp = get_zkapp_utxo(ctx, zkapp_outpoint).await.unwrap().unwrap();
+        assert_eq!(zkapp.confirmations, 0);
+
+        // the orchestrator refuses to sign it
+        let err = orchestrator.handle_request(&bob_request).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<InsufficientConfirmations>(),
+            Some(&InsufficientConfirmations { have: 0, need: 1 })
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! Sanity checks run on a transaction before spending time on it (signing ceremony, broadcast),
+//! to catch transactions that the network would reject or that would burn too much in fees.
+
+use bitcoin::{Amount, FeeRate, Network, Transaction, TxOut, Weight};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{DEFAULT_MAX_FEE_ABSOLUTE_SAT, DEFAULT_MIN_FEERATE_SAT_VB};
+
+/// The maximum weight of a standard transaction (see `MAX_STANDARD_TX_WEIGHT` in Bitcoin Core).
+pub const MAX_STANDARD_TX_WEIGHT: Weight = Weight::from_wu(400_000);
+
+/// The weight of the witness of a taproot key path spend (with the default sighash type, so the smallest there is):
+/// the number of items, the length of the signature, and the signature.
+const KEYSPEND_WITNESS_WEIGHT: u64 = 1 + 1 + 64;
+
+/// What [sanity_check_tx] accepts.
+#[derive(Debug, Clone)]
+pub struct SanityPolicy {
+    /// The maximum fee the transaction can pay.
+    pub max_absolute_fee: Amount,
+
+    /// The maximum fee the transaction can pay, as a fraction of the value of its inputs.
+    pub max_fee_fraction: f64,
+
+    /// The maximum weight of the transaction.
+    pub max_weight: Weight,
+}
+
+impl Default for SanityPolicy {
+    fn default() -> Self {
+        Self {
+            max_absolute_fee: Amount::from_sat(1_000_000),
+            max_fee_fraction: 0.5,
+            max_weight: MAX_STANDARD_TX_WEIGHT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxSanityError {
+    /// The number of prevouts doesn't match the number of inputs.
+    PrevoutsMismatch { inputs: usize, prevouts: usize },
+
+    /// An output is below the dust threshold of its script type.
+    DustOutput {
+        index: usize,
+        value: Amount,
+        threshold: Amount,
+    },
+
+    /// The outputs spend more than the inputs.
+    NegativeFee { inputs: Amount, outputs: Amount },
+
+    /// The fee is above [SanityPolicy::max_absolute_fee].
+    FeeTooHigh { fee: Amount, max: Amount },
+
+    /// The fee is above [SanityPolicy::max_fee_fraction] of the inputs.
+    FeeTooLargeFraction {
+        fee: Amount,
+        inputs: Amount,
+        max_fraction: f64,
+    },
+
+    /// The transaction is heavier than [SanityPolicy::max_weight].
+    TooHeavy { weight: Weight, max: Weight },
+}
+
+impl std::fmt::Display for TxSanityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PrevoutsMismatch { inputs, prevouts } => write!(
+                f,
+                "the transaction has {inputs} inputs but {prevouts} prevouts were given"
+            ),
+            Self::DustOutput {
+                index,
+                value,
+                threshold,
+            } => write!(
+                f,
+                "output {index} is dust ({value} is below the threshold of {threshold})"
+            ),
+            Self::NegativeFee { inputs, outputs } => write!(
+                f,
+                "the outputs ({outputs}) spend more than the inputs ({inputs})"
+            ),
+            Self::FeeTooHigh { fee, max } => {
+                write!(f, "the fee of {fee} is above the maximum of {max}")
+            }
+            Self::FeeTooLargeFraction {
+                fee,
+                inputs,
+                max_fraction,
+            } => write!(
+                f,
+                "the fee of {fee} is more than {}% of the inputs ({inputs})",
+                max_fraction * 100.0
+            ),
+            Self::TooHeavy { weight, max } => write!(
+                f,
+                "the transaction weighs {weight} which is above the maximum of {max}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TxSanityError {}
+
+/// Checks that a transaction (spending `prevouts`) has no dust outputs, pays a sane fee, and is not too heavy.
+/// Note that the weight of an unsigned transaction doesn't include its witnesses.
+pub fn sanity_check_tx(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    policy: &SanityPolicy,
+) -> Result<(), TxSanityError> {
+    if prevouts.len() != tx.input.len() {
+        return Err(TxSanityError::PrevoutsMismatch {
+            inputs: tx.input.len(),
+            prevouts: prevouts.len(),
+        });
+    }
+
+    // dust
+    for (index, output) in tx.output.iter().enumerate() {
+        if output.script_pubkey.is_op_return() {
+            continue;
+        }
+        let threshold = output.script_pubkey.dust_value();
+        if output.value < threshold {
+            return Err(TxSanityError::DustOutput {
+                index,
+                value: output.value,
+                threshold,
+            });
+        }
+    }
+
+    // fee
+    let inputs: Amount = prevouts.iter().map(|prevout| prevout.value).sum();
+    let outputs: Amount = tx.output.iter().map(|output| output.value).sum();
+    let fee = inputs
+        .checked_sub(outputs)
+        .ok_or(TxSanityError::NegativeFee { inputs, outputs })?;
+    if fee > policy.max_absolute_fee {
+        return Err(TxSanityError::FeeTooHigh {
+            fee,
+            max: policy.max_absolute_fee,
+        });
+    }
+    if fee.to_sat() as f64 > inputs.to_sat() as f64 * policy.max_fee_fraction {
+        return Err(TxSanityError::FeeTooLargeFraction {
+            fee,
+            inputs,
+            max_fraction: policy.max_fee_fraction,
+        });
+    }
+
+    // weight
+    let weight = tx.weight();
+    if weight > policy.max_weight {
+        return Err(TxSanityError::TooHeavy {
+            weight,
+            max: policy.max_weight,
+        });
+    }
+
+    Ok(())
+}
+
+/// The fee a committee node accepts to co-sign a transaction (see [check_fee_limits]):
+/// high enough for it to confirm (the spend of a stateless zkapp is one-shot), but not so high that value gets drained to miners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeLimits {
+    pub min_feerate: FeeRate,
+
+    pub max_fee_absolute: Amount,
+}
+
+impl FeeLimits {
+    /// Permissive on regtest (where fees don't matter), and sane on other networks.
+    pub fn for_network(network: Network) -> Self {
+        match network {
+            Network::Regtest => Self {
+                min_feerate: FeeRate::ZERO,
+                max_fee_absolute: Amount::MAX_MONEY,
+            },
+            _ => Self {
+                min_feerate: FeeRate::from_sat_per_vb(DEFAULT_MIN_FEERATE_SAT_VB)
+                    .expect("the default feerate doesn't overflow"),
+                max_fee_absolute: Amount::from_sat(DEFAULT_MAX_FEE_ABSOLUTE_SAT),
+            },
+        }
+    }
+}
+
+/// Why a transaction is outside of [FeeLimits] (sent to the orchestrator along with the refusal to sign).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeRefusal {
+    /// The fee of the transaction (in satoshis).
+    pub fee: u64,
+
+    /// The (estimated) virtual size of the signed transaction.
+    pub vsize: u64,
+
+    /// The feerate of the transaction (in sat/vB).
+    pub feerate: f64,
+
+    /// The minimum feerate accepted (in sat/vB).
+    pub min_feerate: f64,
+
+    /// The maximum fee accepted (in satoshis).
+    pub max_fee: u64,
+}
+
+impl std::fmt::Display for FeeRefusal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.fee > self.max_fee {
+            write!(
+                f,
+                "the fee of {} sat (a feerate of {:.2} sat/vB) is above the maximum of {} sat",
+                self.fee, self.feerate, self.max_fee
+            )
+        } else {
+            write!(
+                f,
+                "the feerate of {:.2} sat/vB is below the minimum of {:.2} sat/vB",
+                self.feerate, self.min_feerate
+            )
+        }
+    }
+}
+
+impl std::error::Error for FeeRefusal {}
+
+/// Estimates the virtual size of a transaction once signed, assuming its unsigned inputs are taproot key path spends.
+/// As these are the smallest inputs, this overestimates the feerate (if anything), so it never refuses a sane fee.
+pub fn estimated_signed_vsize(tx: &Transaction) -> u64 {
+    let unsigned = tx
+        .input
+        .iter()
+        .filter(|input| input.witness.is_empty())
+        .count() as u64;
+    let mut weight = tx.weight().to_wu();
+    if unsigned > 0 {
+        // the segwit marker and flag
+        if unsigned == tx.input.len() as u64 {
+            weight += 2;
+        }
+        weight += unsigned * KEYSPEND_WITNESS_WEIGHT;
+    }
+    Weight::from_wu(weight).to_vbytes_ceil()
+}
+
+/// Checks that the fee of a transaction (spending `prevouts`) is within `limits`.
+pub fn check_fee_limits(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    limits: &FeeLimits,
+) -> Result<(), FeeRefusal> {
+    let inputs: Amount = prevouts.iter().map(|prevout| prevout.value).sum();
+    let outputs: Amount = tx.output.iter().map(|output| output.value).sum();
+    let fee = inputs.checked_sub(outputs).unwrap_or(Amount::ZERO);
+    let vsize = estimated_signed_vsize(tx);
+    let feerate = fee.to_sat() as f64 / vsize as f64;
+    let min_feerate = limits.min_feerate.to_sat_per_kwu() as f64 / 250.0;
+
+    if fee > limits.max_fee_absolute || feerate < min_feerate {
+        return Err(FeeRefusal {
+            fee: fee.to_sat(),
+            vsize,
+            feerate,
+            min_feerate,
+            max_fee: limits.max_fee_absolute.to_sat(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        absolute::LockTime, script::PushBytesBuf, transaction::Version, ScriptBuf, TxIn,
+    };
+
+    use super::*;
+
+    fn p2tr() -> ScriptBuf {
+        ScriptBuf::from_hex(&format!("5120{}", "aa".repeat(32))).unwrap()
+    }
+
+    fn tx_out(value: u64) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: p2tr(),
+        }
+    }
+
+    fn tx(outputs: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: outputs,
+        }
+    }
+
+    #[test]
+    fn test_sane_tx() {
+        let op_return = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_op_return(&PushBytesBuf::try_from(vec![1; 32]).unwrap()),
+        };
+        let tx = tx(vec![tx_out(90_000), op_return]);
+        let policy = SanityPolicy::default();
+        assert_eq!(sanity_check_tx(&tx, &[tx_out(100_000)], &policy), Ok(()));
+    }
+
+    #[test]
+    fn test_prevouts_mismatch() {
+        let tx = tx(vec![tx_out(90_000)]);
+        let res = sanity_check_tx(&tx, &[], &SanityPolicy::default());
+        assert!(matches!(res, Err(TxSanityError::PrevoutsMismatch { .. })));
+    }
+
+    #[test]
+    fn test_dust_output() {
+        let tx = tx(vec![tx_out(90_000), tx_out(100)]);
+        let res = sanity_check_tx(&tx, &[tx_out(100_000)], &SanityPolicy::default());
+        assert!(matches!(
+            res,
+            Err(TxSanityError::DustOutput { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_fees() {
+        let policy = SanityPolicy::default();
+
+        // outputs > inputs
+        let res = sanity_check_tx(&tx(vec![tx_out(200_000)]), &[tx_out(100_000)], &policy);
+        assert!(matches!(res, Err(TxSanityError::NegativeFee { .. })));
+
+        // sats/BTC confusion
+        let res = sanity_check_tx(&tx(vec![tx_out(1_000)]), &[tx_out(100_000_000)], &policy);
+        assert!(matches!(res, Err(TxSanityError::FeeTooHigh { .. })));
+
+        // most of the inputs go to the fee
+        let res = sanity_check_tx(&tx(vec![tx_out(1_000)]), &[tx_out(100_000)], &policy);
+        assert!(matches!(
+            res,
+            Err(TxSanityError::FeeTooLargeFraction { .. })
+        ));
+    }
+
+    #[test]
+    fn test_fee_limits() {
+        let limits = FeeLimits {
+            min_feerate: FeeRate::from_sat_per_vb(2).unwrap(),
+            max_fee_absolute: Amount::from_sat(50_000),
+        };
+        let tx = tx(vec![tx_out(90_000)]);
+        let vsize = estimated_signed_vsize(&tx);
+        assert!(vsize > tx.vsize() as u64);
+
+        // 10_000 sats is a lot
+        check_fee_limits(&tx, &[tx_out(100_000)], &limits).unwrap();
+
+        // too low
+        let prevout = tx_out(90_000 + vsize);
+        let refusal = check_fee_limits(&tx, &[prevout], &limits).unwrap_err();
+        assert_eq!(refusal.fee, vsize);
+        assert_eq!(refusal.feerate, 1.0);
+        assert!(refusal.to_string().contains("1.00 sat/vB"), "{refusal}");
+
+        // too high
+        let refusal = check_fee_limits(&tx, &[tx_out(200_000)], &limits).unwrap_err();
+        assert_eq!(refusal.fee, 110_000);
+        assert!(
+            refusal.to_string().contains("above the maximum"),
+            "{refusal}"
+        );
+
+        // anything goes on regtest
+        let regtest = FeeLimits::for_network(Network::Regtest);
+        check_fee_limits(&tx, &[tx_out(90_000)], &regtest).unwrap();
+        check_fee_limits(&tx, &[tx_out(200_000)], &regtest).unwrap();
+        assert!(check_fee_limits(
+            &tx,
+            &[tx_out(90_000)],
+            &FeeLimits::for_network(Network::Bitcoin)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_too_heavy() {
+        let policy = SanityPolicy {
+            max_weight: Weight::from_wu(100),
+            ..Default::default()
+        };
+        let res = sanity_check_tx(&tx(vec![tx_out(90_000)]), &[tx_out(100_000)], &policy);
+        assert!(matches!(res, Err(TxSanityError::TooHeavy { .. })));
+    }
+}
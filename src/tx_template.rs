@@ -0,0 +1,285 @@
+//! A builder for unsigned transactions,
+//! that can then be funded by the wallet (see [crate::json_rpc_stuff::fund_raw_transaction]).
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{
+    absolute::LockTime, script::PushBytesBuf, transaction::Version, Address, Amount, OutPoint,
+    ScriptBuf, Sequence, Transaction, TxIn, TxOut,
+};
+
+use crate::check_op_return_data_len;
+
+/// The total amount of bitcoins that can ever exist.
+const MAX_MONEY: Amount = Amount::from_sat(21_000_000 * 100_000_000);
+
+/// An output of a [TxTemplate], validated when the transaction is built.
+#[derive(Debug, Clone)]
+enum TemplateOutput {
+    /// A payment of some amount to an address.
+    Payment(Address, Amount),
+
+    /// Some data embedded in an OP_RETURN output.
+    OpReturn(Vec<u8>),
+
+    /// An arbitrary script, paid the minimum non-dust amount.
+    Data(ScriptBuf),
+}
+
+/// A template for an unsigned transaction (the wallet will add the inputs needed to fund it).
+/// For example:
+///
+/// ```ignore
+/// let tx = TxTemplate::new()
+///     .add_output(&address, amount)
+///     .add_op_return(&vk_hash)
+///     .build()?;
+/// let (tx_hex, tx, fee) = fund_raw_transaction(ctx, TransactionOrHex::Transaction(&tx)).await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TxTemplate {
+    /// Inputs that must be spent, with their sequence (if not the default one).
+    inputs: Vec<(OutPoint, Option<Sequence>)>,
+
+    outputs: Vec<TemplateOutput>,
+
+    lock_time: Option<LockTime>,
+}
+
+impl TxTemplate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an input spending `outpoint`.
+    /// If no sequence is given, the input is final unless a lock time is set (see [TxTemplate::lock_time]).
+    pub fn add_input(mut self, outpoint: OutPoint, sequence: Option<Sequence>) -> Self {
+        self.inputs.push((outpoint, sequence));
+        self
+    }
+
+    /// Sets the lock time of the transaction (a block height or a timestamp).
+    pub fn lock_time(mut self, lock_time: LockTime) -> Self {
+        self.lock_time = Some(lock_time);
+        self
+    }
+
+    /// Adds an output paying `amount` to `address`.
+    pub fn add_output(mut self, address: &Address, amount: Amount) -> Self {
+        self.outputs
+            .push(TemplateOutput::Payment(address.clone(), amount));
+        self
+    }
+
+    /// Adds an OP_RETURN output containing `data`.
+    pub fn add_op_return(mut self, data: &[u8]) -> Self {
+        self.outputs.push(TemplateOutput::OpReturn(data.to_vec()));
+        self
+    }
+
+    /// Adds an output with the given script, paying it the minimum non-dust amount.
+    pub fn add_data_output(mut self, script: ScriptBuf) -> Self {
+        self.outputs.push(TemplateOutput::Data(script));
+        self
+    }
+
+    /// Validates the outputs and produces the unsigned transaction.
+    pub fn build(self) -> Result<Transaction> {
+        ensure!(!self.outputs.is_empty(), "the transaction has no outputs");
+
+        let mut output = Vec::with_capacity(self.outputs.len());
+        let mut total = Amount::ZERO;
+        for (idx, template_output) in self.outputs.into_iter().enumerate() {
+            let txout = match template_output {
+                TemplateOutput::Payment(address, value) => {
+                    let script_pubkey = address.script_pubkey();
+                    let dust = script_pubkey.dust_value();
+                    ensure!(
+                        value >= dust,
+                        "output {idx} to {address} is dust ({value} < {dust})"
+                    );
+                    TxOut {
+                        value,
+                        script_pubkey,
+                    }
+                }
+                TemplateOutput::OpReturn(data) => {
+                    check_op_return_data_len(&data).with_context(|| format!("output {idx}"))?;
+                    let data = PushBytesBuf::try_from(data).expect("checked length above");
+                    TxOut {
+                        value: Amount::ZERO,
+                        script_pubkey: ScriptBuf::new_op_return(&data),
+                    }
+                }
+                TemplateOutput::Data(script_pubkey) => TxOut {
+                    value: script_pubkey.dust_value(),
+                    script_pubkey,
+                },
+            };
+
+            total = total
+                .checked_add(txout.value)
+                .filter(|total| *total <= MAX_MONEY)
+                .context("the total of the outputs is more than the bitcoin supply")?;
+            output.push(txout);
+        }
+
+        // a lock time is only enforced if one of the inputs is not final
+        let lock_time = self.lock_time.unwrap_or(LockTime::ZERO);
+        let default_sequence = if lock_time == LockTime::ZERO {
+            Sequence::MAX
+        } else {
+            Sequence::ENABLE_LOCKTIME_NO_RBF
+        };
+        let input = self
+            .inputs
+            .into_iter()
+            .map(|(previous_output, sequence)| TxIn {
+                previous_output,
+                sequence: sequence.unwrap_or(default_sequence),
+                ..Default::default()
+            })
+            .collect();
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time,
+            // the wallet will add more inputs if needed
+            input,
+            output,
+        };
+        check_lock_time(&tx)?;
+
+        Ok(tx)
+    }
+}
+
+/// Checks that the lock time of a transaction is enforced:
+/// a non-zero lock time is ignored if all the inputs are final (with a sequence of `0xffffffff`).
+pub fn check_lock_time(tx: &Transaction) -> Result<()> {
+    if tx.lock_time != LockTime::ZERO && !tx.input.is_empty() {
+        ensure!(
+            tx.input.iter().any(|input| input.sequence != Sequence::MAX),
+            "the lock time {} is not enforced as all the inputs are final (set a sequence below {:#x} on at least one input)",
+            tx.lock_time,
+            Sequence::MAX.to_consensus_u32()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bitcoin::Network;
+
+    use super::*;
+    use crate::constants::MAX_OP_RETURN_DATA_LEN;
+
+    fn address() -> Address {
+        Address::from_str("tb1p5sfstsnt9akcqf9zkm6ulke8ujwakjd8kdk5krws2th4ds238meqq4awtv")
+            .unwrap()
+            .require_network(Network::Testnet)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_empty_template() {
+        assert!(TxTemplate::new().build().is_err());
+    }
+
+    #[test]
+    fn test_add_output() {
+        let tx = TxTemplate::new()
+            .add_output(&address(), Amount::from_sat(10_000))
+            .build()
+            .unwrap();
+        assert_eq!(tx.version, Version::TWO);
+        assert_eq!(tx.lock_time, LockTime::ZERO);
+        assert!(tx.input.is_empty());
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].value, Amount::from_sat(10_000));
+        assert_eq!(tx.output[0].script_pubkey, address().script_pubkey());
+    }
+
+    #[test]
+    fn test_add_dust_output() {
+        let res = TxTemplate::new()
+            .add_output(&address(), Amount::from_sat(1))
+            .build();
+        assert!(res.unwrap_err().to_string().contains("dust"));
+    }
+
+    #[test]
+    fn test_add_op_return() {
+        let tx = TxTemplate::new().add_op_return(&[1; 32]).build().unwrap();
+        assert!(tx.output[0].script_pubkey.is_op_return());
+        assert_eq!(tx.output[0].value, Amount::ZERO);
+
+        let res = TxTemplate::new()
+            .add_op_return(&[1; MAX_OP_RETURN_DATA_LEN + 1])
+            .build();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_add_data_output() {
+        let script = address().script_pubkey();
+        let tx = TxTemplate::new()
+            .add_data_output(script.clone())
+            .build()
+            .unwrap();
+        assert_eq!(tx.output[0].value, script.dust_value());
+        assert_eq!(tx.output[0].script_pubkey, script);
+    }
+
+    #[test]
+    fn test_lock_time_and_sequence() {
+        let outpoint = OutPoint::null();
+        let lock_time = LockTime::from_height(850_000).unwrap();
+
+        // by default, inputs are final
+        let tx = TxTemplate::new()
+            .add_input(outpoint, None)
+            .add_output(&address(), Amount::from_sat(10_000))
+            .build()
+            .unwrap();
+        assert_eq!(tx.lock_time, LockTime::ZERO);
+        assert_eq!(tx.input[0].sequence, Sequence::MAX);
+
+        // unless a lock time is set
+        let tx = TxTemplate::new()
+            .add_input(outpoint, None)
+            .add_output(&address(), Amount::from_sat(10_000))
+            .lock_time(lock_time)
+            .build()
+            .unwrap();
+        assert_eq!(tx.lock_time, lock_time);
+        assert_eq!(tx.input[0].sequence, Sequence::ENABLE_LOCKTIME_NO_RBF);
+
+        // explicit sequence (opting into RBF)
+        let tx = TxTemplate::new()
+            .add_input(outpoint, Some(Sequence::ENABLE_RBF_NO_LOCKTIME))
+            .add_output(&address(), Amount::from_sat(10_000))
+            .build()
+            .unwrap();
+        assert_eq!(tx.input[0].sequence, Sequence::ENABLE_RBF_NO_LOCKTIME);
+
+        // a lock time with only final inputs is rejected
+        let res = TxTemplate::new()
+            .add_input(outpoint, Some(Sequence::MAX))
+            .add_output(&address(), Amount::from_sat(10_000))
+            .lock_time(lock_time)
+            .build();
+        assert!(res.unwrap_err().to_string().contains("not enforced"));
+    }
+
+    #[test]
+    fn test_total_outputs() {
+        let res = TxTemplate::new()
+            .add_output(&address(), MAX_MONEY)
+            .add_output(&address(), Amount::from_sat(10_000))
+            .build();
+        assert!(res.unwrap_err().to_string().contains("supply"));
+    }
+}
@@ -0,0 +1,60 @@
+//! Hardening of processes holding secrets (e.g. the key share of a committee node),
+//! so that their memory doesn't end up in core dumps, or readable by other processes of the same user.
+
+use anyhow::Result;
+
+/// Disables core dumps for the current process.
+/// On Linux, this also marks the process as non-dumpable, which prevents other (non-root) processes from attaching to it.
+pub fn disable_core_dumps() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: `limit` is a valid rlimit that outlives the call
+        if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+            anyhow::bail!(
+                "couldn't disable core dumps: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: PR_SET_DUMPABLE only reads its (integer) argument
+        if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+            anyhow::bail!(
+                "couldn't mark the process as non-dumpable: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_disable_core_dumps() {
+        // run it in a child process, as it can't be undone
+        if std::env::var("ZKBITCOIN_TEST_HARDEN").is_err() {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["utils::harden::tests::test_disable_core_dumps", "--exact"])
+                .env("ZKBITCOIN_TEST_HARDEN", "1")
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+
+        disable_core_dumps().unwrap();
+        // SAFETY: PR_GET_DUMPABLE takes no argument
+        assert_eq!(unsafe { libc::prctl(libc::PR_GET_DUMPABLE) }, 0);
+    }
+}
@@ -1 +1,3 @@
+pub mod harden;
+pub mod secret_file;
 pub mod version;
@@ -0,0 +1,163 @@
+//! Reading and writing files holding secrets (e.g. the key shares of committee members).
+//! Secret files are only readable by their owner (on unix), and are written atomically,
+//! so that a crash never leaves a truncated key share behind.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The permissions of secret files (on unix).
+pub const SECRET_FILE_MODE: u32 = 0o600;
+
+/// Writes `value` as JSON to `path`, only readable by the current user.
+/// The file is written to a temporary file in the same directory first, and then renamed into place.
+/// Fails if `path` already exists (unless `force` is set),
+/// or if the written file doesn't deserialize back to `value`.
+pub fn write_secret_json<T>(path: &Path, value: &T, force: bool) -> Result<()>
+where
+    T: Serialize + DeserializeOwned + PartialEq,
+{
+    ensure!(
+        force || !path.exists(),
+        "{} already exists (use --force to overwrite it)",
+        path.display()
+    );
+
+    let tmp_path = tmp_path_for(path)?;
+    let res = write_tmp(&tmp_path, value).and_then(|_| {
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("couldn't move {} into place", path.display()))
+    });
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    res?;
+
+    // make sure what we wrote is what we meant to write
+    let file = File::open(path).with_context(|| format!("couldn't open {}", path.display()))?;
+    let read: T = serde_json::from_reader(file)
+        .with_context(|| format!("couldn't deserialize {}", path.display()))?;
+    ensure!(
+        read == *value,
+        "{} doesn't contain what was written to it",
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// A (new) temporary file next to `path`.
+fn tmp_path_for(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("invalid file name {}", path.display()))?;
+    let suffix: u32 = rand::random();
+    Ok(path.with_file_name(format!(".{file_name}.{suffix:08x}.tmp")))
+}
+
+fn write_tmp<T: Serialize>(tmp_path: &Path, value: &T) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(SECRET_FILE_MODE);
+    }
+
+    let mut file = options
+        .open(tmp_path)
+        .with_context(|| format!("couldn't create {}", tmp_path.display()))?;
+    serde_json::to_writer_pretty(&mut file, value)?;
+    file.flush()?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Returns the permissions of a file (on unix) if its group or other users can read it.
+pub fn readable_by_others(path: &Path) -> Result<Option<u32>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let metadata =
+            std::fs::metadata(path).with_context(|| format!("couldn't stat {}", path.display()))?;
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Ok(Some(mode));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+
+    Ok(None)
+}
+
+/// Checks that a secret file is only readable by its owner:
+/// if it isn't, this fails if `strict` is set, and only warns otherwise.
+pub fn check_secret_permissions(path: &Path, strict: bool) -> Result<()> {
+    let Some(mode) = readable_by_others(path)? else {
+        return Ok(());
+    };
+
+    let msg = format!(
+        "{} can be read by other users (its permissions are {mode:o}), run `chmod {SECRET_FILE_MODE:o} {}`",
+        path.display(),
+        path.display()
+    );
+    if strict {
+        bail!(msg);
+    }
+    warn!("{msg}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_write_secret_json() {
+        let tmp_dir = TempDir::new("zkbitcoin_secret_file").unwrap();
+        let path = tmp_dir.path().join("key-0.json");
+
+        write_secret_json(&path, &vec![1u8, 2, 3], false).unwrap();
+        let read: Vec<u8> = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(read, vec![1, 2, 3]);
+        assert_eq!(readable_by_others(&path).unwrap(), None);
+        check_secret_permissions(&path, true).unwrap();
+
+        // no temporary file is left behind
+        assert_eq!(std::fs::read_dir(tmp_dir.path()).unwrap().count(), 1);
+
+        // existing files are only overwritten with force
+        let err = write_secret_json(&path, &vec![4u8], false).unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        write_secret_json(&path, &vec![4u8], true).unwrap();
+        let read: Vec<u8> = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(read, vec![4]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_secret_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = TempDir::new("zkbitcoin_secret_file").unwrap();
+        let path = tmp_dir.path().join("key-0.json");
+        std::fs::write(&path, "[]").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        assert_eq!(readable_by_others(&path).unwrap(), Some(0o644));
+        check_secret_permissions(&path, false).unwrap();
+        let err = check_secret_permissions(&path, true).unwrap_err();
+        assert!(err.to_string().contains("chmod 600"), "{err}");
+    }
+}
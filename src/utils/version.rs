@@ -1,42 +1,353 @@
-use anyhow::Result;
-use log::warn;
+//! Checks, at startup, whether a newer release of zkBitcoin is out.
+//! The latest release is fetched at most once every [VERSION_CHECK_INTERVAL_SECONDS] (the answer is cached in
+//! the cache directory of the platform), and never holds startup for more than [VERSION_CHECK_TIMEOUT_MS].
+//! Self-hosted deployments can point the check to their own endpoint with [VERSION_URL_ENV].
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use log::debug;
 use reqwest::Client;
-use serde::Deserialize;
-use versions::Versioning;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{VERSION_CHECK_INTERVAL_SECONDS, VERSION_CHECK_TIMEOUT_MS};
 
 const RELEASES_URL: &str = "https://api.github.com/repos/sigma0-xyz/zkbitcoin/releases/latest";
 
-#[derive(Deserialize, Debug)]
+/// The environment variable overriding the URL of the latest release
+/// (it must answer like the GitHub API, with at least `tag_name` and `html_url`).
+pub const VERSION_URL_ENV: &str = "ZKBTC_VERSION_URL";
+
+/// The file (in the cache directory) holding the last successful check.
+const CACHE_FILE: &str = "version-check.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 struct Release {
     html_url: String,
     tag_name: String,
 }
 
-async fn fetch_latest_version() -> Result<Release> {
-    let client = Client::new();
+/// The last successful check.
+#[derive(Serialize, Deserialize, Debug)]
+struct CachedCheck {
+    /// Where the release was fetched from (a check of another URL isn't reused).
+    url: String,
+
+    /// When it was fetched (in seconds since the UNIX epoch).
+    checked_at: u64,
+
+    release: Release,
+}
+
+/// How the version of this build compares to the latest release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionStatus {
+    pub current: Version,
+    pub latest: Version,
+
+    /// Where to download the latest release.
+    pub url: String,
+
+    /// Whether the latest release is newer than this build (pre-releases are older than their release).
+    pub update_available: bool,
+}
+
+impl VersionStatus {
+    fn new(current: Version, release: Release) -> Result<Self> {
+        let latest = parse_version(&release.tag_name)?;
+        Ok(Self {
+            update_available: current < latest,
+            current,
+            latest,
+            url: release.html_url,
+        })
+    }
+}
+
+/// Parses a version, or a release tag (e.g. `v0.2.0-rc.1`).
+fn parse_version(version: &str) -> Result<Version> {
+    let version = version.trim();
+    Version::parse(version.strip_prefix('v').unwrap_or(version))
+        .with_context(|| format!("`{version}` is not a semantic version"))
+}
+
+/// A version check (see [check_version] for the one the CLIs run).
+pub struct VersionCheck {
+    url: String,
+    current: String,
+
+    /// Where the last successful check is cached (nothing is cached without it).
+    cache_path: Option<PathBuf>,
+
+    max_age: Duration,
+    timeout: Duration,
+}
+
+impl Default for VersionCheck {
+    fn default() -> Self {
+        Self {
+            url: std::env::var(VERSION_URL_ENV).unwrap_or_else(|_| RELEASES_URL.to_string()),
+            current: env!("CARGO_PKG_VERSION").to_string(),
+            cache_path: ProjectDirs::from("xyz", "sigma0", "zkbitcoin")
+                .map(|dirs| dirs.cache_dir().join(CACHE_FILE)),
+            max_age: Duration::from_secs(VERSION_CHECK_INTERVAL_SECONDS),
+            timeout: Duration::from_millis(VERSION_CHECK_TIMEOUT_MS),
+        }
+    }
+}
+
+impl VersionCheck {
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+
+    /// Compares `current` (instead of the version of this build) to the latest release.
+    pub fn with_current(mut self, current: &str) -> Self {
+        self.current = current.to_string();
+        self
+    }
+
+    pub fn with_cache_path(mut self, cache_path: Option<PathBuf>) -> Self {
+        self.cache_path = cache_path;
+        self
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Compares the current version to the latest release, fetched unless a recent enough check is cached.
+    pub async fn run(&self) -> Result<VersionStatus> {
+        let current = parse_version(&self.current)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        if let Some(cached) = self.cache_path.as_deref().and_then(read_cache) {
+            let age = now.saturating_sub(cached.checked_at);
+            if cached.url == self.url && age < self.max_age.as_secs() {
+                debug!("- using the version check from {age}s ago");
+                return VersionStatus::new(current, cached.release);
+            }
+        }
+
+        // the client timeout covers the request, this one also covers connecting and resolving
+        let release =
+            tokio::time::timeout(self.timeout, fetch_latest_version(&self.url, self.timeout))
+                .await
+                .with_context(|| {
+                    format!(
+                        "the release server didn't answer within {}ms",
+                        self.timeout.as_millis()
+                    )
+                })??;
+        let status = VersionStatus::new(current, release.clone())?;
+
+        if let Some(cache_path) = &self.cache_path {
+            let cached = CachedCheck {
+                url: self.url.clone(),
+                checked_at: now,
+                release,
+            };
+            if let Err(err) = write_cache(cache_path, &cached) {
+                debug!("couldn't cache the version check: {err:#}");
+            }
+        }
+        Ok(status)
+    }
+}
+
+fn read_cache(path: &Path) -> Option<CachedCheck> {
+    let cached = std::fs::read(path).ok()?;
+    serde_json::from_slice(&cached).ok()
+}
+
+fn write_cache(path: &Path, cached: &CachedCheck) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_vec(cached)?)?;
+    Ok(())
+}
+
+async fn fetch_latest_version(url: &str, timeout: Duration) -> Result<Release> {
+    let client = Client::builder().timeout(timeout).build()?;
 
     let release = client
-        .get(RELEASES_URL)
+        .get(url)
         .header("User-Agent", "zkbitcoin cli")
         .send()
         .await?
+        .error_for_status()?
         .json::<Release>()
-        .await?;
+        .await
+        .context("unexpected answer from the release server")?;
 
     Ok(release)
 }
 
-pub async fn check_version() -> Result<()> {
-    let current_version = Versioning::new(env!("CARGO_PKG_VERSION"));
-    let latest_release = fetch_latest_version().await?;
-    let latest_version = Versioning::new(&latest_release.tag_name.replace('v', ""));
+/// Compares the version of this build to the latest release.
+/// Returns `None` if the check failed (this is only logged at the debug level: it shouldn't get in the way).
+pub async fn check_version() -> Option<VersionStatus> {
+    match VersionCheck::default().run().await {
+        Ok(status) => Some(status),
+        Err(err) => {
+            debug!("couldn't check the latest version: {err:#}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Instant,
+    };
+
+    use super::*;
 
-    if current_version < latest_version {
-        warn!(
-            "You are using an old version. Please download the latest version: {}",
-            latest_release.html_url
-        )
+    /// Serves the given release (to any number of requests), returns the url of the server and the number of requests served.
+    fn serve_release(tag_name: &'static str) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/releases/latest", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let served = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).unwrap();
+                served.fetch_add(1, Ordering::SeqCst);
+                let body = format!(
+                    r#"{{"html_url": "https://example.com/{tag_name}", "tag_name": "{tag_name}"}}"#
+                );
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+        (url, requests)
     }
 
-    Ok(())
+    fn check(url: &str, current: &str) -> VersionCheck {
+        VersionCheck::default()
+            .with_url(url)
+            .with_current(current)
+            .with_cache_path(None)
+            .with_timeout(Duration::from_secs(5))
+    }
+
+    #[tokio::test]
+    async fn test_up_to_date() {
+        let (url, _) = serve_release("v0.1.0");
+        let status = check(&url, "0.1.0").run().await.unwrap();
+        assert!(!status.update_available);
+        assert_eq!(status.latest, Version::new(0, 1, 0));
+
+        // a build newer than the latest release
+        let status = check(&url, "0.2.0").run().await.unwrap();
+        assert!(!status.update_available);
+
+        // numbers are compared as numbers, not as strings
+        let (url, _) = serve_release("v0.9.0");
+        assert!(!check(&url, "0.10.0").run().await.unwrap().update_available);
+    }
+
+    #[tokio::test]
+    async fn test_outdated() {
+        let (url, _) = serve_release("v0.2.0");
+        let status = check(&url, "0.1.0").run().await.unwrap();
+        assert_eq!(
+            status,
+            VersionStatus {
+                current: Version::new(0, 1, 0),
+                latest: Version::new(0, 2, 0),
+                url: "https://example.com/v0.2.0".to_string(),
+                update_available: true,
+            }
+        );
+
+        // a pre-release is older than its release
+        assert!(
+            check(&url, "0.2.0-rc.1")
+                .run()
+                .await
+                .unwrap()
+                .update_available
+        );
+        let (url, _) = serve_release("v0.3.0-rc.1");
+        assert!(!check(&url, "0.3.0").run().await.unwrap().update_available);
+        assert!(
+            check(&url, "0.3.0-alpha")
+                .run()
+                .await
+                .unwrap()
+                .update_available
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_times_out() {
+        // a server accepting connections but never answering
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/releases/latest", listener.local_addr().unwrap());
+
+        let start = Instant::now();
+        let res = check(&url, "0.1.0")
+            .with_timeout(Duration::from_millis(200))
+            .run()
+            .await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(res.is_err());
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn test_cached_check() {
+        let dir = tempdir::TempDir::new("zkbitcoin_version").unwrap();
+        let cache_path = dir.path().join("cache").join(CACHE_FILE);
+        let (url, requests) = serve_release("v0.2.0");
+        let check = check(&url, "0.1.0").with_cache_path(Some(cache_path.clone()));
+
+        // the first check fetches the release, the next ones reuse it
+        let status = check.run().await.unwrap();
+        assert!(status.update_available);
+        assert_eq!(check.run().await.unwrap(), status);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // until it's too old
+        let check = check.with_max_age(Duration::ZERO);
+        assert_eq!(check.run().await.unwrap(), status);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // a check of another URL isn't reused
+        let (other_url, other_requests) = serve_release("v0.3.0");
+        let status = check
+            .with_max_age(Duration::from_secs(60))
+            .with_url(&other_url)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(status.latest, Version::new(0, 3, 0));
+        assert_eq!(other_requests.load(Ordering::SeqCst), 1);
+    }
 }
@@ -0,0 +1,214 @@
+//! A cache of the verifier keys of the zkapps being spent, ready to verify proofs.
+//! Verifier keys come with every request, but they are only checked against the hash committed to by the zkapp
+//! (and prepared, see [VerifierKey::prepare]) the first time they're seen:
+//! the next requests spending the same zkapp (or another zkapp with the same key) skip straight to verification,
+//! with the key we checked (not the one given with the request).
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use anyhow::{ensure, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    capped_hashmap::CappedHashMap,
+    constants::DEFAULT_VK_CACHE_SIZE,
+    proof_system::{PreparedVerifierKey, VerifierKey},
+};
+
+/// A verifier key that was checked against a zkapp, and the key ready to verify proofs.
+#[derive(Debug)]
+pub struct CachedVk {
+    /// The key, as given with the request (to check the entry still matches its hash, see [VkCache::revalidate]).
+    pub vk: VerifierKey,
+
+    pub prepared: PreparedVerifierKey,
+}
+
+/// A (least recently used) cache of verifier keys, by hash.
+pub struct VkCache {
+    capacity: usize,
+    entries: Mutex<CappedHashMap<[u8; 32], Arc<CachedVk>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// The state of a [VkCache].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VkCacheStats {
+    /// The maximum number of keys kept (0 if the cache is disabled).
+    pub capacity: usize,
+
+    /// The number of keys kept.
+    pub entries: usize,
+
+    /// The number of times a key was already there.
+    pub hits: u64,
+
+    /// The number of times a key had to be checked and prepared.
+    pub misses: u64,
+
+    /// The number of keys evicted because they didn't match their hash anymore.
+    pub evictions: u64,
+}
+
+impl Default for VkCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_VK_CACHE_SIZE)
+    }
+}
+
+impl VkCache {
+    /// A cache of up to `capacity` keys (nothing is cached with a capacity of 0).
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(CappedHashMap::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the verifier key committed to by a zkapp (`vk_hash`), ready to verify proofs.
+    /// If it's not cached yet, the verifier key given with the request (`vk`) is checked against the hash, prepared, and cached.
+    pub fn get_or_insert(&self, vk_hash: &[u8; 32], vk: &VerifierKey) -> Result<Arc<CachedVk>> {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(cached) = entries.remove(vk_hash) {
+                // (re-inserting it makes it the most recently used entry)
+                entries.add_entry(*vk_hash, Arc::clone(&cached));
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(cached);
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        // (outside of the lock, as hashing and preparing large keys takes a while)
+        ensure!(
+            vk.hash() == *vk_hash,
+            "VK does not match the VK hash in the smart contract"
+        );
+        let cached = Arc::new(CachedVk {
+            vk: vk.clone(),
+            prepared: vk.prepare()?,
+        });
+        if self.capacity > 0 {
+            self.entries
+                .lock()
+                .unwrap()
+                .add_entry(*vk_hash, Arc::clone(&cached));
+        }
+        Ok(cached)
+    }
+
+    /// Checks that the key cached for `vk_hash` (if any) still matches it, and evicts it otherwise
+    /// (so that it's checked and prepared again the next time it's needed).
+    /// Returns false if the entry was evicted.
+    pub fn revalidate(&self, vk_hash: &[u8; 32]) -> bool {
+        let Some(cached) = self.entries.lock().unwrap().get(vk_hash).cloned() else {
+            return true;
+        };
+        if cached.vk.hash() == *vk_hash && cached.vk.scheme() == cached.prepared.scheme() {
+            return true;
+        }
+
+        warn!(
+            "- evicting the cached verifier key {}, which doesn't match its hash anymore",
+            hex::encode(vk_hash)
+        );
+        self.entries.lock().unwrap().remove(vk_hash);
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    pub fn stats(&self) -> VkCacheStats {
+        VkCacheStats {
+            capacity: self.capacity,
+            entries: self.entries.lock().unwrap().size(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    #[cfg(test)]
+    fn corrupt(&self, vk_hash: &[u8; 32], vk: VerifierKey) {
+        let mut entries = self.entries.lock().unwrap();
+        let prepared = entries.get(vk_hash).unwrap().prepared.clone();
+        entries.add_entry(*vk_hash, Arc::new(CachedVk { vk, prepared }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plonk_vk() -> VerifierKey {
+        serde_json::from_str(include_str!("../examples/circuit/vk.json")).unwrap()
+    }
+
+    fn groth16_vk() -> VerifierKey {
+        serde_json::from_str(include_str!("../examples/circuit/groth16/vk.json")).unwrap()
+    }
+
+    #[test]
+    fn test_vk_cache() {
+        let cache = VkCache::new(1);
+        let vk = groth16_vk();
+        let vk_hash = vk.hash();
+
+        // the key is checked against the hash of the zkapp
+        assert!(cache.get_or_insert(&[0; 32], &vk).is_err());
+        assert_eq!(cache.stats().entries, 0);
+
+        // then cached
+        cache.get_or_insert(&vk_hash, &vk).unwrap();
+        let cached = cache.get_or_insert(&vk_hash, &plonk_vk()).unwrap();
+        assert_eq!(cached.vk.hash(), vk_hash, "the cached key is used");
+        assert_eq!(
+            cache.stats(),
+            VkCacheStats {
+                capacity: 1,
+                entries: 1,
+                hits: 1,
+                misses: 2,
+                evictions: 0
+            }
+        );
+
+        // the least recently used key makes room for new ones
+        let other = plonk_vk();
+        cache.get_or_insert(&other.hash(), &other).unwrap();
+        assert_eq!(cache.stats().entries, 1);
+        cache.get_or_insert(&vk_hash, &vk).unwrap();
+        assert_eq!(cache.stats().misses, 4);
+
+        // the cached key still matches
+        assert!(cache.revalidate(&vk_hash));
+        assert!(cache.revalidate(&[0; 32]), "nothing to revalidate");
+
+        // a corrupted entry is evicted, and checked again the next time
+        cache.corrupt(&vk_hash, plonk_vk());
+        assert!(!cache.revalidate(&vk_hash));
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(cache.stats().entries, 0);
+        let cached = cache.get_or_insert(&vk_hash, &vk).unwrap();
+        assert_eq!(cached.vk.hash(), vk_hash);
+    }
+
+    #[test]
+    fn test_disabled_vk_cache() {
+        let cache = VkCache::new(0);
+        let vk = groth16_vk();
+        for _ in 0..2 {
+            cache.get_or_insert(&vk.hash(), &vk).unwrap();
+        }
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (0, 0, 2));
+    }
+}
@@ -0,0 +1,290 @@
+//! The commitment of a zkapp to its verifier key, and the check that a verifier key is the one committed to.
+//! A zkapp commits to the hash of its verifier key in the OP_RETURN output of its deployment transaction,
+//! in one of two formats (see [crate::op_return_script_for_scheme]):
+//!
+//! - version 0 (PLONK): `OP_RETURN <vk_hash || state>`,
+//! - version 1 (other schemes): `OP_RETURN <scheme tag> <vk_hash || state>`.
+//!
+//! Whoever relays a request (the orchestrator) could give us another verifier key than the one committed to,
+//! one that accepts anything. So the orchestrator and every node check the key against the deployment transaction
+//! (nodes also against the one they fetch themselves, see [crate::committee::node]) before verifying a proof with it.
+
+use std::fmt;
+
+use bitcoin::Transaction;
+
+use crate::{
+    bob_request::parse_op_return_data_with_scheme,
+    groth16, plonk,
+    proof_system::{ProofScheme, VerifierKey},
+};
+
+/// Why a verifier key couldn't be matched to the commitment of a zkapp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommitmentError {
+    /// The deployment transaction has no OP_RETURN output.
+    Missing,
+
+    /// An OP_RETURN output isn't a commitment (and no other output is).
+    Malformed { vout: usize, reason: String },
+
+    /// Several OP_RETURN outputs are commitments, so there's no telling which one the zkapp is bound to.
+    Ambiguous { vouts: Vec<usize> },
+
+    /// The verifier key can't be parsed as a key of the scheme of the zkapp.
+    InvalidVk { scheme: ProofScheme, reason: String },
+
+    /// The verifier key isn't the one committed to.
+    Mismatch {
+        committed: [u8; 32],
+        computed: [u8; 32],
+    },
+}
+
+impl fmt::Display for CommitmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "the deployment transaction has no OP_RETURN output"),
+            Self::Malformed { vout, reason } => write!(
+                f,
+                "the OP_RETURN output #{vout} of the deployment transaction isn't a verifier key commitment: {reason}"
+            ),
+            Self::Ambiguous { vouts } => write!(
+                f,
+                "the deployment transaction commits to several verifier keys (in outputs {vouts:?})"
+            ),
+            Self::InvalidVk { scheme, reason } => {
+                write!(f, "the verifier key isn't a {scheme} verifier key: {reason}")
+            }
+            Self::Mismatch {
+                committed,
+                computed,
+            } => write!(
+                f,
+                "the verifier key hashes to {}, but the zkapp committed to {}",
+                hex::encode(computed),
+                hex::encode(committed)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CommitmentError {}
+
+/// The commitment of a zkapp, as found in its deployment transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VkCommitment {
+    /// The OP_RETURN output holding the commitment.
+    pub vout: usize,
+
+    /// The format of the commitment (0 for untagged PLONK commitments, 1 for tagged ones).
+    pub version: u8,
+
+    pub scheme: ProofScheme,
+
+    pub vk_hash: [u8; 32],
+
+    /// The (encoded) initial state of the zkapp, empty if it's stateless.
+    pub state: Vec<u8>,
+}
+
+impl VkCommitment {
+    /// Finds the commitment among the OP_RETURN outputs of a deployment transaction.
+    /// OP_RETURN outputs that aren't commitments are ignored, but there must be exactly one that is.
+    pub fn from_tx(deploy_tx: &Transaction) -> Result<Self, CommitmentError> {
+        let mut commitments = vec![];
+        let mut first_error = None;
+        for (vout, output) in deploy_tx.output.iter().enumerate() {
+            if !output.script_pubkey.is_op_return() {
+                continue;
+            }
+            match Self::parse(vout, &output.script_pubkey) {
+                Ok(commitment) => commitments.push(commitment),
+                Err(reason) => {
+                    first_error.get_or_insert(CommitmentError::Malformed { vout, reason });
+                }
+            }
+        }
+
+        match commitments.len() {
+            0 => Err(first_error.unwrap_or(CommitmentError::Missing)),
+            1 => Ok(commitments.remove(0)),
+            _ => Err(CommitmentError::Ambiguous {
+                vouts: commitments.iter().map(|c| c.vout).collect(),
+            }),
+        }
+    }
+
+    fn parse(vout: usize, script: &bitcoin::ScriptBuf) -> Result<Self, String> {
+        let tagged = script.instructions().count() > 2;
+        let (scheme, data) =
+            parse_op_return_data_with_scheme(script).map_err(|err| err.to_string())?;
+        if data.len() < 32 {
+            return Err(format!(
+                "it should at least contain the 32-byte hash of the verifier key, not {} bytes",
+                data.len()
+            ));
+        }
+        let (vk_hash, state) = data.split_at(32);
+        Ok(Self {
+            vout,
+            version: u8::from(tagged),
+            scheme,
+            vk_hash: vk_hash.try_into().unwrap(),
+            state: state.to_vec(),
+        })
+    }
+}
+
+/// Parses a verifier key of `scheme` (in its snarkjs JSON format), and hashes it as zkapps commit to it.
+pub fn hash_vk(scheme: ProofScheme, vk_bytes: &[u8]) -> Result<[u8; 32], CommitmentError> {
+    let invalid = |err: serde_json::Error| CommitmentError::InvalidVk {
+        scheme,
+        reason: err.to_string(),
+    };
+    // the hash is over the serialization of the parsed key, not over the bytes given
+    let vk = match scheme {
+        ProofScheme::Plonk => VerifierKey::Plonk(
+            serde_json::from_slice::<plonk::VerifierKey>(vk_bytes).map_err(invalid)?,
+        ),
+        ProofScheme::Groth16 => VerifierKey::Groth16(
+            serde_json::from_slice::<groth16::VerifierKey>(vk_bytes).map_err(invalid)?,
+        ),
+    };
+    Ok(vk.hash())
+}
+
+/// Checks that a verifier key (in its snarkjs JSON format) is the one a zkapp committed to in its deployment transaction.
+pub fn verify_vk_commitment(
+    deploy_tx: &Transaction,
+    vk_bytes: &[u8],
+) -> Result<(), CommitmentError> {
+    let commitment = VkCommitment::from_tx(deploy_tx)?;
+    let computed = hash_vk(commitment.scheme, vk_bytes)?;
+    if computed != commitment.vk_hash {
+        return Err(CommitmentError::Mismatch {
+            committed: commitment.vk_hash,
+            computed,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, transaction::Version, Amount, ScriptBuf, TxOut};
+
+    use super::*;
+    use crate::{op_return_script_for_scheme, p2tr_script_to, zkbitcoin_pubkey};
+
+    const PLONK_VK: &str = include_str!("../examples/circuit/vk.json");
+    const GROTH16_VK: &str = include_str!("../examples/circuit/groth16/vk.json");
+
+    fn deploy_tx(op_returns: Vec<ScriptBuf>) -> Transaction {
+        let mut output = vec![TxOut {
+            value: Amount::from_sat(1000),
+            script_pubkey: p2tr_script_to(zkbitcoin_pubkey().unwrap()),
+        }];
+        output.extend(op_returns.into_iter().map(|script_pubkey| TxOut {
+            value: Amount::ZERO,
+            script_pubkey,
+        }));
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output,
+        }
+    }
+
+    fn commitment_to(scheme: ProofScheme, vk: &str) -> ScriptBuf {
+        let vk_hash = hash_vk(scheme, vk.as_bytes()).unwrap();
+        op_return_script_for_scheme(scheme, &vk_hash, None).unwrap()
+    }
+
+    #[test]
+    fn test_verify_vk_commitment() {
+        let tx = deploy_tx(vec![commitment_to(ProofScheme::Plonk, PLONK_VK)]);
+        verify_vk_commitment(&tx, PLONK_VK.as_bytes()).unwrap();
+        let commitment = VkCommitment::from_tx(&tx).unwrap();
+        assert_eq!((commitment.vout, commitment.version), (1, 0));
+
+        // the hash doesn't depend on the formatting of the key
+        let reformatted = serde_json::to_vec_pretty(
+            &serde_json::from_str::<serde_json::Value>(PLONK_VK).unwrap(),
+        )
+        .unwrap();
+        verify_vk_commitment(&tx, &reformatted).unwrap();
+
+        // tagged commitments
+        let tx = deploy_tx(vec![commitment_to(ProofScheme::Groth16, GROTH16_VK)]);
+        verify_vk_commitment(&tx, GROTH16_VK.as_bytes()).unwrap();
+        let commitment = VkCommitment::from_tx(&tx).unwrap();
+        assert_eq!(
+            (commitment.version, commitment.scheme),
+            (1, ProofScheme::Groth16)
+        );
+
+        // a key of another scheme
+        let err = verify_vk_commitment(&tx, PLONK_VK.as_bytes()).unwrap_err();
+        assert!(matches!(err, CommitmentError::InvalidVk { .. }), "{err}");
+
+        // no commitment
+        assert_eq!(
+            verify_vk_commitment(&deploy_tx(vec![]), PLONK_VK.as_bytes()),
+            Err(CommitmentError::Missing)
+        );
+    }
+
+    #[test]
+    fn test_tampered_vk() {
+        let tx = deploy_tx(vec![commitment_to(ProofScheme::Plonk, PLONK_VK)]);
+
+        // change a single field of the key
+        let mut vk: serde_json::Value = serde_json::from_str(PLONK_VK).unwrap();
+        vk["k1"] = serde_json::json!("3");
+        let tampered = serde_json::to_vec(&vk).unwrap();
+        let err = verify_vk_commitment(&tx, &tampered).unwrap_err();
+        let CommitmentError::Mismatch {
+            committed,
+            computed,
+        } = err
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(
+            committed,
+            hash_vk(ProofScheme::Plonk, PLONK_VK.as_bytes()).unwrap()
+        );
+        assert_eq!(computed, hash_vk(ProofScheme::Plonk, &tampered).unwrap());
+    }
+
+    #[test]
+    fn test_multiple_op_returns() {
+        let commitment = commitment_to(ProofScheme::Plonk, PLONK_VK);
+        let memo = ScriptBuf::new_op_return(b"hello");
+
+        // OP_RETURNs that aren't commitments are ignored
+        let tx = deploy_tx(vec![memo.clone(), commitment.clone()]);
+        verify_vk_commitment(&tx, PLONK_VK.as_bytes()).unwrap();
+        assert_eq!(VkCommitment::from_tx(&tx).unwrap().vout, 2);
+
+        // but two commitments are ambiguous, even to the same key
+        let other = commitment_to(ProofScheme::Groth16, GROTH16_VK);
+        for op_returns in [
+            vec![commitment.clone(), other],
+            vec![commitment.clone(), memo.clone(), commitment],
+        ] {
+            let tx = deploy_tx(op_returns);
+            let err = verify_vk_commitment(&tx, PLONK_VK.as_bytes()).unwrap_err();
+            assert!(matches!(err, CommitmentError::Ambiguous { .. }), "{err}");
+        }
+
+        // and an OP_RETURN alone that isn't a commitment is reported
+        let err = verify_vk_commitment(&deploy_tx(vec![memo]), PLONK_VK.as_bytes()).unwrap_err();
+        assert!(
+            matches!(err, CommitmentError::Malformed { vout: 1, .. }),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,626 @@
+//! Watch-only descriptor wallets of the node: importing descriptors (rescanning the chain if needed),
+//! and tracking the committee's address to find its deposits (see [preflight_committee]).
+
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Result};
+use bitcoin::{Address, Amount, BlockHash, OutPoint};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::json_rpc_stuff::{
+    get_block_header, json_rpc_request, json_rpc_request_deserialize, list_unspent, JsonRpcError,
+    JsonRpcResponse, RpcCtx,
+};
+
+/// Adds the checksum to a descriptor (unless it already has one), as `importdescriptors` requires it.
+pub async fn checksummed_descriptor(ctx: &RpcCtx, descriptor: &str) -> Result<String> {
+    if descriptor.contains('#') {
+        return Ok(descriptor.to_string());
+    }
+
+    #[derive(Deserialize)]
+    struct DescriptorInfo {
+        checksum: String,
+    }
+    let info: DescriptorInfo = json_rpc_request_deserialize(
+        ctx,
+        "getdescriptorinfo",
+        &[serde_json::value::to_raw_value(descriptor)?],
+    )
+    .await?;
+    Ok(format!("{descriptor}#{}", info.checksum))
+}
+
+/// How long `importdescriptors` may take when the wallet rescans the chain (which can take hours on mainnet).
+const RESCAN_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often the progress of a rescan is logged (see [import_descriptors]).
+const RESCAN_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The JSON RPC error code bitcoind returns for wallets that don't exist.
+const RPC_WALLET_NOT_FOUND: i64 = -18;
+
+/// The JSON RPC error code bitcoind returns for wallets that are already loaded.
+const RPC_WALLET_ALREADY_LOADED: i64 = -35;
+
+/// The wallet tracking the committee's address, unless the context has another one (see [watch_committee_address]).
+pub const COMMITTEE_WATCH_WALLET: &str = "zkbitcoin-committee";
+
+/// A descriptor to import in a wallet (see [import_descriptors]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorImport {
+    /// The descriptor (its checksum is added if it's missing).
+    pub descriptor: String,
+
+    /// The height of the first block that could contain outputs of the descriptor,
+    /// from which the wallet rescans the chain (which can take a while).
+    /// If not set, the wallet only tracks the outputs created from now on.
+    pub birth_height: Option<u64>,
+
+    pub label: Option<String>,
+}
+
+impl DescriptorImport {
+    /// Imports `descriptor` without rescanning the chain.
+    pub fn new(descriptor: impl Into<String>) -> Self {
+        Self {
+            descriptor: descriptor.into(),
+            birth_height: None,
+            label: None,
+        }
+    }
+
+    /// Rescans the chain from the block at `birth_height`.
+    pub fn with_birth_height(mut self, birth_height: u64) -> Self {
+        self.birth_height = Some(birth_height);
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+}
+
+/// The result of importing a descriptor (as returned by `importdescriptors`, in the order of the descriptors).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportResult {
+    pub success: bool,
+
+    #[serde(default)]
+    pub warnings: Vec<String>,
+
+    pub error: Option<JsonRpcError>,
+}
+
+impl ImportResult {
+    /// Whether the import failed because the wallet already has the descriptor (which is as good as a success).
+    pub fn already_imported(&self) -> bool {
+        self.error
+            .as_ref()
+            .map(|error| error.message.to_lowercase().contains("already"))
+            .unwrap_or(false)
+    }
+
+    /// Fails unless `descriptor` was imported (or was already there), logging the warnings of the import.
+    pub fn check(&self, descriptor: &str) -> Result<()> {
+        for warning in &self.warnings {
+            warn!("importdescriptors: {warning}");
+        }
+        if self.success || self.already_imported() {
+            return Ok(());
+        }
+        match &self.error {
+            Some(JsonRpcError { code, message }) => {
+                bail!("couldn't import descriptor {descriptor}: {message} (code {code})")
+            }
+            None => bail!("couldn't import descriptor {descriptor}"),
+        }
+    }
+}
+
+/// Returns the timestamp of the block at `height` (`importdescriptors` wants timestamps rather than heights).
+async fn block_time_at(ctx: &RpcCtx, height: u64) -> Result<u64> {
+    let block_hash: BlockHash = json_rpc_request_deserialize(
+        ctx,
+        "getblockhash",
+        &[serde_json::value::to_raw_value(&height)?],
+    )
+    .await?;
+    Ok(get_block_header(ctx, block_hash).await?.time)
+}
+
+/// The progress of a rescan of a wallet (see [get_rescan_progress]).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct RescanProgress {
+    /// How long the rescan has been running (in seconds).
+    pub duration: u64,
+
+    /// How much of the chain has been rescanned (between 0 and 1).
+    pub progress: f64,
+}
+
+/// Returns the progress of the rescan of the wallet, if it's rescanning the chain.
+pub async fn get_rescan_progress(ctx: &RpcCtx) -> Result<Option<RescanProgress>> {
+    #[derive(Deserialize)]
+    struct WalletInfo {
+        #[serde(default)]
+        scanning: serde_json::Value,
+    }
+    let info: WalletInfo = json_rpc_request_deserialize(ctx, "getwalletinfo", &[]).await?;
+    // `scanning` is `false` when the wallet isn't rescanning
+    Ok(serde_json::from_value(info.scanning).ok())
+}
+
+/// Imports descriptors in a descriptor wallet (see `importdescriptors`), returning the result of each import.
+/// If any of them has a birth height, the wallet rescans the chain before the call returns,
+/// and the progress of the rescan is logged meanwhile.
+/// Use [ImportResult::check] to fail on imports that didn't succeed.
+pub async fn import_descriptors(
+    ctx: &RpcCtx,
+    imports: &[DescriptorImport],
+) -> Result<Vec<ImportResult>> {
+    let mut requests = Vec::with_capacity(imports.len());
+    for import in imports {
+        let descriptor = checksummed_descriptor(ctx, &import.descriptor).await?;
+        let timestamp = match import.birth_height {
+            None => serde_json::json!("now"),
+            // no need to look up the genesis block
+            Some(0) => serde_json::json!(0),
+            Some(height) => serde_json::json!(block_time_at(ctx, height).await?),
+        };
+        let mut request = serde_json::json!({
+            "desc": descriptor,
+            "timestamp": timestamp,
+        });
+        if let Some(label) = &import.label {
+            request["label"] = serde_json::json!(label);
+        }
+        requests.push(request);
+    }
+    let params = [serde_json::value::to_raw_value(&requests)?];
+
+    let rescan = imports.iter().any(|import| import.birth_height.is_some());
+    let results: Vec<ImportResult> = if rescan {
+        // the call only returns once the rescan is over
+        let import_ctx = ctx
+            .duplicate()
+            .with_timeouts(ctx.connect_timeout(), RESCAN_TIMEOUT);
+        let import = json_rpc_request_deserialize(&import_ctx, "importdescriptors", &params);
+        tokio::pin!(import);
+        let mut progress_interval = tokio::time::interval(RESCAN_PROGRESS_INTERVAL);
+        // the first tick is immediate
+        progress_interval.tick().await;
+        loop {
+            tokio::select! {
+                results = &mut import => break results?,
+                _ = progress_interval.tick() => {
+                    // bounded, as the import might hold the last slot of the requests in flight
+                    let progress =
+                        tokio::time::timeout(RESCAN_PROGRESS_INTERVAL, get_rescan_progress(ctx)).await;
+                    if let Ok(Ok(Some(RescanProgress { duration, progress }))) = progress {
+                        info!("- rescanning the chain: {:.1}% ({duration}s)", progress * 100.0);
+                    }
+                }
+            }
+        }
+    } else {
+        json_rpc_request_deserialize(ctx, "importdescriptors", &params).await?
+    };
+
+    ensure!(
+        results.len() == imports.len(),
+        "importdescriptors returned {} results for {} descriptors",
+        results.len(),
+        imports.len()
+    );
+    Ok(results)
+}
+
+/// Imports the descriptor of the committee's address (see [crate::taproot_descriptor_from]) in a descriptor wallet,
+/// so that the wallet tracks the committee's outputs (e.g. in `listunspent`).
+/// The wallet must be watch-only (created with `disable_private_keys`), as the descriptor has no private keys.
+/// If `rescan` is set, the wallet looks for existing outputs in the whole chain (which can take a while),
+/// otherwise it only tracks the outputs created from now on.
+pub async fn import_committee_descriptor(
+    ctx: &RpcCtx,
+    descriptor: &str,
+    rescan: bool,
+) -> Result<()> {
+    let mut import = DescriptorImport::new(descriptor).with_label("zkbitcoin committee");
+    if rescan {
+        import = import.with_birth_height(0);
+    }
+    let results = import_descriptors(ctx, &[import]).await?;
+    results[0].check(descriptor)?;
+
+    info!("- imported descriptor {descriptor}");
+    Ok(())
+}
+
+/// Loads the wallet `name` of the node, creating it if it doesn't exist
+/// (as a blank watch-only descriptor wallet if `watch_only` is set).
+pub async fn load_or_create_wallet(ctx: &RpcCtx, name: &str, watch_only: bool) -> Result<()> {
+    let loaded: Vec<String> = json_rpc_request_deserialize(ctx, "listwallets", &[]).await?;
+    if loaded.iter().any(|wallet| wallet == name) {
+        return Ok(());
+    }
+
+    let response =
+        json_rpc_request(ctx, "loadwallet", &[serde_json::value::to_raw_value(name)?]).await?;
+    let response: JsonRpcResponse<serde_json::Value> = serde_json::from_str(&response)?;
+    match response.error {
+        None => {
+            info!("- loaded wallet {name}");
+            return Ok(());
+        }
+        // someone loaded it in the meantime
+        Some(JsonRpcError {
+            code: RPC_WALLET_ALREADY_LOADED,
+            ..
+        }) => return Ok(()),
+        Some(JsonRpcError {
+            code: RPC_WALLET_NOT_FOUND,
+            ..
+        }) => (),
+        Some(JsonRpcError { code, message }) => {
+            bail!("loadwallet error: {message} (code {code})")
+        }
+    }
+
+    let _: serde_json::Value = json_rpc_request_deserialize(
+        ctx,
+        "createwallet",
+        &[
+            serde_json::value::to_raw_value(name)?,
+            // disable_private_keys
+            serde_json::value::to_raw_value(&watch_only)?,
+            // blank
+            serde_json::value::to_raw_value(&watch_only)?,
+            // passphrase
+            serde_json::value::to_raw_value("")?,
+            // avoid_reuse
+            serde_json::value::to_raw_value(&false)?,
+            // descriptors
+            serde_json::value::to_raw_value(&true)?,
+        ],
+    )
+    .await?;
+    info!("- created wallet {name}");
+    Ok(())
+}
+
+/// Tracks the address of the committee (with public key package `pubkey_package`) in a watch-only wallet of the node,
+/// creating the wallet if needed: the wallet of `ctx` if it has one, [COMMITTEE_WATCH_WALLET] otherwise.
+/// If `rescan_from` is set, the wallet also finds the outputs created since that height (see [DescriptorImport::birth_height]).
+/// Importing the descriptor again is harmless. Returns the name of the wallet.
+pub async fn watch_committee_address(
+    ctx: &RpcCtx,
+    pubkey_package: &crate::frost::PublicKeyPackage,
+    rescan_from: Option<u64>,
+) -> Result<String> {
+    let wallet = ctx.wallet().unwrap_or(COMMITTEE_WATCH_WALLET).to_string();
+    load_or_create_wallet(ctx, &wallet, true).await?;
+    let wallet_ctx = ctx.with_wallet(wallet.clone());
+
+    let internal_key = crate::frost::to_xonly_pubkey(pubkey_package.verifying_key());
+    let descriptor = format!("tr({internal_key})");
+    let mut import = DescriptorImport::new(&descriptor).with_label("zkbitcoin committee");
+    if let Some(height) = rescan_from {
+        import = import.with_birth_height(height);
+    }
+    let results = import_descriptors(&wallet_ctx, &[import]).await?;
+    results[0].check(&descriptor)?;
+
+    info!("- watching {descriptor} in wallet {wallet}");
+    Ok(wallet)
+}
+
+/// An output locked at the committee's address (see [preflight_committee]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommitteeDeposit {
+    pub outpoint: OutPoint,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub amount: Amount,
+    pub confirmations: u32,
+}
+
+/// What [preflight_committee] found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommitteeDeposits {
+    /// The wallet watching the committee's address.
+    pub wallet: String,
+    pub address: Address,
+    pub deposits: Vec<CommitteeDeposit>,
+}
+
+impl CommitteeDeposits {
+    pub fn total(&self) -> Amount {
+        self.deposits.iter().map(|deposit| deposit.amount).sum()
+    }
+}
+
+/// Gets a node ready to serve the committee: tracks its address in a watch-only wallet (see [watch_committee_address]),
+/// rescanning the chain from `rescan_from` (if set) so that the existing deposits show up,
+/// and returns the deposits the wallet knows about (including unconfirmed ones).
+/// A rescan of the whole chain can take hours: its progress is logged, and it isn't subject to the timeout of `ctx`.
+pub async fn preflight_committee(
+    ctx: &RpcCtx,
+    pubkey_package: &crate::frost::PublicKeyPackage,
+    rescan_from: Option<u64>,
+) -> Result<CommitteeDeposits> {
+    let wallet = watch_committee_address(ctx, pubkey_package, rescan_from).await?;
+    let wallet_ctx = ctx.with_wallet(wallet.clone());
+
+    let internal_key = crate::frost::to_xonly_pubkey(pubkey_package.verifying_key());
+    let address = Address::p2tr(
+        &secp256k1::Secp256k1::verification_only(),
+        internal_key,
+        None,
+        crate::get_network(),
+    );
+    let script_pubkey = address.script_pubkey();
+
+    // (the wallet of `ctx` might not only watch the committee)
+    let deposits = list_unspent(&wallet_ctx)
+        .await?
+        .into_iter()
+        .filter(|utxo| utxo.script_pub_key == script_pubkey)
+        .map(|utxo| CommitteeDeposit {
+            outpoint: OutPoint::new(utxo.txid, utxo.vout),
+            amount: utxo.amount,
+            confirmations: utxo.confirmations,
+        })
+        .collect::<Vec<_>>();
+    info!(
+        "- found {} deposits to the committee in wallet {wallet}",
+        deposits.len()
+    );
+
+    Ok(CommitteeDeposits {
+        wallet,
+        address,
+        deposits,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{hashes::Hash, Txid};
+
+    use super::*;
+    use crate::json_rpc_stuff::test_server::{serve_once, serve_sequence};
+
+    #[tokio::test]
+    async fn test_import_committee_descriptor() {
+        let descriptor = crate::taproot_descriptor_from(crate::zkbitcoin_pubkey().unwrap());
+        let import_ok = serde_json::json!({
+            "result": [{ "success": true, "warnings": ["Range not given"] }],
+            "error": null,
+            "id": "whatevs",
+        });
+        let descriptor_info = serde_json::json!({
+            "result": {
+                "descriptor": format!("{descriptor}#abcdefgh"),
+                "checksum": "abcdefgh",
+                "isrange": false,
+                "issolvable": true,
+                "hasprivatekeys": false,
+            },
+            "error": null,
+            "id": "whatevs",
+        });
+
+        // the checksum is fetched first
+        let address = serve_sequence(vec![descriptor_info.to_string(), import_ok.to_string()]);
+        let ctx = RpcCtx::builder().url(address).build().unwrap();
+        import_committee_descriptor(&ctx, &descriptor, false)
+            .await
+            .unwrap();
+
+        // unless the descriptor already has one
+        let address = serve_once(import_ok.to_string());
+        let ctx = RpcCtx::builder().url(address).build().unwrap();
+        import_committee_descriptor(&ctx, &format!("{descriptor}#abcdefgh"), true)
+            .await
+            .unwrap();
+
+        // a failed import is an error
+        let import_failed = serde_json::json!({
+            "result": [{
+                "success": false,
+                "error": {
+                    "code": -4,
+                    "message": "Cannot import descriptor without private keys to a wallet with private keys enabled",
+                },
+            }],
+            "error": null,
+            "id": "whatevs",
+        });
+        let address = serve_once(import_failed.to_string());
+        let ctx = RpcCtx::builder().url(address).build().unwrap();
+        let err = import_committee_descriptor(&ctx, &format!("{descriptor}#abcdefgh"), false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("without private keys"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_preflight_committee() {
+        let response = |result: serde_json::Value| {
+            serde_json::json!({ "result": result, "error": null, "id": "whatevs" }).to_string()
+        };
+        let (_, pubkey_package) = crate::frost::gen_frost_keys(3, 2).unwrap();
+        let internal_key = crate::frost::to_xonly_pubkey(pubkey_package.verifying_key());
+        let committee_script = Address::p2tr(
+            &secp256k1::Secp256k1::verification_only(),
+            internal_key,
+            None,
+            crate::get_network(),
+        )
+        .script_pubkey();
+        let deposit = |txid: u8, sats: u64, confirmations: u32, script: &bitcoin::Script| {
+            serde_json::json!({
+                "txid": Txid::from_byte_array([txid; 32]),
+                "vout": 0,
+                "scriptPubKey": script.to_hex_string(),
+                "amount": Amount::from_sat(sats).to_btc(),
+                "confirmations": confirmations,
+                "spendable": false,
+                "solvable": true,
+                "safe": confirmations > 0,
+            })
+        };
+
+        let address = serve_sequence(vec![
+            // the wallet is already loaded
+            response(serde_json::json!([COMMITTEE_WATCH_WALLET])),
+            response(serde_json::json!({
+                "descriptor": format!("tr({internal_key})#abcdefgh"),
+                "checksum": "abcdefgh",
+                "isrange": false,
+                "issolvable": true,
+                "hasprivatekeys": false,
+            })),
+            // the rescan is over
+            response(serde_json::json!([{ "success": true }])),
+            response(serde_json::json!([
+                deposit(1, 10_000, 120, &committee_script),
+                deposit(2, 5_000, 0, &committee_script),
+                // not to the committee
+                deposit(
+                    3,
+                    100_000,
+                    6,
+                    &bitcoin::ScriptBuf::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6")
+                        .unwrap()
+                ),
+            ])),
+        ]);
+        let ctx = RpcCtx::builder().url(address).build().unwrap();
+        let found = preflight_committee(&ctx, &pubkey_package, Some(0))
+            .await
+            .unwrap();
+        assert_eq!(found.wallet, COMMITTEE_WATCH_WALLET);
+        assert_eq!(found.address.script_pubkey(), committee_script);
+        assert_eq!(found.deposits.len(), 2);
+        assert_eq!(
+            found.deposits[0],
+            CommitteeDeposit {
+                outpoint: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                amount: Amount::from_sat(10_000),
+                confirmations: 120,
+            }
+        );
+        assert_eq!(found.total(), Amount::from_sat(15_000));
+    }
+
+    #[tokio::test]
+    async fn test_import_descriptors() {
+        let response = |result: serde_json::Value| {
+            serde_json::json!({ "result": result, "error": null, "id": "whatevs" }).to_string()
+        };
+        let descriptor = format!(
+            "{}#abcdefgh",
+            crate::taproot_descriptor_from(crate::zkbitcoin_pubkey().unwrap())
+        );
+
+        // the birth height is looked up, and an already imported descriptor is fine
+        let address = serve_sequence(vec![
+            response(serde_json::json!("00".repeat(32))),
+            response(serde_json::json!({ "height": 100, "time": 1_700_000_000 })),
+            response(serde_json::json!([
+                { "success": true },
+                {
+                    "success": false,
+                    "error": { "code": -4, "message": "Descriptor already imported" },
+                },
+            ])),
+        ]);
+        let ctx = RpcCtx::builder().url(address).build().unwrap();
+        let imports = [
+            DescriptorImport::new(&descriptor).with_birth_height(100),
+            DescriptorImport::new(&descriptor).with_label("again"),
+        ];
+        let results = import_descriptors(&ctx, &imports).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].success);
+        assert!(results[1].already_imported());
+        results[1].check(&descriptor).unwrap();
+
+        // results must match the descriptors
+        let address = serve_once(response(serde_json::json!([])));
+        let ctx = RpcCtx::builder().url(address).build().unwrap();
+        assert!(import_descriptors(&ctx, &imports[1..]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rescan_progress() {
+        let address = serve_sequence(vec![
+            serde_json::json!({
+                "result": { "walletname": "w", "scanning": { "duration": 42, "progress": 0.5 } },
+                "error": null,
+                "id": "whatevs",
+            })
+            .to_string(),
+            serde_json::json!({
+                "result": { "walletname": "w", "scanning": false },
+                "error": null,
+                "id": "whatevs",
+            })
+            .to_string(),
+        ]);
+        let ctx = RpcCtx::builder().url(address).build().unwrap();
+        assert_eq!(
+            get_rescan_progress(&ctx).await.unwrap(),
+            Some(RescanProgress {
+                duration: 42,
+                progress: 0.5
+            })
+        );
+        assert_eq!(get_rescan_progress(&ctx).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_load_or_create_wallet() {
+        let listwallets =
+            serde_json::json!({ "result": ["other"], "error": null, "id": "whatevs" });
+
+        // missing wallets are created
+        let address = serve_sequence(vec![
+            listwallets.to_string(),
+            serde_json::json!({
+                "result": null,
+                "error": { "code": RPC_WALLET_NOT_FOUND, "message": "Path does not exist" },
+                "id": "whatevs",
+            })
+            .to_string(),
+            serde_json::json!({ "result": { "name": "watch" }, "error": null, "id": "whatevs" })
+                .to_string(),
+        ]);
+        let ctx = RpcCtx::builder().url(address).build().unwrap();
+        load_or_create_wallet(&ctx, "watch", true).await.unwrap();
+
+        // loaded wallets are left alone
+        let address = serve_once(listwallets.to_string());
+        let ctx = RpcCtx::builder().url(address).build().unwrap();
+        load_or_create_wallet(&ctx, "other", true).await.unwrap();
+
+        // other errors are reported
+        let address = serve_sequence(vec![
+            listwallets.to_string(),
+            serde_json::json!({
+                "result": null,
+                "error": { "code": -4, "message": "Wallet file verification failed" },
+                "id": "whatevs",
+            })
+            .to_string(),
+        ]);
+        let ctx = RpcCtx::builder().url(address).build().unwrap();
+        let err = load_or_create_wallet(&ctx, "watch", true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("verification failed"), "{err}");
+    }
+}
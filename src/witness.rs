@@ -0,0 +1,614 @@
+//! In-process witness generation for circom circuits (behind the `witness` feature).
+//! This runs the WASM witness calculator that `circom --wasm` generates with wasmtime,
+//! instead of `node generate_witness.js`, so that proving doesn't need Node.js.
+//! The witness is written in the `.wtns` format that `snarkjs plonk prove` reads (see [crate::snarkjs::prove]).
+//!
+//! This follows the `witness_calculator.js` that circom generates alongside the WASM module:
+//! inputs are set signal by signal (found by the FNV-1a hash of their name), 32-bit word by 32-bit word,
+//! and the circuit reports errors by calling back `runtime.exceptionHandler`.
+
+use std::{fmt, path::Path};
+
+use anyhow::{bail, Context, Result};
+use log::debug;
+use num_bigint::BigUint;
+use num_traits::{Num, Zero};
+use serde_json::Value;
+use wasmtime::{
+    Caller, Engine, Extern, Instance, Linker, Module, Store, TypedFunc, WasmParams, WasmResults,
+};
+
+/// The (circom) input of a circuit: values (or arrays of values) by input signal name.
+pub type CircuitInputs = serde_json::Map<String, Value>;
+
+/// Why a witness couldn't be computed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WitnessError {
+    /// The circuit has no input signal with that name.
+    UnknownSignal { signal: String },
+
+    /// An input signal was given the wrong number of values.
+    SignalSize {
+        signal: String,
+        expected: usize,
+        given: usize,
+    },
+
+    /// A value isn't a number.
+    InvalidValue {
+        signal: String,
+        value: String,
+        reason: String,
+    },
+
+    /// Some input values of the circuit weren't given.
+    MissingInputs { set: usize, expected: usize },
+
+    /// The circuit itself failed (e.g. a failed assertion), while setting `signal` (the last input triggers the computation).
+    Circuit {
+        signal: Option<String>,
+        code: i32,
+        message: String,
+    },
+}
+
+impl WitnessError {
+    /// What the codes given to `runtime.exceptionHandler` mean.
+    fn describe_code(code: i32) -> &'static str {
+        match code {
+            1 => "signal not found",
+            2 => "too many signals set",
+            3 => "signal already set",
+            4 => "an assertion of the circuit failed",
+            5 => "not enough memory",
+            6 => "input signal array access exceeds its size",
+            _ => "unknown error",
+        }
+    }
+}
+
+impl fmt::Display for WitnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownSignal { signal } => {
+                write!(f, "the circuit has no input signal `{signal}`")
+            }
+            Self::SignalSize {
+                signal,
+                expected,
+                given,
+            } => write!(
+                f,
+                "the input signal `{signal}` takes {expected} values, but {given} were given"
+            ),
+            Self::InvalidValue {
+                signal,
+                value,
+                reason,
+            } => write!(
+                f,
+                "the value `{value}` of the input signal `{signal}` is invalid: {reason}"
+            ),
+            Self::MissingInputs { set, expected } => write!(
+                f,
+                "only {set} of the {expected} input values of the circuit were given (is an input signal missing?)"
+            ),
+            Self::Circuit {
+                signal,
+                code,
+                message,
+            } => {
+                write!(f, "{}", Self::describe_code(*code))?;
+                if let Some(signal) = signal {
+                    write!(f, " (after setting the input signal `{signal}`)")?;
+                }
+                if !message.is_empty() {
+                    write!(f, ": {}", message.trim_end())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for WitnessError {}
+
+/// What the host functions of the witness calculator record.
+#[derive(Default)]
+struct RuntimeState {
+    /// The code the circuit last called `runtime.exceptionHandler` with.
+    exception: Option<i32>,
+
+    /// The messages the circuit printed before failing.
+    error_message: String,
+}
+
+/// A circom witness calculator, loaded from its WASM module.
+pub struct WitnessCalculator {
+    store: Store<RuntimeState>,
+    instance: Instance,
+
+    /// The number of 32-bit words of a field element.
+    n32: usize,
+
+    /// The prime of the field of the circuit.
+    prime: BigUint,
+
+    /// The number of field elements of the witness.
+    witness_size: usize,
+}
+
+impl WitnessCalculator {
+    /// Loads the `<circuit>.wasm` generated by `circom --wasm` (in `<circuit>_js/`).
+    pub fn from_file(wasm_path: &Path) -> Result<Self> {
+        let wasm = std::fs::read(wasm_path)
+            .with_context(|| format!("couldn't read {}", wasm_path.display()))?;
+        Self::new(&wasm)
+    }
+
+    pub fn new(wasm: &[u8]) -> Result<Self> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, wasm).context("couldn't load the witness calculator")?;
+
+        let mut linker = Linker::new(&engine);
+        linker.func_wrap(
+            "runtime",
+            "exceptionHandler",
+            |mut caller: Caller<'_, RuntimeState>, code: i32| -> Result<()> {
+                caller.data_mut().exception = Some(code);
+                bail!("the circuit raised exception {code}")
+            },
+        )?;
+        linker.func_wrap(
+            "runtime",
+            "printErrorMessage",
+            |mut caller: Caller<'_, RuntimeState>| -> Result<()> {
+                let message = read_message(&mut caller)?;
+                let error_message = &mut caller.data_mut().error_message;
+                error_message.push_str(&message);
+                error_message.push('\n');
+                Ok(())
+            },
+        )?;
+        linker.func_wrap(
+            "runtime",
+            "writeBufferMessage",
+            |mut caller: Caller<'_, RuntimeState>| -> Result<()> {
+                let message = read_message(&mut caller)?;
+                debug!("- circuit log: {message}");
+                Ok(())
+            },
+        )?;
+        linker.func_wrap(
+            "runtime",
+            "showSharedRWMemory",
+            |_caller: Caller<'_, RuntimeState>| {},
+        )?;
+
+        let mut store = Store::new(&engine, RuntimeState::default());
+        let instance = linker.instantiate(&mut store, &module).context(
+            "couldn't instantiate the witness calculator (was it generated by circom 2?)",
+        )?;
+
+        let mut calculator = Self {
+            store,
+            instance,
+            n32: 0,
+            prime: BigUint::zero(),
+            witness_size: 0,
+        };
+        let version: i32 = calculator.call("getVersion", ())?;
+        debug!("- loaded a circom {version} witness calculator");
+        calculator.n32 = usize::try_from(calculator.call::<(), i32>("getFieldNumLen32", ())?)?;
+        calculator.call::<(), ()>("getRawPrime", ())?;
+        calculator.prime = calculator.read_shared_memory()?;
+        calculator.witness_size =
+            usize::try_from(calculator.call::<(), i32>("getWitnessSize", ())?)?;
+        Ok(calculator)
+    }
+
+    /// The prime of the field of the circuit.
+    pub fn prime(&self) -> &BigUint {
+        &self.prime
+    }
+
+    /// The number of field elements of the witness (including the constant 1 it starts with).
+    pub fn witness_size(&self) -> usize {
+        self.witness_size
+    }
+
+    /// Computes the witness of the circuit for the given inputs.
+    /// Errors about the inputs (or failures of the circuit) can be downcast to [WitnessError].
+    pub fn calculate(&mut self, inputs: &CircuitInputs) -> Result<Vec<BigUint>> {
+        self.store.data_mut().exception = None;
+        self.store.data_mut().error_message.clear();
+        self.call_circuit::<i32, ()>("init", 0, None)?;
+
+        let mut set = 0;
+        for (signal, value) in inputs {
+            let (msb, lsb) = fnv_hash(signal);
+            let size: i32 = self.call("getInputSignalSize", (msb, lsb))?;
+            let Ok(size) = usize::try_from(size) else {
+                bail!(WitnessError::UnknownSignal {
+                    signal: signal.clone()
+                });
+            };
+
+            let mut values = vec![];
+            flatten(signal, value, &self.prime, &mut values)?;
+            if values.len() != size {
+                bail!(WitnessError::SignalSize {
+                    signal: signal.clone(),
+                    expected: size,
+                    given: values.len(),
+                });
+            }
+
+            for (pos, value) in values.iter().enumerate() {
+                self.write_shared_memory(value)?;
+                self.call_circuit::<(i32, i32, i32), ()>(
+                    "setInputSignal",
+                    (msb, lsb, i32::try_from(pos)?),
+                    Some(signal),
+                )?;
+                set += 1;
+            }
+        }
+
+        let expected = usize::try_from(self.call::<(), i32>("getInputSize", ())?)?;
+        if set < expected {
+            bail!(WitnessError::MissingInputs { set, expected });
+        }
+
+        (0..self.witness_size)
+            .map(|idx| {
+                self.call_circuit::<i32, ()>("getWitness", i32::try_from(idx)?, None)?;
+                self.read_shared_memory()
+            })
+            .collect()
+    }
+
+    /// Computes the witness of the circuit for the given inputs, in the `.wtns` format (version 2) snarkjs reads.
+    pub fn calculate_wtns(&mut self, inputs: &CircuitInputs) -> Result<Vec<u8>> {
+        let witness = self.calculate(inputs)?;
+        Ok(wtns_bytes(&self.prime, self.n32, &witness))
+    }
+
+    /// Calls a function exported by the witness calculator.
+    fn call<P: WasmParams, R: WasmResults>(&mut self, name: &str, params: P) -> Result<R> {
+        self.typed_func(name)?.call(&mut self.store, params)
+    }
+
+    /// Same as [WitnessCalculator::call], for the functions that can fail on a circuit error
+    /// (which is then returned as a [WitnessError::Circuit], rather than as a trap).
+    fn call_circuit<P: WasmParams, R: WasmResults>(
+        &mut self,
+        name: &str,
+        params: P,
+        signal: Option<&str>,
+    ) -> Result<R> {
+        let func = self.typed_func(name)?;
+        match func.call(&mut self.store, params) {
+            Ok(result) => Ok(result),
+            Err(err) => match self.store.data_mut().exception.take() {
+                Some(code) => bail!(WitnessError::Circuit {
+                    signal: signal.map(str::to_string),
+                    code,
+                    message: std::mem::take(&mut self.store.data_mut().error_message),
+                }),
+                None => Err(err.context(format!("the witness calculator failed in `{name}`"))),
+            },
+        }
+    }
+
+    fn typed_func<P: WasmParams, R: WasmResults>(&mut self, name: &str) -> Result<TypedFunc<P, R>> {
+        self.instance
+            .get_typed_func(&mut self.store, name)
+            .with_context(|| format!("the witness calculator doesn't export `{name}` as expected"))
+    }
+
+    /// Reads the field element in the shared memory of the witness calculator.
+    fn read_shared_memory(&mut self) -> Result<BigUint> {
+        let words = (0..self.n32)
+            .map(|idx| {
+                let word: i32 = self.call("readSharedRWMemory", i32::try_from(idx)?)?;
+                Ok(word as u32)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(BigUint::from_slice(&words))
+    }
+
+    /// Writes a field element to the shared memory of the witness calculator.
+    fn write_shared_memory(&mut self, value: &BigUint) -> Result<()> {
+        let words = value.to_u32_digits();
+        for idx in 0..self.n32 {
+            let word = words.get(idx).copied().unwrap_or(0);
+            self.call::<(i32, i32), ()>("writeSharedRWMemory", (i32::try_from(idx)?, word as i32))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the message the circuit is printing, character by character.
+fn read_message(caller: &mut Caller<'_, RuntimeState>) -> Result<String> {
+    let get_message_char = caller
+        .get_export("getMessageChar")
+        .and_then(Extern::into_func)
+        .context("the witness calculator doesn't export `getMessageChar`")?
+        .typed::<(), i32>(&*caller)?;
+    let mut message = String::new();
+    loop {
+        let c = get_message_char.call(&mut *caller, ())?;
+        if c == 0 || message.len() >= 4096 {
+            return Ok(message);
+        }
+        message.push(char::from(c as u8));
+    }
+}
+
+/// The 64-bit FNV-1a hash of a signal name, as the two 32-bit halves the witness calculator takes.
+fn fnv_hash(signal: &str) -> (i32, i32) {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in signal.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    ((hash >> 32) as u32 as i32, hash as u32 as i32)
+}
+
+/// Flattens the (possibly nested arrays of) values of an input signal into field elements,
+/// reduced modulo the prime (as `witness_calculator.js` does, negative values included).
+fn flatten(signal: &str, value: &Value, prime: &BigUint, values: &mut Vec<BigUint>) -> Result<()> {
+    let invalid = |reason: &str| WitnessError::InvalidValue {
+        signal: signal.to_string(),
+        value: value.to_string(),
+        reason: reason.to_string(),
+    };
+    let number = match value {
+        Value::Array(items) => {
+            for item in items {
+                flatten(signal, item, prime, values)?;
+            }
+            return Ok(());
+        }
+        Value::Number(number) => number.to_string(),
+        Value::String(string) => string.clone(),
+        _ => bail!(invalid("expected a number, a string, or an array")),
+    };
+
+    let (negative, digits) = match number.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, number.as_str()),
+    };
+    let parsed = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => BigUint::from_str_radix(hex, 16),
+        None => BigUint::from_str_radix(digits, 10),
+    };
+    let value =
+        parsed.map_err(|_| invalid("expected a decimal or a 0x-prefixed hex integer"))? % prime;
+    values.push(if negative && !value.is_zero() {
+        prime - value
+    } else {
+        value
+    });
+    Ok(())
+}
+
+/// Serializes a witness in the `.wtns` format (version 2): a header section with the prime,
+/// and a section with the witness, as little-endian field elements.
+fn wtns_bytes(prime: &BigUint, n32: usize, witness: &[BigUint]) -> Vec<u8> {
+    let n8 = n32 * 4;
+    let field_element = |value: &BigUint| {
+        let mut bytes = value.to_bytes_le();
+        bytes.resize(n8, 0);
+        bytes
+    };
+
+    let mut wtns = b"wtns".to_vec();
+    wtns.extend(2u32.to_le_bytes()); // version
+    wtns.extend(2u32.to_le_bytes()); // number of sections
+
+    // the header
+    wtns.extend(1u32.to_le_bytes());
+    wtns.extend((8 + n8 as u64).to_le_bytes());
+    wtns.extend((n8 as u32).to_le_bytes());
+    wtns.extend(field_element(prime));
+    wtns.extend((witness.len() as u32).to_le_bytes());
+
+    // the witness
+    wtns.extend(2u32.to_le_bytes());
+    wtns.extend(((n8 * witness.len()) as u64).to_le_bytes());
+    for value in witness {
+        wtns.extend(field_element(value));
+    }
+    wtns
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::constants::CIRCOM_ETH_PRIME;
+
+    /// A hand-written witness calculator following the ABI of circom's, for a circuit with a single input `in`,
+    /// which asserts that `in` isn't 0, and whose witness is `[1, in]`.
+    const MOCK_WITNESS_CALCULATOR: &str = r#"
+(module
+  (import "runtime" "exceptionHandler" (func $exception (param i32)))
+  (import "runtime" "printErrorMessage" (func $print_error))
+  (import "runtime" "writeBufferMessage" (func $write_buffer))
+  (import "runtime" "showSharedRWMemory" (func $show_memory))
+  (memory (export "memory") 1)
+
+  ;; the shared memory is at 0, the witness at 64, the prime at 256, and the error message at 512
+  (data (i32.const 256) "\01\00\00\f0\93\f5\e1\43\91\70\b9\79\48\e8\33\28\5d\58\81\81\b6\45\50\b8\29\a0\31\e1\72\4e\64\30")
+  (data (i32.const 512) "Error in template Mock_0 line: 3\00")
+  (global $input_set (mut i32) (i32.const 0))
+  (global $message_pos (mut i32) (i32.const 0))
+
+  (func $copy (param $dst i32) (param $src i32)
+    (i64.store (local.get $dst) (i64.load (local.get $src)))
+    (i64.store offset=8 (local.get $dst) (i64.load offset=8 (local.get $src)))
+    (i64.store offset=16 (local.get $dst) (i64.load offset=16 (local.get $src)))
+    (i64.store offset=24 (local.get $dst) (i64.load offset=24 (local.get $src))))
+  (func $is_in (param $msb i32) (param $lsb i32) (result i32)
+    (i32.and
+      (i32.eq (local.get $msb) (i32.const 146225159))
+      (i32.eq (local.get $lsb) (i32.const 3042724798))))
+
+  (func (export "getVersion") (result i32) (i32.const 2))
+  (func (export "getFieldNumLen32") (result i32) (i32.const 8))
+  (func (export "getRawPrime") (call $copy (i32.const 0) (i32.const 256)))
+  (func (export "readSharedRWMemory") (param $i i32) (result i32)
+    (i32.load (i32.shl (local.get $i) (i32.const 2))))
+  (func (export "writeSharedRWMemory") (param $i i32) (param $v i32)
+    (i32.store (i32.shl (local.get $i) (i32.const 2)) (local.get $v)))
+  (func (export "init") (param $sanity_check i32)
+    (i64.store (i32.const 64) (i64.const 1))
+    (i64.store (i32.const 72) (i64.const 0))
+    (i64.store (i32.const 80) (i64.const 0))
+    (i64.store (i32.const 88) (i64.const 0))
+    (global.set $input_set (i32.const 0))
+    (global.set $message_pos (i32.const 0)))
+  (func (export "getInputSignalSize") (param $msb i32) (param $lsb i32) (result i32)
+    (if (result i32) (call $is_in (local.get $msb) (local.get $lsb))
+      (then (i32.const 1))
+      (else (i32.const -1))))
+  (func (export "setInputSignal") (param $msb i32) (param $lsb i32) (param $pos i32)
+    (if (i32.eqz (call $is_in (local.get $msb) (local.get $lsb)))
+      (then (call $exception (i32.const 1)) (return)))
+    (if (global.get $input_set)
+      (then (call $exception (i32.const 3)) (return)))
+    (if (i64.eqz
+          (i64.or
+            (i64.or (i64.load (i32.const 0)) (i64.load (i32.const 8)))
+            (i64.or (i64.load (i32.const 16)) (i64.load (i32.const 24)))))
+      (then (call $print_error) (call $exception (i32.const 4)) (return)))
+    (call $copy (i32.const 96) (i32.const 0))
+    (global.set $input_set (i32.const 1)))
+  (func (export "getInputSize") (result i32) (i32.const 1))
+  (func (export "getWitnessSize") (result i32) (i32.const 2))
+  (func (export "getWitness") (param $i i32)
+    (call $copy (i32.const 0) (i32.add (i32.const 64) (i32.shl (local.get $i) (i32.const 5)))))
+  (func (export "getMessageChar") (result i32) (local $c i32)
+    (local.set $c (i32.load8_u (i32.add (i32.const 512) (global.get $message_pos))))
+    (if (local.get $c)
+      (then (global.set $message_pos (i32.add (global.get $message_pos) (i32.const 1)))))
+    (local.get $c))
+)
+"#;
+
+    fn calculator() -> WitnessCalculator {
+        WitnessCalculator::new(MOCK_WITNESS_CALCULATOR.as_bytes()).unwrap()
+    }
+
+    fn inputs(value: Value) -> CircuitInputs {
+        value.as_object().unwrap().clone()
+    }
+
+    fn witness_error(err: anyhow::Error) -> WitnessError {
+        err.downcast::<WitnessError>().unwrap()
+    }
+
+    #[test]
+    fn test_fnv_hash() {
+        // (as computed by witness_calculator.js)
+        assert_eq!(fnv_hash("in"), (146225159, 3042724798u32 as i32));
+        assert_eq!(
+            fnv_hash(""),
+            ((0xcbf29ce4u32) as i32, (0x84222325u32) as i32)
+        );
+    }
+
+    #[test]
+    fn test_calculate_witness() {
+        let mut calculator = calculator();
+        assert_eq!(calculator.prime().to_string(), CIRCOM_ETH_PRIME);
+
+        let witness = calculator.calculate(&inputs(json!({ "in": "7" }))).unwrap();
+        assert_eq!(witness, [BigUint::from(1u8), BigUint::from(7u8)]);
+
+        // hex, numbers, and negative values (reduced modulo the prime)
+        for (value, expected) in [
+            (json!("0x07"), BigUint::from(7u8)),
+            (json!(7), BigUint::from(7u8)),
+            (json!(["7"]), BigUint::from(7u8)),
+            (json!("-1"), calculator.prime().clone() - 1u8),
+        ] {
+            let witness = calculator
+                .calculate(&inputs(json!({ "in": value })))
+                .unwrap();
+            assert_eq!(witness[1], expected);
+        }
+
+        // the witness file
+        let wtns = calculator
+            .calculate_wtns(&inputs(json!({ "in": "7" })))
+            .unwrap();
+        assert_eq!(&wtns[..4], b"wtns");
+        assert_eq!(wtns.len(), 12 + (12 + 8 + 32) + 12 + 2 * 32);
+        assert_eq!(&wtns[wtns.len() - 32..][..2], &[7, 0]);
+    }
+
+    #[test]
+    fn test_witness_errors_name_the_signal() {
+        let mut calculator = calculator();
+
+        // a wrong input name
+        let err = calculator
+            .calculate(&inputs(json!({ "input": "7" })))
+            .unwrap_err();
+        assert_eq!(
+            witness_error(err),
+            WitnessError::UnknownSignal {
+                signal: "input".to_string()
+            }
+        );
+
+        // too many values
+        let err = calculator
+            .calculate(&inputs(json!({ "in": ["7", "8"] })))
+            .unwrap_err();
+        assert_eq!(
+            witness_error(err).to_string(),
+            "the input signal `in` takes 1 values, but 2 were given"
+        );
+
+        // not a number
+        let err = calculator
+            .calculate(&inputs(json!({ "in": "seven" })))
+            .unwrap_err();
+        assert!(matches!(
+            witness_error(err),
+            WitnessError::InvalidValue { signal, .. } if signal == "in"
+        ));
+
+        // a missing signal
+        let err = calculator.calculate(&inputs(json!({}))).unwrap_err();
+        assert_eq!(
+            witness_error(err),
+            WitnessError::MissingInputs {
+                set: 0,
+                expected: 1
+            }
+        );
+
+        // a failed assertion, with the message of the circuit rather than a trap
+        let err = calculator
+            .calculate(&inputs(json!({ "in": "0" })))
+            .unwrap_err();
+        let err = witness_error(err);
+        assert!(matches!(err, WitnessError::Circuit { code: 4, .. }));
+        assert_eq!(
+            err.to_string(),
+            "an assertion of the circuit failed (after setting the input signal `in`): Error in template Mock_0 line: 3"
+        );
+
+        // and the calculator can be used again
+        calculator.calculate(&inputs(json!({ "in": "7" }))).unwrap();
+    }
+}